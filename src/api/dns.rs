@@ -0,0 +1,155 @@
+/// Live DNS resolution for domains
+///
+/// # Records
+/// A, AAAA, MX, NS and TXT, resolved through the system's resolvers (or
+/// Google Public DNS when the system configuration can't be read). Host names
+/// are lowercased without the trailing dot, and duplicates are dropped.
+///
+/// # Failures
+/// A missing record type, an NXDOMAIN answer or a timeout is not an error:
+/// whatever did resolve is returned, with the types that failed listed in
+/// `DnsLookup::failures`.
+///
+/// # Database Integration
+/// Records are stored in `domain_dns`, one row per domain, type and value, with
+/// the time each was last resolved.
+use crate::api::whois;
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_writer::DatabaseHandle;
+use duckdb::params;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::proto::op::ResponseCode;
+use hickory_resolver::proto::rr::{RData, RecordType};
+use hickory_resolver::TokioAsyncResolver;
+use serde::Serialize;
+
+/// Record types looked up for every domain
+pub const RECORD_TYPES: [RecordType; 5] = [RecordType::A, RecordType::AAAA, RecordType::MX, RecordType::NS, RecordType::TXT];
+
+/// One resolved record
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DnsRecord {
+    pub record_type: String,
+    pub value: String,
+    pub ttl: u32,
+}
+
+/// Records resolved for a domain, and the record types that couldn't be
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DnsLookup {
+    pub domain: String,
+    pub records: Vec<DnsRecord>,
+    pub failures: Vec<String>,
+}
+
+fn host(name: &impl ToString) -> String {
+    name.to_string().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Text stored for a record, or `None` for types other than the ones looked up
+fn record_value(data: &RData) -> Option<String> {
+    match data {
+        RData::A(ip) => Some(ip.to_string()),
+        RData::AAAA(ip) => Some(ip.to_string()),
+        RData::MX(mx) => Some(format!("{} {}", mx.preference(), host(mx.exchange()))),
+        RData::NS(ns) => Some(host(&ns.0)),
+        // Strings of one record are joined, as SPF and DKIM readers do
+        RData::TXT(txt) => Some(txt.to_string()),
+        _ => None,
+    }
+}
+
+fn resolver(config: &Config) -> TokioAsyncResolver {
+    let (resolver_config, mut options) = hickory_resolver::system_conf::read_system_conf()
+        .unwrap_or_else(|_| (ResolverConfig::google(), ResolverOpts::default()));
+    options.timeout = config.http_connect_timeout();
+    options.attempts = 2;
+    TokioAsyncResolver::tokio(resolver_config, options)
+}
+
+/// Whether the error says the domain doesn't exist at all
+fn is_nxdomain(err: &ResolveError) -> bool {
+    matches!(err.kind(), ResolveErrorKind::NoRecordsFound { response_code: ResponseCode::NXDomain, .. })
+}
+
+/// Resolves the A, AAAA, MX, NS and TXT records of a domain, or of the domain a URL points to
+pub async fn resolve(config: &Config, domain: &str) -> Result<DnsLookup, FragarachError> {
+    let host_name = whois::host_name(domain)
+        .ok_or_else(|| FragarachError::InvalidInput(format!("'{}' is not a domain name", domain)))?;
+    let resolver = resolver(config);
+    let mut lookup = DnsLookup { domain: host_name.clone(), ..Default::default() };
+
+    for record_type in RECORD_TYPES {
+        let answer = match resolver.lookup(host_name.as_str(), record_type).await {
+            Ok(answer) => answer,
+            Err(e) if is_nxdomain(&e) => {
+                // No other type will resolve either
+                lookup.failures.push(format!("{} does not exist (NXDOMAIN)", host_name));
+                break;
+            }
+            // The domain exists without records of this type
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => continue,
+            Err(e) if matches!(e.kind(), ResolveErrorKind::Timeout) => {
+                lookup.failures.push(format!("{}: timed out", record_type));
+                continue;
+            }
+            Err(e) => {
+                lookup.failures.push(format!("{}: {}", record_type, e));
+                continue;
+            }
+        };
+
+        // Answers also carry the CNAMEs followed to reach the records
+        for record in answer.record_iter().filter(|r| r.record_type() == record_type) {
+            let Some(value) = record.data().and_then(record_value) else { continue };
+            let record = DnsRecord { record_type: record_type.to_string(), value, ttl: record.ttl() };
+            if !lookup.records.iter().any(|r| r.record_type == record.record_type && r.value == record.value) {
+                lookup.records.push(record);
+            }
+        }
+    }
+
+    Ok(lookup)
+}
+
+/// Stores resolved records in `domain_dns`, refreshing the time of ones seen before
+pub async fn save(db: &DatabaseHandle, lookup: &DnsLookup) -> Result<(), FragarachError> {
+    let case_id = db.active_case();
+    let domain = lookup.domain.clone();
+    let records = lookup.records.clone();
+    db.call(move |conn| {
+        let tx = conn.unchecked_transaction()?;
+        for record in &records {
+            tx.execute(
+                "INSERT OR REPLACE INTO domain_dns (domain, record_type, value, ttl, case_id, resolved_at)
+                 VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)",
+                params![domain, record.record_type, record.value, record.ttl, case_id],
+            )?;
+        }
+        tx.commit()
+    }).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_resolver::proto::rr::rdata::{A, MX, NS, TXT};
+    use hickory_resolver::Name;
+    use std::str::FromStr;
+
+    #[test]
+    fn record_values_are_normalized() {
+        let name = |n: &str| Name::from_str(n).unwrap();
+        assert_eq!(record_value(&RData::A(A::new(93, 184, 215, 14))).as_deref(), Some("93.184.215.14"));
+        assert_eq!(record_value(&RData::MX(MX::new(10, name("Mail.Example.COM.")))).as_deref(), Some("10 mail.example.com"));
+        assert_eq!(record_value(&RData::NS(NS(name("a.iana-servers.net.")))).as_deref(), Some("a.iana-servers.net"));
+        assert_eq!(
+            record_value(&RData::TXT(TXT::new(vec!["v=spf1 ".to_string(), "-all".to_string()]))).as_deref(),
+            Some("v=spf1 -all")
+        );
+        assert_eq!(record_value(&RData::CNAME(hickory_resolver::proto::rr::rdata::CNAME(name("example.com.")))), None);
+    }
+}
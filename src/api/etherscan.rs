@@ -0,0 +1,504 @@
+/// Etherscan API integration, the fallback source of accounts and transactions
+///
+/// Serves the same `query_ethereum_account`, `query_ethereum_transactions` and
+/// `stream_ethereum_transactions` calls as `transpose`, through the multichain
+/// Etherscan v2 API, for when Transpose is down or no Transpose key is set.
+/// Rows are mapped into the shape the Transpose templates return (see
+/// `transaction_row` and `account_row` for the field mapping), so they are
+/// saved to the same tables and read back by the same code.
+///
+/// # Limits
+/// - Requests are paced to three a second, the free plan's limit, across the
+///   whole process; addresses are fetched one after another
+/// - `txlist` serves at most 10,000 rows per block window, so long histories
+///   are walked in windows, each ending at the oldest block the last one reached
+/// - Only blocks can be filtered by the API; date bounds are applied to the
+///   rows, stopping at the first one older than the range
+/// - Goerli is no longer served by Etherscan
+///
+/// # Errors
+/// Etherscan refuses requests with a 200 response carrying `"status": "0"`;
+/// rejected keys and rate limits are reported as the 401 and 429 they stand
+/// for, so they are classified like those of the other APIs.
+use crate::api::http;
+use crate::api::rate_limit::{RateLimitInfo, RateLimiter};
+use crate::api::transpose::{validate_address, AddressPull, Chain, TransactionRange, PAGE_BUFFER, PAGE_SIZE};
+use crate::config::Config;
+use crate::error::FragarachError;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+
+const API_URL: &str = "https://api.etherscan.io/v2/api";
+
+/// Wait between requests, keeping under the free plan's three a second
+const REQUEST_INTERVAL: Duration = Duration::from_millis(350);
+
+/// Most rows `txlist` returns for one block window (page × offset)
+const MAX_WINDOW_ROWS: usize = 10_000;
+
+/// Paces all Etherscan requests made by the process
+static LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+
+fn limiter() -> &'static Mutex<RateLimiter> {
+    LIMITER.get_or_init(|| Mutex::new(RateLimiter::new(REQUEST_INTERVAL)))
+}
+
+/// Etherscan's id of a chain
+fn chain_id(chain: Chain) -> Result<u64, FragarachError> {
+    match chain {
+        Chain::Ethereum => Ok(1),
+        Chain::Polygon => Ok(137),
+        Chain::Goerli => Err(FragarachError::InvalidInput("Etherscan no longer serves goerli".to_string())),
+    }
+}
+
+/// Sends one API request for `chain`, returning the `result` of the response
+async fn call(config: &Config, chain: Chain, params: &[(&str, String)]) -> Result<Value, FragarachError> {
+    let api_key = config.etherscan_api_key().ok_or(FragarachError::MissingApiKey("Etherscan"))?;
+    let chain_id = chain_id(chain)?;
+
+    let client = http::client(config)?;
+    let request = client.get(API_URL)
+        .query(&[("chainid", chain_id.to_string())])
+        .query(params)
+        .query(&[("apikey", api_key)]);
+    {
+        let mut limiter = limiter().lock().await;
+        limiter.wait().await;
+        limiter.start(Instant::now());
+    }
+    let response = http::send_with_retry(config, request, "Etherscan API request").await?;
+    limiter().lock().await.record(&RateLimitInfo::from_headers(response.headers()), Instant::now());
+
+    if !response.status().is_success() {
+        return Err(http::status_error("Etherscan API request", response).await);
+    }
+    let body: Value = response.json().await
+        .map_err(|e| http::classify(e, "Reading Etherscan response"))?;
+    response_result(body)
+}
+
+/// The `result` of a response, or the refusal it carries
+///
+/// Lists with nothing in them come back as refusals too ("No transactions
+/// found"), and are returned as empty lists.
+fn response_result(body: Value) -> Result<Value, FragarachError> {
+    if let Some(error) = body.get("error") {
+        return Err(FragarachError::UnexpectedResponse(error.to_string()));
+    }
+    let result = body.get("result").cloned().unwrap_or(Value::Null);
+    if body.get("status").and_then(Value::as_str) != Some("0") || result.is_array() {
+        return Ok(result);
+    }
+
+    let reason = result.as_str()
+        .or_else(|| body.get("message").and_then(Value::as_str))
+        .unwrap_or_default()
+        .to_string();
+    let lower = reason.to_ascii_lowercase();
+    let status = if lower.contains("api key") {
+        StatusCode::UNAUTHORIZED
+    } else if lower.contains("rate limit") {
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    Err(FragarachError::ApiStatus { operation: "Etherscan API request".to_string(), status, body: reason })
+}
+
+/// Confirms the API key is accepted by reading the latest Ethereum block number
+pub async fn check_api_key(config: &Config) -> Result<(), FragarachError> {
+    let params = [("module", "proxy".to_string()), ("action", "eth_blockNumber".to_string())];
+    call(config, Chain::Ethereum, &params).await?;
+    Ok(())
+}
+
+/// Confirms `api_key` is accepted before it replaces the configured key
+pub async fn validate_api_key(config: &Config, api_key: &str) -> Result<(), FragarachError> {
+    let mut config = config.clone();
+    config.set_etherscan_api_key(Some(api_key.to_string()));
+    check_api_key(&config).await
+}
+
+/// One page of an address's transactions from `txlist`
+#[allow(clippy::too_many_arguments)]
+async fn txlist(
+    config: &Config,
+    chain: Chain,
+    address: &str,
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+    page: usize,
+    offset: usize,
+    sort: &str,
+) -> Result<Vec<Value>, FragarachError> {
+    let mut params = vec![
+        ("module", "account".to_string()),
+        ("action", "txlist".to_string()),
+        ("address", address.to_string()),
+        ("page", page.to_string()),
+        ("offset", offset.to_string()),
+        ("sort", sort.to_string()),
+    ];
+    params.extend(from_block.map(|block| ("startblock", block.to_string())));
+    params.extend(to_block.map(|block| ("endblock", block.to_string())));
+
+    match call(config, chain, &params).await? {
+        Value::Array(entries) => Ok(entries),
+        other => Err(FragarachError::UnexpectedResponse(format!("{:?}", other))),
+    }
+}
+
+/// Formats Unix seconds the way Transpose returns timestamps (`2015-08-07T03:30:33Z`)
+pub(crate) fn utc_timestamp(secs: u64) -> String {
+    let (days, time) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// A decimal string field as a JSON number; amounts in wei beyond `u64` lose precision
+fn number(entry: &Value, field: &str) -> Value {
+    let Some(text) = entry.get(field).and_then(Value::as_str) else {
+        return Value::Null;
+    };
+    text.parse::<u64>().map(Value::from)
+        .or_else(|_| text.parse::<f64>().map(Value::from))
+        .unwrap_or(Value::Null)
+}
+
+/// An address field, lowercased; Etherscan sends empty strings for none
+fn address_field(entry: &Value, field: &str) -> Value {
+    match entry.get(field).and_then(Value::as_str) {
+        Some(address) if !address.is_empty() => Value::from(address.to_ascii_lowercase()),
+        _ => Value::Null,
+    }
+}
+
+/// Timestamp of a `txlist` entry, if it has one
+fn entry_timestamp(entry: &Value) -> Option<String> {
+    entry.get("timeStamp").and_then(Value::as_str)?.parse().ok().map(utc_timestamp)
+}
+
+/// Maps a `txlist` entry to the columns of `ethereum_transactions`
+///
+/// - `hash` → `transaction_hash`
+/// - `blockNumber` → `block_number`
+/// - `timeStamp` (Unix seconds) → `timestamp` (UTC)
+/// - `from` / `to` → `from_address` / `to_address` (`to` is empty, so `NULL`,
+///   for contract creations)
+/// - `contractAddress` → `contract_address` (the contract created, if any)
+/// - `value` (wei) → `value`
+/// - `gas` → `gas_limit`
+/// - `gasPrice` → `gas_price`
+/// - `gasUsed` → `gas_used`
+/// - `gasUsed` × `gasPrice` → `transaction_fee`
+/// - `nonce` → `nonce`
+/// - `transactionIndex` → `position`
+/// - `input` → `input`
+///
+/// Etherscan has no counterpart for the base and priority fees, fees burned,
+/// rewarded or saved, log and internal transaction counts, `output` or `type`;
+/// those columns stay `NULL`.
+fn transaction_row(entry: &Value, chain: Chain) -> Value {
+    let text = |field: &str| entry.get(field).and_then(Value::as_str).unwrap_or_default();
+    let fee = match (text("gasUsed").parse::<f64>(), text("gasPrice").parse::<f64>()) {
+        (Ok(used), Ok(price)) => Value::from(used * price),
+        _ => Value::Null,
+    };
+    json!({
+        "transaction_hash": text("hash").to_ascii_lowercase(),
+        "block_number": number(entry, "blockNumber"),
+        "contract_address": address_field(entry, "contractAddress"),
+        "from_address": address_field(entry, "from"),
+        "gas_limit": number(entry, "gas"),
+        "gas_price": number(entry, "gasPrice"),
+        "gas_used": number(entry, "gasUsed"),
+        "input": text("input"),
+        "nonce": number(entry, "nonce"),
+        "position": number(entry, "transactionIndex"),
+        "timestamp": entry_timestamp(entry),
+        "to_address": address_field(entry, "to"),
+        "transaction_fee": fee,
+        "value": number(entry, "value"),
+        "chain": chain.as_str(),
+    })
+}
+
+/// Builds the `ethereum_accounts` row Etherscan can tell about an address
+///
+/// Etherscan keeps no account records, so they are pieced together:
+/// - `type`: `contract` when the address has code, `wallet` otherwise
+/// - `created_timestamp`: the oldest transaction's timestamp
+/// - `last_active_timestamp`: the newest transaction's timestamp
+/// - `creator_address`: the deployer of a contract (`contractCreator`)
+///
+/// Only normal transactions count as activity, not token transfers received.
+fn account_row(
+    chain: Chain,
+    address: &str,
+    contract: bool,
+    oldest: Option<&Value>,
+    newest: Option<&Value>,
+    creator: Option<&Value>,
+) -> Value {
+    json!({
+        "address": address,
+        "created_timestamp": oldest.and_then(entry_timestamp),
+        "creator_address": creator.map(|creation| address_field(creation, "contractCreator")).unwrap_or(Value::Null),
+        "last_active_timestamp": newest.and_then(entry_timestamp),
+        "type": if contract { "contract" } else { "wallet" },
+        "chain": chain.as_str(),
+    })
+}
+
+/// Fetches the account details of an address, tagged with its chain
+///
+/// Returns no rows for an address without code or transactions, as Transpose
+/// does for one it has no record of. Takes three or four requests.
+pub async fn query_ethereum_account(config: &Config, chain: Chain, address: &str) -> Result<Vec<Value>, FragarachError> {
+    let address = validate_address(address)?;
+    let code_params = [
+        ("module", "proxy".to_string()),
+        ("action", "eth_getCode".to_string()),
+        ("address", address.clone()),
+        ("tag", "latest".to_string()),
+    ];
+    let code = call(config, chain, &code_params).await?;
+    let contract = code.as_str().is_some_and(|code| !code.is_empty() && code != "0x");
+
+    let newest = txlist(config, chain, &address, None, None, 1, 1, "desc").await?;
+    if !contract && newest.is_empty() {
+        return Ok(Vec::new());
+    }
+    let oldest = txlist(config, chain, &address, None, None, 1, 1, "asc").await?;
+
+    let creation = if contract {
+        let params = [
+            ("module", "contract".to_string()),
+            ("action", "getcontractcreation".to_string()),
+            ("contractaddresses", address.clone()),
+        ];
+        call(config, chain, &params).await?.get(0).cloned()
+    } else {
+        None
+    };
+
+    Ok(vec![account_row(chain, &address, contract, oldest.first(), newest.first(), creation.as_ref())])
+}
+
+/// Fetches transaction pages for the given addresses and sends each into `pages`
+///
+/// Behaves like `transpose::stream_ethereum_transactions`: only transactions in
+/// `range` are fetched, newest first, in pages of `PAGE_SIZE`; the pull stops
+/// once `max` transactions have been sent in total, if given; and one
+/// `AddressPull` is returned per address, in the order given, an address that
+/// fails not stopping the others. Fails with `ConsumerStopped` if the
+/// receiving side is dropped.
+pub async fn stream_ethereum_transactions(
+    config: &Config,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    range: &TransactionRange,
+    pages: mpsc::Sender<Vec<Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let mut sent = 0;
+    let mut pulls = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let mut fetched = 0;
+        let (truncated, error) = match stream_address(config, chain, address, max, range, &mut sent, &pages, &mut fetched).await {
+            Ok(truncated) => (truncated, None),
+            Err(FragarachError::ConsumerStopped) => return Err(FragarachError::ConsumerStopped),
+            Err(e) => (false, Some(e)),
+        };
+        pulls.push(AddressPull { address: address.clone(), fetched, truncated, error });
+    }
+    Ok(pulls)
+}
+
+/// Fetches the transactions of one address in `range`, up to `max` if given
+///
+/// Collects the pages of `stream_ethereum_transactions` into one list.
+pub async fn query_ethereum_transactions(
+    config: &Config,
+    chain: Chain,
+    address: &str,
+    max: Option<usize>,
+    range: &TransactionRange,
+) -> Result<Vec<Value>, FragarachError> {
+    let (pages_tx, mut pages_rx) = mpsc::channel(PAGE_BUFFER);
+    let addresses = [address.to_string()];
+    let fetch = stream_ethereum_transactions(config, chain, &addresses, max, range, pages_tx);
+    let collect = async {
+        let mut transactions = Vec::new();
+        while let Some(page) = pages_rx.recv().await {
+            transactions.extend(page);
+        }
+        transactions
+    };
+    let (pulls, transactions) = tokio::join!(fetch, collect);
+    match pulls?.into_iter().next().and_then(|pull| pull.error) {
+        Some(e) => Err(e),
+        None => Ok(transactions),
+    }
+}
+
+/// Pages through one address's transactions, adding the rows sent to `fetched`
+///
+/// `sent` counts rows sent for all addresses. Returns whether `max` stopped
+/// the pull before the address ran out of transactions in `range`.
+#[allow(clippy::too_many_arguments)]
+async fn stream_address(
+    config: &Config,
+    chain: Chain,
+    address: &str,
+    max: Option<usize>,
+    range: &TransactionRange,
+    sent: &mut usize,
+    pages: &mpsc::Sender<Vec<Value>>,
+    fetched: &mut usize,
+) -> Result<bool, FragarachError> {
+    let address = validate_address(address)?;
+    let (from_date, to_date) = range.dates();
+    let (from_block, mut to_block) = range.blocks();
+    let mut page = 1;
+    // The next window starts at the oldest block reached, so the transactions
+    // seen from that block are skipped when they come again
+    let mut oldest_block = None;
+    let mut seen_in_oldest_block = HashSet::new();
+    let mut skip = HashSet::new();
+
+    loop {
+        if max.is_some_and(|max| *sent >= max) {
+            return Ok(true);
+        }
+
+        let entries = txlist(config, chain, &address, from_block, to_block, page, PAGE_SIZE, "desc").await?;
+        let last_page = entries.len() < PAGE_SIZE;
+        let mut rows = Vec::with_capacity(entries.len());
+        let mut past_range = false;
+        for entry in &entries {
+            let row = transaction_row(entry, chain);
+            let block = row["block_number"].as_u64();
+            if block != oldest_block {
+                oldest_block = block;
+                seen_in_oldest_block.clear();
+            }
+            let hash = row["transaction_hash"].as_str().unwrap_or_default().to_string();
+            seen_in_oldest_block.insert(hash.clone());
+            if skip.contains(&hash) {
+                continue;
+            }
+
+            // Timestamps start with the day, so they compare with dates as text
+            let day = row["timestamp"].as_str().map(|timestamp| timestamp[..10].to_string());
+            if day.as_deref().zip(from_date).is_some_and(|(day, from)| day < from) {
+                past_range = true;
+                break;
+            }
+            if day.as_deref().zip(to_date).is_none_or(|(day, to)| day <= to) {
+                rows.push(row);
+            }
+        }
+
+        let returned = rows.len();
+        if let Some(max) = max {
+            rows.truncate(max.saturating_sub(*sent));
+        }
+        let cut = rows.len() < returned;
+        *sent += rows.len();
+        *fetched += rows.len();
+        if !rows.is_empty() {
+            // Waits here while the consumer is `PAGE_BUFFER` pages behind
+            pages.send(rows).await
+                .map_err(|_| FragarachError::ConsumerStopped)?;
+        }
+
+        if cut {
+            return Ok(true);
+        }
+        if past_range || last_page {
+            return Ok(false);
+        }
+
+        if page * PAGE_SIZE < MAX_WINDOW_ROWS {
+            page += 1;
+            continue;
+        }
+        let Some(block) = oldest_block else {
+            return Ok(false);
+        };
+        if to_block == Some(block) {
+            return Err(FragarachError::UnexpectedResponse(format!(
+                "block {} holds more than {} transactions of {}, more than Etherscan pages through",
+                block, MAX_WINDOW_ROWS, address
+            )));
+        }
+        to_block = Some(block);
+        skip = std::mem::take(&mut seen_in_oldest_block);
+        page = 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transactions_take_the_transpose_shape() {
+        let entry = json!({
+            "blockNumber": "46147",
+            "timeStamp": "1438918233",
+            "hash": "0x5C504ED432CB51138BCF09AA5E8A410DD4A1E204EF84BFED1BE16DFBA1B22060",
+            "nonce": "0",
+            "transactionIndex": "0",
+            "from": "0xa1e4380a3b1f749673e270229993ee55f35663b4",
+            "to": "0x5df9b87991262f6ba471f09758cde1c0fc1de734",
+            "value": "31337",
+            "gas": "21000",
+            "gasPrice": "50000000000000",
+            "isError": "0",
+            "input": "0x",
+            "contractAddress": "",
+            "gasUsed": "21000",
+        });
+        let row = transaction_row(&entry, Chain::Ethereum);
+        assert_eq!(row["transaction_hash"], "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060");
+        assert_eq!(row["timestamp"], "2015-08-07T03:30:33Z");
+        assert_eq!(row["block_number"], 46147);
+        assert_eq!(row["contract_address"], Value::Null);
+        assert_eq!(row["transaction_fee"], 1.05e18);
+        assert_eq!(row["chain"], "ethereum");
+
+        let huge = json!({ "value": "123456789012345678901234567890" });
+        assert_eq!(number(&huge, "value"), 1.2345678901234568e29);
+        assert_eq!(utc_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn refusals_are_classified_and_empty_lists_are_not() {
+        let none = json!({ "status": "0", "message": "No transactions found", "result": [] });
+        assert_eq!(response_result(none).unwrap(), json!([]));
+
+        let key = json!({ "status": "0", "message": "NOTOK", "result": "Missing/Invalid API Key" });
+        assert!(response_result(key).unwrap_err().is_auth_failure());
+        let limited = json!({ "status": "0", "message": "NOTOK", "result": "Max calls per sec rate limit reached (3/sec)" });
+        assert!(response_result(limited).unwrap_err().is_rate_limited());
+
+        let code = json!({ "jsonrpc": "2.0", "id": 1, "result": "0x" });
+        assert_eq!(response_result(code).unwrap(), "0x");
+    }
+}
@@ -0,0 +1,370 @@
+/// Shared HTTP client for the API integrations
+///
+/// # Timeouts
+/// - Connect timeout: `FRAGARACH_CONNECT_TIMEOUT_SECS` (default 10)
+/// - Total request timeout: `FRAGARACH_HTTP_TIMEOUT_SECS` (default 30)
+///
+/// Individual calls may override the total timeout on their request builder.
+///
+/// # Connection Reuse
+/// One client is built per process for each set of timeouts and cloned for
+/// each call, so paginated pulls reuse pooled connections and TLS sessions
+/// instead of reconnecting for every page, while a config with other timeouts
+/// gets a client of its own. Requests identify themselves as `fragarach/<version>`.
+///
+/// # Retries
+/// `send_with_retry` repeats requests that fail transiently (429, 500, 502,
+/// 503, 504, connection errors and timeouts) up to `FRAGARACH_HTTP_MAX_ATTEMPTS` times,
+/// with exponential backoff from `FRAGARACH_HTTP_RETRY_BASE_MS` plus jitter,
+/// or longer if the response asks for it with `Retry-After`. A 429 without
+/// `Retry-After` waits until its rate-limit reset header says the window resets.
+/// Other client errors, such as rejected API keys, fail immediately.
+///
+/// # Response Cache
+/// When `http_cache` is active, successful responses are served from and
+/// recorded to disk.
+///
+/// # API Keys in URLs
+/// APIs that only take their key as a query parameter (`API_KEY_PARAMS`) would
+/// otherwise print it in every request error, so those errors drop the URL.
+use crate::api::{http_cache, rate_limit};
+use crate::config::Config;
+use crate::error::FragarachError;
+use tracing::warn;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+/// Config settings a client is built with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ClientSettings {
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+impl ClientSettings {
+    fn from_config(config: &Config) -> Self {
+        ClientSettings {
+            connect_timeout: config.http_connect_timeout(),
+            timeout: config.http_timeout(),
+        }
+    }
+}
+
+/// Clients built so far, one per set of settings
+static CLIENTS: OnceLock<Mutex<HashMap<ClientSettings, Client>>> = OnceLock::new();
+
+/// Query parameters carrying the API key of APIs that take it in the URL:
+/// Etherscan's `apikey` and Shodan's `key`
+pub const API_KEY_PARAMS: &[&str] = &["apikey", "key"];
+
+/// Whether a URL carries an API key in its query
+pub fn has_api_key(url: &reqwest::Url) -> bool {
+    url.query_pairs().any(|(name, _)| API_KEY_PARAMS.contains(&name.as_ref()))
+}
+
+/// Whether typed input starts with a scheme (`https://...`)
+///
+/// A `://` only counts before the first `/`, `?` or `#`, so a scheme-less URL
+/// carrying another URL in its query (`evil.com/login?next=https://bank.com`)
+/// still gets the default scheme prepended.
+pub fn has_scheme(input: &str) -> bool {
+    input.find("://").is_some_and(|i| !input[..i].contains(['/', '?', '#']))
+}
+
+/// `User-Agent` sent with every request
+pub const USER_AGENT: &str = concat!("fragarach/", env!("CARGO_PKG_VERSION"));
+
+/// Returns the shared client for the config's timeouts, building it on first use
+pub fn client(config: &Config) -> Result<Client, FragarachError> {
+    let settings = ClientSettings::from_config(config);
+    let mut clients = CLIENTS.get_or_init(Default::default).lock().unwrap();
+    if let Some(client) = clients.get(&settings) {
+        return Ok(client.clone());
+    }
+
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(settings.connect_timeout)
+        .timeout(settings.timeout)
+        .build()?;
+    clients.insert(settings, client.clone());
+    Ok(client)
+}
+
+/// Sends a request, naming the operation if it times out
+pub async fn send(request: RequestBuilder, operation: &str) -> Result<Response, FragarachError> {
+    let Some(cache) = http_cache::active() else {
+        return request.send().await.map_err(|e| classify(e, operation));
+    };
+
+    let (client, request) = request.build_split();
+    let request = request?;
+    let key = http_cache::cache_key(&request);
+    if let Some(response) = cache.load(&key) {
+        return Ok(response);
+    }
+
+    // Keep a copy of the request so its method and URL can be recorded
+    let recorded = request.try_clone();
+    let response = client.execute(request).await.map_err(|e| classify(e, operation))?;
+    let Some(recorded) = recorded.filter(|_| response.status().is_success()) else {
+        return Ok(response);
+    };
+
+    let status = response.status().as_u16();
+    let content_type = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = response.bytes().await.map_err(|e| classify(e, operation))?;
+
+    if let Err(e) = cache.store(&key, &recorded, status, content_type, &body) {
+        warn!(operation, "Could not cache response: {}", e);
+    }
+
+    Ok(cache.load(&key).unwrap_or_else(|| ::http::Response::new(body).into()))
+}
+
+/// Whether a response status is worth retrying
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Whether a failed send might succeed if repeated
+fn is_transient(err: &FragarachError) -> bool {
+    match err {
+        FragarachError::Timeout { .. } => true,
+        FragarachError::Http(e) => e.is_connect() || e.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Whether a request failed before the API could answer, e.g. while offline
+pub fn is_network_error(err: &FragarachError) -> bool {
+    match err {
+        FragarachError::Timeout { .. } => true,
+        FragarachError::Http(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+        _ => false,
+    }
+}
+
+/// Delay before retry number `retry` (1-based): the base delay doubled per
+/// retry, plus up to half of that again as jitter
+fn backoff_delay(base: Duration, retry: u32) -> Duration {
+    let delay = base.saturating_mul(2u32.saturating_pow(retry - 1));
+    // Spread out retries from concurrent runs without pulling in an RNG
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    delay + delay.mul_f64(f64::from(nanos % 1000) / 2000.0)
+}
+
+/// Sends a request like `send`, retrying transient failures with backoff
+///
+/// Returns the last response or error once the attempts are used up. Requests
+/// whose body can't be replayed are sent once.
+pub async fn send_with_retry(config: &Config, request: RequestBuilder, operation: &str) -> Result<Response, FragarachError> {
+    let max_attempts = config.http_max_attempts();
+    let mut attempt = 1;
+
+    loop {
+        let Some(this_attempt) = request.try_clone() else {
+            return send(request, operation).await;
+        };
+        let result = send(this_attempt, operation).await;
+        let (reason, retry_after) = match &result {
+            Ok(response) if is_retryable_status(response.status()) => {
+                let headers = response.headers();
+                let wait = rate_limit::retry_after(headers).or_else(|| {
+                    (response.status() == StatusCode::TOO_MANY_REQUESTS)
+                        .then(|| rate_limit::reset_after(headers))
+                        .flatten()
+                });
+                (format!("status {}", response.status()), wait)
+            }
+            Err(e) if is_transient(e) => (e.to_string(), None),
+            _ => return result,
+        };
+        if attempt >= max_attempts {
+            return result;
+        }
+
+        let delay = backoff_delay(config.http_retry_base_delay(), attempt).max(retry_after.unwrap_or_default());
+        warn!(
+            operation,
+            "Failed ({}), retrying in {:.1}s (attempt {} of {})",
+            reason, delay.as_secs_f64(), attempt + 1, max_attempts
+        );
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Longest response text kept in an `ApiStatus` error
+const MAX_ERROR_BODY: usize = 300;
+
+/// Builds the error for a response with a failed status, keeping the start of
+/// its body, where APIs explain why they refused the request
+pub async fn status_error(operation: &str, response: Response) -> FragarachError {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    let text = text.trim();
+    let body = match text.char_indices().nth(MAX_ERROR_BODY) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    };
+    FragarachError::ApiStatus { operation: operation.to_string(), status, body }
+}
+
+/// Converts a reqwest error, turning timeouts into `FragarachError::Timeout`
+///
+/// Errors for URLs with an API key in them are kept without the URL.
+pub fn classify(err: reqwest::Error, operation: &str) -> FragarachError {
+    if err.is_timeout() {
+        FragarachError::Timeout { operation: operation.to_string() }
+    } else if err.url().is_some_and(has_api_key) {
+        FragarachError::Http(err.without_url())
+    } else {
+        FragarachError::Http(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const UNAUTHORIZED: &str = "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const RATE_LIMITED: &str = "HTTP/1.1 429 Too Many Requests\r\nX-Rate-Limit-Reset-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+
+    /// Answers successive requests with `responses`, returning the server URL
+    async fn serve(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    fn test_config() -> Config {
+        toml::from_str("http_max_attempts = 5\nhttp_retry_base_ms = 1").unwrap()
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_server_recovers() {
+        let url = serve(vec![UNAVAILABLE, UNAVAILABLE, OK]).await;
+        let config = test_config();
+
+        let response = send_with_retry(&config, client(&config).unwrap().get(&url), "test request").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_wait_for_the_reset() {
+        let url = serve(vec![RATE_LIMITED, OK]).await;
+        let config = test_config();
+
+        let started = std::time::Instant::now();
+        let response = send_with_retry(&config, client(&config).unwrap().get(&url), "test request").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn auth_failures_are_not_retried() {
+        let url = serve(vec![UNAUTHORIZED, OK]).await;
+        let config = test_config();
+
+        let response = send_with_retry(&config, client(&config).unwrap().get(&url), "test request").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn requests_reuse_the_connection() {
+        const KEEP_ALIVE_OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        // Accepts a single connection, so a second one would never be answered
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                requests.push(String::from_utf8_lossy(&request).into_owned());
+                socket.write_all(KEEP_ALIVE_OK.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let config = test_config();
+        for _ in 0..2 {
+            let request = client(&config).unwrap().get(&url).timeout(Duration::from_secs(5));
+            assert_eq!(send(request, "test request").await.unwrap().text().await.unwrap(), "ok");
+        }
+        let requests = server.await.unwrap();
+        assert!(requests.iter().all(|request| request.contains(USER_AGENT)));
+    }
+
+    #[tokio::test]
+    async fn later_configs_get_their_own_timeouts() {
+        // Accepts connections but never answers them
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        client(&test_config()).unwrap();
+        let config: Config = toml::from_str("http_timeout_secs = 1").unwrap();
+        let started = std::time::Instant::now();
+        let err = send(client(&config).unwrap().get(&url), "test request").await.unwrap_err();
+        assert!(matches!(err, FragarachError::Timeout { .. }), "{:?}", err);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn refused_connections_are_network_errors() {
+        // Bind a port, then close it so nothing is listening there
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let config: Config = toml::from_str("http_max_attempts = 1").unwrap();
+
+        let err = send_with_retry(&config, client(&config).unwrap().get(&url), "test request").await.unwrap_err();
+        assert!(is_network_error(&err));
+    }
+}
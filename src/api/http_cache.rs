@@ -0,0 +1,266 @@
+/// Disk-backed HTTP response cache keyed by request
+///
+/// When enabled with `--cached`, successful responses are stored under
+/// `http-cache/` in the data directory and served from disk on later runs
+/// instead of hitting the network. Misses go to the network as usual and
+/// are recorded.
+///
+/// # Cache Key
+/// SHA-256 of the method, URL and body. Headers are deliberately left out so
+/// API keys never influence (or leak into) the key; for the same reason API
+/// key query parameters (`http::API_KEY_PARAMS`) are dropped from the URL
+/// hashed and recorded.
+///
+/// # Storage
+/// Each entry is a `<key>.body` file with the raw response body and a
+/// `<key>.json` file with its metadata. The oldest entries are evicted once
+/// the total size exceeds the configured limit.
+use crate::api::http::{self, API_KEY_PARAMS};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static CACHE: OnceLock<HttpCache> = OnceLock::new();
+
+pub struct HttpCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedMeta {
+    method: String,
+    url: String,
+    status: u16,
+    content_type: Option<String>,
+    stored_at: u64,
+}
+
+/// Turns on cached mode for the rest of the process
+pub fn enable(dir: PathBuf, max_bytes: u64) {
+    let _ = CACHE.set(HttpCache { dir, max_bytes });
+}
+
+/// The active cache, if cached mode is on
+pub fn active() -> Option<&'static HttpCache> {
+    CACHE.get()
+}
+
+/// URL of a request without any API key in its query
+fn keyless_url(request: &Request) -> String {
+    let mut url = request.url().clone();
+    if http::has_api_key(&url) {
+        let kept: Vec<(String, String)> = url.query_pairs()
+            .filter(|(name, _)| !API_KEY_PARAMS.contains(&name.as_ref()))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    url.to_string()
+}
+
+/// Hashes the parts of a request that identify its response
+pub fn cache_key(request: &Request) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(request.method().as_str());
+    hasher.update(b"\n");
+    hasher.update(keyless_url(request));
+    hasher.update(b"\n");
+    if let Some(body) = request.body().and_then(|b| b.as_bytes()) {
+        hasher.update(body);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl HttpCache {
+    fn body_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.body", key))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Rebuilds a stored response, if present
+    pub fn load(&self, key: &str) -> Option<Response> {
+        let meta: CachedMeta = serde_json::from_slice(&fs::read(self.meta_path(key)).ok()?).ok()?;
+        let body = fs::read(self.body_path(key)).ok()?;
+
+        let mut builder = ::http::Response::builder().status(meta.status);
+        if let Some(content_type) = meta.content_type {
+            builder = builder.header(reqwest::header::CONTENT_TYPE, content_type);
+        }
+        builder.body(body).ok().map(Response::from)
+    }
+
+    /// Stores a response body and its metadata, then enforces the size limit
+    pub fn store(&self, key: &str, request: &Request, status: u16, content_type: Option<String>, body: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let meta = CachedMeta {
+            method: request.method().to_string(),
+            url: keyless_url(request),
+            status,
+            content_type,
+            stored_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        };
+        fs::write(self.body_path(key), body)?;
+        fs::write(self.meta_path(key), serde_json::to_vec_pretty(&meta)?)?;
+
+        self.evict()
+    }
+
+    /// Removes the oldest entries until the cache fits within its limit
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(key) = path.file_stem().and_then(|s| s.to_str()).map(String::from) else {
+                continue;
+            };
+
+            let metadata = fs::metadata(&path)?;
+            let len = metadata.len() + fs::metadata(self.body_path(&key)).map(|m| m.len()).unwrap_or(0);
+            total += len;
+            entries.push((metadata.modified()?, len, key));
+        }
+
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, key) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(self.meta_path(&key))?;
+            let _ = fs::remove_file(self.body_path(&key));
+            total -= len;
+        }
+
+        Ok(())
+    }
+}
+
+/// Deletes every cached response, returning the number of bytes freed
+///
+/// Only `.body` and `.json` files are removed; anything else in the directory is left alone.
+pub fn purge(dir: &Path) -> io::Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut freed = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let cached = matches!(path.extension().and_then(|e| e.to_str()), Some("body" | "json"));
+        if !cached || !entry.file_type()?.is_file() {
+            continue;
+        }
+        freed += entry.metadata()?.len();
+        fs::remove_file(path)?;
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fragarach-http-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn get(url: &str) -> Request {
+        reqwest::Client::new().get(url).build().unwrap()
+    }
+
+    #[test]
+    fn api_keys_are_left_out_of_the_cache_key() {
+        let keyless = get("https://api.example.com/v1/address?module=account&address=0xabc");
+        for url in [
+            "https://api.example.com/v1/address?module=account&address=0xabc&apikey=secret",
+            "https://api.example.com/v1/address?module=account&key=other&address=0xabc",
+        ] {
+            assert_eq!(cache_key(&get(url)), cache_key(&keyless), "{}", url);
+        }
+        assert!(!keyless_url(&get("https://api.example.com/v1?apikey=secret")).contains("secret"));
+        assert_ne!(cache_key(&get("https://api.example.com/v1/address?address=0xdef")), cache_key(&keyless));
+    }
+
+    #[tokio::test]
+    async fn stored_responses_load_back() {
+        let cache = HttpCache { dir: cache_dir("load"), max_bytes: 1024 * 1024 };
+        let request = get("https://api.example.com/v1/domain/example.com");
+        let key = cache_key(&request);
+        assert!(cache.load(&key).is_none());
+
+        cache.store(&key, &request, 201, Some("application/json".to_string()), br#"{"ok":true}"#).unwrap();
+        let response = cache.load(&key).unwrap();
+        assert_eq!(response.status().as_u16(), 201);
+        assert_eq!(response.headers()[reqwest::header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.bytes().await.unwrap().as_ref(), br#"{"ok":true}"#);
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted_first() {
+        let dir = cache_dir("evict");
+        let unbounded = HttpCache { dir: dir.clone(), max_bytes: u64::MAX };
+        let keys: Vec<String> = (0..3)
+            .map(|i| {
+                let request = get(&format!("https://api.example.com/v1/{}", i));
+                let key = cache_key(&request);
+                unbounded.store(&key, &request, 200, None, &[b'x'; 100]).unwrap();
+                // Entries written within the same clock tick would otherwise tie
+                let modified = SystemTime::now() - Duration::from_secs(60 * (3 - i));
+                fs::File::options().write(true).open(unbounded.meta_path(&key)).unwrap().set_modified(modified).unwrap();
+                key
+            })
+            .collect();
+        let size = |key: &str| fs::metadata(unbounded.meta_path(key)).unwrap().len() + 100;
+        let total: u64 = keys.iter().map(|key| size(key)).sum();
+
+        let cache = HttpCache { dir: dir.clone(), max_bytes: total - 1 };
+        cache.evict().unwrap();
+        let kept: Vec<bool> = keys.iter().map(|key| cache.meta_path(key).exists() && cache.body_path(key).exists()).collect();
+        assert_eq!(kept, [false, true, true]);
+        assert!(!cache.body_path(&keys[0]).exists());
+
+        let cache = HttpCache { dir: dir.clone(), max_bytes: size(&keys[2]) };
+        cache.evict().unwrap();
+        let kept: Vec<bool> = keys.iter().map(|key| cache.meta_path(key).exists()).collect();
+        assert_eq!(kept, [false, false, true]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn purge_removes_only_cached_responses() {
+        let dir = cache_dir("purge");
+        let cache = HttpCache { dir: dir.clone(), max_bytes: u64::MAX };
+        let request = get("https://api.example.com/v1/purge");
+        let key = cache_key(&request);
+        cache.store(&key, &request, 200, None, b"cached").unwrap();
+        let stored = fs::metadata(cache.meta_path(&key)).unwrap().len() + 6;
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("notes.txt"), "keep").unwrap();
+
+        assert_eq!(purge(&dir).unwrap(), stored);
+        let mut left: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["nested", "notes.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
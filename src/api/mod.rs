@@ -1,7 +1,29 @@
 /// API integration modules for external services
 /// 
 /// # Modules
+/// - `dns`: Live DNS record resolution
+/// - `etherscan`: Fallback source of Ethereum accounts and transactions
+/// - `http`: Shared HTTP client and timeout handling
+/// - `http_cache`: Disk-backed response cache for `--cached` runs
+/// - `rate_limit`: Request pacing from rate-limit response headers
+/// - `rdap`: Domain registration data over RDAP
+/// - `releases`: GitHub release lookup for update notices
+/// - `shodan`: Open ports and hosting details of IP addresses
+/// - `source`: Choice between Transpose and Etherscan for accounts and transactions
 /// - `transpose`: Ethereum blockchain data retrieval
 /// - `urlscan`: Domain scanning and analysis
+/// - `virustotal`: Domain reputation and resolution history
+/// - `whois`: Domain registration lookups, over RDAP with a WHOIS port-43 fallback
+pub mod dns;
+pub mod etherscan;
+pub mod http;
+pub mod http_cache;
+pub mod rate_limit;
+pub mod rdap;
+pub mod releases;
+pub mod shodan;
+pub mod source;
 pub mod transpose;
 pub mod urlscan;
+pub mod virustotal;
+pub mod whois;
@@ -0,0 +1,318 @@
+/// Request pacing driven by rate-limit response headers
+///
+/// # Headers
+/// - `X-RateLimit-Remaining`: requests left in the current window
+/// - `X-RateLimit-Reset`: seconds until the window resets, or a Unix timestamp
+/// - `X-Rate-Limit-Remaining`, `X-Rate-Limit-Reset-After` and `X-Rate-Limit-Reset`
+///   (an ISO 8601 UTC time): URLScan's spelling of the same
+/// - `X-Credits-Remaining`: plan credits left
+/// - `Retry-After`: seconds to wait before the next request
+///
+/// While the API reports requests left, the next request goes out immediately.
+/// Once the window is used up, requests wait for the reset. Responses without
+/// rate-limit headers fall back to a fixed minimum interval.
+///
+/// `TokenBucket` spaces out requests an API only allows so many of per period,
+/// such as URLScan submissions.
+use reqwest::header::HeaderMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+/// Rate-limit state reported by one response
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RateLimitInfo {
+    pub remaining: Option<u64>,
+    pub reset_after: Option<Duration>,
+    pub retry_after: Option<Duration>,
+    pub credits_remaining: Option<u64>,
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Reads `Retry-After` given in seconds; HTTP dates are ignored
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    header_u64(headers, "retry-after").map(Duration::from_secs)
+}
+
+/// Seconds since the Unix epoch of an ISO 8601 UTC time such as `2024-05-18T20:19:00.000Z`
+fn parse_utc_timestamp(value: &str) -> Option<u64> {
+    let (date, time) = value.trim().strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.split('.').next()?.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    // Days from 1970-01-01 to the civil date, counting years from March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days * 86_400 + hour * 3600 + minute * 60 + second).ok()
+}
+
+/// Time until the current rate-limit window resets, from whichever reset header is present
+pub fn reset_after(headers: &HeaderMap) -> Option<Duration> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    // Large reset values are Unix timestamps rather than a number of seconds
+    let reset = header_u64(headers, "x-ratelimit-reset")
+        .map(|reset| if reset > 1_000_000_000 { reset.saturating_sub(now) } else { reset })
+        .map(Duration::from_secs);
+    let reset_after = || {
+        let seconds: f64 = headers.get("x-rate-limit-reset-after")?.to_str().ok()?.trim().parse().ok()?;
+        Duration::try_from_secs_f64(seconds).ok()
+    };
+    let reset_at = || {
+        let at = parse_utc_timestamp(headers.get("x-rate-limit-reset")?.to_str().ok()?)?;
+        Some(Duration::from_secs(at.saturating_sub(now)))
+    };
+    reset.or_else(reset_after).or_else(reset_at)
+}
+
+impl RateLimitInfo {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        RateLimitInfo {
+            remaining: header_u64(headers, "x-ratelimit-remaining")
+                .or_else(|| header_u64(headers, "x-rate-limit-remaining")),
+            reset_after: reset_after(headers),
+            retry_after: retry_after(headers),
+            credits_remaining: header_u64(headers, "x-credits-remaining"),
+        }
+    }
+}
+
+/// Spaces requests according to the most recent response's rate-limit headers
+pub struct RateLimiter {
+    min_interval: Duration,
+    last_request: Option<Instant>,
+    not_before: Option<Instant>,
+    limited_by_headers: bool,
+    credits_remaining: Option<u64>,
+}
+
+impl RateLimiter {
+    /// `min_interval` is kept between requests while the API sends no rate-limit headers
+    pub fn new(min_interval: Duration) -> Self {
+        RateLimiter {
+            min_interval,
+            last_request: None,
+            not_before: None,
+            limited_by_headers: false,
+            credits_remaining: None,
+        }
+    }
+
+    /// Changes the interval kept while the API sends no rate-limit headers
+    pub fn set_min_interval(&mut self, min_interval: Duration) {
+        self.min_interval = min_interval;
+    }
+
+    /// Time to wait at `now` before the next request may be sent
+    pub fn delay(&self, now: Instant) -> Duration {
+        let interval_wait = match self.last_request {
+            Some(last) if !self.limited_by_headers => (last + self.min_interval).saturating_duration_since(now),
+            _ => Duration::ZERO,
+        };
+        let header_wait = self.not_before
+            .map(|t| t.saturating_duration_since(now))
+            .unwrap_or_default();
+        interval_wait.max(header_wait)
+    }
+
+    /// Records a request sent at `now`, before its response arrives
+    pub fn start(&mut self, now: Instant) {
+        self.last_request = Some(now);
+    }
+
+    /// Records a response received at `now`
+    pub fn record(&mut self, info: &RateLimitInfo, now: Instant) {
+        self.last_request = Some(now);
+        self.limited_by_headers = info.remaining.is_some() || info.retry_after.is_some();
+        self.not_before = match (info.retry_after, info.remaining, info.reset_after) {
+            (Some(wait), _, _) => Some(now + wait),
+            (None, Some(0), Some(reset)) => Some(now + reset),
+            _ => None,
+        };
+        if info.credits_remaining.is_some() {
+            self.credits_remaining = info.credits_remaining;
+        }
+    }
+
+    /// Sleeps until the next request may be sent
+    pub async fn wait(&self) {
+        let delay = self.delay(Instant::now());
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+    }
+
+    /// Credits left on the plan, as of the last response that reported them
+    pub fn credits_remaining(&self) -> Option<u64> {
+        self.credits_remaining
+    }
+}
+
+/// Allows `capacity` requests per `period`, refilling one at a time
+///
+/// Starts full, so the first `capacity` requests go out immediately. Callers
+/// beyond that reserve the next free slot and wait for it, which queues them in
+/// the order they asked.
+pub struct TokenBucket {
+    capacity: f64,
+    refill_interval: Duration,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, period: Duration, now: Instant) -> Self {
+        let capacity = f64::from(capacity.max(1));
+        TokenBucket {
+            capacity,
+            refill_interval: period.div_f64(capacity),
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Takes a token at `now`, returning how long to wait before it may be used
+    pub fn take(&mut self, now: Instant) -> Duration {
+        let refilled = now.saturating_duration_since(self.updated).as_secs_f64() / self.refill_interval.as_secs_f64();
+        self.tokens = (self.tokens + refilled).min(self.capacity) - 1.0;
+        self.updated = now;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            self.refill_interval.mul_f64(-self.tokens)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn falls_back_to_min_interval_without_headers() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(SECOND);
+        assert_eq!(limiter.delay(start), Duration::ZERO);
+
+        limiter.record(&RateLimitInfo::default(), start);
+        assert_eq!(limiter.delay(start + Duration::from_millis(400)), Duration::from_millis(600));
+        assert_eq!(limiter.delay(start + 2 * SECOND), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn waiting_requests_are_spaced_by_the_interval() {
+        let interval = Duration::from_millis(40);
+        let mut limiter = RateLimiter::new(interval);
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.wait().await;
+            limiter.start(Instant::now());
+        }
+        assert!(start.elapsed() >= 3 * interval);
+    }
+
+    #[test]
+    fn interval_counts_from_a_request_still_in_flight() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(SECOND);
+        limiter.start(start);
+        assert_eq!(limiter.delay(start + Duration::from_millis(300)), Duration::from_millis(700));
+    }
+
+    #[test]
+    fn sends_immediately_while_requests_remain() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(SECOND);
+        limiter.record(&RateLimitInfo { remaining: Some(10), reset_after: Some(30 * SECOND), ..Default::default() }, start);
+        assert_eq!(limiter.delay(start), Duration::ZERO);
+    }
+
+    #[test]
+    fn waits_for_reset_when_window_is_used_up() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(SECOND);
+        limiter.record(&RateLimitInfo { remaining: Some(0), reset_after: Some(7 * SECOND), ..Default::default() }, start);
+        assert_eq!(limiter.delay(start + 2 * SECOND), 5 * SECOND);
+    }
+
+    #[test]
+    fn retry_after_takes_precedence() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(SECOND);
+        limiter.record(&RateLimitInfo { retry_after: Some(12 * SECOND), remaining: Some(3), ..Default::default() }, start);
+        assert_eq!(limiter.delay(start), 12 * SECOND);
+    }
+
+    #[test]
+    fn keeps_last_reported_credits() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(SECOND);
+        limiter.record(&RateLimitInfo { credits_remaining: Some(4200), ..Default::default() }, start);
+        limiter.record(&RateLimitInfo::default(), start + SECOND);
+        assert_eq!(limiter.credits_remaining(), Some(4200));
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_spaces_requests() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(3, 3 * SECOND, start);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(start), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(start), SECOND);
+        // Queued behind the previous reservation
+        assert_eq!(bucket.take(start), 2 * SECOND);
+        assert_eq!(bucket.take(start + 10 * SECOND), Duration::ZERO);
+    }
+
+    #[test]
+    fn parses_utc_timestamps() {
+        assert_eq!(parse_utc_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_utc_timestamp("2024-02-29T12:30:15.250Z"), Some(1_709_209_815));
+        assert_eq!(parse_utc_timestamp("2024-02-29 12:30:15"), None);
+    }
+
+    #[test]
+    fn parses_urlscan_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Rate-Limit-Remaining", HeaderValue::from_static("0"));
+        headers.insert("X-Rate-Limit-Reset-After", HeaderValue::from_static("42.5"));
+        headers.insert("X-Rate-Limit-Reset", HeaderValue::from_static("2000-01-01T00:00:00.000Z"));
+
+        let info = RateLimitInfo::from_headers(&headers);
+        assert_eq!(info.remaining, Some(0));
+        assert_eq!(info.reset_after, Some(Duration::from_millis(42_500)));
+
+        // A reset time in the past means the window has already reset
+        headers.remove("X-Rate-Limit-Reset-After");
+        assert_eq!(reset_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn parses_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        headers.insert("X-RateLimit-Reset", HeaderValue::from_static("15"));
+        headers.insert("X-Credits-Remaining", HeaderValue::from_static("98000"));
+        headers.insert("Retry-After", HeaderValue::from_static("3"));
+
+        assert_eq!(RateLimitInfo::from_headers(&headers), RateLimitInfo {
+            remaining: Some(0),
+            reset_after: Some(15 * SECOND),
+            retry_after: Some(3 * SECOND),
+            credits_remaining: Some(98000),
+        });
+    }
+}
@@ -0,0 +1,193 @@
+/// Domain registration lookups over RDAP, the structured successor to WHOIS
+///
+/// # Lookup
+/// Queries go through the `rdap.org` bootstrap service, which redirects to the
+/// registry responsible for the domain's TLD. Registries only know registered
+/// domains, so a subdomain that isn't found is retried with its parent
+/// (`www.example.co.uk` → `example.co.uk`). A domain no RDAP server knows,
+/// including one under a TLD without RDAP, is no result rather than an error,
+/// so `whois` can fall back to port 43.
+///
+/// # Fields
+/// - Registrar, registrant name and registrant country
+/// - Registration, expiry and last-changed dates, as ISO 8601 text
+/// - Nameservers, lowercased and deduplicated
+///
+/// # Limits
+/// Requests are paced to one a second across the process, since `rdap.org`
+/// and the registries behind it ask clients not to hammer them.
+use crate::api::http;
+use crate::api::rate_limit::{RateLimitInfo, RateLimiter};
+use crate::api::whois::{disclosed, WhoisRecord};
+use crate::config::Config;
+use crate::error::FragarachError;
+use reqwest::{header, StatusCode};
+use serde_json::Value;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const RDAP_URL: &str = "https://rdap.org/domain/";
+
+/// Paces all RDAP requests made by the process
+static LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+
+fn limiter() -> &'static Mutex<RateLimiter> {
+    LIMITER.get_or_init(|| Mutex::new(RateLimiter::new(Duration::from_secs(1))))
+}
+
+/// vCard property of an RDAP entity, e.g. `fn` or `adr`
+fn vcard_property<'a>(entity: &'a Value, name: &str) -> Option<&'a Value> {
+    entity.pointer("/vcardArray/1")?
+        .as_array()?
+        .iter()
+        .find(|property| property.get(0).and_then(Value::as_str) == Some(name))
+}
+
+/// Text of a vCard property, e.g. `fn` or `org`, from an RDAP entity
+fn vcard_field<'a>(entity: &'a Value, name: &str) -> Option<&'a str> {
+    vcard_property(entity, name)?.get(3)?.as_str()
+}
+
+/// Country of an entity's address: the `cc` parameter, or the address's last part
+fn vcard_country(entity: &Value) -> Option<String> {
+    let adr = vcard_property(entity, "adr")?;
+    adr.pointer("/1/cc")
+        .and_then(Value::as_str)
+        .or_else(|| adr.pointer("/3/6").and_then(Value::as_str))
+        .and_then(disclosed)
+}
+
+/// First entity with `role`
+fn entity<'a>(rdap: &'a Value, role: &str) -> Option<&'a Value> {
+    rdap.get("entities")?.as_array()?.iter().find(|entity| {
+        entity.get("roles")
+            .and_then(Value::as_array)
+            .is_some_and(|roles| roles.iter().any(|r| r.as_str() == Some(role)))
+    })
+}
+
+/// Display name of the first entity with `role`, preferring its organisation
+fn entity_name(rdap: &Value, role: &str, prefer_org: bool) -> Option<String> {
+    let entity = entity(rdap, role)?;
+    let fields: &[&str] = if prefer_org { &["org", "fn"] } else { &["fn", "org"] };
+    fields.iter().find_map(|field| vcard_field(entity, field).and_then(disclosed))
+}
+
+/// Date of the first event with `action`, e.g. `registration` or `expiration`
+fn event_date(rdap: &Value, action: &str) -> Option<String> {
+    rdap.get("events")?
+        .as_array()?
+        .iter()
+        .find(|event| event.get("eventAction").and_then(Value::as_str) == Some(action))?
+        .get("eventDate")?
+        .as_str()
+        .and_then(disclosed)
+}
+
+/// Builds a record from an RDAP domain response and its raw text
+fn parse_rdap(domain: &str, rdap: &Value, raw: String) -> WhoisRecord {
+    let mut nameservers: Vec<String> = Vec::new();
+    for nameserver in rdap.get("nameservers").and_then(Value::as_array).into_iter().flatten() {
+        let Some(name) = nameserver.get("ldhName").and_then(Value::as_str) else { continue };
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        if !name.is_empty() && !nameservers.contains(&name) {
+            nameservers.push(name);
+        }
+    }
+
+    WhoisRecord {
+        domain: domain.to_string(),
+        registrar: entity_name(rdap, "registrar", false),
+        registrant: entity_name(rdap, "registrant", true),
+        registrant_country: entity(rdap, "registrant").and_then(vcard_country),
+        registered: event_date(rdap, "registration"),
+        expires: event_date(rdap, "expiration"),
+        updated: event_date(rdap, "last changed"),
+        nameservers,
+        source: "rdap",
+        raw,
+    }
+}
+
+/// Looks up the registration data of `host`, an ASCII (punycoded) host name
+///
+/// Returns `None` when neither the host nor a parent domain is known over RDAP.
+pub async fn lookup(config: &Config, host: &str) -> Result<Option<WhoisRecord>, FragarachError> {
+    let client = http::client(config)?;
+
+    let mut candidate = host;
+    loop {
+        let request = client.get(format!("{}{}", RDAP_URL, candidate))
+            .header(header::ACCEPT, "application/rdap+json, application/json");
+        {
+            let mut limiter = limiter().lock().await;
+            limiter.wait().await;
+            limiter.start(Instant::now());
+        }
+        let resp = http::send_with_retry(config, request, "RDAP lookup").await?;
+        limiter().lock().await.record(&RateLimitInfo::from_headers(resp.headers()), Instant::now());
+
+        let status = resp.status();
+        if status.is_success() {
+            let raw = resp.text().await.map_err(|e| http::classify(e, "Reading RDAP response"))?;
+            let rdap: Value = serde_json::from_str(&raw)?;
+            return Ok(Some(parse_rdap(candidate, &rdap, raw)));
+        }
+
+        // Try the parent domain while it still has a label left of the TLD
+        match candidate.split_once('.') {
+            Some((_, parent)) if status == StatusCode::NOT_FOUND && parent.contains('.') => candidate = parent,
+            _ if status == StatusCode::NOT_FOUND => return Ok(None),
+            _ => return Err(http::status_error("RDAP lookup", resp).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacted_contacts_are_stored_as_null() {
+        let rdap = json!({
+            "entities": [
+                {
+                    "roles": ["registrar"],
+                    "vcardArray": ["vcard", [["version", {}, "text", "4.0"], ["fn", {}, "text", "Example Registrar, Inc."]]]
+                },
+                {
+                    "roles": ["registrant"],
+                    "vcardArray": ["vcard", [
+                        ["fn", {}, "text", "REDACTED FOR PRIVACY"],
+                        ["org", {}, "text", ""],
+                        ["adr", {}, "text", ["", "", "", "", "", "", "NL"]]
+                    ]]
+                }
+            ],
+            "events": [
+                { "eventAction": "registration", "eventDate": "1995-08-14T04:00:00Z" },
+                { "eventAction": "expiration", "eventDate": "2027-08-13T04:00:00Z" }
+            ],
+            "nameservers": [
+                { "ldhName": "A.IANA-SERVERS.NET" },
+                { "ldhName": "b.iana-servers.net." },
+                { "ldhName": "a.iana-servers.net" }
+            ]
+        });
+
+        assert_eq!(parse_rdap("example.com", &rdap, "{}".to_string()), WhoisRecord {
+            domain: "example.com".to_string(),
+            registrar: Some("Example Registrar, Inc.".to_string()),
+            registrant: None,
+            registrant_country: Some("NL".to_string()),
+            registered: Some("1995-08-14T04:00:00Z".to_string()),
+            expires: Some("2027-08-13T04:00:00Z".to_string()),
+            updated: None,
+            nameservers: vec!["a.iana-servers.net".to_string(), "b.iana-servers.net".to_string()],
+            source: "rdap",
+            raw: "{}".to_string(),
+        });
+    }
+}
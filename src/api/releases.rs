@@ -0,0 +1,168 @@
+/// GitHub releases lookup for update notifications
+///
+/// # Daily Check
+/// When `FRAGARACH_UPDATE_CHECK` is enabled, startup asks GitHub for the latest
+/// release at most once per day. The time of the last check and the release it
+/// found are kept in `fragarach_meta`, so the notice keeps showing between
+/// checks without further requests.
+///
+/// Network failures are never reported at startup; the check is simply retried
+/// the next day. Responses are never served from the HTTP cache.
+use crate::api::http;
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_operations;
+use crate::helpers::database_writer::DatabaseHandle;
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/DB14734/fragarach/releases/latest";
+
+/// Upper bound for the startup check so it never holds up the menu
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Minimum time between startup checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const CHECKED_AT_KEY: &str = "update_checked_at";
+const LATEST_TAG_KEY: &str = "update_latest_tag";
+const LATEST_URL_KEY: &str = "update_latest_url";
+
+/// Version of this build
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+}
+
+impl Release {
+    /// Whether this release is newer than the running build
+    pub fn is_newer(&self) -> bool {
+        match (parse_version(&self.tag_name), parse_version(CURRENT_VERSION)) {
+            (Some(latest), Some(current)) => latest > current,
+            _ => false,
+        }
+    }
+}
+
+/// Parses `v1.2.3` or `1.2.3` into comparable parts, ignoring pre-release suffixes
+///
+/// Missing minor and patch parts count as 0; tags with non-numeric parts aren't versions.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.strip_prefix('v').unwrap_or(version).split(['-', '+']).next()?;
+    let parts = core.split('.').map(|p| p.parse::<u64>().ok()).collect::<Option<Vec<_>>>()?;
+    match parts[..] {
+        [major] => Some((major, 0, 0)),
+        [major, minor] => Some((major, minor, 0)),
+        [major, minor, patch] => Some((major, minor, patch)),
+        _ => None,
+    }
+}
+
+/// Fetches the latest published release
+pub async fn latest_release(config: &Config, timeout: Option<Duration>) -> Result<Release, FragarachError> {
+    let client = http::client(config)?;
+    let mut request = client.get(LATEST_RELEASE_URL)
+        .header("Accept", "application/vnd.github+json");
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+
+    // Bypasses `http::send` so a cached answer can't hide a new release
+    let response = request.send().await
+        .map_err(|e| http::classify(e, "Release check"))?;
+    if !response.status().is_success() {
+        return Err(http::status_error("Release check", response).await);
+    }
+
+    response.json().await.map_err(|e| http::classify(e, "Release check"))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Startup check, returning a newer release if one is known
+///
+/// Queries GitHub only if the last check is more than a day old, otherwise
+/// answers from the release recorded in the database.
+pub async fn daily_check(config: &Config, db: &DatabaseHandle) -> Option<Release> {
+    let stored = db.call(|conn| -> duckdb::Result<_> {
+        Ok((
+            database_operations::read_meta(conn, CHECKED_AT_KEY)?,
+            database_operations::read_meta(conn, LATEST_TAG_KEY)?,
+            database_operations::read_meta(conn, LATEST_URL_KEY)?,
+        ))
+    }).await.ok()?;
+
+    let checked_at = stored.0.and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+    let release = if now_secs().saturating_sub(checked_at) < CHECK_INTERVAL.as_secs() {
+        Release { tag_name: stored.1?, html_url: stored.2? }
+    } else {
+        // Failed checks still count, so an offline machine isn't delayed on every start
+        let release = latest_release(config, Some(STARTUP_TIMEOUT)).await.ok();
+        let found = release.as_ref().map(|r| (r.tag_name.clone(), r.html_url.clone()));
+        let _ = db.call(move |conn| -> duckdb::Result<()> {
+            database_operations::write_meta(conn, CHECKED_AT_KEY, &now_secs().to_string())?;
+            if let Some((tag, url)) = found {
+                database_operations::write_meta(conn, LATEST_TAG_KEY, &tag)?;
+                database_operations::write_meta(conn, LATEST_URL_KEY, &url)?;
+            }
+            Ok(())
+        }).await;
+        release?
+    };
+
+    release.is_newer().then_some(release)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str) -> Release {
+        Release { tag_name: tag.to_string(), html_url: String::new() }
+    }
+
+    #[test]
+    fn leading_v_is_optional() {
+        assert_eq!(parse_version("v1.2.3"), Some((1, 2, 3)));
+        assert_eq!(parse_version("1.2.3"), parse_version("v1.2.3"));
+    }
+
+    #[test]
+    fn parts_compare_as_numbers() {
+        assert!(parse_version("1.10.0") > parse_version("1.9.9"));
+        assert!(parse_version("2.0.0") > parse_version("1.99.99"));
+    }
+
+    #[test]
+    fn pre_release_and_build_suffixes_are_ignored() {
+        assert_eq!(parse_version("v1.3.0-rc.1"), Some((1, 3, 0)));
+        assert_eq!(parse_version("1.3.0-rc1"), parse_version("1.3.0"));
+        assert_eq!(parse_version("1.3.0+build.7"), Some((1, 3, 0)));
+    }
+
+    #[test]
+    fn missing_parts_count_as_zero() {
+        assert_eq!(parse_version("1.2"), Some((1, 2, 0)));
+        assert_eq!(parse_version("v2"), Some((2, 0, 0)));
+    }
+
+    #[test]
+    fn non_numeric_tags_are_not_versions() {
+        for tag in ["nightly", "latest", "", "v", "v1.x.3", "1.2.beta", "1.2.3.4", "vv1.2.3"] {
+            assert_eq!(parse_version(tag), None, "{}", tag);
+        }
+        assert!(!release("nightly").is_newer());
+    }
+
+    #[test]
+    fn only_later_releases_are_newer() {
+        assert!(release("v999.0.0").is_newer());
+        assert!(!release(&format!("v{}", CURRENT_VERSION)).is_newer());
+        assert!(!release("v0.0.1").is_newer());
+    }
+}
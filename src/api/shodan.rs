@@ -0,0 +1,197 @@
+/// Shodan API integration for what is known about an IP address
+///
+/// # Lookup
+/// `lookup_ip` reads `/shodan/host/{ip}`:
+/// - Organisation, ISP, ASN and country
+/// - Open ports and host names, sorted
+/// - When Shodan last crawled the address
+///
+/// Shodan answers 404 for addresses it never crawled. Such a lookup is still
+/// a result, returned with `found` unset so the check stays on record.
+///
+/// # Limits
+/// Requests are paced to one a second across the process, as Shodan allows.
+///
+/// # Database Integration
+/// Records are stored in `shodan_host_data`, one row per IP, replaced on each lookup.
+use crate::api::http;
+use crate::api::rate_limit::{RateLimitInfo, RateLimiter};
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_writer::DatabaseHandle;
+use duckdb::params;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const API_URL: &str = "https://api.shodan.io";
+
+/// Paces all Shodan requests made by the process
+static LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+
+fn limiter() -> &'static Mutex<RateLimiter> {
+    LIMITER.get_or_init(|| Mutex::new(RateLimiter::new(Duration::from_secs(1))))
+}
+
+/// What Shodan knows about one IP address
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HostRecord {
+    pub ip: String,
+    /// Whether Shodan had any information on the address
+    pub found: bool,
+    pub org: Option<String>,
+    pub isp: Option<String>,
+    pub asn: Option<String>,
+    pub country: Option<String>,
+    pub ports: Vec<u64>,
+    pub hostnames: Vec<String>,
+    pub last_update: Option<String>,
+}
+
+/// Sends one paced request with the API key
+async fn get(config: &Config, path: &str, operation: &str) -> Result<reqwest::Response, FragarachError> {
+    let api_key = config.shodan_api_key().ok_or(FragarachError::MissingApiKey("Shodan"))?;
+
+    let client = http::client(config)?;
+    let request = client.get(format!("{}{}", API_URL, path))
+        .query(&[("key", api_key)]);
+    {
+        let mut limiter = limiter().lock().await;
+        limiter.wait().await;
+        limiter.start(Instant::now());
+    }
+    let response = http::send_with_retry(config, request, operation).await?;
+    limiter().lock().await.record(&RateLimitInfo::from_headers(response.headers()), Instant::now());
+    Ok(response)
+}
+
+/// Builds a record from a host response
+fn parse_host(ip: &str, host: &Value) -> HostRecord {
+    let text = |field: &str| host.get(field).and_then(Value::as_str).filter(|v| !v.is_empty()).map(String::from);
+    let mut ports: Vec<u64> = host.get("ports")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_u64)
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    let mut hostnames: Vec<String> = host.get("hostnames")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|name| Some(name.as_str()?.to_ascii_lowercase()))
+        .collect();
+    hostnames.sort();
+    hostnames.dedup();
+
+    HostRecord {
+        ip: ip.to_string(),
+        found: true,
+        org: text("org"),
+        isp: text("isp"),
+        asn: text("asn"),
+        country: text("country_name").or_else(|| text("country_code")),
+        ports,
+        hostnames,
+        last_update: text("last_update"),
+    }
+}
+
+/// Looks up an IPv4 or IPv6 address
+pub async fn lookup_ip(config: &Config, ip: &str) -> Result<HostRecord, FragarachError> {
+    let ip: IpAddr = ip.trim().trim_matches(['[', ']']).parse()
+        .map_err(|_| FragarachError::InvalidInput(format!("'{}' is not an IP address", ip)))?;
+    let ip = ip.to_string();
+
+    let resp = get(config, &format!("/shodan/host/{}", ip), "Shodan host lookup").await?;
+    match resp.status() {
+        // "No information available for that IP."
+        StatusCode::NOT_FOUND => Ok(HostRecord { ip, ..HostRecord::default() }),
+        status if status.is_success() => {
+            let host: Value = resp.json().await
+                .map_err(|e| http::classify(e, "Reading Shodan host"))?;
+            Ok(parse_host(&ip, &host))
+        }
+        _ => Err(http::status_error("Shodan host lookup", resp).await),
+    }
+}
+
+/// Confirms the API key is accepted by reading the plan's remaining credits
+pub async fn check_api_key(config: &Config) -> Result<(), FragarachError> {
+    let resp = get(config, "/api-info", "Shodan key check").await?;
+    if !resp.status().is_success() {
+        return Err(http::status_error("Shodan key check", resp).await);
+    }
+    Ok(())
+}
+
+/// Confirms `api_key` is accepted before it replaces the configured key
+pub async fn validate_api_key(config: &Config, api_key: &str) -> Result<(), FragarachError> {
+    let mut config = config.clone();
+    config.set_shodan_api_key(Some(api_key.to_string()));
+    check_api_key(&config).await
+}
+
+/// Stores a lookup in `shodan_host_data`, replacing any earlier one for the IP
+pub async fn save(db: &DatabaseHandle, record: HostRecord) -> Result<(), FragarachError> {
+    let case_id = db.active_case();
+    let ports = Value::from(record.ports).to_string();
+    let hostnames = Value::from(record.hostnames).to_string();
+    db.call(move |conn| conn.execute(
+        "INSERT OR REPLACE INTO shodan_host_data (
+            ip, found, org, isp, asn, country, ports, hostnames, last_update, case_id, looked_up_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, CURRENT_TIMESTAMP)",
+        params![
+            record.ip,
+            record.found,
+            record.org,
+            record.isp,
+            record.asn,
+            record.country,
+            ports,
+            hostnames,
+            record.last_update,
+            case_id
+        ]
+    )).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn hosts_keep_sorted_ports_and_hostnames() {
+        let host = json!({
+            "ip_str": "192.0.2.10",
+            "org": "Example Hosting",
+            "isp": "Example ISP",
+            "asn": "AS64500",
+            "country_name": "Netherlands",
+            "country_code": "NL",
+            "ports": [443, 80, 443, 22],
+            "hostnames": ["Mail.Example.com", "example.com"],
+            "last_update": "2024-03-01T12:34:56.789012",
+            "os": ""
+        });
+
+        assert_eq!(parse_host("192.0.2.10", &host), HostRecord {
+            ip: "192.0.2.10".to_string(),
+            found: true,
+            org: Some("Example Hosting".to_string()),
+            isp: Some("Example ISP".to_string()),
+            asn: Some("AS64500".to_string()),
+            country: Some("Netherlands".to_string()),
+            ports: vec![22, 80, 443],
+            hostnames: vec!["example.com".to_string(), "mail.example.com".to_string()],
+            last_update: Some("2024-03-01T12:34:56.789012".to_string()),
+        });
+    }
+}
@@ -0,0 +1,123 @@
+/// Choice of the API account and transaction records are fetched from
+///
+/// Transpose is the primary source; Etherscan is the fallback for when
+/// Transpose is down or no key for it is configured. Both return rows in the
+/// shape of the Transpose templates, so callers save and read them the same
+/// way whichever source fetched them.
+use crate::api::transpose::{AddressPull, Chain, TransactionRange};
+use crate::api::{etherscan, transpose};
+use crate::config::Config;
+use crate::error::FragarachError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataSource {
+    Transpose,
+    Etherscan,
+}
+
+impl DataSource {
+    /// Every source, in order of preference
+    pub const ALL: [DataSource; 2] = [DataSource::Transpose, DataSource::Etherscan];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataSource::Transpose => "transpose",
+            DataSource::Etherscan => "etherscan",
+        }
+    }
+
+    /// Service name, as shown to users
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataSource::Transpose => "Transpose",
+            DataSource::Etherscan => "Etherscan",
+        }
+    }
+
+    /// Whether an API key for the source is configured
+    pub fn is_configured(&self, config: &Config) -> bool {
+        match self {
+            DataSource::Transpose => config.transpose_api_key().is_some(),
+            DataSource::Etherscan => config.etherscan_api_key().is_some(),
+        }
+    }
+
+    /// Sources with an API key, in order of preference
+    pub fn configured(config: &Config) -> Vec<DataSource> {
+        DataSource::ALL.into_iter().filter(|source| source.is_configured(config)).collect()
+    }
+
+    /// The source `requested`, or without one the most preferred source with an API key
+    ///
+    /// Fails with `MissingApiKey` when the source requested has no key, or
+    /// (for Transpose) when neither has one.
+    pub fn pick(config: &Config, requested: Option<DataSource>) -> Result<DataSource, FragarachError> {
+        match requested {
+            Some(source) if source.is_configured(config) => Ok(source),
+            Some(source) => Err(FragarachError::MissingApiKey(source.name())),
+            None => DataSource::configured(config).first().copied().ok_or(FragarachError::MissingApiKey("Transpose")),
+        }
+    }
+
+    /// Fetches the account details of an address, tagged with its chain
+    pub async fn query_account(&self, config: &Config, chain: Chain, address: &str) -> Result<Vec<Value>, FragarachError> {
+        match self {
+            DataSource::Transpose => transpose::query_ethereum_account(config, chain, address).await,
+            DataSource::Etherscan => etherscan::query_ethereum_account(config, chain, address).await,
+        }
+    }
+
+    /// Fetches transaction pages for the given addresses and sends each into
+    /// `pages`, as `transpose::stream_ethereum_transactions` does
+    pub async fn stream_transactions(
+        &self,
+        config: &Config,
+        chain: Chain,
+        addresses: &[String],
+        max: Option<usize>,
+        range: &TransactionRange,
+        pages: mpsc::Sender<Vec<Value>>,
+    ) -> Result<Vec<AddressPull>, FragarachError> {
+        match self {
+            DataSource::Transpose => transpose::stream_ethereum_transactions(config, chain, addresses, max, range, pages).await,
+            DataSource::Etherscan => etherscan::stream_ethereum_transactions(config, chain, addresses, max, range, pages).await,
+        }
+    }
+
+    /// Fetches the transactions of one address in `range`, up to `max` if given
+    pub async fn query_transactions(
+        &self,
+        config: &Config,
+        chain: Chain,
+        address: &str,
+        max: Option<usize>,
+        range: &TransactionRange,
+    ) -> Result<Vec<Value>, FragarachError> {
+        match self {
+            DataSource::Transpose => transpose::query_ethereum_transactions(config, chain, address, max, range).await,
+            DataSource::Etherscan => etherscan::query_ethereum_transactions(config, chain, address, max, range).await,
+        }
+    }
+}
+
+impl FromStr for DataSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DataSource::ALL.into_iter()
+            .find(|source| source.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown data source '{}' (expected transpose or etherscan)", s))
+    }
+}
+
+impl fmt::Display for DataSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
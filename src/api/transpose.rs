@@ -1,106 +1,1246 @@
-/// Transpose API integration for Ethereum blockchain data retrieval
+/// Transpose API integration for EVM blockchain data retrieval
 /// 
 /// Provides functionality to:
-/// - Query Ethereum account details
+/// - Query account details
 /// - Retrieve transaction history
-/// - Handle rate limiting and pagination
+/// - Retrieve ERC-20 token transfers, NFT transfers and NFT holdings
+/// - Build a full profile of an address (`build_account_profile`): its account,
+///   transactions and token transfers, saved and summarized in one call
+/// - Resolve ENS names to addresses, once per name per session
+/// - Validate addresses, including EIP-55 checksums, before they are queried
+/// - List and run custom SQL queries from the configured SQL directory
+/// - Select the chain (`Chain`) each query reads from
+/// - Handle rate limiting and pagination, fetching several addresses at once
 /// 
 /// # Rate Limiting
-/// Implements a 1-second delay between requests to comply with API limits
+/// Every request goes through one shared `RateLimiter`, which follows the
+/// rate-limit headers of the previous response and falls back to
+/// `FRAGARACH_TRANSPOSE_REQUESTS_PER_MINUTE` (one a second by default) when
+/// there are none.
+/// Transient failures are retried with backoff (`http::send_with_retry`), so a
+/// single bad response doesn't end a paginated pull.
 /// 
-/// # Response Size
-/// Monitors response size and implements a 1MB limit safeguard
+/// # Pagination
+/// Transactions are fetched in pages of `PAGE_SIZE` until a page comes back
+/// short, or until the caller's optional cap is reached.
+/// 
+/// # Streaming
+/// Transaction pages are sent into a bounded channel as they arrive, so the
+/// consumer can persist one page while the next is fetched. A full channel
+/// pauses fetching until the consumer catches up.
+/// 
+/// # Cost Estimation
+/// Probes transaction counts ahead of a full pull to estimate requests, credits and time
+/// 
+/// # Query Templates
+/// Built into the binary; set `FRAGARACH_SQL_DIR` to a directory of same-named
+/// `.sql` files to run customized queries instead
+use crate::api::http;
+use crate::api::rate_limit::{RateLimitInfo, RateLimiter};
 use crate::config::Config;
-use reqwest::Client;
+use crate::error::FragarachError;
+use crate::helpers::database_operations;
+use crate::helpers::database_writer::DatabaseHandle;
+use tracing::warn;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
-use tokio::time::sleep;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tiny_keccak::{Hasher, Keccak};
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::task::JoinSet;
+
+/// Rows requested per page when paginating transactions and transfers
+pub const PAGE_SIZE: usize = 100;
+
+/// Fetched pages allowed to wait for the consumer before fetching pauses
+pub const PAGE_BUFFER: usize = 4;
+
+/// Chains served by Transpose, named after their SQL schema
+///
+/// Returned rows are tagged with their chain so records from different chains
+/// can share an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chain {
+    Ethereum,
+    Polygon,
+    Goerli,
+}
+
+impl Chain {
+    pub const ALL: [Chain; 3] = [Chain::Ethereum, Chain::Polygon, Chain::Goerli];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Chain::Ethereum => "ethereum",
+            Chain::Polygon => "polygon",
+            Chain::Goerli => "goerli",
+        }
+    }
+}
+
+impl FromStr for Chain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Chain::ALL.into_iter()
+            .find(|c| c.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown chain '{}' (expected ethereum, polygon or goerli)", s))
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
-/// Loads SQL query templates from files
-pub async fn load_sql_query(filename: &str) -> String {
-    let filepath = format!("src/sql/{}", filename);
-    fs::read_to_string(&filepath).expect(&format!("Unable to read file: {}", filepath))
+/// Adds the chain to each returned row
+fn tag_chain(rows: &mut [Value], chain: Chain) {
+    for row in rows.iter_mut().filter_map(Value::as_object_mut) {
+        row.insert("chain".to_string(), Value::from(chain.as_str()));
+    }
+}
+
+/// Rows fetched for one address, and the error that stopped it early, if any
+#[derive(Debug)]
+pub struct AddressPull {
+    pub address: String,
+    pub fetched: usize,
+    /// Whether the cap on rows stopped the pull while the address may have more
+    pub truncated: bool,
+    pub error: Option<FragarachError>,
 }
 
-pub async fn query_transpose(config: &Config, sql_query: &str, params: &[(&str, &str)]) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
-    let client = Client::new();
-    let url = "https://api.transpose.io/sql";
+/// Dates and blocks a transaction pull is limited to; bounds left unset are open
+///
+/// Dates are `YYYY-MM-DD` in UTC and both ends are inclusive, as are block
+/// numbers. They fill the `{{from_timestamp}}`, `{{to_timestamp}}`,
+/// `{{from_block}}` and `{{to_block}}` placeholders of the transaction templates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionRange {
+    from_date: Option<String>,
+    to_date: Option<String>,
+    from_block: Option<u64>,
+    to_block: Option<u64>,
+}
+
+/// Checks that `date` is a real calendar date written as `YYYY-MM-DD`
+fn check_date(date: &str) -> Result<String, FragarachError> {
+    let date = date.trim();
+    let invalid = || FragarachError::InvalidInput(format!("{} is not a date in YYYY-MM-DD form", date));
+    let parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return Err(invalid());
+    }
+    let (year, month, day): (u32, u32, u32) = match (year.parse(), month.parse(), day.parse()) {
+        (Ok(year), Ok(month), Ok(day)) => (year, month, day),
+        _ => return Err(invalid()),
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return Err(invalid()),
+    };
+    if day == 0 || day > days {
+        return Err(invalid());
+    }
+    Ok(date.to_string())
+}
+
+impl TransactionRange {
+    /// Checks the dates and that neither range ends before it starts
+    pub fn new(
+        from_date: Option<&str>,
+        to_date: Option<&str>,
+        from_block: Option<u64>,
+        to_block: Option<u64>,
+    ) -> Result<Self, FragarachError> {
+        let from_date = from_date.map(check_date).transpose()?;
+        let to_date = to_date.map(check_date).transpose()?;
+        // Zero-padded dates compare in calendar order
+        if let (Some(from), Some(to)) = (&from_date, &to_date) {
+            if from > to {
+                return Err(FragarachError::InvalidInput(format!("the date range ends ({}) before it starts ({})", to, from)));
+            }
+        }
+        if let (Some(from), Some(to)) = (from_block, to_block) {
+            if from > to {
+                return Err(FragarachError::InvalidInput(format!("the block range ends ({}) before it starts ({})", to, from)));
+            }
+        }
+        Ok(TransactionRange { from_date, to_date, from_block, to_block })
+    }
+
+    /// Whether every bound is open, so the whole history is pulled
+    pub fn is_unbounded(&self) -> bool {
+        *self == TransactionRange::default()
+    }
+
+    /// Only the transactions in or after `block`, up to the latest
+    pub fn since_block(block: u64) -> Self {
+        TransactionRange { from_block: Some(block), ..TransactionRange::default() }
+    }
+
+    /// Block a complete pull of this range has everything from, up to the
+    /// latest block (0 for the whole history)
+    ///
+    /// `None` when a date or an upper block bound leaves transactions out, so
+    /// the pull can't advance a sync mark.
+    pub fn sync_start(&self) -> Option<u64> {
+        let reaches_latest = self.from_date.is_none() && self.to_date.is_none() && self.to_block.is_none();
+        reaches_latest.then(|| self.from_block.unwrap_or(0))
+    }
+
+    /// First and last day of the range (`YYYY-MM-DD`), where bounded
+    pub fn dates(&self) -> (Option<&str>, Option<&str>) {
+        (self.from_date.as_deref(), self.to_date.as_deref())
+    }
+
+    /// First and last block of the range, where bounded
+    pub fn blocks(&self) -> (Option<u64>, Option<u64>) {
+        (self.from_block, self.to_block)
+    }
+
+    /// Template parameters, with open bounds covering every transaction
+    fn params(&self) -> [(&'static str, String); 4] {
+        [
+            ("from_timestamp", format!("{} 00:00:00", self.from_date.as_deref().unwrap_or("1970-01-01"))),
+            ("to_timestamp", format!("{} 23:59:59.999999", self.to_date.as_deref().unwrap_or("9999-12-31"))),
+            ("from_block", self.from_block.unwrap_or(0).to_string()),
+            ("to_block", self.to_block.unwrap_or(i64::MAX as u64).to_string()),
+        ]
+    }
+}
+
+impl fmt::Display for TransactionRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn bounds(f: &mut fmt::Formatter, from: Option<String>, to: Option<String>) -> fmt::Result {
+            match (from, to) {
+                (Some(from), Some(to)) => write!(f, "{} to {}", from, to),
+                (Some(from), None) => write!(f, "since {}", from),
+                (None, Some(to)) => write!(f, "up to {}", to),
+                (None, None) => Ok(()),
+            }
+        }
+
+        if self.is_unbounded() {
+            return f.write_str("all time");
+        }
+        bounds(f, self.from_date.clone(), self.to_date.clone())?;
+        if self.from_block.is_some() || self.to_block.is_some() {
+            if self.from_date.is_some() || self.to_date.is_some() {
+                f.write_str(", ")?;
+            }
+            f.write_str("blocks ")?;
+            bounds(f, self.from_block.map(|b| b.to_string()), self.to_block.map(|b| b.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Account record, transaction history and token transfers of one address, as
+/// saved by `build_account_profile`
+#[derive(Debug, Serialize)]
+pub struct AccountProfile {
+    pub address: String,
+    pub chain: Chain,
+    /// `None` when Transpose has no account record for the address
+    pub account_type: Option<String>,
+    pub created_at: Option<String>,
+    /// Transactions stored for the address, including any saved by earlier pulls
+    pub transactions: u64,
+    /// Whether the cap on rows stopped the transaction pull while the address may have more
+    pub truncated: bool,
+    pub first_activity: Option<String>,
+    pub last_activity: Option<String>,
+    /// ERC-20 transfers fetched, or `None` when that pull failed
+    pub token_transfers: Option<usize>,
+    /// Why the token transfer pull failed
+    pub token_transfers_error: Option<String>,
+}
+
+/// Fetches and saves the account record, transactions and ERC-20 token
+/// transfers of `address`, then summarizes what is stored for it
+///
+/// Transactions and transfers are capped at `max` rows each, if given. The
+/// account and transaction pulls must succeed; token transfers are an extra, so
+/// a failed transfer pull is noted in the profile instead. First and last
+/// activity come from the stored transactions, falling back to the account's
+/// creation and last activity when none are stored. Records go to the active
+/// case, and a complete transaction pull moves the address's sync mark on.
+pub async fn build_account_profile(
+    config: &Config,
+    db: &DatabaseHandle,
+    chain: Chain,
+    address: &str,
+    max: Option<usize>,
+) -> Result<AccountProfile, FragarachError> {
+    let address = validate_address(address)?;
+    let addresses = [address.clone()];
+
+    let account = query_ethereum_account(config, chain, &address).await?;
+    let field = |name: &str| account.first().and_then(|row| row.get(name)).and_then(Value::as_str).map(String::from);
+    let (account_type, created_at, last_active) = (field("type"), field("created_timestamp"), field("last_active_timestamp"));
+    let records = account.len();
+    db.save_records(account, "ethereum_accounts").await?;
+    db.record_query(format!("{} account", chain), address.clone(), records).await?;
 
-    let mut query = sql_query.to_string();
+    let range = TransactionRange::default();
+    let (pages_tx, pages_rx) = mpsc::channel(PAGE_BUFFER);
+    let fetch = stream_ethereum_transactions(config, chain, &addresses, max, &range, pages_tx);
+    let pull = save_streamed(db, "ethereum_transactions", fetch, pages_rx).await?;
+    db.record_query(format!("{} transactions", chain), address.clone(), pull.fetched).await?;
+    if !pull.truncated {
+        let synced = address.clone();
+        db.call(move |conn| database_operations::advance_sync_mark(conn, chain.as_str(), &synced, 0)).await?;
+    }
+
+    let (pages_tx, pages_rx) = mpsc::channel(PAGE_BUFFER);
+    let fetch = query_token_transfers(config, chain, &addresses, max, pages_tx);
+    let (token_transfers, token_transfers_error) = match save_streamed(db, "token_transfers", fetch, pages_rx).await {
+        Ok(transfers) => (Some(transfers.fetched), None),
+        Err(e) => {
+            warn!(address, "Token transfer pull failed: {}", e);
+            (None, Some(e.to_string()))
+        }
+    };
+
+    let case_id = db.active_case();
+    let stored = address.clone();
+    let (transactions, (first, last)) = db.call(move |conn| Ok::<_, duckdb::Error>((
+        database_operations::count_transactions(conn, chain.as_str(), &stored, case_id)?,
+        database_operations::transaction_span(conn, chain.as_str(), &stored, case_id)?,
+    ))).await?;
+
+    Ok(AccountProfile {
+        address,
+        chain,
+        account_type,
+        first_activity: first.or_else(|| created_at.clone()),
+        last_activity: last.or(last_active),
+        created_at,
+        transactions,
+        truncated: pull.truncated,
+        token_transfers,
+        token_transfers_error,
+    })
+}
+
+/// Saves each page `fetch` sends for a single address into `table`
+///
+/// Returns how the pull went, or the error that stopped it; pages saved before
+/// a failure stay saved.
+async fn save_streamed(
+    db: &DatabaseHandle,
+    table: &'static str,
+    fetch: impl Future<Output = Result<Vec<AddressPull>, FragarachError>>,
+    mut pages_rx: mpsc::Receiver<Vec<Value>>,
+) -> Result<AddressPull, FragarachError> {
+    let save = async {
+        while let Some(page) = pages_rx.recv().await {
+            if !page.is_empty() {
+                db.save_records(page, table).await?;
+            }
+        }
+        Ok(())
+    };
+    let (mut pulls, ()) = tokio::try_join!(fetch, save)?;
+    match pulls.pop() {
+        Some(AddressPull { error: Some(e), .. }) => Err(e),
+        Some(pull) => Ok(pull),
+        None => Err(FragarachError::UnexpectedResponse(format!("no {} pull result", table))),
+    }
+}
+
+/// Expected cost of pulling the full transaction history for a set of addresses
+///
+/// `transaction_count` is what the addresses have in the range, and `fetched`
+/// how many of those a pull capped by a limit would save.
+#[derive(Debug, Default)]
+pub struct TransactionPullEstimate {
+    pub transaction_count: u64,
+    pub fetched: u64,
+    pub requests: u64,
+    pub credits: u64,
+    pub duration: Duration,
+}
+
+impl std::ops::AddAssign for TransactionPullEstimate {
+    fn add_assign(&mut self, other: Self) {
+        self.transaction_count += other.transaction_count;
+        self.fetched += other.fetched;
+        self.requests += other.requests;
+        self.credits += other.credits;
+        self.duration += other.duration;
+    }
+}
+
+/// Paces all Transpose requests made by the process; its fallback interval is
+/// taken from the config of each request
+static LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+
+fn limiter() -> &'static Mutex<RateLimiter> {
+    LIMITER.get_or_init(|| Mutex::new(RateLimiter::new(Duration::from_secs(1))))
+}
+
+/// ENS names resolved so far in this process
+static ENS_CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+fn ens_cache() -> &'static Mutex<HashMap<String, String>> {
+    ENS_CACHE.get_or_init(Mutex::default)
+}
+
+/// Transpose credits left on the plan, if a response has reported them
+pub async fn remaining_credits() -> Option<u64> {
+    limiter().lock().await.credits_remaining()
+}
+
+/// SQL query templates from `src/sql/`, compiled into the binary so queries
+/// work from any working directory (cron jobs, scripts)
+const SQL_TEMPLATES: &[(&str, &str)] = &[
+    ("ethereum_accounts.sql", include_str!("../sql/ethereum_accounts.sql")),
+    ("ethereum_transactions.sql", include_str!("../sql/ethereum_transactions.sql")),
+    ("ethereum_transactions_count.sql", include_str!("../sql/ethereum_transactions_count.sql")),
+    ("ens_resolution.sql", include_str!("../sql/ens_resolution.sql")),
+    ("token_transfers.sql", include_str!("../sql/token_transfers.sql")),
+    ("nft_transfers.sql", include_str!("../sql/nft_transfers.sql")),
+    ("nft_holdings.sql", include_str!("../sql/nft_holdings.sql")),
+];
+
+/// Loads a SQL query template by file name
+///
+/// A file of the same name in the configured SQL directory replaces the
+/// built-in template, and other `.sql` files there can be loaded as custom
+/// queries. Names that aren't plain file names fail with `UnknownTemplate`.
+pub fn load_sql_query(config: &Config, filename: &str) -> Result<String, FragarachError> {
+    let plain = Path::new(filename).file_name().is_some_and(|name| name == filename);
+    if let Some(dir) = config.sql_dir().filter(|_| plain) {
+        let custom = dir.join(filename);
+        if custom.is_file() {
+            return Ok(fs::read_to_string(custom)?);
+        }
+    }
+
+    SQL_TEMPLATES.iter()
+        .find(|(name, _)| *name == filename)
+        .map(|(_, sql)| sql.to_string())
+        .ok_or_else(|| FragarachError::UnknownTemplate(filename.to_string()))
+}
+
+/// File names of every query `load_sql_query` can load, sorted
+///
+/// Lists the built-in templates and the `.sql` files in the configured SQL
+/// directory; an unreadable directory only contributes nothing.
+pub fn list_queries(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = SQL_TEMPLATES.iter().map(|(name, _)| name.to_string()).collect();
+    if let Some(entries) = config.sql_dir().and_then(|dir| fs::read_dir(dir).ok()) {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "sql") {
+                names.extend(path.file_name().and_then(|name| name.to_str()).map(String::from));
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Names of the `{{key}}` placeholders in a query template, in order of first use
+pub fn query_params(template: &str) -> Vec<String> {
+    let mut params: Vec<String> = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else { break };
+        let key = rest[..end].trim();
+        if !key.is_empty() && !params.iter().any(|p| p == key) {
+            params.push(key.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    params
+}
+
+/// Substitutes `{{key}}` placeholders in a query template
+fn render_query(template: &str, params: &[(&str, &str)]) -> String {
+    let mut query = template.to_string();
     for (key, value) in params {
         query = query.replace(&format!("{{{{{}}}}}", key), value);
     }
+    query
+}
+
+pub async fn query_transpose(config: &Config, sql_query: &str, params: &[(&str, &str)]) -> Result<Vec<Value>, FragarachError> {
+    let client = http::client(config)?;
+
+    let query = render_query(sql_query, params);
 
     // Obtain the Transpose API key or return an error if not set
-    let api_key = config.transpose_api_key().ok_or("Transpose API key not set")?;
+    let api_key = config.transpose_api_key().ok_or(FragarachError::MissingApiKey("Transpose"))?;
 
-    let response = client.post(url)
+    let request = client.post(config.transpose_api_url())
         .header("Content-Type", "application/json")
         .header("X-API-KEY", api_key)
-        .json(&serde_json::json!({ "query": query }))
-        .send()
-        .await?;
+        .json(&serde_json::json!({ "query": query }));
+    // Held while waiting for a slot so concurrent callers are paced as well,
+    // then released so requests already sent can overlap
+    {
+        let mut limiter = limiter().lock().await;
+        limiter.set_min_interval(config.transpose_request_interval());
+        limiter.wait().await;
+        limiter.start(Instant::now());
+    }
+    let response = http::send_with_retry(config, request, "Transpose SQL query").await?;
+    limiter().lock().await.record(&RateLimitInfo::from_headers(response.headers()), Instant::now());
 
     if !response.status().is_success() {
-        return Err(format!("Transpose API request failed with status: {}", response.status()).into());
+        return Err(http::status_error("Transpose API request", response).await);
     }
 
-    let result: Value = response.json().await?;
+    let result: Value = response.json().await
+        .map_err(|e| http::classify(e, "Reading Transpose response"))?;
     
     if let Some(results) = result.get("results").and_then(|v| v.as_array()) {
         Ok(results.to_vec())
     } else {
-        Err(format!("Unexpected API response: {:?}", result).into())
+        Err(FragarachError::UnexpectedResponse(format!("{:?}", result)))
     }
 }
 
-pub async fn query_ethereum_account(config: &Config, address: &str) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
-    let sql_query = load_sql_query("ethereum_accounts.sql").await;
-    query_transpose(config, &sql_query, &[("address", address)]).await
+/// Confirms the API key is accepted with a query that reads no tables
+pub async fn check_api_key(config: &Config) -> Result<(), FragarachError> {
+    query_transpose(config, "SELECT 1 AS ok", &[]).await?;
+    Ok(())
 }
 
-pub async fn query_ethereum_transactions(config: &Config, addresses: &[String]) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
-    let sql_query = load_sql_query("ethereum_transactions.sql").await;
-    let mut all_transactions = Vec::new();
-    let mut last_request_time = Instant::now();
+/// Confirms `api_key` is accepted before it replaces the configured key
+pub async fn validate_api_key(config: &Config, api_key: &str) -> Result<(), FragarachError> {
+    let mut config = config.clone();
+    config.set_transpose_api_key(Some(api_key.to_string()));
+    check_api_key(&config).await
+}
 
-    for address in addresses {
-        let mut offset = 0;
-        let limit = 100;
-
-        loop {
-            // Ensure at least 1 second has passed since the last request
-            let elapsed = last_request_time.elapsed();
-            if elapsed < Duration::from_secs(1) {
-                sleep(Duration::from_secs(1) - elapsed).await;
+/// Fetches the account details of an address, tagged with its chain
+pub async fn query_ethereum_account(config: &Config, chain: Chain, address: &str) -> Result<Vec<Value>, FragarachError> {
+    let address = validate_address(address)?;
+    let sql_query = load_sql_query(config, "ethereum_accounts.sql")?;
+    let mut rows = query_transpose(config, &sql_query, &[("chain", chain.as_str()), ("address", &address)]).await?;
+    tag_chain(&mut rows, chain);
+    Ok(rows)
+}
+
+/// Whether `address` is `0x` followed by 40 hex characters, in any case
+fn is_hex_address(address: &str) -> bool {
+    address.strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Whether `address` is `0x` followed by 40 hex characters
+///
+/// Mixed-case addresses must carry a valid EIP-55 checksum; all-lowercase and
+/// all-uppercase ones have no checksum to verify.
+pub fn is_valid_eth_address(address: &str) -> bool {
+    is_hex_address(address) && checksum_matches(&address[2..])
+}
+
+/// Whether the letter case of 40 hex characters matches their EIP-55 checksum
+fn checksum_matches(hex: &str) -> bool {
+    if hex == hex.to_ascii_lowercase() || hex == hex.to_ascii_uppercase() {
+        return true;
+    }
+
+    // Each letter is uppercase exactly when the matching nibble of the
+    // Keccak-256 hash of the lowercase address is 8 or more
+    let lower = hex.to_ascii_lowercase();
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(lower.as_bytes());
+    keccak.finalize(&mut hash);
+
+    hex.chars().enumerate().all(|(i, c)| {
+        let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+        !c.is_ascii_alphabetic() || c.is_ascii_uppercase() == (nibble >= 8)
+    })
+}
+
+/// Checks an address before it is sent to the API and returns it lowercased
+///
+/// Fails with `InvalidAddress` unless the address is `0x` followed by 40 hex
+/// characters. A mixed-case address whose EIP-55 checksum doesn't match is
+/// accepted with a warning. Lowercasing keeps one address from being stored
+/// under several spellings.
+pub fn validate_address(address: &str) -> Result<String, FragarachError> {
+    let address = address.trim();
+    if !is_hex_address(address) {
+        return Err(FragarachError::InvalidAddress(address.to_string()));
+    }
+
+    let normalized = address.to_ascii_lowercase();
+    if !is_valid_eth_address(address) {
+        warn!("{} does not match its EIP-55 checksum, check it for typos. Using {}.", address, normalized);
+    }
+    Ok(normalized)
+}
+
+/// Whether `input` is an ENS name (`*.eth`) rather than a hex address
+pub fn is_ens_name(input: &str) -> bool {
+    let input = input.trim();
+    input.len() > ".eth".len() && input.to_ascii_lowercase().ends_with(".eth")
+}
+
+/// Resolves an ENS name to the address it points at
+///
+/// Resolutions are cached for the rest of the process. Fails with
+/// `UnresolvedEns` if the name has no address. Names with characters ENS
+/// doesn't allow fail the same way without being sent to the API.
+pub async fn resolve_ens(config: &Config, name: &str) -> Result<String, FragarachError> {
+    let name = name.trim().to_lowercase();
+    let well_formed = !name.split('.').any(str::is_empty)
+        && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_') || !c.is_ascii());
+    if !well_formed {
+        return Err(FragarachError::UnresolvedEns(name));
+    }
+
+    if let Some(address) = ens_cache().lock().await.get(&name) {
+        return Ok(address.clone());
+    }
+
+    let sql_query = load_sql_query(config, "ens_resolution.sql")?;
+    let rows = query_transpose(config, &sql_query, &[("ens_name", &name)]).await?;
+    let address = rows.first()
+        .and_then(|row| row.get("resolved_address"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| FragarachError::UnresolvedEns(name.clone()))?
+        .to_ascii_lowercase();
+
+    ens_cache().lock().await.insert(name, address.clone());
+    Ok(address)
+}
+
+/// Drops rows whose transaction is in `seen` or earlier in `rows`, adding the rest to `seen`
+///
+/// Addresses that transacted with each other each return their shared
+/// transactions, so a pull of several addresses sees them more than once.
+pub fn retain_unseen_transactions(rows: &mut Vec<Value>, seen: &mut HashSet<String>) {
+    rows.retain(|row| match row.get("transaction_hash").and_then(Value::as_str) {
+        Some(hash) => seen.insert(hash.to_ascii_lowercase()),
+        None => true,
+    });
+}
+
+/// Fetches transaction pages for the given addresses and sends each into `pages`
+///
+/// Only transactions in `range` are fetched. Up to
+/// `FRAGARACH_TRANSPOSE_CONCURRENCY` addresses are fetched at once, each paged
+/// through until a page returns fewer than `PAGE_SIZE` rows; all requests
+/// share the process-wide rate limit. Stops once `max` transactions have been
+/// sent in total, if given. Returns one `AddressPull` per address, in the order
+/// given: an address that fails doesn't stop the others. Fails with
+/// `ConsumerStopped` if the receiving side is dropped.
+pub async fn stream_ethereum_transactions(
+    config: &Config,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    range: &TransactionRange,
+    pages: mpsc::Sender<Vec<Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let sql_query = load_sql_query(config, "ethereum_transactions.sql")?;
+    stream_pages(config, &sql_query, chain, addresses, max, range, pages).await
+}
+
+/// Fetches the transactions of one address in `range`, up to `max` if given
+///
+/// Collects the pages of `stream_ethereum_transactions` into one list, for
+/// callers that don't need to process them as they arrive.
+pub async fn query_ethereum_transactions(
+    config: &Config,
+    chain: Chain,
+    address: &str,
+    max: Option<usize>,
+    range: &TransactionRange,
+) -> Result<Vec<Value>, FragarachError> {
+    let (pages_tx, mut pages_rx) = mpsc::channel(PAGE_BUFFER);
+    let addresses = [address.to_string()];
+    let fetch = stream_ethereum_transactions(config, chain, &addresses, max, range, pages_tx);
+    let collect = async {
+        let mut transactions = Vec::new();
+        while let Some(page) = pages_rx.recv().await {
+            transactions.extend(page);
+        }
+        transactions
+    };
+    let (pulls, transactions) = tokio::join!(fetch, collect);
+    match pulls?.into_iter().next().and_then(|pull| pull.error) {
+        Some(e) => Err(e),
+        None => Ok(transactions),
+    }
+}
+
+/// Fetches ERC-20 transfer pages for the given addresses and sends each into `pages`
+///
+/// Paged, paced and reported like `stream_ethereum_transactions`.
+pub async fn query_token_transfers(
+    config: &Config,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    pages: mpsc::Sender<Vec<Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let sql_query = load_sql_query(config, "token_transfers.sql")?;
+    stream_pages(config, &sql_query, chain, addresses, max, &TransactionRange::default(), pages).await
+}
+
+/// Fetches ERC-721 and ERC-1155 transfer pages for the given addresses and sends each into `pages`
+///
+/// Paged, paced and reported like `stream_ethereum_transactions`.
+pub async fn query_nft_transfers(
+    config: &Config,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    pages: mpsc::Sender<Vec<Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let sql_query = load_sql_query(config, "nft_transfers.sql")?;
+    stream_pages(config, &sql_query, chain, addresses, max, &TransactionRange::default(), pages).await
+}
+
+/// Fetches pages of the ERC-721 and ERC-1155 tokens the given addresses hold and sends each into `pages`
+///
+/// Paged, paced and reported like `stream_ethereum_transactions`.
+pub async fn query_nft_holdings(
+    config: &Config,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    pages: mpsc::Sender<Vec<Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let sql_query = load_sql_query(config, "nft_holdings.sql")?;
+    stream_pages(config, &sql_query, chain, addresses, max, &TransactionRange::default(), pages).await
+}
+
+/// Pages `sql_query` through each address, `PAGE_SIZE` rows at a time, with
+/// `range` filling the template's date and block placeholders
+async fn stream_pages(
+    config: &Config,
+    sql_query: &str,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    range: &TransactionRange,
+    pages: mpsc::Sender<Vec<Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let config = Arc::new(config.clone());
+    let range = Arc::new(range.clone());
+    let sql_query: Arc<str> = Arc::from(sql_query);
+    let slots = Arc::new(Semaphore::new(config.transpose_concurrency()));
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    // Dropping the set, e.g. when the consumer fails, aborts the remaining fetches
+    let mut tasks = JoinSet::new();
+    for (i, address) in addresses.iter().cloned().enumerate() {
+        let (config, sql_query, range, slots, sent, pages) =
+            (config.clone(), sql_query.clone(), range.clone(), slots.clone(), sent.clone(), pages.clone());
+        tasks.spawn(async move {
+            let _slot = slots.acquire_owned().await.expect("semaphore is never closed");
+            let mut fetched = 0;
+            let (truncated, error) = match stream_address(&config, &sql_query, chain, &address, max, &range, &sent, &pages, &mut fetched).await {
+                Ok(truncated) => (truncated, None),
+                Err(e) => (false, Some(e)),
+            };
+            (i, AddressPull { address, fetched, truncated, error })
+        });
+    }
+    drop(pages);
+
+    let mut pulls: Vec<Option<AddressPull>> = addresses.iter().map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        let (i, pull) = joined.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        pulls[i] = Some(pull);
+    }
+    let pulls: Vec<AddressPull> = pulls.into_iter().flatten().collect();
+
+    if pulls.iter().any(|pull| matches!(pull.error, Some(FragarachError::ConsumerStopped))) {
+        return Err(FragarachError::ConsumerStopped);
+    }
+    Ok(pulls)
+}
+
+/// Pages `sql_query` through one address, adding the rows sent to `fetched`
+///
+/// `sent` counts rows sent for all addresses when `max` is given. Returns
+/// whether `max` stopped the pull before a short page showed the address had
+/// no more rows.
+#[allow(clippy::too_many_arguments)]
+async fn stream_address(
+    config: &Config,
+    sql_query: &str,
+    chain: Chain,
+    address: &str,
+    max: Option<usize>,
+    range: &TransactionRange,
+    sent: &AtomicUsize,
+    pages: &mpsc::Sender<Vec<Value>>,
+    fetched: &mut usize,
+) -> Result<bool, FragarachError> {
+    let address = validate_address(address)?;
+    let mut offset = 0;
+    let limit = PAGE_SIZE;
+
+    loop {
+        if max.is_some_and(|max| sent.load(Ordering::SeqCst) >= max) {
+            return Ok(true);
+        }
+
+        let limit_str = limit.to_string();
+        let offset_str = offset.to_string();
+        let range_params = range.params();
+        let mut params = vec![
+            ("chain", chain.as_str()),
+            ("wallet_address", address.as_str()),
+            ("limit", &limit_str),
+            ("offset", &offset_str),
+        ];
+        params.extend(range_params.iter().map(|(key, value)| (*key, value.as_str())));
+
+        let mut rows = query_transpose(config, sql_query, &params).await?;
+
+        if rows.is_empty() {
+            break;
+        }
+        let last_page = rows.len() < limit;
+        tag_chain(&mut rows, chain);
+
+        let returned = rows.len();
+        if let Some(max) = max {
+            let before = sent.fetch_add(rows.len(), Ordering::SeqCst);
+            rows.truncate(max.saturating_sub(before));
+            if rows.is_empty() {
+                return Ok(true);
             }
+        }
+        let cut = rows.len() < returned;
+
+        *fetched += rows.len();
+        offset += limit;
+
+        // Waits here while the consumer is `PAGE_BUFFER` pages behind
+        pages.send(rows).await
+            .map_err(|_| FragarachError::ConsumerStopped)?;
+
+        if cut {
+            return Ok(true);
+        }
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(false)
+}
+
+/// Transactions saved and page requests made when pulling addresses with
+/// `counts` transactions, in order, up to `max` transactions in total
+fn pull_pages(counts: &[u64], max: Option<u64>) -> (u64, u64) {
+    let mut remaining = max.unwrap_or(u64::MAX);
+    let (mut fetched, mut requests) = (0, 0);
+    for &count in counts {
+        let taken = count.min(remaining);
+        // A pull ends on the first page with fewer than `PAGE_SIZE` rows, which is
+        // an empty one when the count is a multiple of the page size; a pull cut by
+        // the limit ends on the page that reaches it
+        requests += if taken < count {
+            taken.div_ceil(PAGE_SIZE as u64)
+        } else {
+            count / PAGE_SIZE as u64 + 1
+        };
+        fetched += taken;
+        remaining -= taken;
+    }
+    (fetched, requests)
+}
+
+/// Estimates the cost of `stream_ethereum_transactions` for the given addresses and range
+///
+/// Runs one count query per address and derives the number of paginated requests
+/// within `max` transactions in total, the credits they consume and the wall-clock
+/// time imposed by the request interval. The count probes themselves are included
+/// in the totals.
+pub async fn estimate_transaction_pull(
+    config: &Config,
+    chain: Chain,
+    addresses: &[String],
+    range: &TransactionRange,
+    max: Option<usize>,
+) -> Result<TransactionPullEstimate, FragarachError> {
+    let sql_query = load_sql_query(config, "ethereum_transactions_count.sql")?;
+    let mut counts = Vec::with_capacity(addresses.len());
+
+    for address in addresses {
+        let address = validate_address(address)?;
+        let range_params = range.params();
+        let mut params = vec![("chain", chain.as_str()), ("wallet_address", address.as_str())];
+        params.extend(range_params.iter().map(|(key, value)| (*key, value.as_str())));
+        let results = query_transpose(config, &sql_query, &params).await?;
+        let count = results.first()
+            .and_then(|row| row.get("transaction_count"))
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| FragarachError::UnexpectedResponse("no transaction_count in count query result".to_string()))?;
+        counts.push(count);
+    }
 
-            let limit_str = limit.to_string();
-            let offset_str = offset.to_string();
-            let params = vec![
-                ("wallet_address", address.as_str()),
-                ("limit", &limit_str),
-                ("offset", &offset_str),
-            ];
+    let (fetched, pages) = pull_pages(&counts, max.map(|max| max as u64));
+    let requests = pages + counts.len() as u64;
+    Ok(TransactionPullEstimate {
+        transaction_count: counts.iter().sum(),
+        fetched,
+        requests,
+        credits: requests * config.transpose_credits_per_request(),
+        duration: config.transpose_request_interval() * u32::try_from(requests.saturating_sub(1)).unwrap_or(u32::MAX),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_templates_are_select_queries() {
+        let config = Config::default();
+        for (name, _) in SQL_TEMPLATES {
+            let sql = load_sql_query(&config, name).unwrap();
+            let statement: String = sql.lines()
+                .filter(|line| !line.trim_start().starts_with("--"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            assert!(statement.trim_start().starts_with("SELECT"), "{} is not a SELECT query", name);
+        }
+    }
+
+    #[test]
+    fn limited_pulls_stop_paging_at_the_limit() {
+        // A short last page, then an empty page after a full one
+        assert_eq!(pull_pages(&[250, 200], None), (450, 3 + 3));
+        // 150 of the first address's 250 reach the limit; the second isn't paged
+        assert_eq!(pull_pages(&[250, 200], Some(150)), (150, 2));
+        assert_eq!(pull_pages(&[50, 200], Some(150)), (150, 1 + 1));
+        assert_eq!(pull_pages(&[50, 200], Some(10_000)), (250, 1 + 3));
+    }
+
+    #[test]
+    fn unknown_template_is_an_error() {
+        let result = load_sql_query(&Config::default(), "missing.sql");
+        assert!(matches!(result, Err(FragarachError::UnknownTemplate(name)) if name == "missing.sql"));
+    }
+
+    #[test]
+    fn unreadable_custom_template_is_an_error() {
+        let dir = std::env::temp_dir().join(format!("fragarach-bad-sql-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ethereum_accounts.sql"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let mut config = Config::default();
+        config.set_sql_dir(Some(dir.clone()));
+        assert!(matches!(load_sql_query(&config, "ethereum_accounts.sql"), Err(FragarachError::Io(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn custom_queries_are_listed_and_loaded() {
+        let dir = std::env::temp_dir().join(format!("fragarach-sql-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("large_transfers.sql"), "SELECT * FROM {{chain}}.transactions WHERE value > {{min_value}}").unwrap();
+        fs::write(dir.join("notes.txt"), "not a query").unwrap();
+
+        let mut config = Config::default();
+        config.set_sql_dir(Some(dir.clone()));
+        let names = list_queries(&config);
+        assert!(names.contains(&"large_transfers.sql".to_string()));
+        assert!(names.contains(&"ethereum_accounts.sql".to_string()));
+        assert!(!names.contains(&"notes.txt".to_string()));
+
+        let sql = load_sql_query(&config, "large_transfers.sql").unwrap();
+        assert_eq!(query_params(&sql), ["chain", "min_value"]);
+        assert!(matches!(load_sql_query(&config, "../notes.txt"), Err(FragarachError::UnknownTemplate(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lowercase_and_uppercase_addresses_are_valid() {
+        assert!(is_valid_eth_address("0xd8da6bf26964af9d7eed9e03e53415d37aa96045"));
+        assert!(is_valid_eth_address("0xD8DA6BF26964AF9D7EED9E03E53415D37AA96045"));
+    }
+
+    #[test]
+    fn checksummed_addresses_are_verified() {
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+            "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045",
+        ] {
+            assert!(is_valid_eth_address(address), "{} should be valid", address);
+        }
+        // One letter's case flipped
+        assert!(!is_valid_eth_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"));
+    }
+
+    #[test]
+    fn too_short_and_non_hex_addresses_are_invalid() {
+        assert!(!is_valid_eth_address("0xd8da6bf26964af9d7eed9e03e53415d37aa9604"));
+        assert!(!is_valid_eth_address("0xd8da6bf26964af9d7eed9e03e53415d37aa960455"));
+        assert!(!is_valid_eth_address("0xg8da6bf26964af9d7eed9e03e53415d37aa96045"));
+        assert!(!is_valid_eth_address("d8da6bf26964af9d7eed9e03e53415d37aa96045"));
+        assert!(matches!(validate_address("0x123"), Err(FragarachError::InvalidAddress(a)) if a == "0x123"));
+    }
+
+    #[test]
+    fn validated_addresses_are_lowercased() {
+        let lower = "0xd8da6bf26964af9d7eed9e03e53415d37aa96045";
+        assert_eq!(validate_address(lower).unwrap(), lower);
+        assert_eq!(validate_address(" 0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045 ").unwrap(), lower);
+        assert_eq!(validate_address("0xD8DA6BF26964AF9D7EED9E03E53415D37AA96045").unwrap(), lower);
+    }
+
+    #[test]
+    fn bad_checksum_is_accepted_with_a_warning() {
+        assert_eq!(
+            validate_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").unwrap(),
+            "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        );
+        assert!(validate_address("0xZZaeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").is_err());
+    }
 
-            let transactions = query_transpose(config, &sql_query, &params).await?;
-            last_request_time = Instant::now();
+    #[test]
+    fn ens_names_are_told_apart_from_addresses() {
+        assert!(is_ens_name("vitalik.eth"));
+        assert!(is_ens_name(" Vitalik.ETH "));
+        assert!(!is_ens_name(".eth"));
+        assert!(!is_ens_name("0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045"));
+    }
+
+    #[tokio::test]
+    async fn malformed_ens_name_is_rejected_before_querying() {
+        let result = resolve_ens(&Config::default(), "x' OR '1'='1.eth").await;
+        let err = result.unwrap_err();
+        assert!(matches!(err, FragarachError::UnresolvedEns(_)));
+    }
+
+    #[tokio::test]
+    async fn missing_api_key_is_reported_before_sending() {
+        let err = query_ethereum_account(&Config::default(), Chain::Ethereum, "0xd8da6bf26964af9d7eed9e03e53415d37aa96045")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FragarachError::MissingApiKey("Transpose")));
+    }
+
+    #[tokio::test]
+    async fn cached_ens_names_resolve_without_querying() {
+        ens_cache().lock().await.insert("cached.eth".to_string(), "0xd8da6bf26964af9d7eed9e03e53415d37aa96045".to_string());
+
+        // No API key is configured, so a query would fail
+        let address = resolve_ens(&Config::default(), "Cached.eth").await.unwrap();
+        assert_eq!(address, "0xd8da6bf26964af9d7eed9e03e53415d37aa96045");
+    }
 
-            if transactions.is_empty() {
-                break;
+    #[test]
+    fn placeholders_are_substituted() {
+        let sql = load_sql_query(&Config::default(), "ethereum_transactions.sql").unwrap();
+        let range = TransactionRange::default().params();
+        let mut params = vec![("chain", "polygon"), ("wallet_address", "0xabc"), ("limit", "100"), ("offset", "200")];
+        params.extend(range.iter().map(|(key, value)| (*key, value.as_str())));
+        let query = render_query(&sql, &params);
+
+        assert!(!query.contains("{{"));
+        assert!(query.contains("FROM polygon.transactions"));
+        assert!(query.contains("WHERE (t.from_address = '0xabc'"));
+        assert!(query.contains("BETWEEN '1970-01-01 00:00:00' AND '9999-12-31 23:59:59.999999'"));
+        assert!(query.contains("LIMIT 100"));
+        assert!(query.contains("OFFSET 200"));
+    }
+
+    #[test]
+    fn transaction_ranges_are_checked_and_described() {
+        let range = TransactionRange::new(Some("2024-02-29"), Some("2024-03-01"), None, Some(19_000_000)).unwrap();
+        assert_eq!(range.params()[0].1, "2024-02-29 00:00:00");
+        assert_eq!(range.params()[1].1, "2024-03-01 23:59:59.999999");
+        assert_eq!(range.params()[2].1, "0");
+        assert_eq!(range.to_string(), "2024-02-29 to 2024-03-01, blocks up to 19000000");
+        assert_eq!(TransactionRange::default().to_string(), "all time");
+        assert_eq!(TransactionRange::new(Some("2024-01-01"), None, None, None).unwrap().to_string(), "since 2024-01-01");
+        assert_eq!(TransactionRange::since_block(18_000_000).to_string(), "blocks since 18000000");
+
+        assert_eq!(TransactionRange::default().sync_start(), Some(0));
+        assert_eq!(TransactionRange::since_block(18_000_000).sync_start(), Some(18_000_000));
+        assert_eq!(range.sync_start(), None);
+
+        for bad in ["2023-02-29", "2024-13-01", "2024-1-01", "01/02/2024", "yesterday"] {
+            assert!(matches!(TransactionRange::new(Some(bad), None, None, None), Err(FragarachError::InvalidInput(_))), "{}", bad);
+        }
+        assert!(TransactionRange::new(Some("2024-03-02"), Some("2024-03-01"), None, None).is_err());
+        assert!(TransactionRange::new(None, None, Some(10), Some(9)).is_err());
+    }
+
+    #[test]
+    fn transactions_between_pulled_addresses_are_kept_once() {
+        let tx = |hash: &str| serde_json::json!({ "transaction_hash": hash });
+        // 0xa sent 0xb the transaction 0x02, so both pulls return it
+        let mut pulled_a = vec![tx("0x01"), tx("0x02")];
+        let mut pulled_b = vec![tx("0x02"), tx("0x03"), tx("0x03")];
+
+        let mut seen = HashSet::new();
+        retain_unseen_transactions(&mut pulled_a, &mut seen);
+        retain_unseen_transactions(&mut pulled_b, &mut seen);
+
+        assert_eq!(pulled_a.len() + pulled_b.len(), 3);
+        assert_eq!(pulled_b, [tx("0x03")]);
+        assert_eq!(seen.len(), 3);
+    }
+
+    /// When a mock Transpose server received each request, and how many it
+    /// was answering at once at most
+    #[derive(Default)]
+    struct ServerLoad {
+        arrivals: std::sync::Mutex<Vec<Instant>>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    /// Serves address `i` a single page of `i + 1` transactions and fails
+    /// `failing` outright. The first address is only answered once the third
+    /// has been, so later addresses finish before earlier ones.
+    async fn serve_transactions(addresses: Vec<String>, failing: String) -> (String, Arc<ServerLoad>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+        use tokio::sync::Notify;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sql", listener.local_addr().unwrap());
+        let load = Arc::new(ServerLoad::default());
+        let third_answered = Arc::new(Notify::new());
+        let server_load = load.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (addresses, failing, load, third_answered) =
+                    (addresses.clone(), failing.clone(), server_load.clone(), third_answered.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    let body_start = loop {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                        if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break end + 4;
+                        }
+                    };
+                    let headers = String::from_utf8_lossy(&request[..body_start]).to_ascii_lowercase();
+                    let length: usize = headers.lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .and_then(|value| value.trim().parse().ok())
+                        .unwrap_or(0);
+                    while request.len() < body_start + length {
+                        let n = socket.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    load.arrivals.lock().unwrap().push(Instant::now());
+                    let in_flight = load.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    load.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+                    let body = String::from_utf8_lossy(&request[body_start..]).to_string();
+                    let i = addresses.iter().position(|address| body.contains(address.as_str())).unwrap();
+                    if i == 0 {
+                        third_answered.notified().await;
+                    }
+                    let (status, body) = if addresses[i] == failing {
+                        ("400 Bad Request", r#"{"message":"bad query"}"#.to_string())
+                    } else {
+                        let rows: Vec<Value> = (0..=i).map(|j| serde_json::json!({ "transaction_hash": format!("{}-{}", i, j) })).collect();
+                        ("200 OK", serde_json::json!({ "results": rows }).to_string())
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status, body.len(), body
+                    );
+                    load.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                    if i == 2 {
+                        // Stores the wakeup if the first address hasn't started waiting yet
+                        third_answered.notify_one();
+                    }
+                });
             }
+        });
+        (url, load)
+    }
 
-            all_transactions.extend(transactions);
-            offset += limit;
+    #[tokio::test]
+    async fn concurrent_pulls_stay_per_address_and_bounded() {
+        let addresses: Vec<String> = ["1", "2", "3", "4"].iter().map(|digit| format!("0x{}", digit.repeat(40))).collect();
+        let (url, load) = serve_transactions(addresses.clone(), addresses[1].clone()).await;
+        let config: Config = toml::from_str(&format!(
+            "transpose_api_key = \"test\"\ntranspose_api_url = \"{}\"\ntranspose_concurrency = 3\ntranspose_requests_per_minute = 120\nhttp_max_attempts = 1",
+            url
+        )).unwrap();
 
-            // Check if we've reached the 1 MB response size limit (approximate)
-            if all_transactions.len() * 1000 > 1_000_000 {
-                println!("Warning: Reached approximate 1 MB response size limit. Some transactions may be missing.");
-                break;
+        let (pages, mut received) = mpsc::channel::<Vec<Value>>(16);
+        let consumer = tokio::spawn(async move {
+            let mut hashes = Vec::new();
+            while let Some(page) = received.recv().await {
+                hashes.extend(page.iter().map(|row| row["transaction_hash"].as_str().unwrap().to_string()));
             }
+            hashes
+        });
+        let pulls = stream_ethereum_transactions(&config, Chain::Ethereum, &addresses, None, &TransactionRange::default(), pages)
+            .await
+            .unwrap();
+        let mut hashes = consumer.await.unwrap();
+
+        // The third address was answered before the first, yet each pull is
+        // reported in input order and the failing one doesn't stop the others
+        let summary: Vec<(&str, usize, bool)> = pulls.iter()
+            .map(|pull| (pull.address.as_str(), pull.fetched, pull.error.is_some()))
+            .collect();
+        assert_eq!(summary, [
+            (addresses[0].as_str(), 1, false),
+            (addresses[1].as_str(), 0, true),
+            (addresses[2].as_str(), 3, false),
+            (addresses[3].as_str(), 4, false),
+        ]);
+        assert!(matches!(pulls[1].error, Some(FragarachError::ApiStatus { .. })));
+        hashes.sort();
+        assert_eq!(hashes, ["0-0", "2-0", "2-1", "2-2", "3-0", "3-1", "3-2", "3-3"]);
+
+        // One request per address, overlapping but never more than the configured concurrency
+        let mut arrivals = load.arrivals.lock().unwrap().clone();
+        assert_eq!(arrivals.len(), 4);
+        let max_in_flight = load.max_in_flight.load(Ordering::SeqCst);
+        assert!((2..=3).contains(&max_in_flight), "{} requests in flight at once", max_in_flight);
+
+        // At 2 requests a second, no one-second window holds a third request,
+        // allowing 100ms for requests to reach the server at uneven speeds
+        arrivals.sort();
+        for window in arrivals.windows(3) {
+            let span = window[2] - window[0];
+            assert!(span >= Duration::from_millis(900), "3 requests within {:?}", span);
         }
     }
-
-    Ok(all_transactions)
 }
@@ -1,24 +1,198 @@
 /// URLScan API integration for domain scanning and analysis
 /// 
 /// # Features
-/// - Domain scanning with private visibility
+/// - Domain and URL scanning with public, unlisted or private visibility;
+///   `ScanTarget::parse` turns the input into the URL submitted and the host
+///   stored for searching, rejecting malformed input before any API call
 /// - Screenshot capture
-/// - DOM snapshot storage
+/// - DOM snapshot storage, gzip-compressed to `<dom dir>/<uuid>.html.gz` unless
+///   small enough to keep in the database (see `Config::store_dom_inline`);
+///   `read_dom` reads either kind back, and `compress_stored_doms` moves large
+///   snapshots saved inline by older releases out to compressed files
 /// - Verdict analysis
+/// - Submission quota reads
+/// - Search of existing scans, which can be imported without a new submission
+/// - Registration data and DNS records of each domain scanned by `scan_and_save`
+///   (see `whois` and `dns`), stored in `domain_whois` and `domain_dns`
+/// - VirusTotal reports of each newly scanned domain and its page IP when
+///   `FRAGARACH_VIRUSTOTAL_ON_SCAN` is on and a VirusTotal key is set (see
+///   `virustotal`), stored in `virustotal_domain_data` and `virustotal_ip_data`
+/// - Shodan data of the page IP when a Shodan key is set (see `shodan`),
+///   stored in `shodan_host_data`
+/// - Submissions paced to `FRAGARACH_URLSCAN_SCANS_PER_MINUTE`, queueing callers
+///   beyond it; rate-limited (429) responses are retried once the window resets
 /// 
 /// # Database Integration
-/// Supports both SQLite and PostgreSQL for storing:
+/// `scan_domain` and `fetch_scan` only return what they collected;
+/// `scan_and_save`, `save_scan` and `import_scan` store it, covering:
 /// - Scan results
+/// - Page details and linked domains
 /// - Domain data
 /// - Screenshots
 /// - DOM snapshots
+/// - VirusTotal reports
+/// - Shodan host data
+use crate::api::http;
+use crate::api::rate_limit::TokenBucket;
+use crate::api::{dns, shodan, virustotal, whois};
 use crate::config::Config;
-use reqwest::{Client, header};
+use crate::error::FragarachError;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::{header, Client, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::time::Duration;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
-use duckdb::{Connection, params};
+use duckdb::{params, Connection, OptionalExt};
+use crate::helpers::database_writer::DatabaseHandle;
+use tracing::{info, warn};
+
+/// Longest wait between result polls when polling backs off
+pub const MAX_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Paces scan submissions made by the process
+static SUBMISSIONS: OnceLock<Mutex<TokenBucket>> = OnceLock::new();
+
+/// Waits for a free slot under the configured submissions per minute
+async fn wait_for_submission_slot(config: &Config) {
+    let bucket = SUBMISSIONS.get_or_init(|| Mutex::new(
+        TokenBucket::new(config.urlscan_scans_per_minute(), Duration::from_secs(60), Instant::now())
+    ));
+    let wait = bucket.lock().unwrap().take(Instant::now());
+    if !wait.is_zero() {
+        info!("Submission rate limit reached, queued for {:.0}s", wait.as_secs_f64().ceil());
+        sleep(wait).await;
+    }
+}
+
+/// Who can see a submitted scan on urlscan.io
+///
+/// Free accounts can usually only submit public scans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    Unlisted,
+    Private,
+}
+
+impl Visibility {
+    pub const ALL: [Visibility; 3] = [Visibility::Public, Visibility::Unlisted, Visibility::Private];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Private => "private",
+        }
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Visibility::ALL.into_iter()
+            .find(|v| v.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown visibility '{}' (expected public, unlisted or private)", s))
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a scan was asked for: the full URL submitted and the host it points at
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanTarget {
+    pub url: String,
+    pub domain: String,
+}
+
+impl ScanTarget {
+    /// Parses a domain or URL, prepending `https://` when no scheme is given
+    ///
+    /// Only http(s) URLs whose host is an IP address or a dotted name are accepted.
+    pub fn parse(input: &str) -> Result<Self, FragarachError> {
+        let input = input.trim();
+        let invalid = |reason: &str| FragarachError::InvalidInput(format!("'{}' is not a valid domain or URL: {}", input, reason));
+        if input.is_empty() {
+            return Err(FragarachError::InvalidInput("Enter a domain or URL to scan".to_string()));
+        }
+
+        let url = if http::has_scheme(input) { Url::parse(input) } else { Url::parse(&format!("https://{}", input)) }
+            .map_err(|e| invalid(&e.to_string()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(invalid("only http and https URLs can be scanned"));
+        }
+        let domain = url.host_str().ok_or_else(|| invalid("no host name"))?.trim_end_matches('.').to_ascii_lowercase();
+        let is_ip = domain.starts_with('[') || domain.parse::<IpAddr>().is_ok();
+        if !is_ip && !domain.contains('.') {
+            return Err(invalid("the host has no top-level domain"));
+        }
+        Ok(ScanTarget { url: url.to_string(), domain })
+    }
+}
+
+/// A submitted scan and whether its result was stored
+///
+/// Scans still running when polling times out stay in `urlscan_domain_data`
+/// with status `pending`. `virustotal` is the VirusTotal report of the scanned
+/// domain, if the scan read one.
+#[derive(Debug)]
+pub struct ScanOutcome {
+    pub uuid: String,
+    pub completed: bool,
+    pub virustotal: Option<virustotal::Report>,
+}
+
+/// A scan accepted by URLScan
+#[derive(Debug, Clone, Serialize)]
+pub struct Submission {
+    pub domain: String,
+    pub url: String,
+    pub uuid: String,
+    pub result_url: String,
+    pub api_url: String,
+    pub visibility: String,
+    pub useragent: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Everything collected for a scan by `scan_domain` or `fetch_scan`
+///
+/// `result` is the full result document, or `None` if the scan was still
+/// running when polling timed out; the screenshot and DOM are then missing too.
+/// `virustotal` holds the VirusTotal reports of a new scan's domain and page
+/// IP, and `shodan` what Shodan knows about that IP; both are left out for
+/// imported scans and without the service's key, and VirusTotal's also unless
+/// `FRAGARACH_VIRUSTOTAL_ON_SCAN` is on.
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub submission: Submission,
+    pub result: Option<Value>,
+    pub screenshot: Option<Vec<u8>>,
+    pub dom: Option<String>,
+    pub virustotal: Vec<virustotal::Report>,
+    pub shodan: Option<shodan::HostRecord>,
+}
+
+impl ScanResult {
+    pub fn completed(&self) -> bool {
+        self.result.is_some()
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ScanResponse {
@@ -30,158 +204,920 @@ struct ScanResponse {
     country: Option<String>,
 }
 
+impl ScanResponse {
+    fn into_submission(self, target: ScanTarget) -> Submission {
+        Submission {
+            domain: target.domain,
+            url: target.url,
+            uuid: self.uuid,
+            result_url: self.result,
+            api_url: self.api,
+            visibility: self.visibility,
+            useragent: self.options.and_then(|opt| opt.useragent),
+            country: self.country,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ScanOptions {
     useragent: Option<String>,
 }
 
-pub async fn scan_domain(
-    config: &Config,
-    domain: &str,
-    conn: &Connection
-) -> Result<(), Box<dyn std::error::Error>> {
+/// Daily submission quota for one visibility level
+#[derive(Debug, Deserialize)]
+pub struct SubmissionQuota {
+    pub limit: u64,
+    pub used: u64,
+    pub remaining: u64,
+}
+
+/// Confirms the API key is accepted by reading the account's quotas
+pub async fn check_api_key(config: &Config) -> Result<(), FragarachError> {
+    let api_key = config.urlscan_api_key().ok_or(FragarachError::MissingApiKey("URLScan"))?;
+
+    let client = http::client(config)?;
+    let request = client.get("https://urlscan.io/user/quotas/")
+        .header("API-Key", api_key);
+    let resp = http::send_with_retry(config, request, "URLScan quota request").await?;
+
+    if !resp.status().is_success() {
+        return Err(http::status_error("URLScan quota request", resp).await);
+    }
+    Ok(())
+}
+
+/// Confirms `api_key` is accepted before it replaces the configured key
+pub async fn validate_api_key(config: &Config, api_key: &str) -> Result<(), FragarachError> {
+    let mut config = config.clone();
+    config.set_urlscan_api_key(Some(api_key.to_string()));
+    check_api_key(&config).await
+}
+
+/// Reads the remaining daily submission quota for the given visibility
+pub async fn get_submission_quota(config: &Config, visibility: &str) -> Result<SubmissionQuota, FragarachError> {
+    let api_key = config.urlscan_api_key().ok_or(FragarachError::MissingApiKey("URLScan"))?;
+
+    let client = http::client(config)?;
+    let request = client.get("https://urlscan.io/user/quotas/")
+        .header("API-Key", api_key);
+    let resp = http::send_with_retry(config, request, "URLScan quota request").await?;
+
+    if !resp.status().is_success() {
+        return Err(http::status_error("URLScan quota request", resp).await);
+    }
+
+    let quotas: Value = resp.json().await?;
+    let day = quotas.get("limits")
+        .and_then(|l| l.get(visibility))
+        .and_then(|v| v.get("day"))
+        .cloned()
+        .ok_or_else(|| FragarachError::UnexpectedResponse(format!("no {} quota in URLScan response", visibility)))?;
+
+    Ok(serde_json::from_value(day)?)
+}
+
+/// Domains the scanned page links to, from `lists.linkDomains`
+///
+/// Falls back to the distinct URLs of the requests the page made when URLScan
+/// reports no linked domains.
+fn linked_domains(scan: &Value) -> Vec<String> {
+    let strings = |v: &Value| v.as_str().map(String::from);
+
+    let links: Vec<String> = scan.pointer("/lists/linkDomains")
+        .and_then(|v| v.as_array())
+        .map(|domains| domains.iter().filter_map(strings).collect())
+        .unwrap_or_default();
+    if !links.is_empty() {
+        return links;
+    }
+
+    let mut urls: Vec<String> = Vec::new();
+    for request in scan.pointer("/data/requests").and_then(|v| v.as_array()).into_iter().flatten() {
+        if let Some(url) = request.pointer("/request/request/url").and_then(strings) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// Distinct values of `field` across the entries of the array at `pointer`
+fn distinct(scan: &Value, pointer: &str, field: Option<&str>) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    for entry in scan.pointer(pointer).and_then(|v| v.as_array()).into_iter().flatten() {
+        let value = field.map_or(Some(entry), |field| entry.get(field));
+        if let Some(value) = value.and_then(|v| v.as_str()) {
+            if !values.iter().any(|v| v == value) {
+                values.push(value.to_string());
+            }
+        }
+    }
+    values
+}
+
+/// Stores the details of a finished scan in `urlscan_scan_data`
+///
+/// Keeps the page details, linked domains, the IPs the page resolved to and
+/// the URLs it links to, along with the complete result JSON so fields
+/// URLScan adds later aren't lost. Re-scans replace the row of their uuid.
+fn save_scan_data(conn: &Connection, uuid: &str, scan: &Value) -> duckdb::Result<usize> {
+    let page = |field: &str| scan.pointer(&format!("/page/{}", field))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let data_links = Value::from(linked_domains(scan)).to_string();
+    let resolved_ips = Value::from(distinct(scan, "/lists/ips", None)).to_string();
+    let page_links = Value::from(distinct(scan, "/data/links", Some("href"))).to_string();
+
+    conn.execute(
+        "INSERT INTO urlscan_scan_data (uuid, ip, data_links, page_asn, page_ip, page_country, page_title,
+             resolved_ips, page_links, raw_result)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+         ON CONFLICT (uuid) DO UPDATE SET ip = $2, data_links = $3, page_asn = $4,
+             page_ip = $5, page_country = $6, page_title = $7,
+             resolved_ips = $8, page_links = $9, raw_result = $10",
+        params![
+            uuid, page("ip"), data_links, page("asn"), page("ip"), page("country"), page("title"),
+            resolved_ips, page_links, scan.to_string()
+        ],
+    )
+}
+
+/// Verdict score of a scan result, from a number or numeric string
+///
+/// urlscan scores range from -100 to 100; anything unparseable is treated as absent.
+fn verdict_score(scan: &Value) -> Option<i64> {
+    match scan.pointer("/verdicts/urlscan/score")? {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Fills the page and verdict columns of a scan's `urlscan_domain_data` row
+///
+/// Fields missing from the result are stored as NULL.
+fn save_verdicts(conn: &Connection, uuid: &str, scan: &Value) -> duckdb::Result<usize> {
+    let page = |field: &str| scan.pointer(&format!("/page/{}", field))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let brands = scan.pointer("/verdicts/urlscan/brands")
+        .filter(|v| !v.is_null())
+        .map(Value::to_string);
+
+    conn.execute(
+        "UPDATE urlscan_domain_data
+         SET asn = $1, ip = $2, title = $3, verdict_score = $4, verdict_brands = $5
+         WHERE uuid = $6",
+        params![page("asn"), page("ip"), page("title"), verdict_score(scan), brands, uuid],
+    )
+}
+
+/// Submits a domain or URL for scanning, after waiting for a free submission slot
+///
+/// Input that `ScanTarget::parse` rejects fails before any request is made.
+pub async fn submit(config: &Config, input: &str, visibility: Visibility) -> Result<Submission, FragarachError> {
+    let target = ScanTarget::parse(input)?;
+
     // Obtain the API key
-    let api_key = config.urlscan_api_key().ok_or("URLScan API key not set")?;
+    let api_key = config.urlscan_api_key().ok_or(FragarachError::MissingApiKey("URLScan"))?;
 
-    let client = Client::new();
+    let client = http::client(config)?;
     
     // Build headers for the request
     let mut headers = header::HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
-    headers.insert("API-Key", header::HeaderValue::from_str(&api_key)?);
+    let api_key = header::HeaderValue::from_str(&api_key)
+        .map_err(|_| FragarachError::InvalidInput("URLScan API key contains characters not allowed in a header".to_string()))?;
+    headers.insert("API-Key", api_key);
 
-    // Prepare request body: scan the domain with private visibility
+    // Prepare request body: scan the URL with the requested visibility
     let body = serde_json::json!({
-        "url": domain,
-        "visibility": "private",
+        "url": target.url,
+        "visibility": visibility.as_str(),
     });
 
     // Send initial scan request
-    let initial_resp = client.post("https://urlscan.io/api/v1/scan/")
-        .headers(headers.clone())
-        .json(&body)
-        .send()
-        .await?;
+    wait_for_submission_slot(config).await;
+    let request = client.post("https://urlscan.io/api/v1/scan/")
+        .headers(headers)
+        .json(&body);
+    let initial_resp = http::send_with_retry(config, request, "URLScan submission").await?;
 
-    if !initial_resp.status().is_success() {
-        return Err(format!("Initial URLScan request failed with status: {}", initial_resp.status()).into());
+    let status = initial_resp.status();
+    if !status.is_success() {
+        // URLScan explains rejections (e.g. a visibility the plan doesn't allow) in the body
+        let rejection: Value = initial_resp.json().await.unwrap_or(Value::Null);
+        let reason = rejection.get("description")
+            .or_else(|| rejection.get("message"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("no reason given");
+        return Err(FragarachError::ScanRejected {
+            domain: target.url,
+            visibility: visibility.to_string(),
+            status,
+            reason: reason.to_string(),
+        });
     }
 
     // Parse the initial response
     let initial_scan: ScanResponse = initial_resp.json().await?;
-    let uuid = &initial_scan.uuid;
-    println!("Scan initiated for domain {}. UUID: {}", domain, uuid);
+    info!(url = %target.url, uuid = %initial_scan.uuid, "Scan initiated");
+    Ok(initial_scan.into_submission(target))
+}
 
-    // Insert initial scan data to URLScan domain data table
-    conn.execute(
-        "INSERT OR REPLACE INTO urlscan_domain_data (
-            domain, uuid, result_url, api_url, visibility, useragent, country
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7)",
-        params![
-            domain,
-            uuid,
-            &initial_scan.result,
-            &initial_scan.api,
-            &initial_scan.visibility,
-            initial_scan.options.as_ref()
-                .and_then(|opt| opt.useragent.as_deref())
-                .unwrap_or("N/A"),
-            initial_scan.country.as_deref().unwrap_or("N/A")
-        ]
-    )?;
-
-    // Poll until the full scan result is available (timeout after 120 secs)
-    let full_scan: Value = {
-        let mut elapsed = Duration::from_secs(0);
-        let timeout = Duration::from_secs(120);
-        let mut result_opt = None;
-        while elapsed < timeout {
-            let result_url = format!("https://urlscan.io/api/v1/result/{}/", uuid);
-            let res = client.get(&result_url).send().await?;
-            if res.status() == reqwest::StatusCode::OK {
-                result_opt = Some(res.json::<Value>().await?);
-                break;
-            } else if res.status() == reqwest::StatusCode::NOT_FOUND {
-                println!("Scan not finished yet, retrying in 5 seconds...");
-                sleep(Duration::from_secs(5)).await;
-                elapsed += Duration::from_secs(5);
-            } else {
-                return Err(format!("Failed to retrieve scan result. Status: {}", res.status()).into());
-            }
+/// Polls until the full result of a scan is available, or `None` once the poll timeout passes
+///
+/// The poll timeout (`FRAGARACH_URLSCAN_POLL_TIMEOUT_SECS`) bounds the whole
+/// wait for the scan; a single poll request that times out or can't connect
+/// is just another unfinished poll, retried until then.
+async fn poll_result(config: &Config, client: &Client, uuid: &str) -> Result<Option<Value>, FragarachError> {
+    let started = Instant::now();
+    let mut interval = config.urlscan_poll_interval();
+    let result_url = format!("https://urlscan.io/api/v1/result/{}/", uuid);
+    while started.elapsed() < config.urlscan_poll_timeout() {
+        match http::send_with_retry(config, client.get(&result_url), "URLScan result poll").await {
+            Ok(res) if res.status() == reqwest::StatusCode::OK => return Ok(Some(res.json::<Value>().await?)),
+            // Not finished yet
+            Ok(res) if res.status() == reqwest::StatusCode::NOT_FOUND => {}
+            Ok(res) => return Err(http::status_error("URLScan result poll", res).await),
+            Err(e) if http::is_network_error(&e) => warn!(%uuid, "Poll request failed, polling again: {}", e),
+            Err(e) => return Err(e),
+        }
+
+        let wait = interval.min(config.urlscan_poll_timeout().saturating_sub(started.elapsed()));
+        info!(%uuid, "Scan not finished yet, retrying in {} seconds", wait.as_secs());
+        sleep(wait).await;
+        if config.urlscan_poll_backoff() {
+            interval = (interval * 2).min(MAX_POLL_INTERVAL.max(config.urlscan_poll_interval()));
+        }
+    }
+    warn!(%uuid, "Scan not finished after {}s", config.urlscan_poll_timeout().as_secs());
+    Ok(None)
+}
+
+/// Downloads the screenshot and DOM snapshot of a finished scan
+async fn collect(config: &Config, client: &Client, submission: Submission, result: Value) -> Result<ScanResult, FragarachError> {
+    let uuid = &submission.uuid;
+
+    // Without a screenshot, the path and image stay NULL when saved
+    let screenshot = match fetch_screenshot(config, client, uuid).await {
+        Ok(screenshot) => Some(screenshot),
+        Err(e) => {
+            warn!(%uuid, "No screenshot available: {}", e);
+            None
         }
-        result_opt.ok_or("Timeout waiting for scan to complete.")?
     };
 
-    // Extract fields from full scan result
-    let default_page = serde_json::Map::new();
-    let page = full_scan.get("page")
-        .and_then(|p| p.as_object())
-        .unwrap_or(&default_page);
+    let dom_url = format!("https://urlscan.io/dom/{}/", uuid);
+    // DOM snapshots of heavy pages can legitimately take longer than other calls
+    let dom_request = client.get(&dom_url).timeout(config.dom_timeout());
+    let dom_resp = http::send_with_retry(config, dom_request, "DOM snapshot download").await?;
+    let dom = dom_resp.text().await
+        .map_err(|e| http::classify(e, "DOM snapshot download"))?;
+
+    Ok(ScanResult {
+        submission,
+        result: Some(result),
+        screenshot,
+        dom: (!dom.is_empty()).then_some(dom),
+        virustotal: Vec::new(),
+        shodan: None,
+    })
+}
+
+/// VirusTotal reports of the scanned domain and the IP its page was served from
+///
+/// Empty unless `FRAGARACH_VIRUSTOTAL_ON_SCAN` is on and a VirusTotal key is
+/// set; a failed report is logged and left out.
+async fn virustotal_reports(config: &Config, domain: &str, result: &Value) -> Vec<virustotal::Report> {
+    if !config.virustotal_on_scan() {
+        return Vec::new();
+    }
 
-    let default_verdicts = serde_json::Map::new();
-    let verdicts = full_scan.get("verdicts")
-        .and_then(|v| v.get("urlscan"))
-        .and_then(|v| v.as_object())
-        .unwrap_or(&default_verdicts);
+    let mut reports = Vec::new();
+    // A scan of a bare IP address has no domain to report on
+    let is_ip = domain.trim_matches(['[', ']']).parse::<IpAddr>().is_ok();
+    if !is_ip {
+        match virustotal::domain_report(config, domain).await {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!(domain, "VirusTotal domain report failed: {}", e),
+        }
+    }
+    if let Some(ip) = result.pointer("/page/ip").and_then(Value::as_str) {
+        match virustotal::ip_report(config, ip).await {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!(domain, ip, "VirusTotal IP report failed: {}", e),
+        }
+    }
+    reports
+}
 
-    let asn = page.get("asn").and_then(|v| v.as_str()).unwrap_or("N/A");
-    let ip = page.get("ip").and_then(|v| v.as_str()).unwrap_or("N/A");
-    let title = page.get("title").and_then(|v| v.as_str()).unwrap_or("N/A");
-    let verdict_score = verdicts.get("score").map(|v| v.to_string()).unwrap_or("N/A".to_string());
-    let verdict_brands = verdicts.get("brands").map(|v| v.to_string()).unwrap_or("[]".to_string());
+/// Shodan data of the IP the scanned page was served from
+///
+/// `None` without a Shodan key or a page IP; a failed lookup is logged.
+async fn shodan_record(config: &Config, domain: &str, result: &Value) -> Option<shodan::HostRecord> {
+    config.shodan_api_key()?;
+    let ip = result.pointer("/page/ip").and_then(Value::as_str)?;
+    shodan::lookup_ip(config, ip).await
+        .inspect_err(|e| warn!(domain, ip, "Shodan lookup failed: {}", e))
+        .ok()
+}
 
-    // Update the domain data record with full scan details
-    conn.execute(
-        "UPDATE urlscan_domain_data
-         SET asn = $1, ip = $2, title = $3, verdict_score = $4, verdict_brands = $5
-         WHERE uuid = $6",
+/// Waits for a submitted scan to finish and downloads its result, screenshot and DOM,
+/// adding VirusTotal reports and Shodan data when they are enabled
+pub async fn wait_for_result(config: &Config, submission: Submission) -> Result<ScanResult, FragarachError> {
+    let client = http::client(config)?;
+    match poll_result(config, &client, &submission.uuid).await? {
+        Some(result) => {
+            let mut scan = collect(config, &client, submission, result).await?;
+            if let Some(result) = &scan.result {
+                scan.virustotal = virustotal_reports(config, &scan.submission.domain, result).await;
+                scan.shodan = shodan_record(config, &scan.submission.domain, result).await;
+            }
+            Ok(scan)
+        }
+        None => Ok(ScanResult { submission, result: None, screenshot: None, dom: None, virustotal: Vec::new(), shodan: None }),
+    }
+}
+
+/// Submits a domain or URL and returns what the scan found, without storing anything
+///
+/// Use `save_scan` to store the result, or `scan_and_save` to keep a pending
+/// record of the submission while polling.
+pub async fn scan_domain(config: &Config, input: &str, visibility: Visibility) -> Result<ScanResult, FragarachError> {
+    let submission = submit(config, input, visibility).await?;
+    wait_for_result(config, submission).await
+}
+
+/// Scans a domain or URL like `scan_domain` and stores the scan, along with
+/// the host's registration data and DNS records
+///
+/// The submission is stored as `pending` before polling starts, so a billed
+/// scan that doesn't finish in time is still on record.
+pub async fn scan_and_save(
+    config: &Config,
+    input: &str,
+    visibility: Visibility,
+    db: &DatabaseHandle
+) -> Result<ScanOutcome, FragarachError> {
+    let submission = submit(config, input, visibility).await?;
+    record_submission(db, &submission).await?;
+    let domain = submission.domain.clone();
+    let domain = domain.as_str();
+
+    // Registration data and DNS are extras; a failed lookup doesn't stop the scan
+    match whois::lookup(config, domain).await {
+        Ok(record) => whois::save(db, record).await?,
+        Err(e) => warn!(domain, "WHOIS lookup failed: {}", e),
+    }
+    match dns::resolve(config, domain).await {
+        Ok(lookup) => {
+            for failure in &lookup.failures {
+                warn!(domain, "DNS lookup incomplete: {}", failure);
+            }
+            dns::save(db, &lookup).await?;
+        }
+        Err(e) => warn!(domain, "DNS lookup failed: {}", e),
+    }
+
+    let scan = wait_for_result(config, submission).await?;
+    save_result(config, db, &scan).await?;
+    let completed = scan.completed();
+    db.record_query("urlscan scan".to_string(), scan.submission.url.clone(), usize::from(completed)).await?;
+    if completed {
+        info!(domain, uuid = %scan.submission.uuid, "Domain scanned successfully");
+    } else {
+        warn!(domain, uuid = %scan.submission.uuid, "Scan saved as pending");
+    }
+    let virustotal = scan.virustotal.into_iter().find(|report| report.kind == virustotal::ReportKind::Domain);
+    Ok(ScanOutcome { uuid: scan.submission.uuid, completed, virustotal })
+}
+
+/// Stores a scan from `scan_domain` or `fetch_scan`
+///
+/// A scan without a result is stored as `pending`.
+pub async fn save_scan(config: &Config, db: &DatabaseHandle, scan: &ScanResult) -> Result<(), FragarachError> {
+    record_submission(db, &scan.submission).await?;
+    save_result(config, db, scan).await
+}
+
+/// Inserts the domain data row for a scan whose result isn't stored yet
+async fn record_submission(db: &DatabaseHandle, submission: &Submission) -> Result<(), FragarachError> {
+    let submission = submission.clone();
+    let useragent = submission.useragent.unwrap_or("N/A".to_string());
+    let country = submission.country.unwrap_or("N/A".to_string());
+    let case_id = db.active_case();
+    db.call(move |conn| conn.execute(
+        "INSERT OR REPLACE INTO urlscan_domain_data (
+            domain, url, uuid, result_url, api_url, visibility, useragent, country, status, case_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'pending', $9)",
         params![
-            asn,
-            ip,
-            title,
-            &verdict_score,
-            &verdict_brands,
-            uuid
+            submission.domain,
+            submission.url,
+            submission.uuid,
+            submission.result_url,
+            submission.api_url,
+            submission.visibility,
+            useragent,
+            country,
+            case_id
         ]
-    )?;
+    )).await?;
+    Ok(())
+}
+
+/// Writes a DOM snapshot gzip-compressed to `<dir>/<uuid>.html.gz` and returns its path
+fn store_dom_to_file(dir: &Path, uuid: &str, dom: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.html.gz", uuid));
+    let mut encoder = GzEncoder::new(File::create(&path)?, Compression::default());
+    encoder.write_all(dom.as_bytes())?;
+    encoder.finish()?;
+    Ok(path)
+}
 
-    // Download the screenshot from URLScan
-    let screenshot_url = format!("https://urlscan.io/screenshots/{}.png", uuid);
-    let screenshot_resp = client.get(&screenshot_url).send().await?;
-    if !screenshot_resp.status().is_success() {
-        println!("Failed to download screenshot for UUID: {}", uuid);
+/// DOM snapshot saved for a scan, decompressing it if it was stored as a file
+///
+/// Returns `None` when no snapshot was saved for `uuid`.
+pub fn read_dom(conn: &Connection, uuid: &str) -> Result<Option<String>, FragarachError> {
+    let snapshot: Option<(Option<String>, Option<String>)> = conn
+        .query_row(
+            "SELECT dom, dom_path FROM urlscan_dom_snapshot WHERE uuid = $1",
+            params![uuid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    match snapshot {
+        Some((Some(dom), _)) => Ok(Some(dom)),
+        Some((None, Some(path))) => {
+            let mut dom = String::new();
+            GzDecoder::new(File::open(path)?).read_to_string(&mut dom)?;
+            Ok(Some(dom))
+        }
+        _ => Ok(None),
     }
-    let screenshot_bytes = screenshot_resp.bytes().await?;
-    let screenshots_dir = "screenshots";
-    tokio::fs::create_dir_all(screenshots_dir).await?;
-    let screenshot_path = format!("{}/{}.png", screenshots_dir, uuid);
-    tokio::fs::write(&screenshot_path, &screenshot_bytes).await?;
+}
 
-    // Update record with screenshot path
-    conn.execute(
-        "UPDATE urlscan_domain_data SET screenshot_path = $1 WHERE uuid = $2",
-        params![&screenshot_path, uuid]
-    )?;
+/// Result of `compress_stored_doms`
+#[derive(Debug, Default, PartialEq)]
+pub struct DomCompaction {
+    /// Snapshots moved out of the database
+    pub moved: usize,
+    /// Their size in the database, in bytes
+    pub stored_bytes: u64,
+    /// Their size as compressed files, in bytes
+    pub compressed_bytes: u64,
+}
 
-    // Retrieve the DOM snapshot and store it
-    let dom_url = format!("https://urlscan.io/dom/{}/", uuid);
-    let dom_resp = client.get(&dom_url).send().await?;
-    let dom_data = dom_resp.text().await?;
-    let dom_snapshot = if !dom_data.is_empty() {
-        Some(dom_data)
-    } else {
-        None
-    };
+/// Moves DOM snapshots stored in the database to compressed files
+///
+/// Only snapshots `Config::store_dom_inline` would no longer keep inline are
+/// moved, each in its own update so an interruption loses nothing. Snapshots
+/// are read one at a time, so memory stays bounded by the largest one however
+/// large the database. The database file itself only shrinks once DuckDB
+/// reuses or vacuums the space.
+pub fn compress_stored_doms(conn: &Connection, config: &Config) -> Result<DomCompaction, FragarachError> {
+    let snapshots: Vec<(String, u64)> = conn
+        .prepare("SELECT uuid, strlen(dom) FROM urlscan_dom_snapshot WHERE dom IS NOT NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<duckdb::Result<_>>()?;
 
-    // Store DOM snapshot
-    if let Some(dom) = dom_snapshot {
+    let dir = config.dom_dir();
+    let mut compaction = DomCompaction::default();
+    for (uuid, _) in snapshots.into_iter().filter(|(_, len)| !config.store_dom_inline(*len as usize)) {
+        let dom: Option<String> = conn
+            .query_row("SELECT dom FROM urlscan_dom_snapshot WHERE uuid = $1", params![uuid], |row| row.get(0))
+            .optional()?
+            .flatten();
+        // Gone or moved since the list was read
+        let Some(dom) = dom else { continue };
+        let path = store_dom_to_file(&dir, &uuid, &dom)?;
         conn.execute(
-            "INSERT INTO urlscan_dom_snapshot (uuid, dom) VALUES ($1, $2)
-             ON CONFLICT (uuid) DO UPDATE SET dom = $2",
-            params![uuid, dom]
+            "UPDATE urlscan_dom_snapshot SET dom = NULL, dom_path = $1 WHERE uuid = $2",
+            params![path.display().to_string(), uuid],
         )?;
+        compaction.moved += 1;
+        compaction.stored_bytes += dom.len() as u64;
+        compaction.compressed_bytes += fs::metadata(&path)?.len();
+    }
+    info!(moved = compaction.moved, "Compressed stored DOM snapshots");
+    Ok(compaction)
+}
+
+/// Whether a response's `Content-Type` is `image/png`, ignoring any parameters
+fn is_png(headers: &header::HeaderMap) -> bool {
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("image/png"))
+}
+
+/// Downloads the screenshot of a scan
+///
+/// URLScan answers with an error page rather than an image when a scan has no
+/// screenshot, so anything but a successful, non-empty PNG response is an error.
+async fn fetch_screenshot(config: &Config, client: &Client, uuid: &str) -> Result<Vec<u8>, FragarachError> {
+    let url = format!("https://urlscan.io/screenshots/{}.png", uuid);
+    let resp = http::send_with_retry(config, client.get(&url), "Screenshot download").await?;
+    if !resp.status().is_success() {
+        return Err(http::status_error("Screenshot download", resp).await);
+    }
+    if !is_png(resp.headers()) {
+        let content_type = resp.headers().get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("none")
+            .to_string();
+        return Err(FragarachError::UnexpectedResponse(format!("screenshot has content type {}", content_type)));
+    }
+    let bytes = resp.bytes().await.map_err(|e| http::classify(e, "Screenshot download"))?;
+    if bytes.is_empty() {
+        return Err(FragarachError::UnexpectedResponse("screenshot is empty".to_string()));
+    }
+    Ok(bytes.to_vec())
+}
+
+/// Stores a finished scan result: verdicts, page details, screenshot, DOM,
+/// VirusTotal reports and Shodan data
+///
+/// Marks the domain data row of the scan as complete; a scan without a result
+/// is left as it is.
+async fn save_result(config: &Config, db: &DatabaseHandle, scan: &ScanResult) -> Result<(), FragarachError> {
+    let Some(full_scan) = scan.result.clone() else {
+        return Ok(());
+    };
+    let uuid = scan.submission.uuid.clone();
+
+    // Update the domain data record with full scan details
+    let scan_uuid = uuid.clone();
+    let result = full_scan.clone();
+    db.call(move |conn| save_verdicts(conn, &scan_uuid, &result)).await?;
+
+    // Keep the page metadata and outgoing links for querying later
+    let scan_uuid = uuid.clone();
+    db.call(move |conn| save_scan_data(conn, &scan_uuid, &full_scan)).await?;
+
+    if let Some(screenshot_bytes) = &scan.screenshot {
+        let screenshots_dir = config.screenshots_dir();
+        tokio::fs::create_dir_all(&screenshots_dir).await?;
+        let screenshot_path = screenshots_dir.join(format!("{}.png", uuid)).display().to_string();
+        tokio::fs::write(&screenshot_path, screenshot_bytes).await?;
+
+        // Update record with screenshot path, and the image itself so the database stays self-contained
+        let screenshot = config.store_screenshots_in_db().then(|| screenshot_bytes.clone());
+        let scan_uuid = uuid.clone();
+        db.call(move |conn| conn.execute(
+            "UPDATE urlscan_domain_data SET screenshot_path = $1, screenshot = $2 WHERE uuid = $3",
+            params![screenshot_path, screenshot, scan_uuid]
+        )).await?;
+    }
+
+    // Store DOM snapshot, large ones as a compressed file to keep the database small
+    let scan_uuid = uuid.clone();
+    if let Some(dom) = scan.dom.clone() {
+        let (dom, dom_path) = if config.store_dom_inline(dom.len()) {
+            (Some(dom), None)
+        } else {
+            let dir = config.dom_dir();
+            let file_uuid = uuid.clone();
+            let path = tokio::task::spawn_blocking(move || store_dom_to_file(&dir, &file_uuid, &dom))
+                .await
+                .map_err(io::Error::other)??;
+            (None, Some(path.display().to_string()))
+        };
+        db.call(move |conn| conn.execute(
+            "INSERT INTO urlscan_dom_snapshot (uuid, dom, dom_path) VALUES ($1, $2, $3)
+             ON CONFLICT (uuid) DO UPDATE SET dom = $2, dom_path = $3",
+            params![scan_uuid, dom, dom_path]
+        )).await?;
     }
 
-    println!("Domain {} scanned successfully.", domain);
+    for report in &scan.virustotal {
+        virustotal::save_report(db, report.clone()).await?;
+    }
+    if let Some(record) = scan.shodan.clone() {
+        shodan::save(db, record).await?;
+    }
+
+    db.call(move |conn| conn.execute(
+        "UPDATE urlscan_domain_data SET status = 'complete', completed_at = CURRENT_TIMESTAMP WHERE uuid = $1",
+        params![uuid]
+    )).await?;
+
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// A previous scan found by `search_domain`
+#[derive(Debug, PartialEq)]
+pub struct SearchHit {
+    pub uuid: String,
+    pub time: String,
+    pub url: String,
+    pub malicious: Option<bool>,
+}
+
+/// One page of search results, with the cursor for the next page if there is one
+#[derive(Debug, PartialEq)]
+pub struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    pub next: Option<String>,
+}
+
+/// Results requested per search page
+pub const SEARCH_PAGE_SIZE: usize = 20;
+
+fn parse_search(body: &Value) -> SearchPage {
+    let text = |v: &Value, path: &str| v.pointer(path).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let results = body.get("results").and_then(|v| v.as_array()).map(Vec::as_slice).unwrap_or_default();
+
+    let hits = results.iter()
+        .map(|r| SearchHit {
+            uuid: text(r, "/task/uuid"),
+            time: text(r, "/task/time"),
+            url: text(r, "/page/url"),
+            malicious: r.pointer("/verdicts/overall/malicious").and_then(|v| v.as_bool()),
+        })
+        .filter(|hit| !hit.uuid.is_empty())
+        .collect();
+
+    // The next page starts after the sort key of the last result
+    let has_more = body.get("has_more").and_then(|v| v.as_bool()).unwrap_or(false);
+    let next = results.last()
+        .and_then(|r| r.get("sort"))
+        .and_then(|v| v.as_array())
+        .filter(|_| has_more)
+        .map(|sort| sort.iter()
+            .map(|key| key.as_str().map(String::from).unwrap_or_else(|| key.to_string()))
+            .collect::<Vec<_>>()
+            .join(","));
+
+    SearchPage { hits, next }
+}
+
+/// Lists recent scans of `domain` already on urlscan.io, newest first
+///
+/// Pass the previous page's `next` cursor as `search_after` to continue.
+pub async fn search_domain(
+    config: &Config,
+    domain: &str,
+    search_after: Option<&str>,
+) -> Result<SearchPage, FragarachError> {
+    let client = http::client(config)?;
+    let mut request = client.get("https://urlscan.io/api/v1/search/")
+        .query(&[("q", format!("domain:{}", domain)), ("size", SEARCH_PAGE_SIZE.to_string())]);
+    if let Some(cursor) = search_after {
+        request = request.query(&[("search_after", cursor)]);
+    }
+    // Searching works without a key, but a key raises the rate limit
+    if let Some(api_key) = config.urlscan_api_key() {
+        request = request.header("API-Key", api_key);
+    }
+
+    let resp = http::send_with_retry(config, request, "URLScan search").await?;
+    if !resp.status().is_success() {
+        return Err(http::status_error("URLScan search", resp).await);
+    }
+    let body: Value = resp.json().await
+        .map_err(|e| http::classify(e, "Reading URLScan search results"))?;
+    Ok(parse_search(&body))
+}
+
+/// Downloads an existing scan, without using a scan credit or storing anything
+pub async fn fetch_scan(config: &Config, domain: &str, uuid: &str) -> Result<ScanResult, FragarachError> {
+    let client = http::client(config)?;
+    let api_url = format!("https://urlscan.io/api/v1/result/{}/", uuid);
+    let resp = http::send_with_retry(config, client.get(&api_url), "URLScan result download").await?;
+    if !resp.status().is_success() {
+        return Err(http::status_error("URLScan result download", resp).await);
+    }
+    let full_scan: Value = resp.json().await
+        .map_err(|e| http::classify(e, "Reading URLScan result"))?;
+
+    let text = |path: &str| full_scan.pointer(path).and_then(|v| v.as_str()).map(String::from);
+    let submission = Submission {
+        domain: domain.to_string(),
+        url: text("/task/url").unwrap_or_else(|| domain.to_string()),
+        uuid: uuid.to_string(),
+        result_url: text("/task/reportURL").unwrap_or_else(|| format!("https://urlscan.io/result/{}/", uuid)),
+        api_url,
+        visibility: text("/task/visibility").unwrap_or_default(),
+        useragent: text("/task/userAgent"),
+        country: text("/page/country"),
+    };
+    collect(config, &client, submission, full_scan).await
+}
+
+/// Stores an existing scan as if it had just been submitted, without using a scan credit
+pub async fn import_scan(
+    config: &Config,
+    domain: &str,
+    uuid: &str,
+    db: &DatabaseHandle,
+) -> Result<ScanOutcome, FragarachError> {
+    let scan = fetch_scan(config, domain, uuid).await?;
+    save_scan(config, db, &scan).await?;
+
+    info!(domain, uuid, "Imported scan");
+    Ok(ScanOutcome { uuid: uuid.to_string(), completed: true, virustotal: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::migrations;
+    use serde_json::json;
+
+    #[test]
+    fn linked_domains_fall_back_to_request_urls() {
+        let scan = json!({
+            "lists": { "linkDomains": [] },
+            "data": { "requests": [
+                { "request": { "request": { "url": "https://example.com/" } } },
+                { "request": { "request": { "url": "https://cdn.example.net/app.js" } } },
+                { "request": { "request": { "url": "https://example.com/" } } }
+            ] }
+        });
+        assert_eq!(linked_domains(&scan), vec!["https://example.com/", "https://cdn.example.net/app.js"]);
+    }
+
+    #[test]
+    fn only_png_content_counts_as_a_screenshot() {
+        let mut headers = header::HeaderMap::new();
+        assert!(!is_png(&headers));
+        headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("text/html; charset=utf-8"));
+        assert!(!is_png(&headers));
+        headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("Image/PNG; charset=binary"));
+        assert!(is_png(&headers));
+    }
+
+    #[test]
+    fn dom_file_decompresses_to_the_snapshot() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("fragarach-dom-{}", std::process::id()));
+        let dom = "<html><body>".to_string() + &"<p>phish</p>".repeat(1000) + "</body></html>";
+        let path = store_dom_to_file(&dir, "abc", &dom).unwrap();
+        assert_eq!(path, dir.join("abc.html.gz"));
+
+        let compressed = fs::read(&path).unwrap();
+        assert!(compressed.len() < dom.len() / 10);
+        let mut restored = String::new();
+        GzDecoder::new(compressed.as_slice()).read_to_string(&mut restored).unwrap();
+        assert_eq!(restored, dom);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn inline_doms_are_compressed_and_read_back() {
+        let conn = migrations::test_db();
+        let page = include_str!("fixtures/example_com_dom.html");
+        conn.execute(
+            "INSERT INTO urlscan_dom_snapshot (uuid, dom) VALUES ('page', $1), ('small', '<html></html>')",
+            params![page],
+        ).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("fragarach-compress-{}", std::process::id()));
+        let mut config = Config::default();
+        config.set_screenshots_dir(dir.join("screenshots"));
+
+        let compaction = compress_stored_doms(&conn, &config).unwrap();
+        assert_eq!(compaction.moved, 2);
+        assert_eq!(compaction.stored_bytes, (page.len() + "<html></html>".len()) as u64);
+        assert!(dir.join("dom").join("page.html.gz").is_file());
+        assert!(fs::metadata(dir.join("dom").join("page.html.gz")).unwrap().len() < page.len() as u64);
+
+        assert_eq!(read_dom(&conn, "page").unwrap().as_deref(), Some(page));
+        assert_eq!(read_dom(&conn, "small").unwrap().as_deref(), Some("<html></html>"));
+        assert_eq!(read_dom(&conn, "missing").unwrap(), None);
+        assert_eq!(compress_stored_doms(&conn, &config).unwrap().moved, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn search_results_carry_a_cursor_while_more_remain() {
+        let body = json!({
+            "results": [
+                {
+                    "task": { "uuid": "a1", "time": "2026-10-01T10:00:00.000Z" },
+                    "page": { "url": "https://example.com/" },
+                    "verdicts": { "overall": { "malicious": false } },
+                    "sort": [1759312800000_u64, "a1"]
+                },
+                {
+                    "task": { "uuid": "b2", "time": "2026-09-30T08:00:00.000Z" },
+                    "page": { "url": "https://example.com/login" },
+                    "sort": [1759219200000_u64, "b2"]
+                }
+            ],
+            "has_more": true
+        });
+
+        let page = parse_search(&body);
+        assert_eq!(page.hits.len(), 2);
+        assert_eq!(page.hits[0].malicious, Some(false));
+        assert_eq!(page.hits[1].malicious, None);
+        assert_eq!(page.next.as_deref(), Some("1759219200000,b2"));
+
+        let last_page = parse_search(&json!({ "results": body["results"], "has_more": false }));
+        assert_eq!(last_page.next, None);
+    }
+
+    #[test]
+    fn scan_targets_keep_the_url_and_its_host() {
+        let bare = ScanTarget::parse(" Example.COM ").unwrap();
+        assert_eq!(bare, ScanTarget { url: "https://example.com/".to_string(), domain: "example.com".to_string() });
+
+        let full = ScanTarget::parse("http://login.example.com/reset?id=7").unwrap();
+        assert_eq!(full.url, "http://login.example.com/reset?id=7");
+        assert_eq!(full.domain, "login.example.com");
+        assert_eq!(ScanTarget::parse("185.199.110.153/admin").unwrap().domain, "185.199.110.153");
+        let redirect = ScanTarget::parse("evil.com/login?next=https://bank.com").unwrap();
+        assert_eq!((redirect.url.as_str(), redirect.domain.as_str()), ("https://evil.com/login?next=https://bank.com", "evil.com"));
+
+        for invalid in ["", "localhost", "ftp://example.com", "exa mple.com", "https://"] {
+            assert!(matches!(ScanTarget::parse(invalid), Err(FragarachError::InvalidInput(_))), "{invalid:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn scan_data_is_saved_by_uuid() {
+        let conn = migrations::test_db();
+        let scan = json!({
+            "page": { "asn": "AS15169", "ip": "142.250.72.14", "country": "US", "title": "Example Domain" },
+            "lists": { "linkDomains": ["www.iana.org"] }
+        });
+        save_scan_data(&conn, "b5f3e2a1", &scan).unwrap();
+        save_scan_data(&conn, "b5f3e2a1", &scan).unwrap();
+
+        let (links, asn, title): (String, String, String) = conn
+            .query_row(
+                "SELECT data_links, page_asn, page_title FROM urlscan_scan_data WHERE uuid = 'b5f3e2a1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(links, r#"["www.iana.org"]"#);
+        assert_eq!(asn, "AS15169");
+        assert_eq!(title, "Example Domain");
+    }
+
+    #[test]
+    fn fixture_result_fills_every_scan_data_column() {
+        let conn = migrations::test_db();
+        let scan: Value = serde_json::from_str(include_str!("fixtures/urlscan_result.json")).unwrap();
+        save_scan_data(&conn, "b5f3e2a1", &scan).unwrap();
+
+        type Row = (String, String, String, String, String);
+        let (country, ips, links, domains, raw): Row = conn
+            .query_row(
+                "SELECT page_country, resolved_ips, page_links, data_links, raw_result FROM urlscan_scan_data WHERE uuid = 'b5f3e2a1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!(country, "NL");
+        assert_eq!(ips, r#"["185.199.110.153","104.16.85.20"]"#);
+        assert_eq!(links, r#"["https://login-example.com/reset","https://www.microsoft.com/privacy"]"#);
+        assert_eq!(domains, r#"["login-example.com","www.microsoft.com"]"#);
+        assert_eq!(serde_json::from_str::<Value>(&raw).unwrap(), scan);
+    }
+
+    #[test]
+    fn verdicts_are_stored_as_numbers_or_null() {
+        let conn = migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO urlscan_domain_data (domain, uuid) VALUES ('phish.example', 'scored'), ('example.com', 'unscored')"
+        ).unwrap();
+
+        let scored = json!({
+            "page": { "asn": "AS13335", "ip": "104.21.3.7", "title": "Sign in" },
+            "verdicts": { "urlscan": { "score": 100, "brands": ["Microsoft"] } }
+        });
+        let unscored = json!({ "page": {}, "verdicts": { "overall": { "malicious": false } } });
+        save_verdicts(&conn, "scored", &scored).unwrap();
+        save_verdicts(&conn, "unscored", &unscored).unwrap();
+        assert_eq!(verdict_score(&json!({ "verdicts": { "urlscan": { "score": "-50" } } })), Some(-50));
+
+        type Row = (Option<i64>, Option<String>, Option<String>, Option<String>, Option<String>);
+        let row = |uuid: &str| -> Row {
+            conn.query_row(
+                "SELECT verdict_score, verdict_brands, asn, ip, title FROM urlscan_domain_data WHERE uuid = $1",
+                params![uuid],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            ).unwrap()
+        };
+        assert_eq!(row("scored"), (
+            Some(100),
+            Some(r#"["Microsoft"]"#.to_string()),
+            Some("AS13335".to_string()),
+            Some("104.21.3.7".to_string()),
+            Some("Sign in".to_string()),
+        ));
+        assert_eq!(row("unscored"), (None, None, None, None, None));
+    }
+}
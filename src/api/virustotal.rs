@@ -0,0 +1,385 @@
+/// VirusTotal API integration for domain and IP reputation
+///
+/// # Reports
+/// `domain_report` and `ip_report` read the v3 report of a domain or IP address:
+/// - Community reputation score
+/// - Categories, as assigned by each categorisation vendor
+/// - Last analysis stats: how many engines rated it malicious, suspicious,
+///   harmless or left it undetected, summed up as a detection ratio (`7/92`)
+///
+/// `lookup_domain` adds the domain's DNS resolution history: the IP addresses
+/// it resolved to and when, newest first. `lookup_resolutions` adds it to a
+/// report already read, such as one a URLScan scan collected.
+///
+/// # Limits
+/// The free API allows four requests a minute. Every request goes through one
+/// shared `TokenBucket`, queueing callers beyond it; a domain lookup takes two.
+///
+/// # Database Integration
+/// - `save` stores lookups in `virustotal_domain_data`, one row per domain
+/// - `save_report` stores reports alone, such as those URLScan scans collect
+///   (see `urlscan::ScanResult`): domains in `virustotal_domain_data`, keeping
+///   any resolution history already there, and IPs in `virustotal_ip_data`
+///
+/// Both replace the earlier report on each lookup.
+use crate::api::etherscan::utc_timestamp;
+use crate::api::http;
+use crate::api::rate_limit::TokenBucket;
+use crate::api::whois::host_name;
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_writer::DatabaseHandle;
+use duckdb::params;
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::info;
+
+const API_URL: &str = "https://www.virustotal.com/api/v3";
+
+/// Requests the free API allows per minute
+const REQUESTS_PER_MINUTE: u32 = 4;
+
+/// Most resolutions read per lookup
+const MAX_RESOLUTIONS: usize = 40;
+
+/// Paces all VirusTotal requests made by the process
+static REQUESTS: OnceLock<Mutex<TokenBucket>> = OnceLock::new();
+
+/// Waits for a free slot under the free API's requests per minute
+async fn wait_for_request_slot() {
+    let bucket = REQUESTS.get_or_init(|| Mutex::new(
+        TokenBucket::new(REQUESTS_PER_MINUTE, Duration::from_secs(60), Instant::now())
+    ));
+    let wait = bucket.lock().unwrap().take(Instant::now());
+    if !wait.is_zero() {
+        info!("VirusTotal rate limit reached, queued for {:.0}s", wait.as_secs_f64().ceil());
+        sleep(wait).await;
+    }
+}
+
+/// IP address a domain resolved to, and when VirusTotal saw it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Resolution {
+    pub ip_address: String,
+    pub date: Option<String>,
+}
+
+/// What a report is about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportKind {
+    #[default]
+    Domain,
+    Ip,
+}
+
+impl ReportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportKind::Domain => "domain",
+            ReportKind::Ip => "ip",
+        }
+    }
+}
+
+/// VirusTotal's view of one domain or IP address
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub kind: ReportKind,
+    pub target: String,
+    pub reputation: Option<i64>,
+    /// Category given by each vendor, keyed by vendor name
+    pub categories: BTreeMap<String, String>,
+    pub malicious: u64,
+    pub suspicious: u64,
+    pub harmless: u64,
+    pub undetected: u64,
+}
+
+impl Report {
+    /// Engines rating the target malicious out of all that analysed it, e.g. `7/92`
+    pub fn detection_ratio(&self) -> String {
+        let engines = self.malicious + self.suspicious + self.harmless + self.undetected;
+        format!("{}/{}", self.malicious, engines)
+    }
+}
+
+/// A domain's report and resolution history
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DomainReport {
+    #[serde(flatten)]
+    pub report: Report,
+    pub resolutions: Vec<Resolution>,
+}
+
+/// Sends one paced request, returning its JSON body
+///
+/// `Ok(None)` means VirusTotal has nothing on the object asked for.
+async fn get(config: &Config, request: RequestBuilder, operation: &str) -> Result<Option<Value>, FragarachError> {
+    wait_for_request_slot().await;
+    let resp = http::send_with_retry(config, request, operation).await?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        return Err(http::status_error(operation, resp).await);
+    }
+    Ok(Some(resp.json().await?))
+}
+
+/// Fills a report from a v3 domain or IP address object
+fn parse_report(kind: ReportKind, target: &str, body: &Value) -> Report {
+    let attributes = body.pointer("/data/attributes").unwrap_or(&Value::Null);
+    let stat = |name: &str| attributes.pointer(&format!("/last_analysis_stats/{}", name)).and_then(Value::as_u64).unwrap_or(0);
+    let categories = attributes.get("categories")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+        .filter_map(|(vendor, category)| Some((vendor.clone(), category.as_str()?.to_string())))
+        .collect();
+
+    Report {
+        kind,
+        target: target.to_string(),
+        reputation: attributes.get("reputation").and_then(Value::as_i64),
+        categories,
+        malicious: stat("malicious"),
+        suspicious: stat("suspicious"),
+        harmless: stat("harmless"),
+        undetected: stat("undetected"),
+    }
+}
+
+/// Reads the resolutions of a `resolutions` relationship response, newest first
+fn parse_resolutions(body: &Value) -> Vec<Resolution> {
+    let mut resolutions: Vec<(Option<u64>, Resolution)> = body.get("data")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let attributes = entry.get("attributes")?;
+            let seen = attributes.get("date").and_then(Value::as_u64);
+            let ip_address = attributes.get("ip_address")?.as_str()?.to_string();
+            Some((seen, Resolution { ip_address, date: seen.map(utc_timestamp) }))
+        })
+        .collect();
+    resolutions.sort_by_key(|(seen, _)| std::cmp::Reverse(*seen));
+    resolutions.into_iter().map(|(_, resolution)| resolution).collect()
+}
+
+/// Reads the report of a domain or IP address, already normalised
+async fn report(config: &Config, kind: ReportKind, target: &str) -> Result<Report, FragarachError> {
+    let api_key = config.virustotal_api_key().ok_or(FragarachError::MissingApiKey("VirusTotal"))?;
+    let collection = match kind {
+        ReportKind::Domain => "domains",
+        ReportKind::Ip => "ip_addresses",
+    };
+
+    let client = http::client(config)?;
+    let request = client.get(format!("{}/{}/{}", API_URL, collection, target))
+        .header("x-apikey", api_key);
+    let operation = format!("VirusTotal {} report", kind.as_str());
+    let body = get(config, request, &operation).await?
+        .ok_or_else(|| FragarachError::UnexpectedResponse(format!("VirusTotal has no report for {}", target)))?;
+    Ok(parse_report(kind, target, &body))
+}
+
+/// Reads the report of a domain, or of the domain a URL points to
+pub async fn domain_report(config: &Config, domain: &str) -> Result<Report, FragarachError> {
+    let domain = host_name(domain)
+        .ok_or_else(|| FragarachError::InvalidInput(format!("'{}' is not a domain name", domain)))?;
+    report(config, ReportKind::Domain, &domain).await
+}
+
+/// Reads the report of an IPv4 or IPv6 address
+pub async fn ip_report(config: &Config, ip: &str) -> Result<Report, FragarachError> {
+    let ip: IpAddr = ip.trim().trim_matches(['[', ']']).parse()
+        .map_err(|_| FragarachError::InvalidInput(format!("'{}' is not an IP address", ip)))?;
+    report(config, ReportKind::Ip, &ip.to_string()).await
+}
+
+/// Looks up the report and resolution history of a domain, or of the domain a URL points to
+pub async fn lookup_domain(config: &Config, domain: &str) -> Result<DomainReport, FragarachError> {
+    let report = domain_report(config, domain).await?;
+    lookup_resolutions(config, report).await
+}
+
+/// Adds the resolution history of a domain to its report, in one request
+pub async fn lookup_resolutions(config: &Config, report: Report) -> Result<DomainReport, FragarachError> {
+    let api_key = config.virustotal_api_key().ok_or(FragarachError::MissingApiKey("VirusTotal"))?;
+
+    let client = http::client(config)?;
+    let request = client.get(format!("{}/domains/{}/resolutions", API_URL, report.target))
+        .query(&[("limit", MAX_RESOLUTIONS)])
+        .header("x-apikey", api_key);
+    let resolutions = get(config, request, "VirusTotal resolutions").await?
+        .map(|body| parse_resolutions(&body))
+        .unwrap_or_default();
+    Ok(DomainReport { report, resolutions })
+}
+
+/// Confirms the API key is accepted by reading the report of a well-known domain
+pub async fn check_api_key(config: &Config) -> Result<(), FragarachError> {
+    let api_key = config.virustotal_api_key().ok_or(FragarachError::MissingApiKey("VirusTotal"))?;
+
+    let client = http::client(config)?;
+    let request = client.get(format!("{}/domains/virustotal.com", API_URL))
+        .header("x-apikey", api_key);
+    get(config, request, "VirusTotal key check").await?;
+    Ok(())
+}
+
+/// Confirms `api_key` is accepted before it replaces the configured key
+pub async fn validate_api_key(config: &Config, api_key: &str) -> Result<(), FragarachError> {
+    let mut config = config.clone();
+    config.set_virustotal_api_key(Some(api_key.to_string()));
+    check_api_key(&config).await
+}
+
+/// Stores a report without its resolution history
+///
+/// A domain's report replaces the one in `virustotal_domain_data` and keeps
+/// the resolutions of an earlier lookup; an IP's replaces the one in
+/// `virustotal_ip_data`.
+pub async fn save_report(db: &DatabaseHandle, report: Report) -> Result<(), FragarachError> {
+    let case_id = db.active_case();
+    let categories = serde_json::to_string(&report.categories)?;
+    let detection_ratio = report.detection_ratio();
+    let sql = match report.kind {
+        ReportKind::Domain => "INSERT INTO virustotal_domain_data (
+                domain, reputation, malicious, suspicious, harmless, undetected,
+                detection_ratio, categories, case_id, looked_up_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, CURRENT_TIMESTAMP)
+            ON CONFLICT (domain) DO UPDATE SET
+                reputation = excluded.reputation,
+                malicious = excluded.malicious,
+                suspicious = excluded.suspicious,
+                harmless = excluded.harmless,
+                undetected = excluded.undetected,
+                detection_ratio = excluded.detection_ratio,
+                categories = excluded.categories,
+                case_id = excluded.case_id,
+                looked_up_at = excluded.looked_up_at",
+        ReportKind::Ip => "INSERT OR REPLACE INTO virustotal_ip_data (
+                ip, reputation, malicious, suspicious, harmless, undetected,
+                detection_ratio, categories, case_id, looked_up_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, CURRENT_TIMESTAMP)",
+    };
+    db.call(move |conn| conn.execute(
+        sql,
+        params![
+            report.target,
+            report.reputation,
+            report.malicious,
+            report.suspicious,
+            report.harmless,
+            report.undetected,
+            detection_ratio,
+            categories,
+            case_id
+        ]
+    )).await?;
+    Ok(())
+}
+
+/// Stores a lookup in `virustotal_domain_data`, replacing any earlier one for the domain
+pub async fn save(db: &DatabaseHandle, lookup: DomainReport) -> Result<(), FragarachError> {
+    let case_id = db.active_case();
+    let report = lookup.report;
+    let categories = serde_json::to_string(&report.categories)?;
+    let resolutions = serde_json::to_string(&lookup.resolutions)?;
+    let detection_ratio = report.detection_ratio();
+    db.call(move |conn| conn.execute(
+        "INSERT OR REPLACE INTO virustotal_domain_data (
+            domain, reputation, categories, malicious, suspicious, harmless, undetected,
+            detection_ratio, resolutions, case_id, looked_up_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, CURRENT_TIMESTAMP)",
+        params![
+            report.target,
+            report.reputation,
+            categories,
+            report.malicious,
+            report.suspicious,
+            report.harmless,
+            report.undetected,
+            detection_ratio,
+            resolutions,
+            case_id
+        ]
+    )).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reports_keep_stats_categories_and_newest_resolutions_first() {
+        let body = json!({
+            "data": {
+                "id": "phish.example",
+                "type": "domain",
+                "attributes": {
+                    "reputation": -12,
+                    "categories": { "Forcepoint ThreatSeeker": "phishing", "Sophos": "phishing and fraud" },
+                    "last_analysis_stats": { "malicious": 7, "suspicious": 1, "harmless": 60, "undetected": 24, "timeout": 0 }
+                }
+            }
+        });
+        let report = parse_report(ReportKind::Domain, "phish.example", &body);
+        assert_eq!(report.reputation, Some(-12));
+        assert_eq!(report.detection_ratio(), "7/92");
+        assert_eq!(report.categories.get("Sophos").map(String::as_str), Some("phishing and fraud"));
+        assert_eq!((report.malicious, report.suspicious, report.harmless, report.undetected), (7, 1, 60, 24));
+
+        let resolutions = json!({
+            "data": [
+                { "attributes": { "ip_address": "192.0.2.10", "date": 1_700_000_000 } },
+                { "attributes": { "ip_address": "198.51.100.7", "date": 1_710_000_000 } },
+                { "attributes": { "date": 1_720_000_000 } }
+            ]
+        });
+        assert_eq!(parse_resolutions(&resolutions), vec![
+            Resolution { ip_address: "198.51.100.7".to_string(), date: Some("2024-03-09T16:00:00Z".to_string()) },
+            Resolution { ip_address: "192.0.2.10".to_string(), date: Some("2023-11-14T22:13:20Z".to_string()) },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn scan_reports_keep_the_resolution_history_of_a_lookup() {
+        use crate::helpers::database_writer::DatabaseWriter;
+        use crate::helpers::migrations;
+
+        let writer = DatabaseWriter::spawn(migrations::test_db(), 8);
+        let db = writer.handle();
+        let domain = Report { target: "phish.example".to_string(), malicious: 1, harmless: 9, ..Report::default() };
+        let resolutions = vec![Resolution { ip_address: "192.0.2.10".to_string(), date: None }];
+        save(&db, DomainReport { report: domain.clone(), resolutions }).await.unwrap();
+
+        save_report(&db, Report { malicious: 4, harmless: 6, ..domain }).await.unwrap();
+        save_report(&db, Report { kind: ReportKind::Ip, target: "192.0.2.10".to_string(), harmless: 10, ..Report::default() }).await.unwrap();
+
+        let (ratio, resolutions): (String, String) = db.call(|conn| conn.query_row(
+            "SELECT detection_ratio, resolutions FROM virustotal_domain_data WHERE domain = 'phish.example'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )).await.unwrap();
+        assert_eq!(ratio, "4/10");
+        assert!(resolutions.contains("192.0.2.10"));
+        let ratio: String = db.call(|conn| conn.query_row(
+            "SELECT detection_ratio FROM virustotal_ip_data WHERE ip = '192.0.2.10'",
+            [],
+            |row| row.get(0),
+        )).await.unwrap();
+        assert_eq!(ratio, "0/10");
+        writer.shutdown().await.unwrap();
+    }
+}
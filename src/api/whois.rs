@@ -0,0 +1,404 @@
+/// Domain registration lookups, over RDAP with a WHOIS fallback
+///
+/// # Lookup
+/// `lookup` asks RDAP first (see `rdap`). For domains RDAP has nothing on,
+/// mostly those under TLDs without an RDAP service, it falls back to a WHOIS
+/// query on port 43: IANA's WHOIS server names the registry server for the
+/// TLD, which is then asked for the domain and, if it doesn't know it, for its
+/// parent domains. Internationalized names are punycoded (`bücher.example` →
+/// `xn--bcher-kva.example`) before either lookup.
+///
+/// # Fields
+/// - Registrar, registrant name and registrant country
+/// - Registration, expiry and last-changed dates, as the text the registry reports
+/// - Nameservers, lowercased and deduplicated
+/// - The raw RDAP JSON or WHOIS text, kept whatever could be parsed from it
+///
+/// WHOIS output has no common format, so fields are read from whichever of the
+/// usual labels (`Creation Date`, `created`, `Registered on`, `[Name Server]`,
+/// ...) a registry uses, including values indented under a label of their own.
+/// Registries redact registrant details for privacy-protected domains; redacted
+/// and missing fields are kept as NULL rather than failing the lookup.
+///
+/// # Limits
+/// WHOIS queries are paced to one a second across the process, and the server
+/// of each TLD is asked of IANA once per run.
+///
+/// # Database Integration
+/// Records are stored in `domain_whois`, one row per domain, replaced on each lookup.
+use crate::api::rate_limit::RateLimiter;
+use crate::api::{http, rdap};
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_writer::DatabaseHandle;
+use duckdb::params;
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+/// IANA's WHOIS server, which names the registry server of each TLD
+const IANA_WHOIS: &str = "whois.iana.org";
+
+const WHOIS_PORT: u16 = 43;
+
+/// Most of a WHOIS response read; real ones are a few kilobytes
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+/// Values registries put in place of redacted contact details
+const REDACTION_MARKERS: &[&str] = &["redacted", "withheld", "not disclosed", "data protected", "privacy"];
+
+/// WHOIS labels of each field, lowercased, most specific first
+const REGISTRAR_LABELS: &[&str] = &[
+    "registrar", "registrar name", "sponsoring registrar", "registrar organization", "registrar organisation",
+];
+const REGISTRANT_LABELS: &[&str] = &[
+    "registrant organization", "registrant organisation", "registrant name", "registrant", "org-name", "holder", "owner",
+];
+const COUNTRY_LABELS: &[&str] = &[
+    "registrant country", "registrant country code", "registrant country/economy", "country",
+];
+const REGISTERED_LABELS: &[&str] = &[
+    "creation date", "created", "created on", "created date", "registration date", "registration time",
+    "registered", "registered on", "registered date", "domain registration date", "domain record activated",
+];
+const EXPIRES_LABELS: &[&str] = &[
+    "registry expiry date", "registrar registration expiration date", "expiration date", "expiry date",
+    "expiration time", "expires", "expires on", "expire date", "paid-till", "renewal date",
+];
+const UPDATED_LABELS: &[&str] = &[
+    "updated date", "last updated", "last modified", "last update", "last-update", "changed", "modified", "updated",
+];
+const NAMESERVER_LABELS: &[&str] = &["name server", "name servers", "nameserver", "nameservers", "nserver", "dns"];
+
+/// Paces all WHOIS queries made by the process
+static LIMITER: OnceLock<Mutex<RateLimiter>> = OnceLock::new();
+
+/// WHOIS server of each TLD looked up so far, `None` for TLDs without one
+static SERVERS: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+
+fn limiter() -> &'static Mutex<RateLimiter> {
+    LIMITER.get_or_init(|| Mutex::new(RateLimiter::new(Duration::from_secs(1))))
+}
+
+/// Registration data of one domain
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WhoisRecord {
+    pub domain: String,
+    pub registrar: Option<String>,
+    pub registrant: Option<String>,
+    pub registrant_country: Option<String>,
+    pub registered: Option<String>,
+    pub expires: Option<String>,
+    pub updated: Option<String>,
+    pub nameservers: Vec<String>,
+    /// Where the record came from, `rdap` or `whois`
+    pub source: &'static str,
+    /// Response the record was read from
+    #[serde(skip)]
+    pub raw: String,
+}
+
+impl WhoisRecord {
+    /// Whether any registration data was found
+    fn is_found(&self) -> bool {
+        self.registrar.is_some() || self.registered.is_some() || self.expires.is_some() || !self.nameservers.is_empty()
+    }
+}
+
+/// Host name of a domain or URL, lowercased, punycoded and without a trailing dot
+pub fn host_name(input: &str) -> Option<String> {
+    let input = input.trim();
+    let url = if http::has_scheme(input) { Url::parse(input) } else { Url::parse(&format!("http://{}", input)) };
+    let host = url.ok()?.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+    host.contains('.').then_some(host)
+}
+
+/// `value`, unless it is empty or a redaction placeholder
+pub(crate) fn disclosed(value: &str) -> Option<String> {
+    let value = value.trim();
+    let lower = value.to_ascii_lowercase();
+    if value.is_empty() || REDACTION_MARKERS.iter().any(|marker| lower.contains(marker)) {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Whether `label` is one a record field is read from
+fn is_field_label(label: &str) -> bool {
+    let label = label.trim().to_ascii_lowercase();
+    [REGISTRAR_LABELS, REGISTRANT_LABELS, COUNTRY_LABELS, REGISTERED_LABELS, EXPIRES_LABELS, UPDATED_LABELS, NAMESERVER_LABELS]
+        .iter()
+        .any(|labels| labels.contains(&label.as_str()))
+}
+
+/// Label and value pairs of a WHOIS response, labels lowercased
+///
+/// Reads `Label: value` and `[Label] value` lines. Lines indented under a
+/// label with no value of its own are values of that label, unless they are
+/// a labelled field themselves. Comments and
+/// everything from the `>>> Last update` footer on are skipped.
+fn whois_fields(text: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut open_label: Option<(String, usize)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with(">>>") {
+            break;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('%') || trimmed.starts_with('#') {
+            open_label = None;
+            continue;
+        }
+
+        let pair = match trimmed.strip_prefix('[') {
+            Some(rest) => rest.split_once(']'),
+            None => trimmed.split_once(':'),
+        };
+        let indent = line.len() - line.trim_start().len();
+        if let Some((label, label_indent)) = &open_label {
+            // Indented lines are values, unless they carry a field label of their own
+            let own_field = pair.is_some_and(|(label, value)| !value.trim().is_empty() && is_field_label(label));
+            if indent > *label_indent && !own_field {
+                fields.push((label.clone(), trimmed.to_string()));
+                continue;
+            }
+            if indent <= *label_indent {
+                open_label = None;
+            }
+        }
+
+        let Some((label, value)) = pair else { continue };
+        let label = label.trim().to_ascii_lowercase();
+        let value = value.trim();
+        if value.is_empty() {
+            open_label = Some((label, indent));
+        } else {
+            fields.push((label, value.to_string()));
+        }
+    }
+    fields
+}
+
+/// First disclosed value of the most specific of `labels` present
+fn first_value(fields: &[(String, String)], labels: &[&str]) -> Option<String> {
+    labels.iter().find_map(|wanted| {
+        fields.iter()
+            .filter(|(label, _)| label == wanted)
+            .find_map(|(_, value)| disclosed(value))
+    })
+}
+
+/// Builds a record from a WHOIS response, keeping whatever fields it has
+fn parse_whois(domain: &str, raw: String) -> WhoisRecord {
+    let fields = whois_fields(&raw);
+    let mut nameservers: Vec<String> = Vec::new();
+    for (_, value) in fields.iter().filter(|(label, _)| NAMESERVER_LABELS.contains(&label.as_str())) {
+        // Some registries follow the name with its addresses
+        let Some(name) = value.split_whitespace().next() else { continue };
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        if name.contains('.') && !nameservers.contains(&name) {
+            nameservers.push(name);
+        }
+    }
+
+    WhoisRecord {
+        domain: domain.to_string(),
+        registrar: first_value(&fields, REGISTRAR_LABELS),
+        registrant: first_value(&fields, REGISTRANT_LABELS),
+        registrant_country: first_value(&fields, COUNTRY_LABELS),
+        registered: first_value(&fields, REGISTERED_LABELS),
+        expires: first_value(&fields, EXPIRES_LABELS),
+        updated: first_value(&fields, UPDATED_LABELS),
+        nameservers,
+        source: "whois",
+        raw,
+    }
+}
+
+/// Registry WHOIS server named in IANA's answer for a TLD
+fn referral(iana: &str) -> Option<String> {
+    whois_fields(iana).into_iter()
+        .find(|(label, _)| label == "whois" || label == "refer")
+        .map(|(_, server)| server.to_ascii_lowercase())
+}
+
+/// Sends one paced WHOIS query and reads the whole response
+async fn query(config: &Config, server: &str, query: &str) -> Result<String, FragarachError> {
+    {
+        let mut limiter = limiter().lock().await;
+        limiter.wait().await;
+        limiter.start(Instant::now());
+    }
+    let operation = format!("WHOIS query to {}", server);
+    let exchange = async {
+        let mut stream = tokio::time::timeout(config.http_connect_timeout(), TcpStream::connect((server, WHOIS_PORT)))
+            .await
+            .map_err(|_| FragarachError::Timeout { operation: operation.clone() })??;
+        stream.write_all(format!("{}\r\n", query).as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.take(MAX_RESPONSE_BYTES).read_to_end(&mut response).await?;
+        Ok::<_, FragarachError>(String::from_utf8_lossy(&response).into_owned())
+    };
+    tokio::time::timeout(config.http_timeout(), exchange)
+        .await
+        .map_err(|_| FragarachError::Timeout { operation })?
+}
+
+/// WHOIS server of `tld`, asking IANA the first time
+async fn whois_server(config: &Config, tld: &str) -> Result<Option<String>, FragarachError> {
+    let servers = SERVERS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(server) = servers.lock().await.get(tld) {
+        return Ok(server.clone());
+    }
+    let server = referral(&query(config, IANA_WHOIS, tld).await?);
+    servers.lock().await.insert(tld.to_string(), server.clone());
+    Ok(server)
+}
+
+/// Looks up `host` on its TLD's WHOIS server, then its parent domains
+///
+/// Returns `None` when no candidate had registration data.
+async fn lookup_whois(config: &Config, host: &str) -> Result<Option<WhoisRecord>, FragarachError> {
+    let tld = host.rsplit('.').next().unwrap_or(host);
+    let server = whois_server(config, tld).await?
+        .ok_or_else(|| FragarachError::UnexpectedResponse(format!("no RDAP or WHOIS service is known for .{}", tld)))?;
+
+    let mut candidate = host;
+    loop {
+        let record = parse_whois(candidate, query(config, &server, candidate).await?);
+        if record.is_found() {
+            return Ok(Some(record));
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => candidate = parent,
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Looks up the registration data of a domain, or of the domain a URL points to
+pub async fn lookup(config: &Config, domain: &str) -> Result<WhoisRecord, FragarachError> {
+    let host = host_name(domain)
+        .ok_or_else(|| FragarachError::InvalidInput(format!("'{}' is not a domain name", domain)))?;
+    if let Some(record) = rdap::lookup(config, &host).await? {
+        return Ok(record);
+    }
+
+    debug!(%host, "No RDAP data, falling back to WHOIS");
+    lookup_whois(config, &host).await?
+        .ok_or_else(|| FragarachError::UnexpectedResponse(format!("no registration data found for {}", host)))
+}
+
+/// Stores a lookup in `domain_whois`, replacing any earlier one for the domain
+pub async fn save(db: &DatabaseHandle, record: WhoisRecord) -> Result<(), FragarachError> {
+    let case_id = db.active_case();
+    let nameservers = Value::from(record.nameservers).to_string();
+    db.call(move |conn| conn.execute(
+        "INSERT OR REPLACE INTO domain_whois (
+            domain, registrar, registrant, registrant_country, registered, expires, updated,
+            nameservers, source, raw, case_id, looked_up_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, CURRENT_TIMESTAMP)",
+        params![
+            record.domain,
+            record.registrar,
+            record.registrant,
+            record.registrant_country,
+            record.registered,
+            record.expires,
+            record.updated,
+            nameservers,
+            record.source,
+            record.raw,
+            case_id
+        ]
+    )).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_names_come_from_domains_and_urls() {
+        assert_eq!(host_name("Example.COM.").as_deref(), Some("example.com"));
+        assert_eq!(host_name("https://www.example.co.uk:8443/login?x=1").as_deref(), Some("www.example.co.uk"));
+        assert_eq!(host_name("https://Bücher.example/shop").as_deref(), Some("xn--bcher-kva.example"));
+        assert_eq!(host_name("evil.com/login?next=https://bank.com").as_deref(), Some("evil.com"));
+        assert_eq!(host_name("localhost"), None);
+    }
+
+    #[test]
+    fn whois_fields_are_read_from_any_layout() {
+        let gtld = "Domain Name: EXAMPLE.IO\r
+Registry Expiry Date: 2027-08-13T04:00:00Z\r
+Registrar: Example Registrar, Inc.\r
+Registrant Organization: REDACTED FOR PRIVACY\r
+Registrant Country: NL\r
+Creation Date: 1995-08-14T04:00:00Z\r
+Name Server: NS1.EXAMPLE.NET 192.0.2.1\r
+Name Server: ns2.example.net.\r
+Name Server: ns1.example.net\r
+>>> Last update of WHOIS database: 2026-10-17T00:00:00Z <<<\r
+Registrar: Not This One\r
+";
+        assert_eq!(parse_whois("example.io", gtld.to_string()), WhoisRecord {
+            domain: "example.io".to_string(),
+            registrar: Some("Example Registrar, Inc.".to_string()),
+            registrant: None,
+            registrant_country: Some("NL".to_string()),
+            registered: Some("1995-08-14T04:00:00Z".to_string()),
+            expires: Some("2027-08-13T04:00:00Z".to_string()),
+            updated: None,
+            nameservers: vec!["ns1.example.net".to_string(), "ns2.example.net".to_string()],
+            source: "whois",
+            raw: gtld.to_string(),
+        });
+
+        let indented = "
+    Domain name:
+        example.co.uk
+
+    Registrar:
+        Example Registrar Ltd [Tag = EXAMPLE]
+        URL: https://registrar.example
+
+    Relevant dates:
+        Registered on: 14-Aug-1995
+        Expiry date:  14-Aug-2027
+
+    Name servers:
+        ns1.example.net
+        ns2.example.net
+
+% Comment
+";
+        let record = parse_whois("example.co.uk", indented.to_string());
+        assert_eq!(record.registrar.as_deref(), Some("Example Registrar Ltd [Tag = EXAMPLE]"));
+        assert_eq!(record.registered.as_deref(), Some("14-Aug-1995"));
+        assert_eq!(record.expires.as_deref(), Some("14-Aug-2027"));
+        assert_eq!(record.nameservers, ["ns1.example.net", "ns2.example.net"]);
+        assert!(record.is_found());
+
+        let bracketed = "[Domain Name]                   EXAMPLE.JP\n[Name Server]                   ns1.example.jp\n[Created on]                    2001/02/03\n";
+        let record = parse_whois("example.jp", bracketed.to_string());
+        assert_eq!((record.registered.as_deref(), record.nameservers.len()), (Some("2001/02/03"), 1));
+
+        assert!(!parse_whois("missing.io", "NOT FOUND\n".to_string()).is_found());
+    }
+
+    #[test]
+    fn iana_answers_name_the_registry_server() {
+        let iana = "% IANA WHOIS server\n\ndomain:       IO\n\nwhois:        whois.nic.io\n\nstatus:       ACTIVE\n";
+        assert_eq!(referral(iana).as_deref(), Some("whois.nic.io"));
+        assert_eq!(referral("domain:       XN--EXAMPLE\n\nstatus:       ACTIVE\n"), None);
+    }
+}
@@ -0,0 +1,235 @@
+/// Command-line arguments for non-interactive use
+///
+/// Running without a subcommand launches the interactive menu.
+use crate::api::source::DataSource;
+use crate::api::transpose::Chain;
+use crate::api::urlscan::Visibility;
+use crate::helpers::export::ExportFormat;
+use crate::helpers::watchlist::WatchKind;
+use clap::{Parser, Subcommand};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// How subcommands report their results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Progress lines for people
+    Text,
+    /// JSON on stdout for other tools
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format '{}' (expected text or json)", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        })
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "fragarach", version, about)]
+pub struct Args {
+    /// Serve HTTP responses from the local cache and record misses
+    #[arg(long, global = true)]
+    pub cached: bool,
+
+    /// DuckDB database file, overriding FRAGARACH_DB_PATH
+    #[arg(long, global = true, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Screenshot directory, overriding FRAGARACH_SCREENSHOTS_DIR
+    #[arg(long, global = true, value_name = "DIR")]
+    pub screenshots_dir: Option<PathBuf>,
+
+    /// Save API keys entered in the menu without testing them, e.g. for offline setup
+    #[arg(long, global = true)]
+    pub skip_key_check: bool,
+
+    /// Skip the animated startup banner
+    #[arg(long, global = true)]
+    pub no_banner: bool,
+
+    /// Print without colors or animations (also set by NO_COLOR)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Log more detail on stderr: -v for debug, -vv for trace (see also FRAGARACH_LOG)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Also append log events to this file, overriding FRAGARACH_LOG_FILE
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// text, or json to print subcommand results as JSON on stdout
+    #[arg(long, global = true, default_value = "text")]
+    pub output: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Query and save Ethereum account details
+    Account {
+        address: String,
+        /// ethereum, polygon or goerli
+        #[arg(long, default_value = "ethereum")]
+        chain: Chain,
+        /// transpose or etherscan (default: Transpose if its key is set, else Etherscan)
+        #[arg(long)]
+        source: Option<DataSource>,
+    },
+    /// Query and save Ethereum transactions
+    Transactions {
+        /// Addresses to query, fetched concurrently
+        #[arg(required = true)]
+        addresses: Vec<String>,
+        /// ethereum, polygon or goerli
+        #[arg(long, default_value = "ethereum")]
+        chain: Chain,
+        /// Stop after this many transactions
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Only transactions on or after this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        from: Option<String>,
+        /// Only transactions on or before this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        to: Option<String>,
+        /// Only transactions in or after this block
+        #[arg(long)]
+        from_block: Option<u64>,
+        /// Only transactions in or before this block
+        #[arg(long)]
+        to_block: Option<u64>,
+        /// Only fetch transactions newer than each address's last complete pull
+        #[arg(long, conflicts_with_all = ["from", "to", "from_block", "to_block"])]
+        incremental: bool,
+        /// transpose or etherscan (default: Transpose if its key is set, else Etherscan)
+        #[arg(long)]
+        source: Option<DataSource>,
+        /// Pull even if the estimate is above FRAGARACH_CONFIRM_REQUESTS requests
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Query and save an address's account, transactions and token transfers, and summarize them
+    Profile {
+        address: String,
+        /// ethereum, polygon or goerli
+        #[arg(long, default_value = "ethereum")]
+        chain: Chain,
+        /// Stop after this many transactions, and as many token transfers
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Scan domains with URLScan and save the results
+    Scan {
+        /// Domains or URLs to scan, in order
+        #[arg(required_unless_present = "input")]
+        domains: Vec<String>,
+        /// File with one domain or URL per line, scanned after any given as arguments
+        #[arg(long, value_name = "FILE")]
+        input: Option<PathBuf>,
+        /// public, unlisted or private (default: the configured visibility)
+        #[arg(long)]
+        visibility: Option<Visibility>,
+        /// Scan even if there are more domains than FRAGARACH_CONFIRM_REQUESTS or
+        /// submissions left in today's quota
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Write a table to a local file
+    Export {
+        table: String,
+        /// parquet, csv or json
+        #[arg(long, default_value = "csv")]
+        format: ExportFormat,
+        /// Only rows for this address (Ethereum tables) or domain (URLScan tables)
+        #[arg(long)]
+        filter: Option<String>,
+        /// Only records in this investigation case
+        #[arg(long)]
+        case: Option<String>,
+        /// Output file (default: <exports dir>/<table>.<format>)
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Replace the output file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write the screenshot stored for a URLScan scan to a PNG file
+    ExtractScreenshot {
+        uuid: String,
+        out: PathBuf,
+    },
+    /// Move DOM snapshots stored in the database to compressed files
+    CompressDoms,
+    /// Manage and run the watchlist of addresses and domains
+    Watchlist {
+        #[command(subcommand)]
+        action: WatchlistAction,
+    },
+    /// Manage the HTTP response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Read newline-delimited JSON commands from stdin and answer on stdout
+    Pipe,
+    /// Print the version; with --check, exit 1 if a newer release exists
+    Version {
+        /// Ask GitHub for the latest release
+        #[arg(long)]
+        check: bool,
+    },
+    /// Check the database, APIs and disk space; exits non-zero on failure
+    Health {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WatchlistAction {
+    /// Watch an address (or ENS name) or a domain, in the active case
+    Add {
+        /// address or domain
+        kind: WatchKind,
+        value: String,
+        /// Chain of a watched address: ethereum, polygon or goerli
+        #[arg(long, default_value = "ethereum")]
+        chain: Chain,
+    },
+    /// Stop watching an address or domain
+    Remove {
+        value: String,
+    },
+    /// List the watched items of the active case
+    List,
+    /// Re-check every watched item of the active case and summarize what changed
+    Run,
+}
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Delete every cached response
+    Purge,
+}
@@ -0,0 +1,404 @@
+/// Non-interactive subcommands for scripts, cron jobs and CI
+///
+/// # Commands
+/// - `account <address> [--chain C] [--source S]`: account details
+/// - `transactions <address>... [--chain C] [--limit N] [--from D] [--to D] [--from-block B] [--to-block B] [--incremental] [--source S] [--yes]`:
+///   transaction history, optionally within a date or block range or only
+///   what is new since each address's last complete pull, with
+///   several addresses fetched concurrently and `--limit` capping the total; the
+///   remaining addresses are still fetched when one fails, and addresses cut off by
+///   the limit are reported as truncated. Transpose pulls are estimated first and
+///   refused above `FRAGARACH_CONFIRM_REQUESTS` requests unless `--yes` is given
+/// - `profile <address> [--chain C] [--limit N]`: account, transactions and token
+///   transfers together, with a summary of account type, creation and activity
+///
+/// Addresses may be given as ENS names (`vitalik.eth`), which are resolved first.
+/// `account` and `transactions` read from Transpose, or from Etherscan with
+/// `--source etherscan` or when only an Etherscan key is set.
+/// - `scan <domain>... [--input FILE] [--visibility V] [--yes]`: URLScan domain scans, submitted at
+///   the configured rate; the remaining domains are still scanned when one fails.
+///   More domains than submissions left today or than `FRAGARACH_CONFIRM_REQUESTS`
+///   are refused unless `--yes` is given
+/// - `export <table> [--format F] [--filter VALUE] [--case NAME] [--out PATH] [--force]`: table export to a local file
+/// - `extract-screenshot <uuid> <out>`: stored URLScan screenshot to a PNG file
+/// - `compress-doms`: DOM snapshots kept in the database moved to compressed files
+/// - `watchlist add <address|domain> <value> [--chain C]`, `watchlist remove <value>`,
+///   `watchlist list` and `watchlist run`: the watchlist of the active case; `run`
+///   re-checks every item and fails if any item could not be checked
+///
+/// Query results are persisted to DuckDB exactly as in the interactive menu, in
+/// the case named by `FRAGARACH_CASE` or the last case chosen in the menu. Any API
+/// or database failure is returned as an error so the process exits non-zero.
+///
+/// With `--output json`, progress lines are dropped and stdout carries one JSON
+/// document per line instead: the account rows as an array, an object per
+/// address pulled, domain scanned or watchlist item checked (failures carry an
+/// `error`), and an object describing each written file. Errors still go to stderr.
+use super::args::{Command, OutputFormat, WatchlistAction};
+use super::watchlist::{self, Change};
+use super::{incremental_range, print_profile, print_tags, pull_transactions, resolve_address};
+use crate::api::source::DataSource;
+use crate::api::{transpose, urlscan};
+use crate::api::transpose::{TransactionPullEstimate, TransactionRange};
+use crate::config::{paths, Config};
+use crate::error::FragarachError;
+use crate::helpers::database_operations::{self, EntityType, Filter};
+use crate::helpers::database_writer::DatabaseHandle;
+use crate::helpers::export;
+use crate::output;
+use colored::*;
+use serde_json::{json, Value};
+
+/// Prints one JSON document on its own line of stdout
+fn emit(value: Value) {
+    println!("{}", value);
+}
+
+/// Stored row of a URLScan scan, without the screenshot image
+async fn scan_record(db: &DatabaseHandle, uuid: String) -> Result<Value, FragarachError> {
+    let mut records = db.call(move |conn| {
+        database_operations::query_records(conn, "urlscan_domain_data", &[Filter::Equals("uuid", &uuid)], Some(1))
+    }).await?;
+    let mut record = records.pop().unwrap_or_else(|| json!({}));
+    if let Some(fields) = record.as_object_mut() {
+        fields.remove("screenshot");
+    }
+    Ok(record)
+}
+
+/// Runs a headless subcommand to completion
+pub async fn run(
+    config: &Config,
+    db: &DatabaseHandle,
+    command: &Command,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let json = format == OutputFormat::Json;
+    match command {
+        Command::Account { address, chain, source } => {
+            let source = DataSource::pick(config, *source)?;
+            let address = &resolve_address(config, db, address).await?;
+            print_tags(db, EntityType::Address, address).await?;
+
+            output::status(format!("[Step 1] Querying {} account details from {}", chain, source).yellow());
+            let account_data = source.query_account(config, *chain, address).await?;
+
+            output::status("[Step 2] Saving data to database".yellow());
+            let records = account_data.len();
+            if json {
+                emit(Value::Array(account_data.clone()));
+            }
+            db.save_records(account_data, "ethereum_accounts").await?;
+            db.record_query(format!("{} account", chain), address.clone(), records).await?;
+            output::status(format!("✔ Saved account data for address {}", address).bright_green());
+        }
+        Command::Transactions { addresses, chain, limit, from, to, from_block, to_block, incremental, source, yes } => {
+            let range = TransactionRange::new(from.as_deref(), to.as_deref(), *from_block, *to_block)?;
+            let source = DataSource::pick(config, *source)?;
+
+            let mut failed = 0;
+            let mut resolved = Vec::new();
+            for address in addresses {
+                match resolve_address(config, db, address).await {
+                    Ok(address) => resolved.push(address),
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "✘ Skipping".bright_red(), address, e);
+                        if json {
+                            emit(json!({ "address": address, "chain": chain, "fetched": 0, "error": e.to_string() }));
+                        }
+                        failed += 1;
+                    }
+                }
+            }
+
+            // Etherscan can't count transactions ahead of a pull, and charges no credits
+            if source == DataSource::Transpose && !resolved.is_empty() {
+                output::status("[Step 0] Estimating cost".yellow());
+                let estimate = if *incremental {
+                    // Incremental pulls run one address at a time, each up to the limit
+                    let mut estimate = TransactionPullEstimate::default();
+                    for address in &resolved {
+                        let (_, range) = incremental_range(db, *chain, address).await?;
+                        estimate += transpose::estimate_transaction_pull(config, *chain, std::slice::from_ref(address), &range, *limit).await?;
+                    }
+                    estimate
+                } else {
+                    transpose::estimate_transaction_pull(config, *chain, &resolved, &range, *limit).await?
+                };
+                if estimate.fetched < estimate.transaction_count {
+                    output::status(format!("├─ Transactions: {} ({} within --limit)", estimate.transaction_count, estimate.fetched));
+                } else {
+                    output::status(format!("├─ Transactions: {}", estimate.transaction_count));
+                }
+                output::status(format!("├─ Requests: {}", estimate.requests));
+                output::status(format!("├─ Credits: {}", estimate.credits));
+                output::status(format!("└─ Estimated time: {}s", estimate.duration.as_secs()));
+
+                if estimate.requests > config.confirm_request_threshold() && !yes {
+                    return Err(format!(
+                        "This pull needs {} requests, more than FRAGARACH_CONFIRM_REQUESTS ({}); pass --yes to run it anyway",
+                        estimate.requests, config.confirm_request_threshold()
+                    ).into());
+                }
+            }
+
+            // Each address resumes from its own sync mark, so incremental pulls go one at a time
+            let (pulls, unique, new) = if *incremental {
+                let (mut pulls, mut unique, mut new) = (Vec::new(), 0, 0);
+                for (i, address) in resolved.iter().enumerate() {
+                    let (_, range) = incremental_range(db, *chain, address).await?;
+                    output::status(format!("[{}/{}] Syncing {} transactions ({}) for {}", i + 1, resolved.len(), chain, range, address).yellow());
+                    let pull = pull_transactions(config, db, source, *chain, std::slice::from_ref(address), *limit, &range).await?;
+                    pulls.extend(pull.pulls);
+                    unique += pull.unique;
+                    new += pull.new;
+                }
+                (pulls, unique, new)
+            } else {
+                output::status(format!("[Step 1] Fetching and saving {} transactions ({}) for {} addresses from {}", chain, range, resolved.len(), source).yellow());
+                let pull = pull_transactions(config, db, source, *chain, &resolved, *limit, &range).await?;
+                (pull.pulls, pull.unique, pull.new)
+            };
+            for pull in pulls {
+                if json {
+                    emit(json!({
+                        "address": pull.address,
+                        "chain": chain,
+                        "fetched": pull.fetched,
+                        "truncated": pull.truncated,
+                        "error": pull.error.as_ref().map(|e| e.to_string()),
+                    }));
+                }
+                match pull.error {
+                    None if pull.truncated => output::status(format!(
+                        "✔ Saved {} transactions for address {}; --limit reached, so older ones may be missing",
+                        pull.fetched, pull.address
+                    ).yellow()),
+                    None => output::status(format!("✔ Saved {} transactions for address {}", pull.fetched, pull.address).bright_green()),
+                    Some(e) => {
+                        eprintln!("{} {} after {} transactions: {}", "✘ Stopped fetching".bright_red(), pull.address, pull.fetched, e);
+                        failed += 1;
+                    }
+                }
+            }
+            if resolved.len() > 1 {
+                output::status(format!("✔ {} distinct transactions saved in total", unique).bright_green());
+            }
+            output::status(format!("✔ {} transactions were not stored before", new).bright_green());
+            if failed > 0 {
+                return Err(format!("{} of {} addresses failed", failed, addresses.len()).into());
+            }
+        }
+        Command::Scan { domains, input, visibility, yes } => {
+            config.urlscan_api_key().ok_or(FragarachError::MissingApiKey("URLScan"))?;
+            let visibility = visibility.unwrap_or(config.urlscan_visibility());
+
+            let mut queue = domains.clone();
+            if let Some(input) = input {
+                let contents = std::fs::read_to_string(input)
+                    .map_err(|e| format!("Could not read {}: {}", input.display(), e))?;
+                queue.extend(contents.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from));
+            }
+
+            output::status("[Step 0] Checking submission quota".yellow());
+            let submissions = queue.len() as u64;
+            match urlscan::get_submission_quota(config, visibility.as_str()).await {
+                Ok(quota) => {
+                    output::status(format!("├─ Submissions: {} of {} remaining today ({}/{} used)", submissions, quota.remaining, quota.used, quota.limit));
+                    let wait = config.urlscan_poll_timeout() * u32::try_from(queue.len()).unwrap_or(u32::MAX);
+                    output::status(format!("└─ Estimated time: up to {}s", wait.as_secs()));
+
+                    if submissions > quota.remaining && !yes {
+                        return Err(format!(
+                            "{} scans need more than the {} {} submissions left today; pass --yes to submit anyway",
+                            submissions, quota.remaining, visibility
+                        ).into());
+                    }
+                }
+                Err(e) => eprintln!("{}", format!("Could not read URLScan quota: {}", e).yellow()),
+            }
+            if submissions > config.confirm_request_threshold() && !yes {
+                return Err(format!(
+                    "{} scans are more than FRAGARACH_CONFIRM_REQUESTS ({}); pass --yes to submit them anyway",
+                    submissions, config.confirm_request_threshold()
+                ).into());
+            }
+
+            let mut failed = 0;
+            for (i, domain) in queue.iter().enumerate() {
+                output::status(format!("[{}/{}] Initiating {} domain scan of {}", i + 1, queue.len(), visibility, domain).yellow());
+                if let Ok(target) = urlscan::ScanTarget::parse(domain) {
+                    print_tags(db, EntityType::Domain, &target.domain).await?;
+                }
+                let scan = match urlscan::scan_and_save(config, domain, visibility, db).await {
+                    Ok(scan) => scan,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "✘ Scan failed for".bright_red(), domain, e);
+                        if json {
+                            emit(json!({ "domain": domain, "error": e.to_string() }));
+                        }
+                        failed += 1;
+                        continue;
+                    }
+                };
+                if scan.completed {
+                    output::status(format!("✔ Domain scan completed for {} (UUID: {})", domain, scan.uuid).bright_green());
+                } else {
+                    output::status(format!("[!] Domain scan for {} is still running; saved as pending (UUID: {})", domain, scan.uuid).yellow());
+                }
+                if json {
+                    let mut record = scan_record(db, scan.uuid.clone()).await?;
+                    if let Some(fields) = record.as_object_mut() {
+                        fields.insert("completed".to_string(), scan.completed.into());
+                    }
+                    emit(record);
+                }
+            }
+            if failed > 0 {
+                return Err(format!("{} of {} scans failed", failed, queue.len()).into());
+            }
+        }
+        Command::Export { table, format, filter, case, out, force } => {
+            let table = export::EXPORTABLE_TABLES.iter()
+                .find(|t| **t == table.as_str())
+                .ok_or_else(|| format!("Unknown table: {} (expected one of {})", table, export::EXPORTABLE_TABLES.join(", ")))?;
+            let case_id = match case.clone() {
+                Some(name) => Some(
+                    db.call(move |conn| database_operations::find_case(conn, &name)).await?
+                        .ok_or_else(|| format!("Unknown case: {}", case.as_deref().unwrap_or_default()))?
+                ),
+                None => None,
+            };
+            let format = *format;
+            let filter = filter.clone();
+            let force = *force;
+            let out = out.clone()
+                .unwrap_or_else(|| paths::exports_dir().join(format!("{}.{}", table, format.extension())));
+
+            output::status("[Step 1] Writing export file".yellow());
+            let destination = out.display().to_string();
+            let rows = db.call(move |conn| export::export_table_local(conn, table, format, filter.as_deref(), case_id, &out, force)).await?;
+            output::status(format!("✔ Exported {} rows to {}", rows, destination).bright_green());
+            if json {
+                emit(json!({ "table": table, "format": format.extension(), "rows": rows, "path": destination }));
+            }
+        }
+        Command::ExtractScreenshot { uuid, out } => {
+            let scan_uuid = uuid.clone();
+            let (screenshot, path) = match db.call(move |conn| database_operations::read_screenshot(conn, &scan_uuid)).await {
+                Err(FragarachError::Database(duckdb::Error::QueryReturnedNoRows)) => {
+                    return Err(format!("No URLScan scan with UUID {}", uuid).into());
+                }
+                result => result?,
+            };
+
+            // Scans saved before images were kept in the database still have the file
+            let screenshot = match (screenshot, path) {
+                (Some(bytes), _) => bytes,
+                (None, Some(path)) => std::fs::read(&path)
+                    .map_err(|e| format!("No screenshot stored in the database for {}, and {} is unreadable: {}", uuid, path, e))?,
+                (None, None) => return Err(format!("No screenshot was saved for scan {} (the download failed or the scan is pending)", uuid).into()),
+            };
+            if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(out, &screenshot)?;
+            output::status(format!("✔ Wrote {} byte screenshot to {}", screenshot.len(), out.display()).bright_green());
+            if json {
+                emit(json!({ "uuid": uuid, "bytes": screenshot.len(), "path": out.display().to_string() }));
+            }
+        }
+        Command::Profile { address, chain, limit } => {
+            config.transpose_api_key().ok_or("Transpose API key not set")?;
+            let address = resolve_address(config, db, address).await?;
+            print_tags(db, EntityType::Address, &address).await?;
+
+            output::status(format!("[Step 1] Fetching and saving {} account, transactions and token transfers", chain).yellow());
+            let profile = transpose::build_account_profile(config, db, *chain, &address, *limit).await?;
+            print_profile(&profile);
+            if json {
+                emit(serde_json::to_value(&profile)?);
+            }
+        }
+        Command::CompressDoms => {
+            let dom_config = config.clone();
+            let compaction = db.call(move |conn| urlscan::compress_stored_doms(conn, &dom_config)).await?;
+            output::status(format!(
+                "✔ Moved {} DOM snapshots ({} bytes) to {} ({} bytes compressed)",
+                compaction.moved, compaction.stored_bytes, config.dom_dir().display(), compaction.compressed_bytes
+            ).bright_green());
+            if json {
+                emit(json!({
+                    "moved": compaction.moved,
+                    "stored_bytes": compaction.stored_bytes,
+                    "compressed_bytes": compaction.compressed_bytes,
+                }));
+            }
+        }
+        Command::Watchlist { action } => match action {
+            WatchlistAction::Add { kind, value, chain } => {
+                let (value, added) = watchlist::add(config, db, *kind, value, *chain).await?;
+                if added {
+                    output::status(format!("✔ Watching {} {}", kind, value).bright_green());
+                } else {
+                    output::status(format!("{} is already on the watchlist", value).yellow());
+                }
+                if json {
+                    emit(json!({ "type": kind, "value": value, "added": added }));
+                }
+            }
+            WatchlistAction::Remove { value } => {
+                let removed = watchlist::remove(db, value).await?;
+                if removed == 0 {
+                    return Err(format!("{} is not on the watchlist", value).into());
+                }
+                output::status(format!("✔ Stopped watching {}", value).bright_green());
+                if json {
+                    emit(json!({ "value": value, "removed": removed }));
+                }
+            }
+            WatchlistAction::List => {
+                let items = watchlist::items(db).await?;
+                if json {
+                    emit(serde_json::to_value(&items)?);
+                } else {
+                    for item in &items {
+                        println!("{} {}{} (last checked {})", item.kind, item.value,
+                            item.chain.as_ref().map(|chain| format!(" on {}", chain)).unwrap_or_default(),
+                            item.last_checked_at.as_deref().unwrap_or("never"));
+                    }
+                }
+            }
+            WatchlistAction::Run => {
+                let outcomes = watchlist::run(config, db).await?;
+                let failed = watchlist::print_summary(&outcomes);
+                if json {
+                    for outcome in &outcomes {
+                        let mut record = json!({ "type": outcome.item.kind, "value": outcome.item.value });
+                        match &outcome.result {
+                            Ok(Change::Transactions(new)) => record["new_transactions"] = json!(new),
+                            Ok(Change::Scans { new, verdict }) => {
+                                record["new_scans"] = json!(new);
+                                if let Some((from, to)) = verdict {
+                                    record["previous_verdict"] = json!(watchlist::verdict_name(*from));
+                                    record["verdict"] = json!(watchlist::verdict_name(*to));
+                                }
+                            }
+                            Err(e) => record["error"] = json!(e.to_string()),
+                        }
+                        emit(record);
+                    }
+                }
+                if failed > 0 {
+                    return Err(format!("{} of {} watchlist items could not be checked", failed, outcomes.len()).into());
+                }
+            }
+        },
+        _ => return Err("Not a headless subcommand".into()),
+    }
+
+    Ok(())
+}
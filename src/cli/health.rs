@@ -0,0 +1,278 @@
+/// Machine-checkable health probe for scheduled monitoring
+///
+/// # Checks
+/// - `database`: database opens read-only and its schema version matches this build;
+///   skipped while another instance holds it open
+/// - `writer`: the database writer task answers within the deadline
+/// - `transpose` / `etherscan` / `urlscan` / `virustotal` / `shodan`: each configured API accepts an
+///   authenticated request
+/// - `disk_space`: the database directory has more free space than the threshold
+/// - `job_queue`: stuck background jobs (skipped, no job queue exists yet)
+///
+/// The overall status is `fail` if any check fails; skipped checks don't count.
+use crate::api::{etherscan, shodan, transpose, urlscan, virustotal};
+use crate::config::Config;
+use crate::helpers::database_writer::DatabaseWriter;
+use crate::helpers::migrations;
+use colored::*;
+use duckdb::{AccessMode, Connection};
+use serde::Serialize;
+use std::future::Future;
+use std::path::Path;
+use std::time::Instant;
+use tokio::time::timeout;
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Fail,
+    Skip,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    pub duration_ms: u128,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub status: Status,
+    pub checks: Vec<Check>,
+}
+
+/// Runs a check, bounding it by the configured deadline
+async fn run_check<F>(name: &'static str, config: &Config, check: F) -> Check
+where
+    F: Future<Output = Result<String, String>>,
+{
+    let started = Instant::now();
+    let (status, detail) = match timeout(config.health_deadline(), check).await {
+        Ok(Ok(detail)) => (Status::Ok, detail),
+        Ok(Err(detail)) => (Status::Fail, detail),
+        Err(_) => (Status::Fail, format!("no answer within {}s", config.health_deadline().as_secs())),
+    };
+
+    Check {
+        name,
+        status,
+        detail,
+        duration_ms: started.elapsed().as_millis(),
+    }
+}
+
+fn skipped(name: &'static str, detail: &str) -> Check {
+    Check {
+        name,
+        status: Status::Skip,
+        detail: detail.to_string(),
+        duration_ms: 0,
+    }
+}
+
+/// Opens the database read-only, or returns the `database` check explaining why not
+///
+/// DuckDB lets one process at a time hold a database file open for writing;
+/// that process is a running instance rather than a fault.
+fn open_database(path: &Path) -> Result<Connection, Check> {
+    let failed = |detail| Check {
+        name: "database",
+        status: Status::Fail,
+        detail,
+        duration_ms: 0,
+    };
+    // Opening a missing file would create an empty database, so check first
+    if !path.exists() {
+        return Err(failed(format!("{} does not exist", path.display())));
+    }
+
+    duckdb::Config::default()
+        .access_mode(AccessMode::ReadOnly)
+        .and_then(|flags| Connection::open_with_flags(path, flags))
+        .map_err(|e| {
+            if e.to_string().contains("Could not set lock on file") {
+                skipped("database", "in use by a running instance")
+            } else {
+                failed(format!("cannot open {}: {}", path.display(), e))
+            }
+        })
+}
+
+/// Runs every check and builds the report
+pub async fn collect(config: &Config) -> Report {
+    let mut checks = Vec::new();
+
+    let db_path = config.database_path();
+    let conn = match open_database(&db_path) {
+        Ok(conn) => Some(conn),
+        Err(check) => {
+            checks.push(check);
+            None
+        }
+    };
+
+    if let Some(conn) = conn {
+        checks.push(run_check("database", config, async {
+            let version = migrations::current_version(&conn).map_err(|e| e.to_string())?;
+            let expected = migrations::latest_version();
+            if version == expected {
+                Ok(format!("schema version {}", version))
+            } else {
+                Err(format!("schema version {}, expected {}", version, expected))
+            }
+        }).await);
+
+        let writer = DatabaseWriter::spawn(conn, config.db_queue_capacity());
+        let db = writer.handle();
+        checks.push(run_check("writer", config, async {
+            db.call(|conn| conn.query_row("SELECT 1", [], |row| row.get::<_, i32>(0)))
+                .await
+                .map(|_| "responding".to_string())
+                .map_err(|e| e.to_string())
+        }).await);
+        let _ = writer.shutdown().await;
+    }
+
+    if config.transpose_api_key().is_some() {
+        checks.push(run_check("transpose", config, async {
+            transpose::check_api_key(config).await
+                .map(|_| "authenticated".to_string())
+                .map_err(|e| e.to_string())
+        }).await);
+    } else {
+        checks.push(skipped("transpose", "API key not configured"));
+    }
+
+    if config.etherscan_api_key().is_some() {
+        checks.push(run_check("etherscan", config, async {
+            etherscan::check_api_key(config).await
+                .map(|_| "authenticated".to_string())
+                .map_err(|e| e.to_string())
+        }).await);
+    } else {
+        checks.push(skipped("etherscan", "API key not configured"));
+    }
+
+    if config.urlscan_api_key().is_some() {
+        checks.push(run_check("urlscan", config, async {
+            urlscan::get_submission_quota(config, config.urlscan_visibility().as_str()).await
+                .map(|quota| format!("{} {} submissions remaining today", quota.remaining, config.urlscan_visibility()))
+                .map_err(|e| e.to_string())
+        }).await);
+    } else {
+        checks.push(skipped("urlscan", "API key not configured"));
+    }
+
+    if config.virustotal_api_key().is_some() {
+        checks.push(run_check("virustotal", config, async {
+            virustotal::check_api_key(config).await
+                .map(|_| "authenticated".to_string())
+                .map_err(|e| e.to_string())
+        }).await);
+    } else {
+        checks.push(skipped("virustotal", "API key not configured"));
+    }
+
+    if config.shodan_api_key().is_some() {
+        checks.push(run_check("shodan", config, async {
+            shodan::check_api_key(config).await
+                .map(|_| "authenticated".to_string())
+                .map_err(|e| e.to_string())
+        }).await);
+    } else {
+        checks.push(skipped("shodan", "API key not configured"));
+    }
+
+    checks.push(run_check("disk_space", config, async {
+        // Measure the nearest existing directory, as the data directory may not exist yet
+        let data_dir = db_path.ancestors()
+            .skip(1)
+            .find(|p| p.is_dir())
+            .unwrap_or(Path::new("."));
+        let free_mb = fs2::available_space(data_dir)
+            .map_err(|e| e.to_string())? / (1024 * 1024);
+        if free_mb >= config.health_min_free_mb() {
+            Ok(format!("{} MB free", free_mb))
+        } else {
+            Err(format!("{} MB free, below {} MB threshold", free_mb, config.health_min_free_mb()))
+        }
+    }).await);
+
+    checks.push(skipped("job_queue", "no background job queue"));
+
+    let failed = checks.iter().any(|c| matches!(c.status, Status::Fail));
+    Report {
+        status: if failed { Status::Fail } else { Status::Ok },
+        checks,
+    }
+}
+
+/// Prints the report and returns whether every check passed
+pub async fn run(config: &Config, json: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let report = collect(config).await;
+    let healthy = !matches!(report.status, Status::Fail);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for check in &report.checks {
+            let status = match check.status {
+                Status::Ok => "OK".green(),
+                Status::Fail => "FAIL".red(),
+                Status::Skip => "SKIP".yellow(),
+            };
+            println!("[{}] {}: {}", status, check.name, check.detail);
+        }
+    }
+
+    Ok(healthy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::database_setup::setup_database_schema;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn probe_reports_every_check_without_api_keys() {
+        let dir = std::env::temp_dir().join(format!("fragarach-health-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("fragarach.duckdb");
+        let conn = Connection::open(&db_path).unwrap();
+        setup_database_schema(&conn).unwrap();
+        drop(conn);
+        let config: Config = toml::from_str(&format!(
+            "database_path = {:?}\nhealth_min_free_mb = 0",
+            db_path.display().to_string()
+        )).unwrap();
+
+        let report = serde_json::to_value(collect(&config).await).unwrap();
+        assert_eq!(report["status"], "ok");
+        let checks: Vec<(&str, &str)> = report["checks"].as_array().unwrap().iter()
+            .map(|check| (check["name"].as_str().unwrap(), check["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(checks, [
+            ("database", "ok"),
+            ("writer", "ok"),
+            ("transpose", "skip"),
+            ("etherscan", "skip"),
+            ("urlscan", "skip"),
+            ("virustotal", "skip"),
+            ("shodan", "skip"),
+            ("disk_space", "ok"),
+            ("job_queue", "skip"),
+        ]);
+        for check in report["checks"].as_array().unwrap() {
+            let keys: Vec<&str> = check.as_object().unwrap().keys().map(String::as_str).collect();
+            assert_eq!(keys, ["name", "status", "detail", "duration_ms"]);
+            assert!(check["duration_ms"].is_u64());
+        }
+        assert_eq!(report["checks"][2]["detail"], Value::from("API key not configured"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
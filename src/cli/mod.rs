@@ -6,24 +6,72 @@
 /// - Progress animations
 /// - Configuration management
 /// - Database operations
+/// - Pre-flight cost estimates for expensive operations
 ///
 /// # Menu Options
 /// - System Setup
 /// - Ethereum Account Query
-/// - Ethereum Transaction Query
+/// - Ethereum Transaction Query, from Transpose or the Etherscan fallback
+/// - Full Profile: account, transactions and token transfers in one go
+/// - ERC-20 Token Transfer Query
+/// - NFT Transfer Query
 /// - Domain Scanning
+/// - URLScan Search and Import
+/// - DNS Lookup
+/// - WHOIS Lookup of registration data, over RDAP or WHOIS
+/// - VirusTotal Domain Lookup with resolution history, also offered after each domain scan
+/// - IP Lookup on Shodan
+/// - Scanned Domain Search by name or verdict score
+/// - Saved Data Browser for addresses and scanned domains
+/// - Stored Record Viewer
+/// - Data Export (local files or S3)
+/// - Investigation Cases attached to saved records
+/// - Tags on addresses and domains, shown whenever they are queried again
+/// - Watchlist of addresses and domains re-checked on demand
+/// - Maintenance: purging old records and compacting the database
 /// - Settings Management
+///
+/// Non-interactive subcommands are defined in `args`; `headless` runs the data
+/// commands, `health` implements the monitoring probe, `pipe` the JSON
+/// command protocol and `watchlist` the watchlist runs both share.
+pub mod args;
+pub mod headless;
+pub mod health;
+pub mod pipe;
+pub mod watchlist;
+
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Select, Input};
+use dialoguer::{theme::ColorfulTheme, Confirm, Select, Input};
 use console::Style;
-use crate::config::Config;
-use crate::api::transpose;
-use crate::helpers::{database_setup, database_operations};
+use crate::config::{self, paths, Config};
+use crate::config::secrets::{self, ApiKey};
+use crate::api::{dns, etherscan, http, http_cache, releases, shodan, transpose, urlscan, virustotal, whois};
+use crate::api::source::DataSource;
+use crate::api::transpose::{AccountProfile, AddressPull, Chain, TransactionRange};
+use crate::api::urlscan::Visibility;
+use crate::helpers::{database_operations, database_setup};
+use crate::helpers::database_operations::{EntityType, Filter};
+use crate::helpers::database_writer::{DatabaseHandle, DatabaseWriter};
+use crate::helpers::export::{self, ExportFormat};
+use crate::helpers::graph::{self, GraphFormat};
+use crate::helpers::migrations;
+use crate::helpers::report::{self, ReportFormat, ReportSubject};
+use crate::helpers::watchlist::WatchKind;
+use crate::error::FragarachError;
+use crate::{logging, output};
+use args::{Args, Command, OutputFormat};
 use duckdb::Connection;
-use std::fs::OpenOptions;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fs;
+use std::future::Future;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::warn;
 
 const FRAGARACH_LOGO: &str = r#"
     ___                                    _
@@ -43,11 +91,49 @@ fn print_cyber_header(text: &str) {
     println!("{}\n", CYBER_BORDER.bright_blue());
 }
 
+/// Longest cell shown by `print_cyber_table`; longer values are cut with `…`
+const TABLE_CELL_WIDTH: usize = 28;
+
+fn print_cyber_table(headers: &[String], rows: &[Vec<String>]) {
+    let cut = |cell: &str| if cell.chars().count() > TABLE_CELL_WIDTH {
+        format!("{}…", cell.chars().take(TABLE_CELL_WIDTH - 1).collect::<String>())
+    } else {
+        cell.to_string()
+    };
+    let headers: Vec<String> = headers.iter().map(|h| cut(h)).collect();
+    let rows: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(|c| cut(c)).collect()).collect();
+
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| rows.iter()
+            .map(|row| row[i].chars().count())
+            .chain(std::iter::once(headers[i].chars().count()))
+            .max()
+            .unwrap_or(0))
+        .collect();
+    let line = |cells: &[String]| cells.iter()
+        .zip(&widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join(" │ ");
+    let rule = widths.iter().map(|w| "─".repeat(*w)).collect::<Vec<_>>().join("─┼─");
+
+    println!("{}", line(&headers).bright_cyan());
+    println!("{}", rule.bright_blue());
+    for row in &rows {
+        println!("{}", line(row));
+    }
+}
+
 fn print_cyber_step(step: &str, text: &str) {
     println!("\n>> {} {}", format!("[{}]", step).bright_yellow(), text.bright_green());
 }
 
+/// Types `text` out a few characters at a time; printed at once in plain mode
 fn animate_text(text: &str) {
+    if output::is_plain() {
+        println!("{}", text);
+        return;
+    }
     print!("\r");
     for (i, c) in text.chars().enumerate() {
         print!("{}", c.to_string().bright_cyan());
@@ -59,17 +145,266 @@ fn animate_text(text: &str) {
     println!();
 }
 
+/// Pauses between animated lines; skipped in plain mode
+fn animation_pause(duration: Duration) {
+    if !output::is_plain() {
+        thread::sleep(duration);
+    }
+}
+
+/// Loads the configuration and applies command-line overrides
+fn configure(args: &Args) -> Config {
+    let mut config = Config::new();
+    if let Some(db) = &args.db {
+        config.set_database_path(db.clone());
+    }
+    if let Some(dir) = &args.screenshots_dir {
+        config.set_screenshots_dir(dir.clone());
+    }
+    if args.skip_key_check {
+        config.set_check_api_keys(false);
+    }
+    config
+}
+
+/// Initializes the application, sets up database connections, and runs the
+/// selected subcommand or the interactive menu
+///
+/// # Database Initialization
+/// - Offers to move legacy working-directory files to platform locations
+///   (interactive menu only)
+/// - Creates DuckDB database if it doesn't exist
+/// - Applies pending schema migrations after confirmation and backup; subcommands
+///   refuse to run against an existing database that needs migrating
+///
+/// # Errors
+/// Returns an error if:
+/// - Database directory creation fails
+/// - Database connection fails
+/// - The database schema is newer than this build or migrations are declined
+/// - CLI execution fails
+pub async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    // Any non-empty NO_COLOR value disables colors, per no-color.org
+    if args.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        output::set_plain(true);
+    }
+    // JSON output keeps stdout for results, so progress lines are dropped
+    if args.output == OutputFormat::Json {
+        if args.command.is_none() {
+            return Err("--output json needs a subcommand; the interactive menu only prints text".into());
+        }
+        output::set_plain(true);
+        output::set_quiet(true);
+    }
+    // Pipe mode keeps stdout for protocol messages
+    if matches!(args.command, Some(Command::Pipe)) {
+        output::set_quiet(true);
+    }
+    if args.command.is_none() {
+        output::set_progress_bars(true);
+    }
+
+    let mut config = configure(&args);
+
+    // Held until exit so buffered log lines reach the file
+    let log_guard = {
+        let log_file = args.log_file.clone().or_else(|| config.log_file());
+        logging::init(args.verbose, config.log_filter().as_deref(), log_file.as_deref())?
+    };
+    for warning in config.warnings() {
+        warn!("{}", warning);
+    }
+
+    // Scheduled health checks must never block on a prompt
+    if let Some(Command::Health { json }) = &args.command {
+        if !health::run(&config, *json || args.output == OutputFormat::Json).await? {
+            drop(log_guard);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Version { check }) = &args.command {
+        let code = run_version_command(&config, *check).await;
+        drop(log_guard);
+        std::process::exit(code);
+    }
+
+    // Subcommands never prompt
+    let interactive = args.command.is_none();
+    if interactive {
+        offer_legacy_migration()?;
+    }
+
+    if args.cached {
+        http_cache::enable(paths::http_cache_dir(), config.http_cache_max_bytes());
+    }
+
+    if let Some(Command::Cache { action }) = &args.command {
+        return run_cache_command(action);
+    }
+
+    // Create the database directory if it doesn't exist
+    let db_path = config.database_path();
+    if let Some(parent) = db_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Cannot create database directory {}: {}", parent.display(), e))?;
+    }
+
+    // Initialize DuckDB connection, before any menu is shown
+    let conn = Connection::open(&db_path).map_err(|e| format!(
+        "Cannot open database {}: {} (set FRAGARACH_DB_PATH or pass --db to use another location)",
+        db_path.display(), e
+    ))?;
+
+    // Check schema and config compatibility, migrating if needed
+    check_compatibility(&conn, &db_path, interactive)?;
+
+    let writer = DatabaseWriter::spawn(conn, config.db_queue_capacity());
+    if let Some(case) = config.active_case() {
+        writer.handle().open_case(case).await?;
+    }
+    let result = match &args.command {
+        None => run_cli(&mut config, &writer.handle(), !args.no_banner).await,
+        Some(Command::Pipe) => pipe::run(&config, &writer.handle()).await,
+        Some(command) => headless::run(&config, &writer.handle(), command, args.output).await,
+    };
+
+    // Flush queued writes even if the CLI exited with an error
+    writer.shutdown().await?;
+    result?;
+
+    Ok(())
+}
+
+/// Executes a `cache` subcommand
+pub fn run_cache_command(action: &args::CacheAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        args::CacheAction::Purge => {
+            let freed = http_cache::purge(&paths::http_cache_dir())?;
+            println!("{}", format!("✔ Purged HTTP cache ({} KB freed).", freed / 1024).bright_green());
+        }
+    }
+    Ok(())
+}
+
+/// Executes the `version` subcommand, returning the process exit code
+///
+/// With `check`, exits 0 when up to date, 1 when a newer release exists and
+/// 2 when the release lookup fails.
+pub async fn run_version_command(config: &Config, check: bool) -> i32 {
+    println!("fragarach {}", releases::CURRENT_VERSION);
+    if !check {
+        return 0;
+    }
+
+    match releases::latest_release(config, None).await {
+        Ok(release) if release.is_newer() => {
+            println!("{}", format!("[!] Fragarach {} is available: {}", release.tag_name, release.html_url).bright_yellow());
+            1
+        }
+        Ok(_) => {
+            println!("{}", "✔ Up to date.".bright_green());
+            0
+        }
+        Err(e) => {
+            println!("{} {}", "✘ Release check failed:".bright_red(), e);
+            2
+        }
+    }
+}
+
+/// Offers to move files left in the working directory by older releases
+pub fn offer_legacy_migration() -> Result<(), Box<dyn std::error::Error>> {
+    let legacy = paths::legacy_files();
+    if legacy.is_empty() {
+        return Ok(());
+    }
+
+    println!("{}", "[!] Found files from an older Fragarach layout:".bright_yellow());
+    for (from, to) in &legacy {
+        println!("├─ {} -> {}", from.display(), to.display());
+    }
+
+    let migrate = Confirm::new()
+        .with_prompt("Move them to the platform data and config directories?")
+        .default(true)
+        .interact()?;
+
+    if migrate {
+        let moved = paths::migrate_legacy_files()?;
+        println!("{}", format!("✔ Moved {} item(s).", moved.len()).bright_green());
+    } else {
+        println!("{}", "Keeping the working-directory layout.".yellow());
+    }
+
+    Ok(())
+}
+
+/// Verifies the database and config match what this binary expects
+///
+/// Pending migrations are applied after a confirmation and a backup of the
+/// database file. A database written by a newer binary is rejected so it is
+/// never modified with an outdated schema. Without `interactive`, pending
+/// migrations on an existing database are an error instead of a prompt.
+pub fn check_compatibility(conn: &Connection, db_path: &Path, interactive: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let version = migrations::current_version(conn)?;
+    let expected = migrations::latest_version();
+
+    if version > expected {
+        return Err(format!(
+            "Database schema version {} is newer than this build supports ({}). Upgrade Fragarach before using {}.",
+            version, expected, db_path.display()
+        ).into());
+    }
+
+    let pending = migrations::pending(conn)?;
+    if !pending.is_empty() {
+        if database_setup::has_user_tables(conn)? {
+            if !interactive {
+                return Err(format!(
+                    "Database schema is at version {}, this build expects {}. Run fragarach interactively once to back up and migrate.",
+                    version, expected
+                ).into());
+            }
+
+            println!("{}", format!("[!] Database schema is at version {}, this build expects {}:", version, expected).bright_yellow());
+            for migration in &pending {
+                println!("├─ {}: {}", migration.version, migration.description);
+            }
+
+            let proceed = Confirm::new()
+                .with_prompt("Back up the database and apply these migrations?")
+                .default(true)
+                .interact()?;
+            if !proceed {
+                return Err("Pending database migrations must be applied before Fragarach can continue.".into());
+            }
+
+            let backup = database_setup::backup_database(conn, db_path, version)?;
+            println!("{}", format!("✔ Backup written to {}", backup.display()).bright_green());
+        }
+
+        database_setup::setup_database_schema(conn)?;
+    }
+
+    Ok(())
+}
+
 pub async fn run_cli(
     config: &mut Config,
-    conn: &Connection,
+    db: &DatabaseHandle,
+    banner: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Animated startup sequence
-    println!("{}", CYBER_BORDER.bright_blue());
-    animate_text("INITIALIZING FRAGARACH SYSTEMS...");
-    thread::sleep(Duration::from_millis(500));
-    println!("{}", FRAGARACH_LOGO.bright_magenta());
-    animate_text("BLOCKCHAIN INVESTIGATION TOOLKIT ACTIVE");
-    println!("{}", CYBER_BORDER.bright_blue());
+    if banner {
+        println!("{}", CYBER_BORDER.bright_blue());
+        animate_text("INITIALIZING FRAGARACH SYSTEMS...");
+        animation_pause(Duration::from_millis(500));
+        println!("{}", FRAGARACH_LOGO.bright_magenta());
+        animate_text("BLOCKCHAIN INVESTIGATION TOOLKIT ACTIVE");
+        println!("{}", CYBER_BORDER.bright_blue());
+    }
 
     if config.transpose_api_key().is_none() {
         println!("\n{}", "[!] WARNING: Transpose API key not detected. Run 'setup' to configure.".bright_red());
@@ -79,6 +414,16 @@ pub async fn run_cli(
         println!("{}", "[!] WARNING: URLScan API key not detected. Run 'setup' to configure.".bright_red());
     }
 
+    if let Some(case) = config.active_case() {
+        println!("{}", format!("[*] Active case: {}", case).bright_cyan());
+    }
+
+    if config.update_check() {
+        if let Some(release) = releases::daily_check(config, db).await {
+            println!("{}", format!("[!] Fragarach {} is available (running {}): {}", release.tag_name, releases::CURRENT_VERSION, release.html_url).bright_yellow());
+        }
+    }
+
     let custom_theme = ColorfulTheme {
         defaults_style: Style::new().cyan(),
         prompt_style: Style::new().yellow(),
@@ -109,21 +454,61 @@ pub async fn run_cli(
                 "⚙️  Setup",
                 "🔍 Query Ethereum Account",
                 "📊 Query Ethereum Transactions",
+                "🧾 Full Profile",
+                "🪙 Query Token Transfers",
+                "🖼️  NFTs",
+                "📜 Run Transpose Query",
                 "🌐 Scan Domain",
+                "🔎 Search URLScan",
+                "🧭 DNS Lookup",
+                "📇 WHOIS Lookup",
+                "🦠 VirusTotal Lookup",
+                "🛰️  Lookup IP",
+                "🗂️  Search Scanned Domains",
+                "📂 Browse Data",
+                "🗄️  View Stored Records",
+                "🧮 Run Query",
+                "🕘 History",
+                "📦 Export Data",
+                "📝 Generate Report",
+                "🏷️  Tag Entity",
+                "🗃️  Cases",
+                "👁️  Watchlist",
+                "🧹 Maintenance",
                 "⚡ Settings",
                 "🚪 Exit"
             ])
             .interact()?;
 
         match selection {
-            0 => setup(config, conn).await?,
-            1 => query_ethereum_account(config, conn).await?,
-            2 => query_ethereum_transactions(config, conn).await?,
-            3 => scan_domain(config, conn).await?,
-            4 => settings_menu(config).await?,
-            5 => {
+            0 => setup(config, db).await?,
+            1 => query_ethereum_account(config, db).await?,
+            2 => query_ethereum_transactions(config, db).await?,
+            3 => full_profile(config, db).await?,
+            4 => query_token_transfers(config, db).await?,
+            5 => nft_menu(config, db).await?,
+            6 => run_transpose_query(config, db).await?,
+            7 => scan_domain(config, db).await?,
+            8 => search_urlscan(config, db).await?,
+            9 => dns_lookup(config, db).await?,
+            10 => whois_lookup(config, db).await?,
+            11 => virustotal_menu(config, db).await?,
+            12 => lookup_ip(config, db).await?,
+            13 => search_scanned_domains(db).await?,
+            14 => browse_data(db).await?,
+            15 => view_records(db).await?,
+            16 => run_query(db).await?,
+            17 => show_history(db).await?,
+            18 => export_data(config, db).await?,
+            19 => generate_report(config, db).await?,
+            20 => tag_entity(config, db).await?,
+            21 => cases_menu(config, db).await?,
+            22 => watchlist_menu(config, db).await?,
+            23 => maintenance_menu(config, db).await?,
+            24 => settings_menu(config).await?,
+            25 => {
                 animate_text("SHUTTING DOWN FRAGARACH SYSTEMS...");
-                thread::sleep(Duration::from_millis(500));
+                animation_pause(Duration::from_millis(500));
                 println!("{}", "System offline! 👋".bright_magenta());
                 break;
             }
@@ -134,11 +519,11 @@ pub async fn run_cli(
     Ok(())
 }
 
-async fn setup(config: &mut Config, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+async fn setup(config: &mut Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
     print_cyber_header("SYSTEM SETUP AND CONFIGURATION");
 
     print_cyber_step("01", "Configuring Database Schema");
-    if let Err(e) = database_setup::setup_database_schema(conn) {
+    if let Err(e) = db.setup_schema().await {
         println!("{} {}", "✘ Database schema setup failed:".bright_red(), e);
     } else {
         println!("{}", "✔ Database schema configured successfully.".bright_green());
@@ -146,201 +531,2179 @@ async fn setup(config: &mut Config, conn: &Connection) -> Result<(), Box<dyn std
 
     print_cyber_step("02", "API Authentication Setup");
     if config.transpose_api_key().is_none() {
-        set_transpose_api_key(config).await?;
+        enter_api_key(config, ApiKey::Transpose).await?;
     } else {
         println!("{}", "✔ Transpose API key already configured.".bright_green());
     }
 
     print_cyber_step("03", "URLScan Integration Setup");
     if config.urlscan_api_key().is_none() {
-        set_urlscan_api_key(config).await?;
+        enter_api_key(config, ApiKey::Urlscan).await?;
     } else {
         println!("{}", "✔ URLScan API key already configured.".bright_green());
     }
 
+    print_cyber_step("04", "Etherscan Fallback Setup");
+    if config.etherscan_api_key().is_some() {
+        println!("{}", "✔ Etherscan API key already configured.".bright_green());
+    } else if Confirm::new()
+        .with_prompt("Add an Etherscan API key, used for accounts and transactions when Transpose is unavailable?")
+        .default(false)
+        .interact()?
+    {
+        enter_api_key(config, ApiKey::Etherscan).await?;
+    }
+
+    print_cyber_step("05", "VirusTotal Enrichment Setup");
+    if config.virustotal_api_key().is_some() {
+        println!("{}", "✔ VirusTotal API key already configured.".bright_green());
+    } else if Confirm::new()
+        .with_prompt("Add a VirusTotal API key, used to look up the reputation of scanned domains?")
+        .default(false)
+        .interact()?
+    {
+        enter_api_key(config, ApiKey::Virustotal).await?;
+    }
+
+    print_cyber_step("06", "Shodan Enrichment Setup");
+    if config.shodan_api_key().is_some() {
+        println!("{}", "✔ Shodan API key already configured.".bright_green());
+    } else if Confirm::new()
+        .with_prompt("Add a Shodan API key, used to look up the IP address of scanned pages?")
+        .default(false)
+        .interact()?
+    {
+        enter_api_key(config, ApiKey::Shodan).await?;
+    }
+
     println!("\n{}", CYBER_SEPARATOR.bright_blue());
     animate_text("SETUP SEQUENCE COMPLETE");
     Ok(())
 }
 
-async fn query_ethereum_account(config: &Config, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    if config.transpose_api_key().is_none() {
-        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
-        return Ok(());
+fn select_chain() -> Result<Chain, Box<dyn std::error::Error>> {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select chain")
+        .default(0)
+        .items(&Chain::ALL)
+        .interact()?;
+    Ok(Chain::ALL[selection])
+}
+
+/// Picks where accounts and transactions are fetched from
+///
+/// Asks only when both Transpose and Etherscan keys are set. Returns `None`,
+/// after saying so, when neither is.
+fn select_source(config: &Config) -> Result<Option<DataSource>, Box<dyn std::error::Error>> {
+    let sources = DataSource::configured(config);
+    match sources[..] {
+        [] => {
+            println!("{}", "Neither a Transpose nor an Etherscan API key is set. Please run 'setup' to set one.".red());
+            Ok(None)
+        }
+        [source] => Ok(Some(source)),
+        _ => {
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select data source")
+                .default(0)
+                .items(&sources)
+                .interact()?;
+            Ok(Some(sources[selection]))
+        }
     }
+}
 
-    let address: String = Input::new()
-        .with_prompt("Enter Ethereum address")
-        .interact_text()?;
+/// Turns an address prompt's input into a hex address
+///
+/// ENS names are resolved through Transpose and the mapping is saved to
+/// `ens_names`. Anything else must be a valid address and is returned
+/// lowercased; otherwise this fails with `InvalidAddress` before any API call.
+async fn resolve_address(
+    config: &Config,
+    db: &DatabaseHandle,
+    input: &str,
+) -> Result<String, FragarachError> {
+    if !transpose::is_ens_name(input) {
+        return transpose::validate_address(input);
+    }
+
+    let name = input.trim().to_lowercase();
+    let address = transpose::resolve_ens(config, &name).await?;
+    db.save_records(vec![serde_json::json!({ "ens_name": name, "address": address })], "ens_names").await?;
+    output::status(format!("├─ {} resolves to {}", name, address));
+    Ok(address)
+}
+
+/// Prompts for an address or ENS name until one resolves, then shows its tags
+async fn prompt_address(config: &Config, db: &DatabaseHandle, chain: Chain) -> Result<String, Box<dyn std::error::Error>> {
+    let address = prompt_untagged_address(config, db, chain).await?;
+    print_tags(db, EntityType::Address, &address).await?;
+    Ok(address)
+}
+
+/// Prompts for an address or ENS name until one resolves
+async fn prompt_untagged_address(config: &Config, db: &DatabaseHandle, chain: Chain) -> Result<String, Box<dyn std::error::Error>> {
+    loop {
+        let input: String = Input::new()
+            .with_prompt(format!("Enter {} address or ENS name", chain))
+            .interact_text()?;
+        match resolve_address(config, db, &input).await {
+            Ok(address) => return Ok(address),
+            Err(e) => println!("{} {}", "✘".bright_red(), e),
+        }
+    }
+}
+
+async fn query_ethereum_account(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(source) = select_source(config)? else {
+        return Ok(());
+    };
+
+    let chain = select_chain()?;
+    let address = prompt_address(config, db, chain).await?;
 
-    println!("{}", "[Step 1] Querying Ethereum account details".yellow());
-    let account_data = transpose::query_ethereum_account(config, &address).await?;
+    println!("{}", format!("[Step 1] Querying {} account details from {}", chain, source).yellow());
+    let account_data = match source.query_account(config, chain, &address).await {
+        Ok(account_data) => account_data,
+        Err(e) => {
+            print_failure("Account query failed", &e);
+            return Ok(());
+        }
+    };
 
     println!("{}", "[Step 2] Saving data to database".yellow());
-    if let Err(e) = database_operations::save_records(conn, &account_data, "ethereum_accounts") {
+    let records = account_data.len();
+    if let Err(e) = db.save_records(account_data, "ethereum_accounts").await {
         println!("{} {}", "✘ Error saving data:".bright_red(), e);
     } else {
         println!("{}", "✔ Data saved successfully.".bright_green());
+        db.record_query(format!("{} account", chain), address.clone(), records).await?;
     }
 
     println!("{}", format!("\nRetrieved account data for address {}", address).green());
     Ok(())
 }
 
-async fn query_ethereum_transactions(config: &Config, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    if config.transpose_api_key().is_none() {
-        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
+async fn query_ethereum_transactions(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(source) = select_source(config)? else {
         return Ok(());
-    }
+    };
 
-    let address: String = Input::new()
-        .with_prompt("Enter Ethereum address")
-        .interact_text()?;
+    let chain = select_chain()?;
+    let address = prompt_address(config, db, chain).await?;
+    let incremental = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Fetch mode")
+        .default(0)
+        .items(&["Full", "Incremental (only what is new since the last sync)"])
+        .interact()? == 1;
+    let range = if incremental {
+        let (mark, range) = incremental_range(db, chain, &address).await?;
+        match mark {
+            Some(block) => println!("├─ Synced up to block {}", block),
+            None => println!("├─ Not synced before; fetching the whole history"),
+        }
+        range
+    } else {
+        prompt_transaction_range()?
+    };
 
-    println!("{}", "[Step 1] Querying Ethereum transactions".yellow());
-    let transactions = transpose::query_ethereum_transactions(config, &[address.clone()]).await?;
+    // Etherscan can't count transactions ahead of a pull, and charges no credits
+    if source == DataSource::Transpose {
+        println!("{}", "[Step 0] Estimating cost".yellow());
+        let estimate = transpose::estimate_transaction_pull(config, chain, std::slice::from_ref(&address), &range, None).await?;
+        println!("├─ Transactions: {}", estimate.transaction_count);
+        println!("├─ Requests: {}", estimate.requests);
+        println!("├─ Credits: {}", estimate.credits);
+        println!("└─ Estimated time: {}s", estimate.duration.as_secs());
 
-    if transactions.is_empty() {
-        println!("{}", "No transactions found for the provided address".yellow());
-        return Ok(());
+        if estimate.requests > config.confirm_request_threshold() {
+            let proceed = Confirm::new()
+                .with_prompt(format!("This pull needs {} requests. Continue?", estimate.requests))
+                .default(false)
+                .interact()?;
+            if !proceed {
+                println!("{}", "Transaction query cancelled.".yellow());
+                return Ok(());
+            }
+        }
     }
 
-    let total_transactions = transactions.len();
+    println!("{}", format!("[Step 1] Fetching and saving Ethereum transactions from {}", source).yellow());
+    let pulled = pull_transactions(config, db, source, chain, std::slice::from_ref(&address), None, &range).await;
+    let (total_transactions, new) = match pulled.and_then(|pull| only_pull(pull.pulls).map(|_| (pull.unique, pull.new))) {
+        Ok(counts) => counts,
+        Err(e) => {
+            print_failure("Transaction pull stopped", &e);
+            return Ok(());
+        }
+    };
 
-    println!("{}", "[Step 2] Saving data to database".yellow());
-    if let Err(e) = database_operations::save_records(conn, &transactions, "ethereum_transactions") {
-        println!("{} {}", "✘ Error saving data:".bright_red(), e);
-    } else {
-        println!("{}", "✔ Data saved successfully.".bright_green());
+    if total_transactions == 0 {
+        println!("{}", format!("No transactions found for the provided address ({})", range).yellow());
+        return Ok(());
     }
 
-    println!("{}", format!("\nRetrieved and processed {} transactions for address {}", total_transactions, address).green());
+    println!("{}", "✔ Data saved successfully.".bright_green());
+    println!("{}", format!(
+        "\nRetrieved and processed {} transactions for address {} ({}), {} of them new",
+        total_transactions, address, range, new
+    ).green());
     Ok(())
 }
 
-async fn scan_domain(config: &Config, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    if config.urlscan_api_key().is_none() {
-        println!("{}", "URLScan API key is not set. Please run 'setup' to configure.".red());
+async fn full_profile(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.transpose_api_key().is_none() {
+        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
         return Ok(());
     }
 
-    let domain: String = Input::new()
-        .with_prompt("Enter domain to scan")
-        .interact_text()?;
+    let chain = select_chain()?;
+    let address = prompt_address(config, db, chain).await?;
 
-    println!("{}", "[Step 1] Initiating domain scan".yellow());
-    match crate::api::urlscan::scan_domain(config, &domain, conn).await {
-        Ok(_) => println!("{}", format!("\nDomain scan completed for {}", domain).green()),
-        Err(e) => println!("{}", format!("Error scanning domain: {}", e).red()),
+    println!("{}", "[Step 0] Estimating cost".yellow());
+    let range = TransactionRange::default();
+    let estimate = transpose::estimate_transaction_pull(config, chain, std::slice::from_ref(&address), &range, None).await?;
+    println!("├─ Transactions: {}", estimate.transaction_count);
+    println!("└─ Requests: {} (plus token transfers)", estimate.requests);
+    if estimate.requests > config.confirm_request_threshold() {
+        let proceed = Confirm::new()
+            .with_prompt(format!("This profile needs at least {} requests. Continue?", estimate.requests))
+            .default(false)
+            .interact()?;
+        if !proceed {
+            println!("{}", "Full profile cancelled.".yellow());
+            return Ok(());
+        }
     }
 
+    println!("{}", "[Step 1] Fetching and saving account, transactions and token transfers".yellow());
+    match transpose::build_account_profile(config, db, chain, &address, None).await {
+        Ok(profile) => print_profile(&profile),
+        Err(e) => print_failure("Full profile stopped", &e),
+    }
     Ok(())
 }
 
-async fn settings_menu(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
-    println!("\nCurrent Settings:");
-    println!("\nAPI Integrations:");
-    println!("├─ Transpose API: {}", if config.transpose_api_key().is_some() {
-        "✅ Active".green()
-    } else {
-        "❌ API key not detected".red()
-    });
-    println!("└─ URLScan API: {}", if config.urlscan_api_key().is_some() {
-        "✅ Active".green()
-    } else {
-        "❌ API key not detected".red()
-    });
-
-    println!("\nDatabase: DuckDB");
-    println!("└─ Location: data/fragarach.duckdb");
+/// Prints the consolidated summary of a full profile
+fn print_profile(profile: &AccountProfile) {
+    output::status(format!("\nProfile of {} on {}", profile.address, profile.chain).bright_cyan());
+    output::status(format!("├─ Account type: {}", or_dash(profile.account_type.as_deref())));
+    output::status(format!("├─ Created: {}", or_dash(profile.created_at.as_deref())));
+    let capped = if profile.truncated { " (limit reached, older ones may be missing)" } else { "" };
+    output::status(format!("├─ Transactions: {}{}", profile.transactions, capped));
+    output::status(format!("├─ First activity: {}", or_dash(profile.first_activity.as_deref())));
+    output::status(format!("├─ Last activity: {}", or_dash(profile.last_activity.as_deref())));
+    match (&profile.token_transfers, &profile.token_transfers_error) {
+        (Some(transfers), _) => output::status(format!("└─ Token transfers: {}", transfers)),
+        (None, error) => output::status(format!("└─ Token transfers: not fetched ({})", error.as_deref().unwrap_or("unknown error")).yellow()),
+    }
+}
 
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Settings Menu")
+/// Asks for an optional date or block window, re-asking until it is valid
+fn prompt_transaction_range() -> Result<TransactionRange, dialoguer::Error> {
+    let limit = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Transactions to fetch")
         .default(0)
-        .items(&[
-            "🔌 Manage API Keys",
-            "↩️  Back"
-        ])
+        .items(&["Whole history", "Date range", "Block range"])
         .interact()?;
+    if limit == 0 {
+        return Ok(TransactionRange::default());
+    }
 
-    match selection {
-        0 => manage_integrations(config).await?,
-        1 => return Ok(()),
-        _ => unreachable!(),
+    loop {
+        let (from, to) = if limit == 1 {
+            ("From date (YYYY-MM-DD, empty for the first transaction)", "To date (YYYY-MM-DD, empty for today)")
+        } else {
+            ("From block (empty for the first)", "To block (empty for the latest)")
+        };
+        let from: String = Input::new().with_prompt(from).allow_empty(true).interact_text()?;
+        let to: String = Input::new().with_prompt(to).allow_empty(true).interact_text()?;
+        let (from, to) = (Some(from.trim()).filter(|s| !s.is_empty()), Some(to.trim()).filter(|s| !s.is_empty()));
+
+        let range = if limit == 1 {
+            TransactionRange::new(from, to, None, None)
+        } else {
+            let block = |value: Option<&str>| value
+                .map(|v| v.parse::<u64>().map_err(|_| FragarachError::InvalidInput(format!("{} is not a block number", v))))
+                .transpose();
+            block(from).and_then(|from| block(to).and_then(|to| TransactionRange::new(None, None, from, to)))
+        };
+        match range {
+            Ok(range) => return Ok(range),
+            Err(e) => println!("{} {}", "✘".bright_red(), e),
+        }
+    }
+}
+
+async fn query_token_transfers(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.transpose_api_key().is_none() {
+        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
+        return Ok(());
+    }
+
+    let chain = select_chain()?;
+    let address = prompt_address(config, db, chain).await?;
+
+    println!("{}", "[Step 1] Fetching and saving ERC-20 token transfers".yellow());
+    let (pages_tx, pages_rx) = mpsc::channel(transpose::PAGE_BUFFER);
+    let addresses = [address.clone()];
+    let fetch = transpose::query_token_transfers(config, chain, &addresses, None, pages_tx);
+    let total_transfers = match save_pages(db, "token_transfers", None, fetch, pages_rx).await.and_then(only_pull) {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            print_failure("Token transfer pull stopped", &e);
+            return Ok(());
+        }
+    };
+
+    if total_transfers == 0 {
+        println!("{}", "No token transfers found for the provided address".yellow());
+        return Ok(());
     }
 
+    println!("{}", "✔ Data saved successfully.".bright_green());
+    println!("{}", format!("\nRetrieved and processed {} token transfers for address {}", total_transfers, address).green());
     Ok(())
 }
 
-async fn manage_integrations(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
-    println!("\nCurrent Integration Status:");
-    println!("Transpose API: {}", if config.transpose_api_key().is_some() {
-        "✅ Active".green()
-    } else {
-        "❌ API key not detected".red()
-    });
-    println!("URLScan API: {}", if config.urlscan_api_key().is_some() {
-        "✅ Active".green()
-    } else {
-        "❌ API key not detected".red()
-    });
-
+async fn nft_menu(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
     let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select Integration to Configure")
+        .with_prompt("NFTs")
         .default(0)
-        .items(&[
-            "🔑 Configure Transpose API",
-            "🔑 Configure URLScan API",
-            "↩️  Back"
-        ])
+        .items(&["🔁 Transfers", "👜 Holdings", "↩️  Back"])
         .interact()?;
 
     match selection {
-        0 => set_transpose_api_key(config).await?,
-        1 => set_urlscan_api_key(config).await?,
-        2 => return Ok(()),
-        _ => unreachable!(),
+        0 => query_nft_transfers(config, db).await,
+        1 => query_nft_holdings(config, db).await,
+        _ => Ok(()),
+    }
+}
+
+async fn query_nft_transfers(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.transpose_api_key().is_none() {
+        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
+        return Ok(());
+    }
+
+    let chain = select_chain()?;
+    let address = prompt_address(config, db, chain).await?;
+
+    println!("{}", "[Step 1] Fetching and saving NFT transfers".yellow());
+    let (pages_tx, pages_rx) = mpsc::channel(transpose::PAGE_BUFFER);
+    let addresses = [address.clone()];
+    let fetch = transpose::query_nft_transfers(config, chain, &addresses, None, pages_tx);
+    let total_transfers = match save_pages(db, "nft_transfers", None, fetch, pages_rx).await.and_then(only_pull) {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            print_failure("NFT transfer pull stopped", &e);
+            return Ok(());
+        }
+    };
+
+    if total_transfers == 0 {
+        println!("{}", "No NFT transfers found for the provided address".yellow());
+        return Ok(());
     }
 
+    println!("{}", "✔ Data saved successfully.".bright_green());
+    println!("{}", format!("\nRetrieved and processed {} NFT transfers for address {}", total_transfers, address).green());
     Ok(())
 }
 
-async fn set_transpose_api_key(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
-    let api_key: String = Input::new()
-        .with_prompt("Enter your Transpose API key")
-        .interact_text()?;
+/// Runs a built-in or custom Transpose SQL query, prompting for each of its parameters
+async fn run_transpose_query(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.transpose_api_key().is_none() {
+        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
+        return Ok(());
+    }
+
+    let names = transpose::list_queries(config);
+    if let Some(dir) = config.sql_dir() {
+        println!("{}", format!("Custom queries are read from {}", dir.display()).bright_cyan());
+    }
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select query")
+        .default(0)
+        .items(&names)
+        .interact()?;
+    let name = &names[selection];
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(".env")?;
+    let template = match transpose::load_sql_query(config, name) {
+        Ok(template) => template,
+        Err(e) => {
+            print_failure("Could not load the query", &e);
+            return Ok(());
+        }
+    };
+    let mut values = Vec::new();
+    for param in transpose::query_params(&template) {
+        let value: String = Input::new()
+            .with_prompt(&param)
+            .interact_text()?;
+        values.push((param, value));
+    }
+    let params: Vec<(&str, &str)> = values.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
 
-    writeln!(file, "TRANSPOSE_API_KEY={}", api_key)?;
-    println!("{}", "Transpose API key saved successfully.".green());
-    
-    // Update the config with the new API key
-    config.set_transpose_api_key(Some(api_key));
+    println!("{}", "[Step 1] Running query".yellow());
+    let records = match transpose::query_transpose(config, &template, &params).await {
+        Ok(records) => records,
+        Err(e) => {
+            print_failure("Query failed", &e);
+            return Ok(());
+        }
+    };
+    db.record_query("transpose query".to_string(), name.clone(), records.len()).await?;
 
+    let Some(first) = records.first().and_then(|r| r.as_object()) else {
+        println!("{}", "Query returned no rows".yellow());
+        return Ok(());
+    };
+    let headers: Vec<String> = first.keys().cloned().collect();
+    let rows: Vec<Vec<String>> = records.iter()
+        .map(|record| headers.iter().map(|h| cell_text(&record[h])).collect())
+        .collect();
+    print_cyber_header(&format!("{} ({} rows)", name.to_uppercase(), rows.len()));
+    print_cyber_table(&headers, &rows);
     Ok(())
 }
 
-async fn set_urlscan_api_key(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
-    let api_key: String = Input::new()
-        .with_prompt("Enter your URLScan API key")
-        .interact_text()?;
+async fn query_nft_holdings(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.transpose_api_key().is_none() {
+        println!("{}", "Transpose API key is not set. Please run 'setup' to set it.".red());
+        return Ok(());
+    }
+
+    let chain = select_chain()?;
+    let address = prompt_address(config, db, chain).await?;
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(".env")?;
+    println!("{}", "[Step 1] Fetching and saving NFT holdings".yellow());
+    let owner = address.clone();
+    db.call(move |conn| database_operations::clear_nft_holdings(conn, chain.as_str(), &owner)).await?;
+    let (pages_tx, pages_rx) = mpsc::channel(transpose::PAGE_BUFFER);
+    let addresses = [address.clone()];
+    let fetch = transpose::query_nft_holdings(config, chain, &addresses, None, pages_tx);
+    let total_holdings = match save_pages(db, "nft_holdings", None, fetch, pages_rx).await.and_then(only_pull) {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            print_failure("NFT holdings pull stopped", &e);
+            return Ok(());
+        }
+    };
+    db.record_query(format!("{} nft holdings", chain), address.clone(), total_holdings).await?;
 
-    writeln!(file, "URLSCAN_API_KEY={}", api_key)?;
-    println!("{}", "✅ URLScan API key saved successfully.".green());
-    
-    // Update the config with the new API key
-    config.set_urlscan_api_key(Some(api_key));
+    if total_holdings == 0 {
+        println!("{}", "No NFTs held by the provided address".yellow());
+        return Ok(());
+    }
 
+    println!("{}", "✔ Data saved successfully.".bright_green());
+    println!("{}", format!("\nRetrieved and processed {} NFT holdings for address {}", total_holdings, address).green());
     Ok(())
 }
+
+/// How a transaction pull went
+struct TransactionPull {
+    /// How each address went, in the order given
+    pulls: Vec<AddressPull>,
+    /// Distinct transactions saved, fewer than the sum fetched when the
+    /// addresses transacted with each other
+    unique: usize,
+    /// Transactions that weren't stored before the pull
+    new: u64,
+}
+
+/// Fetches transactions in `range` for addresses from `source` and saves them page by page
+///
+/// Renders fetch and save positions while running. Addresses pulled completely
+/// up to the latest block have their sync mark moved on (see
+/// `database_operations::advance_sync_mark`). On failure, pages saved so far
+/// stay committed.
+async fn pull_transactions(
+    config: &Config,
+    db: &DatabaseHandle,
+    source: DataSource,
+    chain: Chain,
+    addresses: &[String],
+    max: Option<usize>,
+    range: &TransactionRange,
+) -> Result<TransactionPull, FragarachError> {
+    let stored = || db.call(|conn| database_operations::count_records(conn, "ethereum_transactions", &[]));
+    let before = stored().await?;
+
+    let (pages_tx, pages_rx) = mpsc::channel(transpose::PAGE_BUFFER);
+    let mut seen = HashSet::new();
+    let fetch = source.stream_transactions(config, chain, addresses, max, range, pages_tx);
+    let pulls = save_pages(db, "ethereum_transactions", Some(&mut seen), fetch, pages_rx).await?;
+
+    let complete = pulls.iter().filter(|pull| pull.error.is_none());
+    for pull in complete.clone() {
+        db.record_query(format!("{} transactions", chain), pull.address.clone(), pull.fetched).await?;
+    }
+    if let Some(from_block) = range.sync_start() {
+        for pull in complete.filter(|pull| !pull.truncated) {
+            let address = pull.address.clone();
+            db.call(move |conn| database_operations::advance_sync_mark(conn, chain.as_str(), &address, from_block)).await?;
+        }
+    }
+
+    let new = stored().await?.saturating_sub(before);
+    Ok(TransactionPull { pulls, unique: seen.len(), new })
+}
+
+/// Range an incremental sync of `address` pulls: everything from its sync mark
+/// on, or the whole history before its first complete pull
+async fn incremental_range(db: &DatabaseHandle, chain: Chain, address: &str) -> Result<(Option<u64>, TransactionRange), FragarachError> {
+    let address = address.to_string();
+    let mark = db.call(move |conn| database_operations::sync_mark(conn, chain.as_str(), &address)).await?;
+    Ok((mark, mark.map(TransactionRange::since_block).unwrap_or_default()))
+}
+
+/// Rows fetched for the only address of a pull, or the error that stopped it
+fn only_pull(mut pulls: Vec<AddressPull>) -> Result<usize, FragarachError> {
+    match pulls.pop() {
+        Some(AddressPull { error: Some(e), .. }) => Err(e),
+        Some(pull) => Ok(pull.fetched),
+        None => Ok(0),
+    }
+}
+
+/// Saves each page `fetch` sends into `table` while it is still fetching
+///
+/// Renders fetch and save positions while running. With `seen_transactions`,
+/// transactions already in it are skipped and the rest added. Returns what
+/// `fetch` returns; on failure, pages saved so far stay committed.
+async fn save_pages(
+    db: &DatabaseHandle,
+    table: &'static str,
+    mut seen_transactions: Option<&mut HashSet<String>>,
+    fetch: impl Future<Output = Result<Vec<AddressPull>, FragarachError>>,
+    mut pages_rx: mpsc::Receiver<Vec<serde_json::Value>>,
+) -> Result<Vec<AddressPull>, FragarachError> {
+    let progress = PipelineProgress::new();
+
+    let save = async {
+        while let Some(mut page) = pages_rx.recv().await {
+            progress.fetched.set(progress.saved.get() + 1 + pages_rx.len());
+            progress.render();
+
+            if let Some(seen) = seen_transactions.as_deref_mut() {
+                transpose::retain_unseen_transactions(&mut page, seen);
+            }
+            let rows = page.len();
+            if rows > 0 {
+                db.save_records(page, table).await?;
+            }
+            progress.saved.set(progress.saved.get() + 1);
+            progress.saved_rows.set(progress.saved_rows.get() + rows);
+            progress.render();
+        }
+        Ok(())
+    };
+
+    // Whichever side fails first cancels the other; saved pages stay committed
+    let result = tokio::try_join!(fetch, save);
+    progress.finish();
+
+    let failed = match &result {
+        Ok((pulls, ())) => pulls.iter().any(|pull| pull.error.is_some()),
+        Err(_) => true,
+    };
+    if failed {
+        output::status(format!("{} rows from {} pages were saved before the failure.",
+            progress.saved_rows.get(), progress.saved.get()).yellow());
+    }
+    if let Some(credits) = transpose::remaining_credits().await {
+        output::status(format!("└─ Transpose credits remaining: {}", credits));
+    }
+    result.map(|(fetched, ())| fetched)
+}
+
+/// Page positions of a fetch-and-save pipeline
+///
+/// Shown as a spinner in the interactive menu, otherwise as a line rewritten
+/// in place.
+struct PipelineProgress {
+    fetched: Cell<usize>,
+    saved: Cell<usize>,
+    saved_rows: Cell<usize>,
+    bar: Option<ProgressBar>,
+}
+
+impl PipelineProgress {
+    fn new() -> Self {
+        let bar = output::progress_bars().then(|| {
+            let bar = ProgressBar::new_spinner().with_message("Waiting for the first page").with_style(
+                ProgressStyle::with_template("├─ {spinner:.cyan} {msg} [{elapsed}]")
+                    .expect("valid progress template"),
+            );
+            bar.enable_steady_tick(Duration::from_millis(120));
+            bar
+        });
+        PipelineProgress { fetched: Cell::new(0), saved: Cell::new(0), saved_rows: Cell::new(0), bar }
+    }
+
+    fn render(&self) {
+        let line = format!(
+            "Fetched pages: {} │ Saved pages: {} ({} rows)",
+            self.fetched.get(), self.saved.get(), self.saved_rows.get()
+        );
+        if let Some(bar) = &self.bar {
+            bar.set_message(line);
+        } else if !output::is_quiet() {
+            print!("\r├─ {}", line);
+            let _ = std::io::stdout().flush();
+        }
+    }
+
+    /// Leaves the final positions on screen and ends the progress line
+    fn finish(&self) {
+        match &self.bar {
+            Some(bar) => bar.finish(),
+            None => output::status(""),
+        }
+    }
+}
+
+async fn scan_domain(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.urlscan_api_key().is_none() {
+        println!("{}", "URLScan API key is not set. Please run 'setup' to configure.".red());
+        return Ok(());
+    }
+
+    let input: String = Input::new()
+        .with_prompt("Enter domain or URL")
+        .interact_text()?;
+    let target = match urlscan::ScanTarget::parse(&input) {
+        Ok(target) => target,
+        Err(e) => {
+            println!("{}", e.to_string().red());
+            return Ok(());
+        }
+    };
+    print_tags(db, EntityType::Domain, &target.domain).await?;
+
+    let visibility_idx = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Scan visibility")
+        .default(Visibility::ALL.iter().position(|v| *v == config.urlscan_visibility()).unwrap_or(0))
+        .items(&Visibility::ALL)
+        .interact()?;
+    let visibility = Visibility::ALL[visibility_idx];
+
+    println!("{}", "[Step 0] Checking submission quota".yellow());
+    match urlscan::get_submission_quota(config, visibility.as_str()).await {
+        Ok(quota) => {
+            println!("├─ Submissions: 1 of {} remaining today ({}/{} used)", quota.remaining, quota.used, quota.limit);
+            println!("└─ Estimated time: up to {}s", config.urlscan_poll_timeout().as_secs());
+
+            if quota.remaining == 0 {
+                let proceed = Confirm::new()
+                    .with_prompt(format!("Daily {} scan quota is exhausted. Submit anyway?", visibility))
+                    .default(false)
+                    .interact()?;
+                if !proceed {
+                    println!("{}", "Domain scan cancelled.".yellow());
+                    return Ok(());
+                }
+            }
+        }
+        Err(e) => println!("{}", format!("Could not read URLScan quota: {}", e).yellow()),
+    }
+
+    println!("{}", "[Step 1] Initiating domain scan".yellow());
+    let scan = match urlscan::scan_and_save(config, &target.url, visibility, db).await {
+        Ok(scan) => scan,
+        Err(e) => {
+            print_failure("Error scanning domain", &e);
+            return Ok(());
+        }
+    };
+    if scan.completed {
+        println!("{}", format!("\nScan completed for {}", target.url).green());
+    } else {
+        println!("{}", format!("\nScan of {} is still running; saved as pending (UUID: {})", target.url, scan.uuid).yellow());
+    }
+
+    // A report the scan already read only needs its resolution history
+    let prompt = match &scan.virustotal {
+        Some(_) => format!("Look up the VirusTotal resolution history of {}?", target.domain),
+        None => format!("Look up {} on VirusTotal?", target.domain),
+    };
+    if config.virustotal_api_key().is_some() && Confirm::new()
+        .with_prompt(prompt)
+        .default(true)
+        .interact()?
+    {
+        println!("{}", "[Step 2] Looking up VirusTotal report".yellow());
+        virustotal_lookup(config, db, &target.domain, scan.virustotal).await?;
+    }
+
+    Ok(())
+}
+
+/// Looks up a domain on VirusTotal alone, without a URLScan submission
+async fn virustotal_menu(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.virustotal_api_key().is_none() {
+        println!("{}", "VirusTotal API key is not set. Please run 'setup' to configure.".red());
+        return Ok(());
+    }
+
+    let domain: String = Input::new()
+        .with_prompt("Enter domain to look up")
+        .interact_text()?;
+    virustotal_lookup(config, db, &domain, None).await
+}
+
+/// Looks up an IP address on Shodan, saves what it knows and prints it
+async fn lookup_ip(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    if config.shodan_api_key().is_none() {
+        println!("{}", "Shodan API key is not set. Please run 'setup' to configure.".red());
+        return Ok(());
+    }
+
+    let ip: String = Input::new()
+        .with_prompt("Enter IP address")
+        .interact_text()?;
+    let record = match shodan::lookup_ip(config, &ip).await {
+        Ok(record) => record,
+        Err(e) => {
+            print_failure("Error looking up IP on Shodan", &e);
+            return Ok(());
+        }
+    };
+    if let Err(e) = shodan::save(db, record.clone()).await {
+        println!("{} {}", "✘ Could not save Shodan data:".bright_red(), e);
+    }
+    db.record_query("shodan lookup".to_string(), record.ip.clone(), usize::from(record.found)).await?;
+
+    if !record.found {
+        println!("{}", format!("Shodan has no information on {}", record.ip).yellow());
+        return Ok(());
+    }
+    print_cyber_header(&format!("SHODAN {}", record.ip));
+    let ports: Vec<String> = record.ports.iter().map(u64::to_string).collect();
+    println!("├─ Organisation: {}", or_dash(record.org.as_ref()));
+    println!("├─ ISP: {}", or_dash(record.isp.as_ref()));
+    println!("├─ ASN: {}", or_dash(record.asn.as_ref()));
+    println!("├─ Country: {}", or_dash(record.country.as_ref()));
+    println!("├─ Open ports: {}", if ports.is_empty() { "-".to_string() } else { ports.join(", ") });
+    println!("├─ Host names: {}", if record.hostnames.is_empty() { "-".to_string() } else { record.hostnames.join(", ") });
+    println!("└─ Last crawled: {}", or_dash(record.last_update.as_ref()));
+    Ok(())
+}
+
+/// Fetches, saves and prints the VirusTotal report of a domain
+///
+/// `scanned` is a report already read for the domain, which is reused rather
+/// than requested again.
+async fn virustotal_lookup(
+    config: &Config,
+    db: &DatabaseHandle,
+    domain: &str,
+    scanned: Option<virustotal::Report>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let lookup = match scanned {
+        Some(report) => virustotal::lookup_resolutions(config, report).await,
+        None => virustotal::lookup_domain(config, domain).await,
+    };
+    let lookup = match lookup {
+        Ok(lookup) => lookup,
+        Err(e) => {
+            print_failure("Error looking up domain on VirusTotal", &e);
+            return Ok(());
+        }
+    };
+    if let Err(e) = virustotal::save(db, lookup.clone()).await {
+        println!("{} {}", "✘ Could not save VirusTotal report:".bright_red(), e);
+    }
+    let report = &lookup.report;
+    db.record_query("virustotal lookup".to_string(), report.target.clone(), 1).await?;
+
+    print_cyber_header(&format!("VIRUSTOTAL {}", report.target));
+    let detections = format!(
+        "{} ({} malicious, {} suspicious, {} harmless, {} undetected)",
+        report.detection_ratio(), report.malicious, report.suspicious, report.harmless, report.undetected
+    );
+    if report.malicious > 0 {
+        println!("├─ Detections: {}", detections.bright_red());
+    } else {
+        println!("├─ Detections: {}", detections);
+    }
+    println!("├─ Reputation: {}", or_dash(report.reputation));
+    let categories: Vec<String> = report.categories.iter()
+        .map(|(vendor, category)| format!("{} ({})", category, vendor))
+        .collect();
+    println!("└─ Categories: {}", if categories.is_empty() { "-".to_string() } else { categories.join(", ") });
+
+    if !lookup.resolutions.is_empty() {
+        let headers: Vec<String> = ["ip_address", "last_resolved"].iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<String>> = lookup.resolutions.iter()
+            .map(|resolution| vec![resolution.ip_address.clone(), or_dash(resolution.date.as_ref())])
+            .collect();
+        print_cyber_table(&headers, &rows);
+    }
+    Ok(())
+}
+
+async fn search_urlscan(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let domain: String = Input::new()
+        .with_prompt("Enter domain to search for")
+        .interact_text()?;
+
+    println!("{}", "[Step 1] Searching existing URLScan scans".yellow());
+    let mut hits = Vec::new();
+    let mut cursor: Option<String> = None;
+    let selected = loop {
+        match urlscan::search_domain(config, &domain, cursor.as_deref()).await {
+            Ok(page) => {
+                hits.extend(page.hits);
+                cursor = page.next;
+            }
+            Err(e) => {
+                print_failure("Error searching URLScan", &e);
+                return Ok(());
+            }
+        }
+        if hits.is_empty() {
+            println!("{}", format!("No existing scans found for {}", domain).yellow());
+            return Ok(());
+        }
+
+        let mut items: Vec<String> = hits.iter()
+            .map(|hit| {
+                let verdict = match hit.malicious {
+                    Some(true) => "malicious".red().to_string(),
+                    Some(false) => "clean".green().to_string(),
+                    None => "no verdict".to_string(),
+                };
+                format!("{} │ {} │ {} │ {}", hit.time, hit.uuid, hit.url, verdict)
+            })
+            .collect();
+        if cursor.is_some() {
+            items.push("⬇️  Load more results".to_string());
+        }
+        items.push("↩️  Back".to_string());
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select a scan to import")
+            .default(0)
+            .items(&items)
+            .interact()?;
+        if selection < hits.len() {
+            break &hits[selection];
+        }
+        if cursor.is_none() || selection > hits.len() {
+            return Ok(());
+        }
+    };
+
+    println!("{}", "[Step 2] Importing scan result".yellow());
+    match urlscan::import_scan(config, &domain, &selected.uuid, db).await {
+        Ok(scan) => println!("{}", format!("✔ Imported scan {} for {}", scan.uuid, domain).bright_green()),
+        Err(e) => print_failure("Error importing scan", &e),
+    }
+
+    Ok(())
+}
+
+/// Prints why an operation failed, with what the user can do about it when known
+fn print_failure(context: &str, err: &FragarachError) {
+    println!("{} {}", format!("✘ {}:", context).bright_red(), err);
+    if let Some(hint) = err.hint() {
+        println!("{}", format!("  {}", hint).yellow());
+    }
+}
+
+/// Resolves and saves the DNS records of a domain, without a URLScan submission
+async fn dns_lookup(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let domain: String = Input::new()
+        .with_prompt("Enter domain to resolve")
+        .interact_text()?;
+
+    let lookup = match dns::resolve(config, &domain).await {
+        Ok(lookup) => lookup,
+        Err(e) => {
+            print_failure("Error resolving domain", &e);
+            return Ok(());
+        }
+    };
+    for failure in &lookup.failures {
+        println!("{}", format!("⚠ {}", failure).yellow());
+    }
+    if lookup.records.is_empty() {
+        println!("{}", format!("No DNS records found for {}", lookup.domain).yellow());
+        return Ok(());
+    }
+
+    if let Err(e) = dns::save(db, &lookup).await {
+        println!("{} {}", "✘ Could not save DNS records:".bright_red(), e);
+    }
+    db.record_query("dns lookup".to_string(), lookup.domain.clone(), lookup.records.len()).await?;
+
+    let headers: Vec<String> = ["type", "value", "ttl"].iter().map(|h| h.to_string()).collect();
+    let rows: Vec<Vec<String>> = lookup.records.iter()
+        .map(|record| vec![record.record_type.clone(), record.value.clone(), record.ttl.to_string()])
+        .collect();
+    print_cyber_header(&format!("DNS {}", lookup.domain));
+    print_cyber_table(&headers, &rows);
+    Ok(())
+}
+
+async fn whois_lookup(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let domain: String = Input::new()
+        .with_prompt("Enter domain or URL")
+        .interact_text()?;
+
+    let record = match whois::lookup(config, &domain).await {
+        Ok(record) => record,
+        Err(e) => {
+            print_failure("Error looking up registration data", &e);
+            return Ok(());
+        }
+    };
+    if let Err(e) = whois::save(db, record.clone()).await {
+        println!("{} {}", "✘ Could not save registration data:".bright_red(), e);
+    }
+    db.record_query("whois lookup".to_string(), record.domain.clone(), 1).await?;
+
+    print_cyber_header(&format!("WHOIS {}", record.domain));
+    println!("├─ Registrar: {}", or_dash(record.registrar.as_ref()));
+    println!("├─ Registrant: {}", or_dash(record.registrant.as_ref()));
+    println!("├─ Registrant country: {}", or_dash(record.registrant_country.as_ref()));
+    println!("├─ Registered: {}", or_dash(record.registered.as_ref()));
+    println!("├─ Expires: {}", or_dash(record.expires.as_ref()));
+    println!("├─ Updated: {}", or_dash(record.updated.as_ref()));
+    println!("├─ Nameservers: {}", if record.nameservers.is_empty() { "-".to_string() } else { record.nameservers.join(", ") });
+    println!("└─ Source: {}", if record.source == "rdap" { "RDAP" } else { "WHOIS" });
+    Ok(())
+}
+
+/// Text shown for a stored value in a table cell
+fn cell_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "-".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+async fn search_scanned_domains(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Search scanned domains by")
+        .default(0)
+        .items(&["Domain name contains", "Verdict score at least", "Page content (DOM snapshot) contains", "↩️  Back"])
+        .interact()?;
+    if mode == 2 {
+        return search_doms(db).await;
+    }
+
+    let (column, value, label) = match mode {
+        0 => {
+            let fragment: String = Input::new()
+                .with_prompt("Part of the domain")
+                .interact_text()?;
+            let label = format!("domains matching '{}'", fragment.trim());
+            ("domain", format!("%{}%", fragment.trim()), label)
+        }
+        1 => {
+            let threshold: String = Input::new()
+                .with_prompt("Minimum verdict score (0-100)")
+                .interact_text()?;
+            let Ok(threshold) = threshold.trim().parse::<i64>() else {
+                println!("{}", format!("'{}' is not a whole number.", threshold.trim()).red());
+                return Ok(());
+            };
+            ("verdict_score", threshold.to_string(), format!("verdict score ≥ {}", threshold))
+        }
+        _ => return Ok(()),
+    };
+
+    let query = move |conn: &Connection| {
+        let filter = if mode == 0 { Filter::Like(column, &value) } else { Filter::AtLeast(column, &value) };
+        database_operations::query_records(conn, "urlscan_domain_data", &[filter], None)
+    };
+    let records = match db.call(query).await {
+        Ok(records) => records,
+        Err(e) => {
+            println!("{} {}", "✘ Search failed:".bright_red(), e);
+            return Ok(());
+        }
+    };
+
+    if records.is_empty() {
+        println!("{}", format!("No scanned domains with {}", label).yellow());
+        return Ok(());
+    }
+
+    let headers: Vec<String> = ["domain", "uuid", "title", "ip", "verdict_score", "screenshot_path"]
+        .iter().map(|h| h.to_string()).collect();
+    let rows: Vec<Vec<String>> = records.iter()
+        .map(|record| headers.iter().map(|h| cell_text(&record[h])).collect())
+        .collect();
+
+    print_cyber_header(&format!("SCANNED DOMAINS: {} ({} found)", label.to_uppercase(), rows.len()));
+    print_paged_table(&headers, &rows)?;
+    Ok(())
+}
+
+/// Characters of page content shown on each side of a DOM search match
+const DOM_SNIPPET_CONTEXT: usize = 40;
+
+/// Finds scans whose stored page content contains a string, such as a wallet address
+async fn search_doms(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let needle: String = Input::new()
+        .with_prompt("Text to find (case-insensitive)")
+        .interact_text()?;
+    let limit: usize = Input::new()
+        .with_prompt("Maximum matches to show")
+        .default(BROWSE_PAGE_SIZE)
+        .interact_text()?;
+
+    let case_id = db.active_case();
+    let text = needle.trim().to_string();
+    let matches = match db.call(move |conn| database_operations::search_doms(conn, &text, DOM_SNIPPET_CONTEXT, limit, case_id)).await {
+        Ok(matches) => matches,
+        Err(e) => {
+            println!("{} {}", "✘ Search failed:".bright_red(), e);
+            return Ok(());
+        }
+    };
+    if matches.is_empty() {
+        println!("{}", format!("No DOM snapshots contain '{}'", needle.trim()).yellow());
+        return Ok(());
+    }
+
+    let headers: Vec<String> = ["domain", "scanned", "uuid", "context"]
+        .iter().map(|h| h.to_string()).collect();
+    let rows: Vec<Vec<String>> = matches.iter()
+        .map(|m| vec![
+            or_dash(m.domain.as_deref()),
+            or_dash(m.scanned_at.as_deref()),
+            m.uuid.clone(),
+            m.snippet.clone(),
+        ])
+        .collect();
+    print_cyber_header(&format!("DOM SNAPSHOTS CONTAINING '{}' ({} found)", needle.trim(), rows.len()));
+    print_paged_table(&headers, &rows)?;
+    Ok(())
+}
+
+/// Rows shown per page when browsing stored data or search results
+const BROWSE_PAGE_SIZE: usize = 20;
+
+/// Asks whether to show the next page after `shown` of `total` rows
+fn show_more(shown: usize, total: usize) -> Result<bool, dialoguer::Error> {
+    let answer: String = Input::new()
+        .with_prompt(format!("Showing {} of {}. Enter for the next page, q to stop", shown, total))
+        .allow_empty(true)
+        .interact_text()?;
+    Ok(!answer.trim().eq_ignore_ascii_case("q"))
+}
+
+/// Prints rows already read `BROWSE_PAGE_SIZE` at a time, asking before each further page
+fn print_paged_table(headers: &[String], rows: &[Vec<String>]) -> Result<(), dialoguer::Error> {
+    let mut shown = 0;
+    for page in rows.chunks(BROWSE_PAGE_SIZE) {
+        if shown > 0 {
+            if !show_more(shown, rows.len())? {
+                break;
+            }
+            println!();
+        }
+        print_cyber_table(headers, page);
+        shown += page.len();
+    }
+    Ok(())
+}
+
+/// Text shown for an optional stored value
+fn or_dash(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+async fn browse_data(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Browse saved data")
+        .default(0)
+        .items(&["💰 Ethereum Address", "🌐 Scanned Domain", "↩️  Back"])
+        .interact()?;
+
+    match selection {
+        0 => browse_account(db).await,
+        1 => browse_domain(db).await,
+        _ => Ok(()),
+    }
+}
+
+async fn browse_account(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let case_id = db.active_case();
+    let accounts = match db.call(move |conn| database_operations::list_accounts(conn, case_id)).await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            println!("{} {}", "✘ Could not read saved accounts:".bright_red(), e);
+            return Ok(());
+        }
+    };
+    if accounts.is_empty() {
+        println!("{}", "No saved accounts yet. Query one with 'Query Ethereum Account' first.".yellow());
+        return Ok(());
+    }
+
+    let items: Vec<String> = accounts.iter()
+        .map(|a| format!("{} │ {} │ {}", a.address, a.chain, or_dash(a.account_type.as_deref())))
+        .collect();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select an address")
+        .default(0)
+        .items(&items)
+        .interact()?;
+    let account = &accounts[selection];
+
+    print_cyber_header(&format!("ACCOUNT {}", account.address));
+    println!("├─ Chain: {}", account.chain);
+    println!("├─ Type: {}", or_dash(account.account_type.as_deref()));
+    println!("├─ Created: {}", or_dash(account.created_timestamp.as_deref()));
+    println!("├─ Last active: {}", or_dash(account.last_active_timestamp.as_deref()));
+    print_tags(db, EntityType::Address, &account.address).await?;
+    println!("└─ Creator: {}", or_dash(account.creator_address.as_deref()));
+
+    let (chain, address) = (account.chain.clone(), account.address.clone());
+    let total = db.call(move |conn| database_operations::count_transactions(conn, &chain, &address, case_id)).await? as usize;
+    println!("
+{}", format!("Saved transactions: {}", total).bright_cyan());
+
+    let headers: Vec<String> = ["block", "timestamp", "hash", "from", "to", "value"]
+        .iter().map(|h| h.to_string()).collect();
+    let mut offset = 0;
+    while offset < total {
+        let (chain, address) = (account.chain.clone(), account.address.clone());
+        let page = db.call(move |conn| {
+            database_operations::get_transactions_for_address(conn, &chain, &address, case_id, BROWSE_PAGE_SIZE, offset)
+        }).await?;
+        if page.is_empty() {
+            break;
+        }
+        offset += page.len();
+
+        let rows: Vec<Vec<String>> = page.iter()
+            .map(|tx| vec![
+                or_dash(tx.block_number),
+                or_dash(tx.timestamp.as_deref()),
+                tx.transaction_hash.clone(),
+                or_dash(tx.from_address.as_deref()),
+                or_dash(tx.to_address.as_deref()),
+                or_dash(tx.value),
+            ])
+            .collect();
+        println!();
+        print_cyber_table(&headers, &rows);
+
+        if offset < total && !show_more(offset, total)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+async fn browse_domain(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let case_id = db.active_case();
+    let scans = match db.call(move |conn| database_operations::list_scanned_domains(conn, case_id)).await {
+        Ok(scans) => scans,
+        Err(e) => {
+            println!("{} {}", "✘ Could not read scanned domains:".bright_red(), e);
+            return Ok(());
+        }
+    };
+    if scans.is_empty() {
+        println!("{}", "No scanned domains yet. Scan one with 'Scan Domain' first.".yellow());
+        return Ok(());
+    }
+
+    // Scans are newest first, so each domain is listed where its latest scan is
+    let mut domains: Vec<&str> = Vec::new();
+    for scan in &scans {
+        if !domains.contains(&scan.domain.as_str()) {
+            domains.push(&scan.domain);
+        }
+    }
+    let items: Vec<String> = domains.iter()
+        .map(|d| format!("{} ({} scans)", d, scans.iter().filter(|s| s.domain == *d).count()))
+        .collect();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select a domain")
+        .default(0)
+        .items(&items)
+        .interact()?;
+    let domain = domains[selection];
+
+    let headers: Vec<String> = ["scanned", "uuid", "status", "title", "ip", "score", "brands"]
+        .iter().map(|h| h.to_string()).collect();
+    let rows: Vec<Vec<String>> = scans.iter()
+        .filter(|s| s.domain == domain)
+        .map(|s| vec![
+            or_dash(s.created_at.as_deref()),
+            s.uuid.clone(),
+            or_dash(s.status.as_deref()),
+            or_dash(s.title.as_deref()),
+            or_dash(s.ip.as_deref()),
+            or_dash(s.verdict_score),
+            or_dash(s.verdict_brands.as_deref()),
+        ])
+        .collect();
+
+    print_cyber_header(&format!("DOMAIN {}", domain));
+    print_tags(db, EntityType::Domain, domain).await?;
+    print_cyber_table(&headers, &rows);
+    Ok(())
+}
+
+/// Lists the most recent queries in the active case (or in any), newest first
+async fn show_history(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let limit: usize = Input::new()
+        .with_prompt("Number of queries to show")
+        .default(BROWSE_PAGE_SIZE)
+        .interact_text()?;
+
+    let case_id = db.active_case();
+    let history = match db.call(move |conn| database_operations::list_history(conn, case_id, limit)).await {
+        Ok(history) => history,
+        Err(e) => {
+            println!("{} {}", "✘ Could not read query history:".bright_red(), e);
+            return Ok(());
+        }
+    };
+    if history.is_empty() {
+        println!("{}", "No queries logged yet.".yellow());
+        return Ok(());
+    }
+
+    let headers: Vec<String> = ["time", "operation", "target", "results"]
+        .iter().map(|h| h.to_string()).collect();
+    let rows: Vec<Vec<String>> = history.iter()
+        .map(|entry| vec![
+            or_dash(entry.queried_at.as_deref()),
+            entry.operation.clone(),
+            entry.target.clone(),
+            or_dash(entry.result_count),
+        ])
+        .collect();
+    print_cyber_header("QUERY HISTORY");
+    print_cyber_table(&headers, &rows);
+    Ok(())
+}
+
+async fn view_records(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let table_idx = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select table to view")
+        .default(0)
+        .items(export::EXPORTABLE_TABLES)
+        .interact()?;
+    let table = export::EXPORTABLE_TABLES[table_idx];
+
+    let filter: String = Input::new()
+        .with_prompt("Filter as column=value (leave empty for all rows)")
+        .allow_empty(true)
+        .interact_text()?;
+    let filter = match filter.split_once('=') {
+        Some((column, value)) => Some((column.trim().to_string(), value.trim().to_string())),
+        None if filter.trim().is_empty() => None,
+        None => {
+            println!("{}", "Filters must look like column=value.".red());
+            return Ok(());
+        }
+    };
+
+    let count_filter = filter.clone();
+    let total = match db.call(move |conn| {
+        let filters: Vec<Filter> = count_filter.iter().map(|(c, v)| Filter::Equals(c, v)).collect();
+        database_operations::count_records(conn, table, &filters)
+    }).await {
+        Ok(total) => total as usize,
+        Err(e) => {
+            println!("{} {}", "✘ Query failed:".bright_red(), e);
+            return Ok(());
+        }
+    };
+    if total == 0 {
+        println!("{}", format!("No records found in {}", table).yellow());
+        return Ok(());
+    }
+    print_cyber_header(&format!("{} ({} rows)", table.to_uppercase(), total));
+
+    let mut offset = 0;
+    while offset < total {
+        let page_filter = filter.clone();
+        let records = db.call(move |conn| {
+            let filters: Vec<Filter> = page_filter.iter().map(|(c, v)| Filter::Equals(c, v)).collect();
+            database_operations::query_records_page(conn, table, &filters, Some(BROWSE_PAGE_SIZE), offset)
+        }).await?;
+        let Some(first) = records.first().and_then(|r| r.as_object()) else {
+            break;
+        };
+        offset += records.len();
+
+        let headers: Vec<String> = first.keys().cloned().collect();
+        let rows: Vec<Vec<String>> = records.iter()
+            .map(|record| headers.iter().map(|h| cell_text(&record[h])).collect())
+            .collect();
+        println!();
+        print_cyber_table(&headers, &rows);
+
+        if offset < total && !show_more(offset, total)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs an ad-hoc `SELECT` against the database and shows or saves its rows
+async fn run_query(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", format!("Tables: {}", export::EXPORTABLE_TABLES.join(", ")).bright_cyan());
+    let sql: String = Input::new()
+        .with_prompt("SELECT query")
+        .interact_text()?;
+
+    let query = sql.clone();
+    let records = match db.call(move |conn| database_operations::run_read_query(conn, &query)).await {
+        Ok(records) => records,
+        Err(FragarachError::Database(duckdb::Error::InvalidQuery)) => {
+            println!("{}", "✘ Only SELECT (or WITH ... SELECT) queries can be run here.".bright_red());
+            return Ok(());
+        }
+        Err(e) => {
+            println!("{} {}", "✘ Query failed:".bright_red(), e);
+            return Ok(());
+        }
+    };
+
+    let Some(first) = records.first().and_then(|r| r.as_object()) else {
+        println!("{}", "Query returned no rows".yellow());
+        return Ok(());
+    };
+    let headers: Vec<String> = first.keys().cloned().collect();
+    let rows: Vec<Vec<String>> = records.iter()
+        .map(|record| headers.iter().map(|h| cell_text(&record[h])).collect())
+        .collect();
+
+    print_cyber_header(&format!("QUERY RESULT ({} rows)", rows.len()));
+    print_paged_table(&headers, &rows)?;
+
+    let save = Confirm::new()
+        .with_prompt("Save the result to a CSV file?")
+        .default(false)
+        .interact()?;
+    if !save {
+        return Ok(());
+    }
+
+    let out: String = Input::new()
+        .with_prompt("Output file")
+        .default(paths::exports_dir().join("query.csv").display().to_string())
+        .interact_text()?;
+    let out = PathBuf::from(out);
+    let overwrite = out.exists() && Confirm::new()
+        .with_prompt(format!("{} already exists. Overwrite it?", out.display()))
+        .default(false)
+        .interact()?;
+    if out.exists() && !overwrite {
+        println!("{}", "Export cancelled.".yellow());
+        return Ok(());
+    }
+
+    let destination = out.display().to_string();
+    match db.call(move |conn| export::export_query_csv(conn, &sql, &out, overwrite)).await {
+        Ok(rows) => println!("{}", format!("✔ Exported {} rows to {}", rows, destination).bright_green()),
+        Err(e) => println!("{} {}", "✘ Export failed:".bright_red(), e),
+    }
+
+    Ok(())
+}
+
+async fn export_data(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let mut items: Vec<&str> = export::EXPORTABLE_TABLES.to_vec();
+    items.push("address graph (DOT/GraphML)");
+    let table_idx = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select table to export")
+        .default(0)
+        .items(&items)
+        .interact()?;
+    if table_idx == export::EXPORTABLE_TABLES.len() {
+        return export_graph(db).await;
+    }
+    let table = export::EXPORTABLE_TABLES[table_idx];
+
+    let format = match Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select export format")
+        .default(0)
+        .items(&["Parquet", "CSV", "JSON"])
+        .interact()?
+    {
+        0 => ExportFormat::Parquet,
+        1 => ExportFormat::Csv,
+        _ => ExportFormat::Json,
+    };
+
+    let filter: String = Input::new()
+        .with_prompt(format!("Filter by {} (leave empty for all rows)", export::filter_kind(table)))
+        .allow_empty(true)
+        .interact_text()?;
+    let filter = Some(filter.trim().to_string()).filter(|f| !f.is_empty());
+
+    let case_id = match (db.active_case(), config.active_case()) {
+        (Some(id), Some(name)) => Confirm::new()
+            .with_prompt(format!("Only export records in case '{}'?", name))
+            .default(true)
+            .interact()?
+            .then_some(id),
+        _ => None,
+    };
+
+    // JSON is written row by row and can only go to a local file
+    let to_s3 = !matches!(format, ExportFormat::Json) && Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select destination")
+        .default(0)
+        .items(&["💾 Local file", "☁️  S3"])
+        .interact()? == 1;
+
+    if to_s3 {
+        match config.s3_credentials() {
+            Some(credentials) => {
+                let mut prompt = Input::<String>::new().with_prompt("S3 prefix (s3://bucket/path)");
+                if let Some(uri) = config.s3_uri() {
+                    prompt = prompt.default(uri);
+                }
+                let prefix = prompt.interact_text()?;
+
+                println!("{}", "[Step 1] Uploading to S3".yellow());
+                let filter = filter.clone();
+                match db.call(move |conn| export::export_table_s3(conn, table, format, filter.as_deref(), case_id, &prefix, &credentials)).await {
+                    Ok((destination, rows)) => {
+                        println!("{}", format!("✔ Exported and verified {} rows to {}", rows, destination).bright_green());
+                        return Ok(());
+                    }
+                    Err(FragarachError::ExtensionUnavailable { .. }) => {
+                        println!("{}", "✘ DuckDB httpfs extension is unavailable (offline?). Falling back to a local file.".bright_red());
+                    }
+                    Err(e) => {
+                        println!("{} {}", "✘ S3 export failed:".bright_red(), e);
+                        return Ok(());
+                    }
+                }
+            }
+            None => println!("{}", "S3 credentials are not configured. Falling back to a local file.".yellow()),
+        }
+    }
+
+    let default_out = paths::exports_dir().join(format!("{}.{}", table, format.extension()));
+    let out: String = Input::new()
+        .with_prompt("Output file")
+        .default(default_out.display().to_string())
+        .interact_text()?;
+    let out = PathBuf::from(out);
+
+    let overwrite = out.exists();
+    if overwrite {
+        let confirmed = Confirm::new()
+            .with_prompt(format!("{} already exists. Overwrite it?", out.display()))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            println!("{}", "Export cancelled.".yellow());
+            return Ok(());
+        }
+    }
+
+    println!("{}", "[Step 1] Writing export file".yellow());
+    let destination = out.display().to_string();
+    let export = move |conn: &Connection| match format {
+        ExportFormat::Parquet => export::export_table_parquet(conn, table, &out, filter.as_deref(), case_id, overwrite),
+        _ => export::export_table_local(conn, table, format, filter.as_deref(), case_id, &out, overwrite),
+    };
+    match db.call(export).await {
+        Ok(rows) => println!("{}", format!("✔ Exported {} rows to {}", rows, destination).bright_green()),
+        Err(e) => println!("{} {}", "✘ Export failed:".bright_red(), e),
+    }
+
+    Ok(())
+}
+
+/// Exports the transactions around seed addresses as a graph, one hop deep
+async fn export_graph(db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let seeds: String = Input::new()
+        .with_prompt("Seed addresses (comma-separated)")
+        .interact_text()?;
+    let seeds: Vec<String> = seeds.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect();
+    if seeds.is_empty() {
+        println!("{}", "No seed addresses given.".yellow());
+        return Ok(());
+    }
+
+    let format = GraphFormat::ALL[Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select graph format")
+        .default(0)
+        .items(&GraphFormat::ALL)
+        .interact()?];
+
+    let stem = if seeds.len() == 1 { seeds[0].clone() } else { "graph".to_string() };
+    let default_out = paths::exports_dir().join(format!("{}.{}", stem, format.extension()));
+    let out: String = Input::new()
+        .with_prompt("Output file")
+        .default(default_out.display().to_string())
+        .interact_text()?;
+    let out = PathBuf::from(out);
+
+    let overwrite = out.exists();
+    if overwrite {
+        let confirmed = Confirm::new()
+            .with_prompt(format!("{} already exists. Overwrite it?", out.display()))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            println!("{}", "Export cancelled.".yellow());
+            return Ok(());
+        }
+    }
+
+    let destination = out.display().to_string();
+    match db.call(move |conn| graph::export_graph(conn, &seeds, format, &out, overwrite)).await {
+        Ok((_, 0)) => println!("{}", format!("No stored transactions involve the seeds; wrote an empty graph to {}", destination).yellow()),
+        Ok((nodes, edges)) => println!("{}", format!("✔ Exported {} addresses and {} edges to {}", nodes, edges, destination).bright_green()),
+        Err(e) => println!("{} {}", "✘ Export failed:".bright_red(), e),
+    }
+
+    Ok(())
+}
+
+async fn generate_report(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let kind = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Report on")
+        .default(0)
+        .items(&["Address", "Domain", "Case"])
+        .interact()?;
+
+    let subject = match kind {
+        0 => ReportSubject::Address(prompt_address(config, db, Chain::Ethereum).await?),
+        1 => ReportSubject::Domain(Input::<String>::new().with_prompt("Enter domain").interact_text()?.trim().to_string()),
+        _ => {
+            let cases = db.call(database_operations::list_cases).await?;
+            if cases.is_empty() {
+                println!("{}", "No cases yet. Create one from the Cases menu.".yellow());
+                return Ok(());
+            }
+            let names: Vec<String> = cases.into_iter().map(|c| c.name).collect();
+            let default = config.active_case()
+                .and_then(|active| names.iter().position(|n| *n == active))
+                .unwrap_or(0);
+            let idx = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select case")
+                .default(default)
+                .items(&names)
+                .interact()?;
+            ReportSubject::Case(names[idx].clone())
+        }
+    };
+
+    let format = ReportFormat::ALL[Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select report format")
+        .default(0)
+        .items(&ReportFormat::ALL)
+        .interact()?];
+
+    let default_out = paths::reports_dir().join(subject.file_name(format));
+    let out: String = Input::new()
+        .with_prompt("Output file")
+        .default(default_out.display().to_string())
+        .interact_text()?;
+    let out = PathBuf::from(out);
+
+    let overwrite = out.exists();
+    if overwrite {
+        let confirmed = Confirm::new()
+            .with_prompt(format!("{} already exists. Overwrite it?", out.display()))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            println!("{}", "Report cancelled.".yellow());
+            return Ok(());
+        }
+    }
+
+    let template_dir = config.report_template_dir();
+    match db.call(move |conn| report::write_report(conn, &subject, format, template_dir.as_deref(), &out, overwrite)).await {
+        Ok(path) => println!("{}", format!("✔ Report written to {}", path.display()).bright_green()),
+        Err(e) => println!("{} {}", "✘ Report failed:".bright_red(), e),
+    }
+
+    Ok(())
+}
+
+async fn cases_menu(config: &mut Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let cases = db.call(database_operations::list_cases).await?;
+
+    println!("\nActive case: {}", config.active_case().unwrap_or_else(|| "none".to_string()).bright_cyan());
+    if !cases.is_empty() {
+        let headers: Vec<String> = ["case", "records", "created"].iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<String>> = cases.iter()
+            .map(|c| vec![c.name.clone(), c.records.to_string(), or_dash(c.created_at.as_deref())])
+            .collect();
+        println!();
+        print_cyber_table(&headers, &rows);
+    }
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Cases")
+        .default(0)
+        .items(&[
+            "➕ New Case",
+            "📌 Switch Active Case",
+            "🚫 Work Outside Any Case",
+            "↩️  Back"
+        ])
+        .interact()?;
+
+    let case = match selection {
+        0 => {
+            let name: String = Input::new()
+                .with_prompt("Case name")
+                .interact_text()?;
+            Some(name.trim().to_string())
+        }
+        1 => {
+            if cases.is_empty() {
+                println!("{}", "No cases yet. Create one with 'New Case'.".yellow());
+                return Ok(());
+            }
+            let names: Vec<&str> = cases.iter().map(|c| c.name.as_str()).collect();
+            let idx = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select the active case")
+                .default(0)
+                .items(&names)
+                .interact()?;
+            Some(names[idx].to_string())
+        }
+        2 => None,
+        _ => return Ok(()),
+    };
+
+    match &case {
+        Some(name) => {
+            db.open_case(name.clone()).await?;
+        }
+        None => db.set_active_case(None),
+    }
+    config::update_config_file(|c| c.set_active_case(case.clone()))?;
+    config::remove_env_key("FRAGARACH_CASE")?;
+    config.set_active_case(case.clone());
+
+    match case {
+        Some(name) => println!("{}", format!("✔ Records saved from now on go to case '{}'.", name).bright_green()),
+        None => println!("{}", "✔ Records saved from now on are not attached to a case.".bright_green()),
+    }
+    Ok(())
+}
+
+/// Prints the tags of an address or domain, if it has any
+async fn print_tags(db: &DatabaseHandle, entity_type: EntityType, entity_id: &str) -> Result<(), FragarachError> {
+    let entity_id = entity_id.to_string();
+    let tags = db.call(move |conn| database_operations::list_tags(conn, entity_type, Some(&entity_id))).await?;
+    if !tags.is_empty() {
+        let labels: Vec<String> = tags.iter()
+            .map(|t| match &t.note {
+                Some(note) => format!("{} ({})", t.tag, note),
+                None => t.tag.clone(),
+            })
+            .collect();
+        output::status(format!("├─ Tags: {}", labels.join(", ")).bright_magenta());
+    }
+    Ok(())
+}
+
+async fn tag_entity(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let kind = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Tag an")
+        .default(0)
+        .items(&EntityType::ALL)
+        .interact()?;
+    let entity_type = EntityType::ALL[kind];
+    let entity_id = match entity_type {
+        EntityType::Address => prompt_untagged_address(config, db, Chain::Ethereum).await?,
+        EntityType::Domain => loop {
+            let input: String = Input::new().with_prompt("Enter domain or URL").interact_text()?;
+            match urlscan::ScanTarget::parse(&input) {
+                Ok(target) => break target.domain,
+                Err(e) => println!("{} {}", "✘".bright_red(), e),
+            }
+        },
+    };
+
+    let id = entity_id.clone();
+    let tags = db.call(move |conn| database_operations::list_tags(conn, entity_type, Some(&id))).await?;
+    if tags.is_empty() {
+        println!("{}", format!("{} has no tags yet.", entity_id).yellow());
+    } else {
+        let headers: Vec<String> = ["tag", "note", "added"].iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<String>> = tags.iter()
+            .map(|t| vec![t.tag.clone(), or_dash(t.note.as_deref()), or_dash(t.created_at.as_deref())])
+            .collect();
+        println!();
+        print_cyber_table(&headers, &rows);
+    }
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Tags of {}", entity_id))
+        .default(0)
+        .items(&["➕ Add or Update Tag", "➖ Remove Tag", "↩️  Back"])
+        .interact()?;
+    match selection {
+        0 => {
+            let tag: String = Input::new().with_prompt("Tag (e.g. exchange, suspect)").interact_text()?;
+            let note: String = Input::new().with_prompt("Note (optional)").allow_empty(true).interact_text()?;
+            let (id, label) = (entity_id.clone(), tag.clone());
+            db.call(move |conn| database_operations::add_tag(conn, entity_type, &id, &label, Some(&note))).await?;
+            println!("{}", format!("✔ Tagged {} as {}", entity_id, tag.trim().to_lowercase()).bright_green());
+        }
+        1 if !tags.is_empty() => {
+            let names: Vec<&str> = tags.iter().map(|t| t.tag.as_str()).collect();
+            let idx = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Remove which tag")
+                .default(0)
+                .items(&names)
+                .interact()?;
+            let (id, label) = (entity_id.clone(), names[idx].to_string());
+            db.call(move |conn| database_operations::remove_tag(conn, entity_type, &id, &label)).await?;
+            println!("{}", format!("✔ Removed tag {} from {}", names[idx], entity_id).bright_green());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn watchlist_menu(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let items = watchlist::items(db).await?;
+    if !items.is_empty() {
+        let headers: Vec<String> = ["type", "value", "chain", "last checked", "last verdict"].iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<String>> = items.iter()
+            .map(|item| vec![
+                item.kind.to_string(),
+                item.value.clone(),
+                or_dash(item.chain.as_deref()),
+                or_dash(item.last_checked_at.as_deref()),
+                or_dash(item.last_scan_uuid.as_ref().map(|_| watchlist::verdict_name(item.last_malicious))),
+            ])
+            .collect();
+        println!();
+        print_cyber_table(&headers, &rows);
+    }
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Watchlist")
+        .default(0)
+        .items(&[
+            "▶️  Run Watchlist",
+            "➕ Watch Address",
+            "➕ Watch Domain",
+            "➖ Stop Watching",
+            "↩️  Back"
+        ])
+        .interact()?;
+
+    let (kind, chain) = match selection {
+        0 => {
+            if items.is_empty() {
+                println!("{}", "The watchlist is empty. Add an address or domain first.".yellow());
+                return Ok(());
+            }
+            let outcomes = watchlist::run(config, db).await?;
+            watchlist::print_summary(&outcomes);
+            return Ok(());
+        }
+        1 => (WatchKind::Address, select_chain()?),
+        2 => (WatchKind::Domain, Chain::Ethereum),
+        3 => {
+            if items.is_empty() {
+                println!("{}", "The watchlist is empty.".yellow());
+                return Ok(());
+            }
+            let values: Vec<&str> = items.iter().map(|item| item.value.as_str()).collect();
+            let idx = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Stop watching")
+                .default(0)
+                .items(&values)
+                .interact()?;
+            watchlist::remove(db, values[idx]).await?;
+            println!("{}", format!("✔ Stopped watching {}", values[idx]).bright_green());
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+
+    let input: String = Input::new()
+        .with_prompt(match kind {
+            WatchKind::Address => format!("Enter {} address or ENS name", chain),
+            WatchKind::Domain => "Enter domain or URL".to_string(),
+        })
+        .interact_text()?;
+    match watchlist::add(config, db, kind, &input, chain).await {
+        Ok((value, true)) => println!("{}", format!("✔ Watching {} {}", kind, value).bright_green()),
+        Ok((value, false)) => println!("{}", format!("{} is already on the watchlist", value).yellow()),
+        Err(e) => print_failure("Could not add to the watchlist", &e),
+    }
+    Ok(())
+}
+
+async fn maintenance_menu(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Maintenance")
+        .default(0)
+        .items(&[
+            "🗑️  Purge Old Records",
+            "🗜️  Compact Database",
+            "↩️  Back"
+        ])
+        .interact()?;
+
+    match selection {
+        0 => {
+            let tables: Vec<&str> = database_operations::PURGEABLE_TABLES.iter().map(|(table, _)| *table).collect();
+            let idx = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Purge records from")
+                .default(0)
+                .items(&tables)
+                .interact()?;
+            let table = tables[idx];
+            let days: u32 = Input::new()
+                .with_prompt("Purge records older than (days)")
+                .default(90)
+                .interact_text()?;
+
+            let count = db.call(move |conn| database_operations::count_older_than(conn, table, days)).await?;
+            if count == 0 {
+                println!("{}", format!("No records in {} are older than {} days.", table, days).yellow());
+                return Ok(());
+            }
+            let prompt = if table == "urlscan_domain_data" {
+                format!("Delete {} scans older than {} days, with their scan data and DOM snapshots?", count, days)
+            } else {
+                format!("Delete {} records from {} older than {} days?", count, table, days)
+            };
+            if !Confirm::new().with_prompt(prompt).default(false).interact()? {
+                println!("{}", "Purge cancelled.".yellow());
+                return Ok(());
+            }
+            let deleted = db.call(move |conn| database_operations::purge_older_than(conn, table, days)).await?;
+            println!("{}", format!("✔ Purged {} records from {}.", deleted, table).bright_green());
+        }
+        1 => {
+            let path = config.database_path();
+            let size = || fs::metadata(&path).map(|meta| meta.len() / 1024).unwrap_or(0);
+            let before = size();
+            db.call(database_operations::compact).await?;
+            println!("{}", format!("✔ Database compacted ({} KB → {} KB).", before, size()).bright_green());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn settings_menu(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    println!("\nCurrent Settings:");
+    println!("\nAPI Integrations:");
+    println!("├─ Transpose API: {}", key_status(config, ApiKey::Transpose));
+    println!("├─ URLScan API: {}", key_status(config, ApiKey::Urlscan));
+    println!("├─ Etherscan API (fallback): {}", key_status(config, ApiKey::Etherscan));
+    println!("├─ VirusTotal API: {}", key_status(config, ApiKey::Virustotal));
+    println!("├─ Shodan API: {}", key_status(config, ApiKey::Shodan));
+    println!("└─ URLScan visibility: {}", config.urlscan_visibility());
+
+    println!("\nDatabase: DuckDB");
+    println!("├─ Location: {}", config.database_path().display());
+    println!("└─ Screenshots: {}", config.screenshots_dir().display());
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Settings Menu")
+        .default(0)
+        .items(&[
+            "🔌 Manage API Keys",
+            "👁️  URLScan Visibility",
+            "🗄️  Database Location",
+            "↩️  Back"
+        ])
+        .interact()?;
+
+    match selection {
+        0 => manage_integrations(config).await?,
+        1 => set_urlscan_visibility(config)?,
+        2 => set_database_path(config)?,
+        3 => return Ok(()),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn set_urlscan_visibility(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Private scans need a paid URLScan plan; free accounts can usually only submit public scans.");
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Default visibility for URLScan submissions")
+        .default(Visibility::ALL.iter().position(|v| *v == config.urlscan_visibility()).unwrap_or(0))
+        .items(&Visibility::ALL)
+        .interact()?;
+    let visibility = Visibility::ALL[selection];
+
+    config::update_config_file(|c| c.set_urlscan_visibility(visibility))?;
+    config::remove_env_key("URLSCAN_VISIBILITY")?;
+    config.set_urlscan_visibility(visibility);
+
+    println!("{}", format!("✔ URLScan visibility set to {}.", visibility).bright_green());
+    Ok(())
+}
+
+/// Saves a new database location, used from the next launch on
+fn set_database_path(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let input: String = Input::new()
+        .with_prompt("DuckDB database file")
+        .with_initial_text(config.database_path().display().to_string())
+        .interact_text()?;
+    let path = std::path::absolute(input.trim())?;
+
+    config::update_config_file(|c| c.set_database_path(path.clone()))?;
+    config::remove_env_key("FRAGARACH_DB_PATH")?;
+
+    println!("{}", format!("✔ Database location set to {}. Restart Fragarach to open it.", path.display()).bright_green());
+    Ok(())
+}
+
+/// Whether an API key is set, and where it is read from
+fn key_status(config: &Config, key: ApiKey) -> ColoredString {
+    match (config.api_key(key), config::api_key_source(key)) {
+        (Some(_), Some(source)) => format!("✅ Active ({})", source).green(),
+        (Some(_), None) => "✅ Active".green(),
+        (None, _) => "❌ API key not detected".red(),
+    }
+}
+
+async fn manage_integrations(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    println!("\nCurrent Integration Status:");
+    println!("Transpose API: {}", key_status(config, ApiKey::Transpose));
+    println!("URLScan API: {}", key_status(config, ApiKey::Urlscan));
+    println!("Etherscan API: {}", key_status(config, ApiKey::Etherscan));
+    println!("VirusTotal API: {}", key_status(config, ApiKey::Virustotal));
+    println!("Shodan API: {}", key_status(config, ApiKey::Shodan));
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select Integration to Configure")
+        .default(0)
+        .items(&[
+            "🔑 Configure Transpose API",
+            "🔑 Configure URLScan API",
+            "🔑 Configure Etherscan API",
+            "🔑 Configure VirusTotal API",
+            "🔑 Configure Shodan API",
+            "🔐 Move API Key to OS Keyring",
+            "🗑️  Remove API Key",
+            "↩️  Back"
+        ])
+        .interact()?;
+
+    match selection {
+        0 => enter_api_key(config, ApiKey::Transpose).await?,
+        1 => enter_api_key(config, ApiKey::Urlscan).await?,
+        2 => enter_api_key(config, ApiKey::Etherscan).await?,
+        3 => enter_api_key(config, ApiKey::Virustotal).await?,
+        4 => enter_api_key(config, ApiKey::Shodan).await?,
+        5 => move_api_key_to_keyring(config)?,
+        6 => remove_api_key(config)?,
+        7 => return Ok(()),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Asks which API key to act on
+fn select_api_key(prompt: &str) -> Result<Option<ApiKey>, Box<dyn std::error::Error>> {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(0)
+        .items(&["Transpose", "URLScan", "Etherscan", "VirusTotal", "Shodan", "↩️  Back"])
+        .interact()?;
+    Ok(ApiKey::ALL.get(selection).copied())
+}
+
+/// Moves a key kept in plain text into the OS keyring
+fn move_api_key_to_keyring(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(key) = select_api_key("Select API key to move")? else {
+        return Ok(());
+    };
+    let Some(value) = config.api_key(key) else {
+        println!("{}", format!("{} API key is not set.", key.name()).yellow());
+        return Ok(());
+    };
+    if config::api_key_source(key) == Some(config::KeySource::Keyring) {
+        println!("{}", format!("{} API key is already in the OS keyring.", key.name()).yellow());
+        return Ok(());
+    }
+
+    match store_in_keyring(key, &value) {
+        Ok(()) if !secrets::PERSISTENT => println!("{}", format!(
+            "✔ {} API key copied to the OS keyring. Its file copy is kept, since the kernel keyring is cleared on logout.",
+            key.name()
+        ).bright_green()),
+        Ok(()) => println!("{}", format!("✔ {} API key moved to the OS keyring.", key.name()).bright_green()),
+        Err(e) => println!("{} {}", "✘ Could not use the OS keyring; the key was left where it is:".bright_red(), e),
+    }
+    Ok(())
+}
+
+/// Stores an API key in the OS keyring and, where the keyring persists, deletes its plain-text copies
+fn store_in_keyring(key: ApiKey, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    secrets::store(key, value)?;
+    // Some backends accept writes they can't read back
+    if secrets::read(key).as_deref() != Some(value) {
+        return Err("the keyring did not keep the key".into());
+    }
+    if secrets::PERSISTENT {
+        config::update_config_file(|c| c.set_api_key(key, None))?;
+        config::remove_env_key(key.env_key())?;
+    }
+    Ok(())
+}
+
+/// Saves a checked API key in the keyring or the configuration files, as the user chooses
+fn save_api_key(config: &mut Config, key: ApiKey, value: String) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Where should the {} API key be stored?", key.name()))
+        .default(0)
+        .items(&["Configuration file (plain text)", "OS keyring"])
+        .interact()?;
+
+    if storage == 1 {
+        match store_in_keyring(key, &value) {
+            Ok(()) if secrets::PERSISTENT => {
+                println!("{}", format!("✔ {} API key saved in the OS keyring.", key.name()).bright_green());
+                config.set_api_key(key, Some(value));
+                return Ok(());
+            }
+            // The kernel keyring is cleared on logout, so the file keeps a copy
+            Ok(()) => println!("{}", format!(
+                "✔ {} API key saved in the OS keyring; a copy is also saved to the configuration file, since the kernel keyring is cleared on logout.",
+                key.name()
+            ).bright_green()),
+            Err(e) => println!("{}", format!("[!] Could not use the OS keyring ({}); saving to the configuration file instead.", e).yellow()),
+        }
+    } else {
+        // A key left in the keyring would override the file on the next launch
+        secrets::delete(key)?;
+    }
+
+    // A key kept in .env would override fragarach.toml, so it's updated there
+    if config::env_key_is_set(key.env_key()) {
+        config::set_env_key(key.env_key(), &value)?;
+    } else {
+        config::update_config_file(|c| c.set_api_key(key, Some(value.clone())))?;
+    }
+    println!("{}", format!("✔ {} API key saved successfully.", key.name()).bright_green());
+    config.set_api_key(key, Some(value));
+    Ok(())
+}
+
+fn remove_api_key(config: &mut Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(key) = select_api_key("Select API key to remove")? else {
+        return Ok(());
+    };
+    let name = key.name();
+
+    if config.api_key(key).is_none() {
+        println!("{}", format!("{} API key is not set.", name).yellow());
+        return Ok(());
+    }
+
+    let confirmed = Confirm::new()
+        .with_prompt(format!("Remove the {} API key?", name))
+        .default(false)
+        .interact()?;
+    if !confirmed {
+        println!("{}", "API key kept.".yellow());
+        return Ok(());
+    }
+
+    // Clear it from every place it can persist, so it stays gone on the next launch
+    secrets::delete(key)?;
+    config::update_config_file(|c| c.set_api_key(key, None))?;
+    config::remove_env_key(key.env_key())?;
+    config.set_api_key(key, None);
+
+    println!("{}", format!("✔ {} API key removed.", name).bright_green());
+    Ok(())
+}
+
+/// What to do with a newly entered API key after checking it
+enum KeyCheck {
+    Save,
+    Reenter,
+    Discard,
+}
+
+/// Tries a newly entered API key with a live request
+///
+/// A key the API rejects is never saved; the user can enter another one. When
+/// the check itself fails, e.g. offline, the user decides whether to save it.
+async fn check_new_api_key(
+    name: &str,
+    check: impl Future<Output = Result<(), FragarachError>>,
+) -> Result<KeyCheck, Box<dyn std::error::Error>> {
+    println!("{}", format!("Checking the {} API key...", name).yellow());
+    let reason = match check.await {
+        Ok(()) => {
+            println!("{}", format!("✔ {} accepted the API key.", name).bright_green());
+            return Ok(KeyCheck::Save);
+        }
+        Err(e) if e.is_auth_failure() => {
+            println!("{}", format!("✘ {} rejected the API key: invalid credentials. It was not saved.", name).bright_red());
+            println!("  {}", e);
+            let again = Confirm::new()
+                .with_prompt("Enter a different key?")
+                .default(true)
+                .interact()?;
+            return Ok(if again { KeyCheck::Reenter } else { KeyCheck::Discard });
+        }
+        Err(e) if http::is_network_error(&e) => format!("{} could not be reached: {}", name, e),
+        Err(e) => format!("The key could not be checked: {}", e),
+    };
+
+    println!("{}", format!("[!] {}", reason).yellow());
+    let save = Confirm::new()
+        .with_prompt("Save the key anyway?")
+        .default(false)
+        .interact()?;
+    Ok(if save { KeyCheck::Save } else { KeyCheck::Discard })
+}
+
+/// Asks for an API key until the service accepts one or the user gives up, then saves it
+///
+/// With `--skip-key-check` or `FRAGARACH_CHECK_API_KEYS=false`, the key is saved untested.
+async fn enter_api_key(config: &mut Config, key: ApiKey) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        let api_key: String = Input::new()
+            .with_prompt(format!("Enter your {} API key", key.name()))
+            .interact_text()?;
+        if !config.check_api_keys() {
+            println!("{}", format!("[!] Saving the {} API key without checking it.", key.name()).yellow());
+            return save_api_key(config, key, api_key);
+        }
+
+        let check = match key {
+            ApiKey::Transpose => check_new_api_key(key.name(), transpose::validate_api_key(config, &api_key)).await?,
+            ApiKey::Urlscan => check_new_api_key(key.name(), urlscan::validate_api_key(config, &api_key)).await?,
+            ApiKey::Etherscan => check_new_api_key(key.name(), etherscan::validate_api_key(config, &api_key)).await?,
+            ApiKey::Virustotal => check_new_api_key(key.name(), virustotal::validate_api_key(config, &api_key)).await?,
+            ApiKey::Shodan => check_new_api_key(key.name(), shodan::validate_api_key(config, &api_key)).await?,
+        };
+        match check {
+            KeyCheck::Save => return save_api_key(config, key, api_key),
+            KeyCheck::Reenter => continue,
+            KeyCheck::Discard => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+/// Newline-delimited JSON command protocol for driving Fragarach from other programs
+///
+/// Started with `fragarach pipe`. Commands are read from stdin one JSON object
+/// per line and executed in order; every message written to stdout is a single
+/// JSON object on its own line. Decorative output is suppressed for the
+/// lifetime of the process.
+///
+/// # Commands
+/// - `{"op":"scan_domain","domain":"example.com","visibility":"unlisted"}` (`visibility` is optional)
+/// - `{"op":"query_account","address":"0x...","chain":"polygon","source":"etherscan"}`
+/// - `{"op":"query_transactions","address":"0x...","chain":"ethereum","max":500}`
+///
+/// `chain` defaults to `ethereum`. `address` may be an ENS name, which is resolved
+/// first; results report the resolved address. `source` (`transpose` or
+/// `etherscan`) defaults to Transpose when its key is set, else Etherscan.
+///
+/// Any command may carry an `id`, echoed back on every message it produces, and
+/// a `protocol` version, which is rejected if it doesn't match `PROTOCOL_VERSION`.
+///
+/// # Messages
+/// - `{"type":"ready","protocol":1,"version":"..."}` once at startup
+/// - `{"type":"progress","id":...,"op":"...",...}` while a command runs
+/// - `{"type":"result","id":...,"op":"...","data":{...}}` when it succeeds
+/// - `{"type":"error","id":...,"op":"...","error":{"code":"...","message":"..."}}` when it fails
+///
+/// A failed command never ends the session; the process exits when stdin closes.
+use super::{only_pull, resolve_address};
+use crate::api::urlscan::{self, Visibility};
+use crate::api::source::DataSource;
+use crate::api::transpose::{self, Chain, TransactionRange};
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_writer::DatabaseHandle;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Version of the command and message schema
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    protocol: Option<u32>,
+    #[serde(flatten)]
+    operation: Operation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Operation {
+    ScanDomain { domain: String, visibility: Option<Visibility> },
+    QueryAccount { address: String, chain: Option<Chain>, source: Option<DataSource> },
+    QueryTransactions { address: String, chain: Option<Chain>, max: Option<usize>, source: Option<DataSource> },
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::ScanDomain { .. } => "scan_domain",
+            Operation::QueryAccount { .. } => "query_account",
+            Operation::QueryTransactions { .. } => "query_transactions",
+        }
+    }
+}
+
+/// Structured failure reported back to the caller
+#[derive(Debug)]
+struct PipeError {
+    code: &'static str,
+    message: String,
+}
+
+impl PipeError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        PipeError { code, message: message.into() }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for PipeError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        let code = match err.downcast_ref::<FragarachError>() {
+            Some(FragarachError::MissingApiKey(_)) => "missing_api_key",
+            Some(e) if e.is_auth_failure() => "auth_failed",
+            Some(e) if e.is_rate_limited() => "rate_limited",
+            Some(FragarachError::ApiStatus { .. } | FragarachError::ScanRejected { .. }) => "api_status",
+            Some(FragarachError::Config { .. }) => "config",
+            Some(FragarachError::InvalidInput(_)) => "invalid_input",
+            Some(FragarachError::Timeout { .. }) => "timeout",
+            Some(FragarachError::Http(_)) => "http",
+            Some(FragarachError::Database(_) | FragarachError::DatabaseClosed) => "database",
+            Some(FragarachError::UnresolvedEns(_)) => "unresolved_ens",
+            Some(FragarachError::InvalidAddress(_)) => "invalid_address",
+            _ => "failed",
+        };
+        PipeError::new(code, err.to_string())
+    }
+}
+
+impl From<FragarachError> for PipeError {
+    fn from(err: FragarachError) -> Self {
+        Box::<dyn std::error::Error>::from(err).into()
+    }
+}
+
+/// Parses one command line
+///
+/// A line that is JSON but not a valid command fails with the `id` it
+/// carries, so the caller can still match the error to its command.
+fn parse_request(line: &str) -> Result<Request, (Value, PipeError)> {
+    let invalid = |e: serde_json::Error| PipeError::new("invalid_request", e.to_string());
+    let value: Value = serde_json::from_str(line).map_err(|e| (Value::Null, invalid(e)))?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value).map_err(|e| (id, invalid(e)))
+}
+
+/// Rejects a command asking for a protocol version other than `PROTOCOL_VERSION`
+fn check_protocol(request: &Request) -> Result<(), PipeError> {
+    match request.protocol {
+        Some(version) if version != PROTOCOL_VERSION => Err(PipeError::new(
+            "unsupported_protocol",
+            format!("Protocol version {} requested, this build speaks {}", version, PROTOCOL_VERSION),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn emit(message: Value) {
+    println!("{}", message);
+}
+
+/// Reads commands from stdin until it closes
+pub async fn run(config: &Config, db: &DatabaseHandle) -> Result<(), Box<dyn std::error::Error>> {
+    emit(json!({
+        "type": "ready",
+        "protocol": PROTOCOL_VERSION,
+        "version": env!("CARGO_PKG_VERSION"),
+    }));
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err((id, e)) => {
+                emit(json!({
+                    "type": "error",
+                    "id": id,
+                    "error": { "code": e.code, "message": e.message },
+                }));
+                continue;
+            }
+        };
+
+        let op = request.operation.name();
+        let result = match check_protocol(&request) {
+            Ok(()) => execute(config, db, &request.id, request.operation).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(data) => emit(json!({ "type": "result", "id": request.id, "op": op, "data": data })),
+            Err(e) => emit(json!({
+                "type": "error",
+                "id": request.id,
+                "op": op,
+                "error": { "code": e.code, "message": e.message },
+            })),
+        }
+    }
+
+    Ok(())
+}
+
+async fn execute(config: &Config, db: &DatabaseHandle, id: &Value, operation: Operation) -> Result<Value, PipeError> {
+    match operation {
+        Operation::ScanDomain { domain, visibility } => {
+            if config.urlscan_api_key().is_none() {
+                return Err(FragarachError::MissingApiKey("URLScan").into());
+            }
+            let visibility = visibility.unwrap_or(config.urlscan_visibility());
+            let scan = urlscan::scan_and_save(config, &domain, visibility, db).await?;
+            let status = if scan.completed { "complete" } else { "pending" };
+            Ok(json!({ "domain": domain, "uuid": scan.uuid, "visibility": visibility, "status": status }))
+        }
+        Operation::QueryAccount { address, chain, source } => {
+            let source = DataSource::pick(config, source)?;
+            let chain = chain.unwrap_or(Chain::Ethereum);
+            let address = resolve_address(config, db, &address).await?;
+            let records = source.query_account(config, chain, &address).await?;
+            let count = records.len();
+            db.save_records(records, "ethereum_accounts").await?;
+            db.record_query(format!("{} account", chain), address.clone(), count).await?;
+            Ok(json!({ "address": address, "chain": chain, "source": source, "records": count }))
+        }
+        Operation::QueryTransactions { address, chain, max, source } => {
+            let source = DataSource::pick(config, source)?;
+            let address = resolve_address(config, db, &address).await?;
+            query_transactions(config, db, id, source, chain.unwrap_or(Chain::Ethereum), address, max).await
+        }
+    }
+}
+
+/// Streams transaction pages into the database, reporting each saved page
+async fn query_transactions(
+    config: &Config,
+    db: &DatabaseHandle,
+    id: &Value,
+    source: DataSource,
+    chain: Chain,
+    address: String,
+    max: Option<usize>,
+) -> Result<Value, PipeError> {
+    let (pages_tx, mut pages_rx) = mpsc::channel(transpose::PAGE_BUFFER);
+    let addresses = [address.clone()];
+
+    let range = TransactionRange::default();
+    let fetch = source.stream_transactions(config, chain, &addresses, max, &range, pages_tx);
+    let save = async {
+        let mut saved = 0;
+        while let Some(page) = pages_rx.recv().await {
+            saved += page.len();
+            db.save_records(page, "ethereum_transactions").await?;
+            emit(json!({
+                "type": "progress",
+                "id": id,
+                "op": "query_transactions",
+                "saved": saved,
+            }));
+        }
+        Ok(saved)
+    };
+
+    let (pulls, saved) = tokio::try_join!(fetch, save)?;
+    let fetched = only_pull(pulls)?;
+    db.record_query(format!("{} transactions", chain), address.clone(), fetched).await?;
+    Ok(json!({ "address": address, "chain": chain, "source": source, "fetched": fetched, "saved": saved }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn optional_fields_default_when_absent() {
+        let request = parse_request(r#"{"op":"query_transactions","address":"0xabc"}"#).unwrap();
+        assert_eq!(request.id, Value::Null);
+        assert_eq!(request.protocol, None);
+        assert!(matches!(
+            request.operation,
+            Operation::QueryTransactions { chain: None, max: None, source: None, .. }
+        ));
+        assert!(check_protocol(&request).is_ok());
+    }
+
+    #[test]
+    fn invalid_commands_keep_their_id() {
+        for line in [
+            r#"{"id":7,"op":"delete_everything"}"#,
+            r#"{"id":7,"op":"query_transactions","address":"0xabc","max":"all"}"#,
+            r#"{"id":7,"op":"scan_domain"}"#,
+        ] {
+            let (id, error) = parse_request(line).unwrap_err();
+            assert_eq!((id, error.code), (json!(7), "invalid_request"), "{}", line);
+        }
+
+        let (id, error) = parse_request("not json").unwrap_err();
+        assert_eq!((id, error.code), (Value::Null, "invalid_request"));
+    }
+
+    #[test]
+    fn other_protocol_versions_are_rejected() {
+        let request = parse_request(r#"{"id":"a","protocol":1,"op":"scan_domain","domain":"example.com"}"#).unwrap();
+        assert!(check_protocol(&request).is_ok());
+
+        let request = parse_request(r#"{"id":"a","protocol":2,"op":"scan_domain","domain":"example.com"}"#).unwrap();
+        assert_eq!(request.id, json!("a"));
+        assert_eq!(check_protocol(&request).unwrap_err().code, "unsupported_protocol");
+    }
+
+    #[test]
+    fn errors_map_to_stable_codes() {
+        let status = |status| FragarachError::ApiStatus { operation: "Lookup".to_string(), status, body: String::new() };
+        let cases = [
+            (FragarachError::MissingApiKey("URLScan"), "missing_api_key"),
+            (status(StatusCode::UNAUTHORIZED), "auth_failed"),
+            (status(StatusCode::TOO_MANY_REQUESTS), "rate_limited"),
+            (status(StatusCode::BAD_REQUEST), "api_status"),
+            (FragarachError::InvalidInput("max".to_string()), "invalid_input"),
+            (FragarachError::Timeout { operation: "Lookup".to_string() }, "timeout"),
+            (FragarachError::DatabaseClosed, "database"),
+            (FragarachError::UnresolvedEns("nobody.eth".to_string()), "unresolved_ens"),
+            (FragarachError::InvalidAddress("0x12".to_string()), "invalid_address"),
+            (FragarachError::ConsumerStopped, "failed"),
+        ];
+        for (error, code) in cases {
+            let message = error.to_string();
+            let error = PipeError::from(error);
+            assert_eq!((error.code, error.message), (code, message));
+        }
+
+        let other: Box<dyn std::error::Error> = "disk full".into();
+        assert_eq!(PipeError::from(other).code, "failed");
+    }
+}
@@ -0,0 +1,184 @@
+/// Watchlist runs, shared by the menu and the `watchlist` subcommand
+///
+/// A run re-checks every item in the active case, or every item when no case
+/// is active:
+/// - addresses: transactions from the day before the last check onwards are
+///   pulled from Transpose (Etherscan without a Transpose key) and saved like
+///   any other pull; the change is how many weren't stored before
+/// - domains: looked up in the URLScan search instead of scanned again; scans
+///   newer than the one seen last time are counted (within the newest page of
+///   results), the newest is imported without using a scan credit, and a change
+///   in its verdict is reported
+///
+/// An item that fails keeps its `last_checked_at`, so the next run covers the
+/// same period again.
+use super::{pull_transactions, resolve_address};
+use crate::api::source::DataSource;
+use crate::api::transpose::{Chain, TransactionRange};
+use crate::api::urlscan::{self, ScanTarget};
+use crate::config::Config;
+use crate::error::FragarachError;
+use crate::helpers::database_writer::DatabaseHandle;
+use crate::helpers::watchlist::{self, WatchItem, WatchKind};
+use crate::output;
+use colored::*;
+use std::fmt;
+
+/// What a run found for one item
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// Transactions stored for an address that weren't before the run
+    Transactions(u64),
+    /// Scans of a domain newer than the last one seen, and the previous and
+    /// new verdict when the newest scan's verdict differs
+    Scans { new: usize, verdict: Option<(Option<bool>, Option<bool>)> },
+}
+
+impl Change {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Change::Transactions(0) | Change::Scans { new: 0, .. })
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Transactions(new) => write!(f, "{} new transactions", new),
+            Change::Scans { new, verdict: None } => write!(f, "{} new scans", new),
+            Change::Scans { new, verdict: Some((from, to)) } => {
+                write!(f, "{} new scans, verdict {} → {}", new, verdict_name(*from), verdict_name(*to))
+            }
+        }
+    }
+}
+
+/// How URLScan rated a scan, as shown to people
+pub fn verdict_name(malicious: Option<bool>) -> &'static str {
+    match malicious {
+        Some(true) => "malicious",
+        Some(false) => "clean",
+        None => "no verdict",
+    }
+}
+
+/// One item of a run and what checking it found
+pub struct Outcome {
+    pub item: WatchItem,
+    pub result: Result<Change, FragarachError>,
+}
+
+/// Adds an address (or ENS name) or a domain to the watchlist, in the active case
+///
+/// Returns the value stored, and whether it was new.
+pub async fn add(
+    config: &Config,
+    db: &DatabaseHandle,
+    kind: WatchKind,
+    input: &str,
+    chain: Chain,
+) -> Result<(String, bool), FragarachError> {
+    let (value, chain) = match kind {
+        WatchKind::Address => (resolve_address(config, db, input).await?, Some(chain.to_string())),
+        WatchKind::Domain => (ScanTarget::parse(input)?.domain, None),
+    };
+    let case_id = db.active_case();
+    let stored = value.clone();
+    let added = db.call(move |conn| watchlist::add(conn, kind, &stored, chain.as_deref(), case_id)).await?;
+    Ok((value, added))
+}
+
+/// Stops watching `value`, returning how many items watched it
+pub async fn remove(db: &DatabaseHandle, value: &str) -> Result<usize, FragarachError> {
+    let value = value.to_string();
+    db.call(move |conn| watchlist::remove(conn, &value)).await
+}
+
+/// Watched items in the active case, or all of them without one
+pub async fn items(db: &DatabaseHandle) -> Result<Vec<WatchItem>, FragarachError> {
+    let case_id = db.active_case();
+    db.call(move |conn| watchlist::list(conn, case_id)).await
+}
+
+/// Re-checks every watched item, carrying on past items that fail
+pub async fn run(config: &Config, db: &DatabaseHandle) -> Result<Vec<Outcome>, FragarachError> {
+    let items = items(db).await?;
+    let mut outcomes = Vec::with_capacity(items.len());
+    for (i, item) in items.into_iter().enumerate() {
+        output::status(format!("[{}] Checking {} {}", i + 1, item.kind, item.value).yellow());
+        let result = match item.kind {
+            WatchKind::Address => check_address(config, db, &item).await,
+            WatchKind::Domain => check_domain(config, db, &item).await,
+        };
+        outcomes.push(Outcome { item, result });
+    }
+    Ok(outcomes)
+}
+
+async fn check_address(config: &Config, db: &DatabaseHandle, item: &WatchItem) -> Result<Change, FragarachError> {
+    let chain: Chain = item.chain.as_deref().unwrap_or("ethereum").parse().map_err(FragarachError::InvalidInput)?;
+    let range = TransactionRange::new(item.pull_from.as_deref(), None, None, None)?;
+    let source = DataSource::pick(config, None)?;
+    let pull = pull_transactions(config, db, source, chain, std::slice::from_ref(&item.value), None, &range).await?;
+    if let Some(e) = pull.pulls.into_iter().next().and_then(|pull| pull.error) {
+        return Err(e);
+    }
+
+    let id = item.id;
+    db.call(move |conn| watchlist::mark_address_checked(conn, id)).await?;
+    Ok(Change::Transactions(pull.new))
+}
+
+async fn check_domain(config: &Config, db: &DatabaseHandle, item: &WatchItem) -> Result<Change, FragarachError> {
+    let hits = urlscan::search_domain(config, &item.value, None).await?.hits;
+    // Results are newest first, so everything before the last scan seen is new
+    let new = item.last_scan_uuid.as_ref()
+        .and_then(|last| hits.iter().position(|hit| &hit.uuid == last))
+        .unwrap_or(hits.len());
+
+    let newest = hits.first().filter(|_| new > 0);
+    if let Some(hit) = newest {
+        urlscan::import_scan(config, &item.value, &hit.uuid, db).await?;
+    }
+    let verdict = newest
+        .filter(|hit| item.last_scan_uuid.is_some() && hit.malicious != item.last_malicious)
+        .map(|hit| (item.last_malicious, hit.malicious));
+
+    let id = item.id;
+    let newest = newest.map(|hit| (hit.uuid.clone(), hit.malicious));
+    db.call(move |conn| {
+        watchlist::mark_domain_checked(conn, id, newest.as_ref().map(|(uuid, malicious)| (uuid.as_str(), *malicious)))
+    }).await?;
+    Ok(Change::Scans { new, verdict })
+}
+
+/// Prints one line per item of a run, returning how many items failed
+pub fn print_summary(outcomes: &[Outcome]) -> usize {
+    output::status("\nWatchlist summary:".bright_cyan());
+    let mut failed = 0;
+    for Outcome { item, result } in outcomes {
+        match result {
+            Ok(change) if change.is_empty() => output::status(format!("├─ {}: {}", item.value, change)),
+            Ok(change) => output::status(format!("├─ {}: {}", item.value, change).bright_green()),
+            Err(e) => {
+                eprintln!("{} {}: {}", "✘ Check failed for".bright_red(), item.value, e);
+                failed += 1;
+            }
+        }
+    }
+    output::status(format!("└─ {} items checked, {} failed", outcomes.len(), failed));
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_read_as_a_summary_line() {
+        assert_eq!(Change::Transactions(12).to_string(), "12 new transactions");
+        assert!(Change::Scans { new: 0, verdict: None }.is_empty());
+        let flipped = Change::Scans { new: 2, verdict: Some((Some(false), Some(true))) };
+        assert_eq!(flipped.to_string(), "2 new scans, verdict clean → malicious");
+        assert!(!flipped.is_empty());
+    }
+}
@@ -2,28 +2,554 @@
 /// 
 /// Handles loading and saving of application configuration, including:
 /// - API keys management
+/// - `fragarach.toml` settings file in the working or config directory
 /// - Environment variable integration
+/// - Platform-specific file locations (`paths`)
+/// - Opt-in OS keyring storage for API keys (`secrets`)
+///
+/// # Precedence
+/// Built-in defaults, then `fragarach.toml`, then the `.env` file and process
+/// environment, then API keys in the OS keyring. Settings changed from the menu
+/// are written to `fragarach.toml`.
+/// The file may set any subset of settings; unknown keys are ignored.
 /// 
 /// # Environment Variables
 /// - `TRANSPOSE_API_KEY`: API key for Transpose service
 /// - `URLSCAN_API_KEY`: API key for URLScan service
-use dotenv::dotenv;
+/// - `ETHERSCAN_API_KEY`: API key for Etherscan, the fallback source of accounts and transactions
+/// - `VT_API_KEY` (or `VIRUSTOTAL_API_KEY`): API key for VirusTotal domain and IP reputation lookups
+/// - `SHODAN_API_KEY`: API key for Shodan lookups of scanned page IPs
+/// - `FRAGARACH_VIRUSTOTAL_ON_SCAN`: Add VirusTotal reports of the domain and page IP to every URLScan scan
+///   (default `false`)
+/// - `URLSCAN_VISIBILITY`: Visibility of submitted scans: `public` (default), `unlisted` or `private`
+/// - `FRAGARACH_URLSCAN_POLL_TIMEOUT_SECS`: Time to wait for a submitted scan to finish (default 120)
+/// - `FRAGARACH_URLSCAN_POLL_INTERVAL_SECS`: Wait between scan result polls (default 5)
+/// - `FRAGARACH_URLSCAN_POLL_BACKOFF`: Double the poll wait after each unfinished poll, up to 30s (default `false`)
+/// - `FRAGARACH_URLSCAN_SCANS_PER_MINUTE`: URLScan submissions sent per minute before further scans queue (default 60)
+/// - `FRAGARACH_CONFIRM_REQUESTS`: Request count above which expensive operations ask for confirmation
+/// - `TRANSPOSE_CREDITS_PER_REQUEST`: Credits charged by Transpose per SQL request
+/// - `FRAGARACH_TRANSPOSE_CONCURRENCY`: Addresses fetched from Transpose at once (default 4)
+/// - `FRAGARACH_TRANSPOSE_API_URL`: Transpose SQL endpoint (default `https://api.transpose.io/sql`)
+/// - `FRAGARACH_TRANSPOSE_REQUESTS_PER_MINUTE`: Transpose requests sent per minute when responses carry
+///   no rate-limit headers (default 60)
+/// - `FRAGARACH_CONNECT_TIMEOUT_SECS`: HTTP connect timeout
+/// - `FRAGARACH_HTTP_TIMEOUT_SECS`: Total HTTP request timeout
+/// - `FRAGARACH_DOM_TIMEOUT_SECS`: Total timeout for URLScan DOM downloads
+/// - `FRAGARACH_HTTP_MAX_ATTEMPTS`: Attempts made for a request that fails transiently (default 3)
+/// - `FRAGARACH_HTTP_RETRY_BASE_MS`: Delay before the first retry, doubled for each further one (default 500)
+/// - `FRAGARACH_DB_QUEUE_CAPACITY`: Pending database commands before callers wait
+/// - `FRAGARACH_HTTP_CACHE_MAX_MB`: Size limit of the HTTP response cache
+/// - `FRAGARACH_HEALTH_MIN_FREE_MB`: Free disk space below which `health` fails
+/// - `FRAGARACH_HEALTH_DEADLINE_SECS`: Time each `health` check may take
+/// - `FRAGARACH_UPDATE_CHECK`: Check GitHub for new releases once a day (default `false`)
+/// - `FRAGARACH_CHECK_API_KEYS`: Test API keys with a live request before saving them (default `true`)
+/// - `FRAGARACH_CASE`: Investigation case that saved records are attached to
+/// - `FRAGARACH_DB_PATH`: DuckDB database file (default `paths::database_file()`)
+/// - `FRAGARACH_SCREENSHOTS_DIR`: URLScan screenshot directory (default `paths::screenshots_dir()`)
+/// - `FRAGARACH_STORE_SCREENSHOTS_IN_DB`: Also keep screenshot PNGs in the database (default `true`)
+/// - `FRAGARACH_DOM_INLINE`: Store every DOM snapshot in the database as older releases did (default `false`)
+/// - `FRAGARACH_DOM_INLINE_MAX_KB`: DOM snapshots up to this size stay in the database; larger ones are
+///   written gzip-compressed next to the screenshot directory (default 0)
+/// - `FRAGARACH_SQL_DIR`: Directory of `.sql` files replacing the built-in Transpose query templates
+/// - `FRAGARACH_REPORT_TEMPLATE_DIR`: Directory of `report.md` / `report.html` templates replacing the built-in report layouts
+/// - `FRAGARACH_S3_URI`: Default S3 export prefix, e.g. `s3://bucket/cases`
+/// - `FRAGARACH_S3_ACCESS_KEY_ID` / `AWS_ACCESS_KEY_ID`: S3 access key
+/// - `FRAGARACH_S3_SECRET_ACCESS_KEY` / `AWS_SECRET_ACCESS_KEY`: S3 secret key
+/// - `FRAGARACH_S3_REGION` / `AWS_REGION`: S3 region (default `us-east-1`)
+/// - `FRAGARACH_S3_ENDPOINT`: Custom S3-compatible endpoint
+/// - `FRAGARACH_LOG`: Log filter, e.g. `debug` or `fragarach=trace` (default `info`; see `logging`)
+/// - `FRAGARACH_LOG_FILE`: File that log events are also appended to
+pub mod paths;
+pub mod secrets;
+
+use crate::api::urlscan::Visibility;
+use crate::helpers::export::S3Credentials;
+use crate::error::FragarachError;
+use secrets::ApiKey;
+use std::fmt;
 use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+/// Keys Fragarach reads from the environment file
+const KNOWN_ENV_KEYS: &[&str] = &[
+    "TRANSPOSE_API_KEY",
+    "URLSCAN_API_KEY",
+    "ETHERSCAN_API_KEY",
+    "VT_API_KEY",
+    "VIRUSTOTAL_API_KEY",
+    "SHODAN_API_KEY",
+    "FRAGARACH_VIRUSTOTAL_ON_SCAN",
+    "URLSCAN_VISIBILITY",
+    "FRAGARACH_URLSCAN_POLL_TIMEOUT_SECS",
+    "FRAGARACH_URLSCAN_POLL_INTERVAL_SECS",
+    "FRAGARACH_URLSCAN_POLL_BACKOFF",
+    "FRAGARACH_URLSCAN_SCANS_PER_MINUTE",
+    "FRAGARACH_CONFIRM_REQUESTS",
+    "TRANSPOSE_CREDITS_PER_REQUEST",
+    "FRAGARACH_TRANSPOSE_CONCURRENCY",
+    "FRAGARACH_TRANSPOSE_API_URL",
+    "FRAGARACH_TRANSPOSE_REQUESTS_PER_MINUTE",
+    "FRAGARACH_CONNECT_TIMEOUT_SECS",
+    "FRAGARACH_HTTP_TIMEOUT_SECS",
+    "FRAGARACH_DOM_TIMEOUT_SECS",
+    "FRAGARACH_HTTP_MAX_ATTEMPTS",
+    "FRAGARACH_HTTP_RETRY_BASE_MS",
+    "FRAGARACH_DB_QUEUE_CAPACITY",
+    "FRAGARACH_HTTP_CACHE_MAX_MB",
+    "FRAGARACH_HEALTH_MIN_FREE_MB",
+    "FRAGARACH_HEALTH_DEADLINE_SECS",
+    "FRAGARACH_UPDATE_CHECK",
+    "FRAGARACH_CHECK_API_KEYS",
+    "FRAGARACH_CASE",
+    "FRAGARACH_DB_PATH",
+    "FRAGARACH_SCREENSHOTS_DIR",
+    "FRAGARACH_STORE_SCREENSHOTS_IN_DB",
+    "FRAGARACH_DOM_INLINE",
+    "FRAGARACH_DOM_INLINE_MAX_KB",
+    "FRAGARACH_SQL_DIR",
+    "FRAGARACH_REPORT_TEMPLATE_DIR",
+    "FRAGARACH_S3_URI",
+    "FRAGARACH_S3_ACCESS_KEY_ID",
+    "FRAGARACH_S3_SECRET_ACCESS_KEY",
+    "FRAGARACH_S3_REGION",
+    "FRAGARACH_S3_ENDPOINT",
+    "FRAGARACH_LOG",
+    "FRAGARACH_LOG_FILE",
+];
+
+/// Prefixes marking a key as intended for Fragarach
+const ENV_KEY_PREFIXES: &[&str] = &["FRAGARACH_", "TRANSPOSE_", "URLSCAN_", "ETHERSCAN_", "VT_", "VIRUSTOTAL_", "SHODAN_"];
+
+/// Keys in the environment file that look like Fragarach settings but aren't recognized
+///
+/// These are usually typos of a known key and would otherwise be silently ignored.
+fn unknown_env_keys() -> Vec<String> {
+    let contents = fs::read_to_string(paths::env_file()).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(env_line_key)
+        .filter(|key| ENV_KEY_PREFIXES.iter().any(|p| key.starts_with(p)))
+        .filter(|key| !KNOWN_ENV_KEYS.contains(key))
+        .map(String::from)
+        .collect()
+}
+
+/// Applies a change to `fragarach.toml` without copying environment values into it
+pub fn update_config_file(change: impl FnOnce(&mut Config)) -> Result<(), FragarachError> {
+    let path = paths::config_file();
+    let mut file_config = Config::load_from_file(&path)?;
+    change(&mut file_config);
+    file_config.save()
+}
+
+/// Key assigned by an environment file line, or `None` for blanks and comments
+fn env_line_key(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    line.trim_start_matches("export ").split('=').next().map(str::trim)
+}
+
+/// Whether the environment file assigns `key`
+pub fn env_key_is_set(key: &str) -> bool {
+    fs::read_to_string(paths::env_file())
+        .map(|contents| contents.lines().any(|line| env_line_key(line) == Some(key)))
+        .unwrap_or(false)
+}
+
+/// Where an API key is read from, highest precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Keyring,
+    EnvFile,
+    Environment,
+    ConfigFile,
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeySource::Keyring => "OS keyring",
+            KeySource::EnvFile => ".env file",
+            KeySource::Environment => "environment variable",
+            KeySource::ConfigFile => "fragarach.toml",
+        })
+    }
+}
+
+/// Where `key` is currently read from, or `None` if it isn't set anywhere
+///
+/// Variables from `.env` are loaded into the environment at startup, so a key
+/// assigned in the file is reported as coming from it.
+pub fn api_key_source(key: ApiKey) -> Option<KeySource> {
+    if secrets::read(key).is_some() {
+        Some(KeySource::Keyring)
+    } else if env_key_is_set(key.env_key()) {
+        Some(KeySource::EnvFile)
+    } else if env::var_os(key.env_key()).is_some() {
+        Some(KeySource::Environment)
+    } else if Config::load_from_file(&paths::config_file()).is_ok_and(|c| c.api_key(key).is_some()) {
+        Some(KeySource::ConfigFile)
+    } else {
+        None
+    }
+}
+
+/// Assigns `key` in the environment file
+pub fn set_env_key(key: &str, value: &str) -> io::Result<()> {
+    write_env_key(&paths::env_file(), key, Some(value))
+}
+
+/// Removes every assignment of `key` from the environment file
+///
+/// Used when a setting moves to `fragarach.toml`, since a stale value in `.env`
+/// would otherwise override it on the next launch.
+pub fn remove_env_key(key: &str) -> io::Result<()> {
+    write_env_key(&paths::env_file(), key, None)
+}
+
+/// Rewrites the assignment of `key` in the env file at `path`, removing it when `value` is `None`
+///
+/// The first assignment is replaced in place and later duplicates are dropped,
+/// so other variables and comments are kept as they are; a key not yet present
+/// is appended. The new contents go to a temporary file that is then renamed
+/// over `path`, so an interrupted write never leaves the file truncated. The
+/// file keeps its permissions; a new one is readable by its owner only.
+fn write_env_key(path: &Path, key: &str, value: Option<&str>) -> io::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    let mut found = false;
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        if env_line_key(line) != Some(key) {
+            lines.push(line.to_string());
+            continue;
+        }
+        if let (Some(value), false) = (value, found) {
+            // Keeps an `export` prefix and the spacing before `=`
+            let assignee = line.split('=').next().unwrap_or(key);
+            lines.push(format!("{}={}", assignee, value));
+        }
+        found = true;
+    }
+    match value {
+        Some(value) if !found => lines.push(format!("{}={}", key, value)),
+        None if !found => return Ok(()),
+        _ => {}
+    }
+
+    let mut rewritten = lines.join("\n");
+    if !rewritten.is_empty() {
+        rewritten.push('\n');
+    }
+    if rewritten == contents {
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    // Named per process so concurrent writers don't share a temporary file
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut temp = options.open(&temp_path)?;
+    match fs::metadata(path) {
+        Ok(metadata) => temp.set_permissions(metadata.permissions())?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    temp.write_all(rewritten.as_bytes())?;
+    temp.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 /// Core configuration structure for the application
 pub struct Config {
     transpose_api_key: Option<String>,
     urlscan_api_key: Option<String>,
+    etherscan_api_key: Option<String>,
+    virustotal_api_key: Option<String>,
+    shodan_api_key: Option<String>,
+    virustotal_on_scan: bool,
+    urlscan_visibility: Visibility,
+    urlscan_poll_timeout_secs: u64,
+    urlscan_poll_interval_secs: u64,
+    urlscan_poll_backoff: bool,
+    urlscan_scans_per_minute: u32,
+    confirm_request_threshold: u64,
+    transpose_credits_per_request: u64,
+    transpose_concurrency: usize,
+    transpose_api_url: String,
+    transpose_requests_per_minute: u32,
+    http_connect_timeout_secs: u64,
+    http_timeout_secs: u64,
+    dom_timeout_secs: u64,
+    http_max_attempts: u32,
+    http_retry_base_ms: u64,
+    db_queue_capacity: usize,
+    http_cache_max_mb: u64,
+    health_min_free_mb: u64,
+    health_deadline_secs: u64,
+    update_check: bool,
+    check_api_keys: bool,
+    active_case: Option<String>,
+    database_path: Option<PathBuf>,
+    screenshots_dir: Option<PathBuf>,
+    store_screenshots_in_db: bool,
+    dom_inline: bool,
+    dom_inline_max_kb: u64,
+    sql_dir: Option<PathBuf>,
+    report_template_dir: Option<PathBuf>,
+    s3_uri: Option<String>,
+    s3_access_key_id: Option<String>,
+    s3_secret_access_key: Option<String>,
+    s3_region: String,
+    s3_endpoint: Option<String>,
+    log_filter: Option<String>,
+    log_file: Option<PathBuf>,
+    /// Problems found while loading, such as unrecognized settings
+    #[serde(skip)]
+    warnings: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            transpose_api_key: None,
+            urlscan_api_key: None,
+            etherscan_api_key: None,
+            virustotal_api_key: None,
+            shodan_api_key: None,
+            virustotal_on_scan: false,
+            urlscan_visibility: Visibility::Public,
+            urlscan_poll_timeout_secs: 120,
+            urlscan_poll_interval_secs: 5,
+            urlscan_poll_backoff: false,
+            urlscan_scans_per_minute: 60,
+            confirm_request_threshold: 10,
+            transpose_credits_per_request: 1,
+            transpose_concurrency: 4,
+            transpose_api_url: "https://api.transpose.io/sql".to_string(),
+            transpose_requests_per_minute: 60,
+            http_connect_timeout_secs: 10,
+            http_timeout_secs: 30,
+            dom_timeout_secs: 120,
+            http_max_attempts: 3,
+            http_retry_base_ms: 500,
+            db_queue_capacity: 64,
+            http_cache_max_mb: 512,
+            health_min_free_mb: 500,
+            health_deadline_secs: 5,
+            update_check: false,
+            check_api_keys: true,
+            active_case: None,
+            database_path: None,
+            screenshots_dir: None,
+            store_screenshots_in_db: true,
+            dom_inline: false,
+            dom_inline_max_kb: 0,
+            sql_dir: None,
+            report_template_dir: None,
+            s3_uri: None,
+            s3_access_key_id: None,
+            s3_secret_access_key: None,
+            s3_region: "us-east-1".to_string(),
+            s3_endpoint: None,
+            log_filter: None,
+            log_file: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Replaces `field` with the parsed value of `key`, if it is set and valid
+///
+/// A value that doesn't parse is left out and described in `warnings`.
+fn env_override<T: FromStr>(key: &str, field: &mut T, warnings: &mut Vec<String>)
+where
+    T::Err: fmt::Display,
+{
+    let Ok(value) = env::var(key) else { return };
+    match value.parse() {
+        Ok(value) => *field = value,
+        Err(e) => warnings.push(format!("Ignoring {}={}: {}", key, value, e)),
+    }
+}
+
+/// Known name closest to `name`, if one is similar enough to be what was meant
+fn closest<'a>(name: &str, known: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    known.into_iter()
+        .map(|candidate| (strsim::normalized_levenshtein(name, candidate), candidate))
+        .filter(|(similarity, _)| *similarity >= 0.7)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate)
+}
+
+/// Describes an unrecognized setting, suggesting the closest known one
+fn unknown_setting(key: &str, source: &Path, suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(known) => format!("Unrecognized setting '{}' in {} (did you mean '{}'?)", key, source.display(), known),
+        None => format!("Unrecognized setting '{}' in {}", key, source.display()),
+    }
+}
+
+/// Names of the settings `fragarach.toml` can hold
+fn file_keys() -> Vec<String> {
+    // Serialized as JSON so settings without a default value are listed too
+    match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Top-level keys of `fragarach.toml` contents that no setting reads
+///
+/// These would otherwise be silently ignored, like a misspelled setting.
+fn unknown_file_keys(contents: &str) -> Vec<String> {
+    let Ok(table) = contents.parse::<toml::Table>() else { return Vec::new() };
+    let known = file_keys();
+    table.keys().filter(|key| !known.contains(key)).cloned().collect()
+}
+
+/// Replaces an optional `field` with the first of `keys` that is set
+fn env_override_opt<T: From<String>>(keys: &[&str], field: &mut Option<T>) {
+    if let Some(value) = keys.iter().find_map(|key| env::var(key).ok()) {
+        *field = Some(T::from(value));
+    }
 }
 
 impl Config {
+    /// Loads `fragarach.toml`, then applies the environment file and variables on top
     pub fn new() -> Self {
-        dotenv().ok();
-        Config {
-            transpose_api_key: env::var("TRANSPOSE_API_KEY").ok(),
-            urlscan_api_key: env::var("URLSCAN_API_KEY").ok(),
+        dotenv::from_path(paths::env_file()).ok();
+        let config_file = paths::config_file();
+        let mut config = Self::load_from_file(&config_file).unwrap_or_else(|e| Config {
+            warnings: vec![format!("Ignoring {}: {}", config_file.display(), e)],
+            ..Config::default()
+        });
+        let contents = fs::read_to_string(&config_file).unwrap_or_default();
+        let known = file_keys();
+        for key in unknown_file_keys(&contents) {
+            let suggestion = closest(&key, known.iter().map(String::as_str));
+            config.warnings.push(unknown_setting(&key, &config_file, suggestion));
+        }
+        for key in unknown_env_keys() {
+            let suggestion = closest(&key, KNOWN_ENV_KEYS.iter().copied());
+            config.warnings.push(unknown_setting(&key, &paths::env_file(), suggestion));
+        }
+        config.apply_env();
+        for key in ApiKey::ALL {
+            if let Some(value) = secrets::read(key) {
+                config.set_api_key(key, Some(value));
+            }
+        }
+        config
+    }
+
+    /// Reads settings from a TOML file; a missing file yields the defaults
+    pub fn load_from_file(path: &Path) -> Result<Self, FragarachError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| FragarachError::Config { path: path.to_path_buf(), source: Box::new(e) }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes every setting to `fragarach.toml`
+    ///
+    /// Values taken from the environment are written as well; to change one
+    /// setting without copying them, use `update_config_file`.
+    pub fn save(&self) -> Result<(), FragarachError> {
+        self.save_to_file(&paths::config_file())
+    }
+
+    /// Writes every setting to a TOML file, creating its directory if needed
+    pub fn save_to_file(&self, path: &Path) -> Result<(), FragarachError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(fs::write(path, contents)?)
+    }
+
+    /// Overrides file values with any settings present in the environment
+    fn apply_env(&mut self) {
+        env_override_opt(&["TRANSPOSE_API_KEY"], &mut self.transpose_api_key);
+        env_override_opt(&["URLSCAN_API_KEY"], &mut self.urlscan_api_key);
+        env_override_opt(&["ETHERSCAN_API_KEY"], &mut self.etherscan_api_key);
+        env_override_opt(&["VT_API_KEY", "VIRUSTOTAL_API_KEY"], &mut self.virustotal_api_key);
+        env_override_opt(&["SHODAN_API_KEY"], &mut self.shodan_api_key);
+        env_override("FRAGARACH_VIRUSTOTAL_ON_SCAN", &mut self.virustotal_on_scan, &mut self.warnings);
+        env_override("URLSCAN_VISIBILITY", &mut self.urlscan_visibility, &mut self.warnings);
+        env_override("FRAGARACH_URLSCAN_POLL_TIMEOUT_SECS", &mut self.urlscan_poll_timeout_secs, &mut self.warnings);
+        env_override("FRAGARACH_URLSCAN_POLL_INTERVAL_SECS", &mut self.urlscan_poll_interval_secs, &mut self.warnings);
+        env_override("FRAGARACH_URLSCAN_POLL_BACKOFF", &mut self.urlscan_poll_backoff, &mut self.warnings);
+        env_override("FRAGARACH_URLSCAN_SCANS_PER_MINUTE", &mut self.urlscan_scans_per_minute, &mut self.warnings);
+        env_override("FRAGARACH_CONFIRM_REQUESTS", &mut self.confirm_request_threshold, &mut self.warnings);
+        env_override("TRANSPOSE_CREDITS_PER_REQUEST", &mut self.transpose_credits_per_request, &mut self.warnings);
+        env_override("FRAGARACH_TRANSPOSE_CONCURRENCY", &mut self.transpose_concurrency, &mut self.warnings);
+        env_override("FRAGARACH_TRANSPOSE_API_URL", &mut self.transpose_api_url, &mut self.warnings);
+        env_override("FRAGARACH_TRANSPOSE_REQUESTS_PER_MINUTE", &mut self.transpose_requests_per_minute, &mut self.warnings);
+        env_override("FRAGARACH_CONNECT_TIMEOUT_SECS", &mut self.http_connect_timeout_secs, &mut self.warnings);
+        env_override("FRAGARACH_HTTP_TIMEOUT_SECS", &mut self.http_timeout_secs, &mut self.warnings);
+        env_override("FRAGARACH_DOM_TIMEOUT_SECS", &mut self.dom_timeout_secs, &mut self.warnings);
+        env_override("FRAGARACH_HTTP_MAX_ATTEMPTS", &mut self.http_max_attempts, &mut self.warnings);
+        env_override("FRAGARACH_HTTP_RETRY_BASE_MS", &mut self.http_retry_base_ms, &mut self.warnings);
+        env_override("FRAGARACH_DB_QUEUE_CAPACITY", &mut self.db_queue_capacity, &mut self.warnings);
+        env_override("FRAGARACH_HTTP_CACHE_MAX_MB", &mut self.http_cache_max_mb, &mut self.warnings);
+        env_override("FRAGARACH_HEALTH_MIN_FREE_MB", &mut self.health_min_free_mb, &mut self.warnings);
+        env_override("FRAGARACH_HEALTH_DEADLINE_SECS", &mut self.health_deadline_secs, &mut self.warnings);
+        env_override("FRAGARACH_UPDATE_CHECK", &mut self.update_check, &mut self.warnings);
+        env_override("FRAGARACH_CHECK_API_KEYS", &mut self.check_api_keys, &mut self.warnings);
+        env_override_opt(&["FRAGARACH_CASE"], &mut self.active_case);
+        env_override_opt(&["FRAGARACH_DB_PATH"], &mut self.database_path);
+        env_override_opt(&["FRAGARACH_SCREENSHOTS_DIR"], &mut self.screenshots_dir);
+        env_override("FRAGARACH_STORE_SCREENSHOTS_IN_DB", &mut self.store_screenshots_in_db, &mut self.warnings);
+        env_override("FRAGARACH_DOM_INLINE", &mut self.dom_inline, &mut self.warnings);
+        env_override("FRAGARACH_DOM_INLINE_MAX_KB", &mut self.dom_inline_max_kb, &mut self.warnings);
+        env_override_opt(&["FRAGARACH_SQL_DIR"], &mut self.sql_dir);
+        env_override_opt(&["FRAGARACH_REPORT_TEMPLATE_DIR"], &mut self.report_template_dir);
+        env_override_opt(&["FRAGARACH_S3_URI"], &mut self.s3_uri);
+        env_override_opt(&["FRAGARACH_S3_ACCESS_KEY_ID", "AWS_ACCESS_KEY_ID"], &mut self.s3_access_key_id);
+        env_override_opt(&["FRAGARACH_S3_SECRET_ACCESS_KEY", "AWS_SECRET_ACCESS_KEY"], &mut self.s3_secret_access_key);
+        env_override("FRAGARACH_S3_REGION", &mut self.s3_region, &mut self.warnings);
+        if env::var("FRAGARACH_S3_REGION").is_err() {
+            env_override("AWS_REGION", &mut self.s3_region, &mut self.warnings);
+        }
+        env_override_opt(&["FRAGARACH_S3_ENDPOINT"], &mut self.s3_endpoint);
+        env_override_opt(&["FRAGARACH_LOG"], &mut self.log_filter);
+        env_override_opt(&["FRAGARACH_LOG_FILE"], &mut self.log_file);
+    }
+
+    pub fn api_key(&self, key: ApiKey) -> Option<String> {
+        match key {
+            ApiKey::Transpose => self.transpose_api_key(),
+            ApiKey::Urlscan => self.urlscan_api_key(),
+            ApiKey::Etherscan => self.etherscan_api_key(),
+            ApiKey::Virustotal => self.virustotal_api_key(),
+            ApiKey::Shodan => self.shodan_api_key(),
         }
     }
 
@@ -35,6 +561,183 @@ impl Config {
         self.urlscan_api_key.clone()
     }
 
+    pub fn etherscan_api_key(&self) -> Option<String> {
+        self.etherscan_api_key.clone()
+    }
+
+    pub fn virustotal_api_key(&self) -> Option<String> {
+        self.virustotal_api_key.clone()
+    }
+
+    pub fn shodan_api_key(&self) -> Option<String> {
+        self.shodan_api_key.clone()
+    }
+
+    /// Whether URLScan scans also read VirusTotal reports, given a VirusTotal key
+    pub fn virustotal_on_scan(&self) -> bool {
+        self.virustotal_on_scan && self.virustotal_api_key().is_some()
+    }
+
+    pub fn urlscan_visibility(&self) -> Visibility {
+        self.urlscan_visibility
+    }
+
+    pub fn urlscan_poll_timeout(&self) -> Duration {
+        Duration::from_secs(self.urlscan_poll_timeout_secs)
+    }
+
+    /// Wait between result polls, at least one second
+    pub fn urlscan_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.urlscan_poll_interval_secs.max(1))
+    }
+
+    pub fn urlscan_poll_backoff(&self) -> bool {
+        self.urlscan_poll_backoff
+    }
+
+    /// Scan submissions allowed per minute, at least one
+    pub fn urlscan_scans_per_minute(&self) -> u32 {
+        self.urlscan_scans_per_minute.max(1)
+    }
+
+    pub fn confirm_request_threshold(&self) -> u64 {
+        self.confirm_request_threshold
+    }
+
+    pub fn transpose_credits_per_request(&self) -> u64 {
+        self.transpose_credits_per_request
+    }
+
+    /// Addresses fetched from Transpose at once, at least one
+    pub fn transpose_concurrency(&self) -> usize {
+        self.transpose_concurrency.max(1)
+    }
+
+    /// Endpoint Transpose SQL queries are posted to
+    pub fn transpose_api_url(&self) -> &str {
+        &self.transpose_api_url
+    }
+
+    /// Delay between Transpose requests when responses carry no rate-limit headers
+    pub fn transpose_request_interval(&self) -> Duration {
+        Duration::from_secs(60) / self.transpose_requests_per_minute.max(1)
+    }
+
+    pub fn http_connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.http_connect_timeout_secs)
+    }
+
+    pub fn http_timeout(&self) -> Duration {
+        Duration::from_secs(self.http_timeout_secs)
+    }
+
+    pub fn dom_timeout(&self) -> Duration {
+        Duration::from_secs(self.dom_timeout_secs)
+    }
+
+    pub fn http_max_attempts(&self) -> u32 {
+        self.http_max_attempts.max(1)
+    }
+
+    pub fn http_retry_base_delay(&self) -> Duration {
+        Duration::from_millis(self.http_retry_base_ms)
+    }
+
+    pub fn db_queue_capacity(&self) -> usize {
+        self.db_queue_capacity
+    }
+
+    pub fn http_cache_max_bytes(&self) -> u64 {
+        self.http_cache_max_mb * 1024 * 1024
+    }
+
+    pub fn health_min_free_mb(&self) -> u64 {
+        self.health_min_free_mb
+    }
+
+    pub fn health_deadline(&self) -> Duration {
+        Duration::from_secs(self.health_deadline_secs)
+    }
+
+    pub fn update_check(&self) -> bool {
+        self.update_check
+    }
+
+    /// Whether API keys entered in the menu are tested before they are saved
+    pub fn check_api_keys(&self) -> bool {
+        self.check_api_keys
+    }
+
+    /// Name of the case saved records are attached to, if any
+    pub fn active_case(&self) -> Option<String> {
+        self.active_case.clone()
+    }
+
+    /// DuckDB database file; relative paths resolve against the working directory
+    pub fn database_path(&self) -> PathBuf {
+        let path = self.database_path.clone().unwrap_or_else(paths::database_file);
+        std::path::absolute(&path).unwrap_or(path)
+    }
+
+    /// Problems found while loading: an unreadable `fragarach.toml`, settings
+    /// that aren't recognized in it or the environment file, and environment
+    /// values that don't parse
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Log filter directives overriding the `--verbose` level
+    pub fn log_filter(&self) -> Option<String> {
+        self.log_filter.clone()
+    }
+
+    /// File log events are appended to, if any
+    pub fn log_file(&self) -> Option<PathBuf> {
+        self.log_file.clone()
+    }
+
+    pub fn screenshots_dir(&self) -> PathBuf {
+        self.screenshots_dir.clone().unwrap_or_else(paths::screenshots_dir)
+    }
+
+    /// Whether screenshots are stored in `urlscan_domain_data` as well as on disk
+    pub fn store_screenshots_in_db(&self) -> bool {
+        self.store_screenshots_in_db
+    }
+
+    /// Directory for compressed DOM snapshots, next to the screenshot directory
+    pub fn dom_dir(&self) -> PathBuf {
+        let screenshots = self.screenshots_dir();
+        screenshots.parent().unwrap_or(Path::new("")).join("dom")
+    }
+
+    /// Whether a DOM snapshot of `len` bytes is stored in the database rather than a file
+    pub fn store_dom_inline(&self, len: usize) -> bool {
+        self.dom_inline || len as u64 <= self.dom_inline_max_kb * 1024
+    }
+
+    pub fn sql_dir(&self) -> Option<PathBuf> {
+        self.sql_dir.clone()
+    }
+
+    pub fn report_template_dir(&self) -> Option<PathBuf> {
+        self.report_template_dir.clone()
+    }
+
+    pub fn s3_uri(&self) -> Option<String> {
+        self.s3_uri.clone()
+    }
+
+    /// S3 credentials, if both the access key and secret are configured
+    pub fn s3_credentials(&self) -> Option<S3Credentials> {
+        Some(S3Credentials {
+            access_key_id: self.s3_access_key_id.clone()?,
+            secret_access_key: self.s3_secret_access_key.clone()?,
+            region: self.s3_region.clone(),
+            endpoint: self.s3_endpoint.clone(),
+        })
+    }
+
     pub fn set_transpose_api_key(&mut self, key: Option<String>) {
         self.transpose_api_key = key;
     }
@@ -42,4 +745,160 @@ impl Config {
     pub fn set_urlscan_api_key(&mut self, key: Option<String>) {
         self.urlscan_api_key = key;
     }
-}
\ No newline at end of file
+
+    pub fn set_etherscan_api_key(&mut self, key: Option<String>) {
+        self.etherscan_api_key = key;
+    }
+
+    pub fn set_virustotal_api_key(&mut self, key: Option<String>) {
+        self.virustotal_api_key = key;
+    }
+
+    pub fn set_shodan_api_key(&mut self, key: Option<String>) {
+        self.shodan_api_key = key;
+    }
+
+    pub fn set_api_key(&mut self, key: ApiKey, value: Option<String>) {
+        match key {
+            ApiKey::Transpose => self.set_transpose_api_key(value),
+            ApiKey::Urlscan => self.set_urlscan_api_key(value),
+            ApiKey::Etherscan => self.set_etherscan_api_key(value),
+            ApiKey::Virustotal => self.set_virustotal_api_key(value),
+            ApiKey::Shodan => self.set_shodan_api_key(value),
+        }
+    }
+
+    pub fn set_urlscan_visibility(&mut self, visibility: Visibility) {
+        self.urlscan_visibility = visibility;
+    }
+
+    pub fn set_active_case(&mut self, case: Option<String>) {
+        self.active_case = case;
+    }
+
+    pub fn set_database_path(&mut self, path: PathBuf) {
+        self.database_path = Some(path);
+    }
+
+    pub fn set_check_api_keys(&mut self, check: bool) {
+        self.check_api_keys = check;
+    }
+
+    pub fn set_screenshots_dir(&mut self, dir: PathBuf) {
+        self.screenshots_dir = Some(dir);
+    }
+
+    pub fn set_sql_dir(&mut self, dir: Option<PathBuf>) {
+        self.sql_dir = dir;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_files_with_unknown_keys_load() {
+        let dir = env::temp_dir().join(format!("fragarach-config-{}", std::process::id()));
+        let path = dir.join("fragarach.toml");
+        assert_eq!(Config::load_from_file(&path).unwrap().urlscan_poll_timeout_secs, 120);
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "urlscan_poll_timeout_secs = 300\nstorage_backend = \"duckdb\"\n").unwrap();
+        let config = Config::load_from_file(&path).unwrap();
+        assert_eq!(config.urlscan_poll_timeout_secs, 300);
+        assert_eq!(config.urlscan_poll_interval_secs, 5);
+
+        config.save_to_file(&path).unwrap();
+        assert_eq!(Config::load_from_file(&path).unwrap().urlscan_poll_timeout_secs, 300);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_file_keys_are_found_with_the_closest_setting() {
+        let contents = "urlscan_poll_timeout = 300\nhttp_timeout_secs = 5\nsql_dir = \"queries\"\nstorage_backend = \"duckdb\"\n";
+        assert_eq!(unknown_file_keys(contents), vec!["storage_backend", "urlscan_poll_timeout"]);
+
+        let known = file_keys();
+        assert_eq!(closest("urlscan_poll_timeout", known.iter().map(String::as_str)), Some("urlscan_poll_timeout_secs"));
+        assert_eq!(closest("storage_backend", known.iter().map(String::as_str)), None);
+        assert_eq!(closest("FRAGARACH_HTTP_TIMOUT_SECS", KNOWN_ENV_KEYS.iter().copied()), Some("FRAGARACH_HTTP_TIMEOUT_SECS"));
+    }
+
+    #[test]
+    fn invalid_env_values_keep_the_setting_and_warn() {
+        let mut warnings = Vec::new();
+        let mut timeout = 30_u64;
+        env::set_var("FRAGARACH_TEST_INVALID_TIMEOUT", "30s");
+        env_override("FRAGARACH_TEST_INVALID_TIMEOUT", &mut timeout, &mut warnings);
+        assert_eq!(timeout, 30);
+        assert_eq!(warnings, vec!["Ignoring FRAGARACH_TEST_INVALID_TIMEOUT=30s: invalid digit found in string"]);
+
+        env::set_var("FRAGARACH_TEST_INVALID_TIMEOUT", "45");
+        env_override("FRAGARACH_TEST_INVALID_TIMEOUT", &mut timeout, &mut warnings);
+        env::remove_var("FRAGARACH_TEST_INVALID_TIMEOUT");
+        assert_eq!((timeout, warnings.len()), (45, 1));
+    }
+
+    fn env_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("fragarach-env-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn env_key_is_replaced_in_place() {
+        let dir = env_dir("replace");
+        let path = dir.join(".env");
+        fs::write(&path, "# keys\nTRANSPOSE_API_KEY=old\nURLSCAN_API_KEY=scan\nTRANSPOSE_API_KEY=stale\n").unwrap();
+
+        write_env_key(&path, "TRANSPOSE_API_KEY", Some("new")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# keys\nTRANSPOSE_API_KEY=new\nURLSCAN_API_KEY=scan\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "temporary file left behind");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_env_key_is_appended() {
+        let dir = env_dir("append");
+        let path = dir.join(".env");
+        write_env_key(&path, "URLSCAN_API_KEY", Some("scan")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "URLSCAN_API_KEY=scan\n");
+
+        fs::write(&path, "export FRAGARACH_CASE=acme").unwrap();
+        write_env_key(&path, "TRANSPOSE_API_KEY", Some("key")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "export FRAGARACH_CASE=acme\nTRANSPOSE_API_KEY=key\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removing_an_env_key_keeps_other_lines() {
+        let dir = env_dir("remove");
+        let path = dir.join(".env");
+        let contents = "# Fragarach\n\nexport TRANSPOSE_API_KEY = old\nFRAGARACH_DB_PATH=cases/acme.duckdb\n";
+        fs::write(&path, contents).unwrap();
+
+        write_env_key(&path, "URLSCAN_API_KEY", None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        write_env_key(&path, "TRANSPOSE_API_KEY", None).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Fragarach\n\nFRAGARACH_DB_PATH=cases/acme.duckdb\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rewritten_env_file_keeps_its_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let dir = env_dir("mode");
+        let path = dir.join(".env");
+
+        write_env_key(&path, "TRANSPOSE_API_KEY", Some("key")).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_env_key(&path, "URLSCAN_API_KEY", Some("scan")).unwrap();
+        assert_eq!(mode(&path), 0o640);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
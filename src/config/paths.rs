@@ -0,0 +1,278 @@
+/// Filesystem locations used by Fragarach
+///
+/// Every path the application reads or writes is resolved here, so the layout
+/// lives in one place.
+///
+/// # Layout
+/// - Data directory (`dirs::data_dir()/fragarach`): database and scan artifacts
+/// - Config directory (`dirs::config_dir()/fragarach`): `fragarach.toml` and `.env`
+/// - A `fragarach.toml` in the working directory is used instead of the one in
+///   the config directory, so a case folder can carry its own settings
+///
+/// The platform directories follow the same rules as the `dirs` crate:
+/// - Linux: `$XDG_DATA_HOME` and `$XDG_CONFIG_HOME`, else `~/.local/share` and `~/.config`
+/// - macOS: `~/Library/Application Support` for both
+/// - Windows: `%APPDATA%` for both
+///
+/// # Legacy Layout
+/// Earlier releases wrote `data/`, `screenshots/` and `.env` relative to the
+/// current working directory. When those files still exist there, they keep
+/// being used until the user accepts the migration offer.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "fragarach";
+const DATABASE_FILE: &str = "fragarach.duckdb";
+const SCREENSHOTS_DIR: &str = "screenshots";
+const HTTP_CACHE_DIR: &str = "http-cache";
+const EXPORTS_DIR: &str = "exports";
+const REPORTS_DIR: &str = "reports";
+const ENV_FILE: &str = ".env";
+const CONFIG_FILE: &str = "fragarach.toml";
+
+const LEGACY_DATABASE: &str = "data/fragarach.duckdb";
+/// Write-ahead log DuckDB keeps next to the database until it checkpoints
+const LEGACY_WAL: &str = "data/fragarach.duckdb.wal";
+const LEGACY_SCREENSHOTS: &str = "screenshots";
+const LEGACY_ENV: &str = ".env";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Platform {
+    Linux,
+    MacOs,
+    Windows,
+}
+
+impl Platform {
+    const CURRENT: Platform = if cfg!(target_os = "windows") {
+        Platform::Windows
+    } else if cfg!(target_os = "macos") {
+        Platform::MacOs
+    } else {
+        Platform::Linux
+    };
+}
+
+/// Base data and config directories of `platform`, from the environment
+/// variables `var` reads; `None` where the variables they come from are unset
+fn base_dirs(platform: Platform, var: impl Fn(&str) -> Option<PathBuf>) -> (Option<PathBuf>, Option<PathBuf>) {
+    let set = |name: &str| var(name).filter(|path| !path.as_os_str().is_empty());
+    match platform {
+        Platform::Linux => {
+            let home = set("HOME");
+            // Relative XDG paths are invalid and ignored
+            let xdg = |name: &str| set(name).filter(|path| path.is_absolute());
+            (
+                xdg("XDG_DATA_HOME").or_else(|| home.as_ref().map(|home| home.join(".local/share"))),
+                xdg("XDG_CONFIG_HOME").or_else(|| home.map(|home| home.join(".config"))),
+            )
+        }
+        Platform::MacOs => {
+            let support = set("HOME").map(|home| home.join("Library").join("Application Support"));
+            (support.clone(), support)
+        }
+        Platform::Windows => {
+            let appdata = set("APPDATA");
+            (appdata.clone(), appdata)
+        }
+    }
+}
+
+/// Base directories of the running platform, with `dirs` filling in any the
+/// environment doesn't name (e.g. a home directory only in the user database)
+fn current_base_dirs() -> (Option<PathBuf>, Option<PathBuf>) {
+    let (data, config) = base_dirs(Platform::CURRENT, |name| std::env::var_os(name).map(PathBuf::from));
+    (data.or_else(dirs::data_dir), config.or_else(dirs::config_dir))
+}
+
+/// Platform data directory, falling back to the working directory
+fn platform_data_dir() -> PathBuf {
+    current_base_dirs().0
+        .map(|d| d.join(APP_DIR))
+        .unwrap_or_else(|| PathBuf::from("data"))
+}
+
+/// Platform config directory, falling back to the working directory
+fn platform_config_dir() -> PathBuf {
+    current_base_dirs().1
+        .map(|d| d.join(APP_DIR))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Directory holding the database and scan artifacts
+pub fn data_dir() -> PathBuf {
+    if Path::new(LEGACY_DATABASE).exists() {
+        PathBuf::from("data")
+    } else {
+        platform_data_dir()
+    }
+}
+
+/// Directory holding configuration files
+pub fn config_dir() -> PathBuf {
+    if Path::new(LEGACY_ENV).exists() {
+        PathBuf::from(".")
+    } else {
+        platform_config_dir()
+    }
+}
+
+/// DuckDB database file
+pub fn database_file() -> PathBuf {
+    data_dir().join(DATABASE_FILE)
+}
+
+/// Directory where URLScan screenshots are stored
+pub fn screenshots_dir() -> PathBuf {
+    if Path::new(LEGACY_SCREENSHOTS).is_dir() {
+        PathBuf::from(LEGACY_SCREENSHOTS)
+    } else {
+        data_dir().join(SCREENSHOTS_DIR)
+    }
+}
+
+/// Directory holding cached HTTP responses
+pub fn http_cache_dir() -> PathBuf {
+    data_dir().join(HTTP_CACHE_DIR)
+}
+
+/// Directory where local exports are written
+pub fn exports_dir() -> PathBuf {
+    data_dir().join(EXPORTS_DIR)
+}
+
+/// Directory where investigation reports are written
+pub fn reports_dir() -> PathBuf {
+    data_dir().join(REPORTS_DIR)
+}
+
+/// Environment file holding API keys
+pub fn env_file() -> PathBuf {
+    config_dir().join(ENV_FILE)
+}
+
+/// TOML settings file written by the settings menu
+pub fn config_file() -> PathBuf {
+    if Path::new(CONFIG_FILE).is_file() {
+        PathBuf::from(CONFIG_FILE)
+    } else {
+        config_dir().join(CONFIG_FILE)
+    }
+}
+
+/// Legacy working-directory files paired with their platform destinations
+///
+/// Only entries whose source exists and whose destination does not are returned.
+pub fn legacy_files() -> Vec<(PathBuf, PathBuf)> {
+    legacy_files_in(Path::new(""), &platform_data_dir(), &platform_config_dir())
+}
+
+/// `legacy_files` for a working directory `root` and the given platform directories
+///
+/// The database's write-ahead log goes with the database, so writes it holds
+/// that were never checkpointed aren't lost or left behind.
+fn legacy_files_in(root: &Path, data_dir: &Path, config_dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut files: Vec<(PathBuf, PathBuf)> = [
+        (root.join(LEGACY_DATABASE), data_dir.join(DATABASE_FILE)),
+        (root.join(LEGACY_SCREENSHOTS), data_dir.join(SCREENSHOTS_DIR)),
+        (root.join(LEGACY_ENV), config_dir.join(ENV_FILE)),
+    ]
+    .into_iter()
+    .filter(|(from, to)| from.exists() && !to.exists() && from != to)
+    .collect();
+
+    let wal = root.join(LEGACY_WAL);
+    if wal.exists() && files.iter().any(|(from, _)| *from == root.join(LEGACY_DATABASE)) {
+        files.insert(1, (wal, data_dir.join(format!("{}.wal", DATABASE_FILE))));
+    }
+    files
+}
+
+/// Moves legacy working-directory files to their platform locations
+pub fn migrate_legacy_files() -> io::Result<Vec<PathBuf>> {
+    let mut moved = Vec::new();
+    for (from, to) in legacy_files() {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_path(&from, &to)?;
+        moved.push(to);
+    }
+    Ok(moved)
+}
+
+/// Renames a file or directory, copying across filesystems when needed
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::remove_dir(from)
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<PathBuf> {
+        let vars: HashMap<String, PathBuf> = vars.iter().map(|(k, v)| (k.to_string(), PathBuf::from(v))).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn linux_prefers_xdg_directories_over_home() {
+        assert_eq!(base_dirs(Platform::Linux, env(&[("HOME", "/home/ana")])), (
+            Some(PathBuf::from("/home/ana/.local/share")),
+            Some(PathBuf::from("/home/ana/.config")),
+        ));
+        let xdg = env(&[("HOME", "/home/ana"), ("XDG_DATA_HOME", "/data"), ("XDG_CONFIG_HOME", "relative/config")]);
+        assert_eq!(base_dirs(Platform::Linux, xdg), (
+            Some(PathBuf::from("/data")),
+            Some(PathBuf::from("/home/ana/.config")),
+        ));
+        assert_eq!(base_dirs(Platform::Linux, env(&[("HOME", "")])), (None, None));
+    }
+
+    #[test]
+    fn macos_and_windows_share_one_directory() {
+        let support = Some(PathBuf::from("/Users/ana/Library/Application Support"));
+        assert_eq!(base_dirs(Platform::MacOs, env(&[("HOME", "/Users/ana"), ("XDG_DATA_HOME", "/data")])), (support.clone(), support));
+
+        let roaming = Some(PathBuf::from(r"C:\Users\ana\AppData\Roaming"));
+        assert_eq!(base_dirs(Platform::Windows, env(&[("APPDATA", r"C:\Users\ana\AppData\Roaming")])), (roaming.clone(), roaming));
+        assert_eq!(base_dirs(Platform::Windows, env(&[("HOME", "/home/ana")])), (None, None));
+    }
+
+    #[test]
+    fn legacy_database_moves_with_its_write_ahead_log() {
+        let root = std::env::temp_dir().join(format!("fragarach-paths-{}", std::process::id()));
+        let (data, config) = (root.join("platform-data"), root.join("platform-config"));
+        fs::create_dir_all(root.join("data")).unwrap();
+        fs::write(root.join(LEGACY_DATABASE), b"db").unwrap();
+        fs::write(root.join(LEGACY_WAL), b"wal").unwrap();
+
+        assert_eq!(legacy_files_in(&root, &data, &config), [
+            (root.join(LEGACY_DATABASE), data.join("fragarach.duckdb")),
+            (root.join(LEGACY_WAL), data.join("fragarach.duckdb.wal")),
+        ]);
+
+        // A database already at the destination leaves both where they are
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("fragarach.duckdb"), b"other").unwrap();
+        assert!(legacy_files_in(&root, &data, &config).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
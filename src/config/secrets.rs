@@ -0,0 +1,92 @@
+/// API keys kept in the operating system's credential store
+///
+/// Opt-in: a key is only stored here when chosen during setup or moved from
+/// the settings menu. Entries live under the service `fragarach` as
+/// `transpose`, `urlscan`, `etherscan`, `virustotal` and `shodan`, and take precedence over the
+/// configuration files.
+///
+/// # Backends
+/// - macOS: Keychain
+/// - Windows: Credential Manager
+/// - Linux: the kernel keyring, which keeps keys until the user logs out
+///
+/// The kernel keyring doesn't survive a logout or reboot, so on Linux a key
+/// stored here keeps its copy in `fragarach.toml` or `.env` (see `PERSISTENT`).
+/// Without a usable backend, reads find nothing and writes fail, so keys stay
+/// in `fragarach.toml` or `.env` as before.
+use crate::error::FragarachError;
+use keyring::Entry;
+
+const SERVICE: &str = "fragarach";
+
+/// Whether keys stored here are still there after a reboot
+///
+/// False on Linux, where the kernel keyring is cleared when the user logs out;
+/// plain-text copies of a key must not be deleted there.
+pub const PERSISTENT: bool = !cfg!(target_os = "linux");
+
+/// API keys that can be kept in the keyring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKey {
+    Transpose,
+    Urlscan,
+    Etherscan,
+    Virustotal,
+    Shodan,
+}
+
+impl ApiKey {
+    pub const ALL: [ApiKey; 5] = [ApiKey::Transpose, ApiKey::Urlscan, ApiKey::Etherscan, ApiKey::Virustotal, ApiKey::Shodan];
+
+    /// Service the key belongs to, as shown to users
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiKey::Transpose => "Transpose",
+            ApiKey::Urlscan => "URLScan",
+            ApiKey::Etherscan => "Etherscan",
+            ApiKey::Virustotal => "VirusTotal",
+            ApiKey::Shodan => "Shodan",
+        }
+    }
+
+    /// Variable holding the key in the environment or `.env`
+    pub fn env_key(&self) -> &'static str {
+        match self {
+            ApiKey::Transpose => "TRANSPOSE_API_KEY",
+            ApiKey::Urlscan => "URLSCAN_API_KEY",
+            ApiKey::Etherscan => "ETHERSCAN_API_KEY",
+            ApiKey::Virustotal => "VT_API_KEY",
+            ApiKey::Shodan => "SHODAN_API_KEY",
+        }
+    }
+
+    fn entry(&self) -> keyring::Result<Entry> {
+        let user = match self {
+            ApiKey::Transpose => "transpose",
+            ApiKey::Urlscan => "urlscan",
+            ApiKey::Etherscan => "etherscan",
+            ApiKey::Virustotal => "virustotal",
+            ApiKey::Shodan => "shodan",
+        };
+        Entry::new(SERVICE, user)
+    }
+}
+
+/// The key stored in the keyring, or `None` if it isn't there or there is no keyring
+pub fn read(key: ApiKey) -> Option<String> {
+    key.entry().and_then(|entry| entry.get_password()).ok()
+}
+
+/// Stores a key in the keyring, replacing any stored before
+pub fn store(key: ApiKey, value: &str) -> Result<(), FragarachError> {
+    key.entry()?.set_password(value)?;
+    Ok(())
+}
+
+/// Deletes a key from the keyring; succeeds if it wasn't there or there is no keyring
+pub fn delete(key: ApiKey) -> Result<(), FragarachError> {
+    match key.entry().and_then(|entry| entry.delete_credential()) {
+        Ok(()) | Err(keyring::Error::NoEntry | keyring::Error::NoStorageAccess(_)) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
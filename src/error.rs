@@ -0,0 +1,154 @@
+/// Typed errors for Fragarach operations
+///
+/// Returned by the API, config and helper modules. Converts into
+/// `Box<dyn std::error::Error>` through `?`, so callers that don't need to match
+/// on the failure can keep propagating it unchanged.
+///
+/// `hint` suggests what the user can do about a failure, e.g. replacing
+/// an API key that was rejected or waiting out a rate limit.
+use reqwest::StatusCode;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FragarachError {
+    #[error("{0} API key not set")]
+    MissingApiKey(&'static str),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("{operation} timed out: no response within the request timeout")]
+    Timeout { operation: String },
+
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("{operation} failed with status: {status}{}", body_suffix(.body))]
+    ApiStatus { operation: String, status: StatusCode, body: String },
+
+    #[error("URLScan rejected the {visibility} scan of {domain} ({status}): {reason}")]
+    ScanRejected { domain: String, visibility: String, status: StatusCode, reason: String },
+
+    #[error("Unexpected API response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Page consumer stopped")]
+    ConsumerStopped,
+
+    #[error("Database error: {0}")]
+    Database(#[from] duckdb::Error),
+
+    #[error("Database writer has shut down")]
+    DatabaseClosed,
+
+    #[error("Invalid config file {path}: {source}")]
+    Config { path: PathBuf, source: Box<toml::de::Error> },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Unknown SQL template: {0}")]
+    UnknownTemplate(String),
+
+    #[error("{0} is not a valid Ethereum address (expected 0x followed by 40 hex characters)")]
+    InvalidAddress(String),
+
+    #[error("ENS name {0} does not resolve to an address")]
+    UnresolvedEns(String),
+
+    #[error("OS keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+
+    #[error("Unknown table: {0}")]
+    UnknownTable(String),
+
+    #[error("DuckDB {name} extension unavailable: {source}")]
+    ExtensionUnavailable { name: &'static str, source: duckdb::Error },
+
+    #[error("{0} already exists")]
+    ExportExists(std::path::PathBuf),
+
+    #[error("{format} exports are not supported for {target}")]
+    UnsupportedFormat { format: &'static str, target: &'static str },
+
+    #[error("Export verification failed: expected {expected} rows, found {found}")]
+    VerificationFailed { expected: u64, found: u64 },
+
+    #[error("Report template error: {0}")]
+    Template(#[from] minijinja::Error),
+}
+
+/// Response text shown after a failed status, if the API sent any
+fn body_suffix(body: &str) -> String {
+    if body.is_empty() { String::new() } else { format!(" ({})", body) }
+}
+
+impl FragarachError {
+    /// HTTP status of an API response that was refused
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            FragarachError::ApiStatus { status, .. } | FragarachError::ScanRejected { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Whether the API key is missing or was rejected
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self, FragarachError::MissingApiKey(_))
+            || matches!(self.status(), Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN))
+    }
+
+    /// Whether the API refused the request for exceeding its rate limit
+    pub fn is_rate_limited(&self) -> bool {
+        self.status() == Some(StatusCode::TOO_MANY_REQUESTS)
+    }
+
+    /// What the user can do about the failure, when there is something
+    pub fn hint(&self) -> Option<&'static str> {
+        if self.is_auth_failure() {
+            Some("Set a valid key under Settings → Manage API Keys, or in its environment variable.")
+        } else if self.is_rate_limited() {
+            Some("The API is rate limiting requests; wait a minute before trying again.")
+        } else if matches!(self, FragarachError::Timeout { .. }) {
+            Some("Check the connection, or raise FRAGARACH_HTTP_TIMEOUT_SECS for slow responses.")
+        } else if matches!(self, FragarachError::Config { .. }) {
+            Some("Fix or remove the file, or change the setting from the Settings menu.")
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_status(status: StatusCode, body: &str) -> FragarachError {
+        FragarachError::ApiStatus { operation: "Transpose API request".to_string(), status, body: body.to_string() }
+    }
+
+    #[test]
+    fn api_status_shows_the_response_body() {
+        assert_eq!(
+            api_status(StatusCode::BAD_REQUEST, "syntax error at or near \"FORM\"").to_string(),
+            "Transpose API request failed with status: 400 Bad Request (syntax error at or near \"FORM\")"
+        );
+        assert_eq!(
+            api_status(StatusCode::BAD_GATEWAY, "").to_string(),
+            "Transpose API request failed with status: 502 Bad Gateway"
+        );
+    }
+
+    #[test]
+    fn failures_are_classified() {
+        assert!(FragarachError::MissingApiKey("URLScan").is_auth_failure());
+        assert!(api_status(StatusCode::UNAUTHORIZED, "").is_auth_failure());
+        assert!(api_status(StatusCode::TOO_MANY_REQUESTS, "").is_rate_limited());
+        assert!(!api_status(StatusCode::TOO_MANY_REQUESTS, "").is_auth_failure());
+        assert_eq!(api_status(StatusCode::INTERNAL_SERVER_ERROR, "").hint(), None);
+    }
+}
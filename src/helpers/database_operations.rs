@@ -4,36 +4,1479 @@
 /// - Data persistence
 /// - Record updates
 /// - Batch operations
-use duckdb::{Connection, Result, ToSql};
-use serde_json::Value;
+/// - Record reads filtered by column values
+/// - Ad-hoc read-only `SELECT` queries
+/// - Typed reads of saved accounts, transactions and scanned domains
+/// - Text search across stored DOM snapshots
+/// - Investigation cases records are attached to
+/// - Tags on addresses and domains, kept in `entity_tags` apart from the
+///   records themselves so re-querying an entity never drops them
+/// - `fragarach_meta` key/value reads and writes
+/// - Maintenance: purging records older than a cutoff and compacting the file
+///
+/// Each batch is written in a single transaction, so a batch is either fully
+/// stored or not at all.
+use duckdb::types::{Value as SqlValue, ValueRef};
+use duckdb::{params, params_from_iter, Connection, Error, OptionalExt, Result};
+use flate2::read::GzDecoder;
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use tracing::{debug, trace, warn};
 
+/// Maps a JSON value to the DuckDB value it should be stored as
+///
+/// Nested arrays and objects are stored as their JSON text.
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Boolean(*b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                SqlValue::BigInt(i)
+            } else if let Some(u) = n.as_u64() {
+                SqlValue::UBigInt(u)
+            } else {
+                SqlValue::Double(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Array(_) | Value::Object(_) => SqlValue::Text(value.to_string()),
+    }
+}
+
+/// Most parameters bound to one multi-row insert
+const MAX_PARAMS_PER_INSERT: usize = 10_000;
+
+/// Inserts JSON records into `table_name`, replacing rows with the same key
+///
+/// Each record's keys name the columns it fills. All records are written in one
+/// transaction, so if any record fails none are stored. Consecutive records
+/// filling the same columns go in as multi-row inserts, each statement
+/// prepared once and reused for every full chunk; a row per statement is
+/// several times slower once batches reach the thousands. Within a run, only
+/// the last record with a given primary key is kept, so later records still
+/// replace earlier ones.
+///
+/// # Panics
+/// If a record is not a JSON object.
 pub fn save_records(conn: &Connection, data: &[Value], table_name: &str) -> Result<()> {
-    for record in data {
-        let obj = record.as_object().unwrap();
-        let columns = obj.keys().map(|s| s.as_str()).collect::<Vec<_>>().join(", ");
-        let placeholders = (0..obj.len())
-            .map(|i| format!("${}", i + 1))
-            .collect::<Vec<_>>()
-            .join(", ");
-        
-        let sql = format!(
-            "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
-            table_name,
-            columns,
-            placeholders
-        );
-        
-        let values: Vec<String> = obj.values()
-            .map(|v| v.to_string())
+    debug!(table = table_name, records = data.len(), "Saving records");
+    let tx = conn.unchecked_transaction()?;
+    let primary_key = primary_key(&tx, table_name)?;
+
+    let records = data.iter().map(|record| record.as_object().unwrap()).collect::<Vec<_>>();
+    for run in records.chunk_by(|a, b| a.keys().eq(b.keys())) {
+        let run = last_per_key(run, &primary_key);
+        let columns = run[0].len().max(1);
+        for chunk in run.chunks((MAX_PARAMS_PER_INSERT / columns).max(1)) {
+            let sql = insert_sql(table_name, chunk[0], chunk.len());
+            trace!(%sql, rows = chunk.len());
+            tx.prepare_cached(&sql)?
+                .execute(params_from_iter(chunk.iter().flat_map(|record| record.values().map(sql_value))))?;
+        }
+    }
+    tx.commit()
+}
+
+/// Columns of `table_name`'s primary key, empty when it has none
+fn primary_key(conn: &Connection, table_name: &str) -> Result<Vec<String>> {
+    conn.query_row(
+        "SELECT constraint_column_names FROM duckdb_constraints()
+         WHERE table_name = $1 AND constraint_type = 'PRIMARY KEY'",
+        params![table_name],
+        |row| row.get::<_, SqlValue>(0),
+    )
+    .optional()
+    .map(|names| match names {
+        Some(SqlValue::List(names)) => names
+            .into_iter()
+            .filter_map(|name| match name {
+                SqlValue::Text(name) => Some(name),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+/// Drops records a later one in `run` replaces
+///
+/// A statement that hits the same key twice keeps an arbitrary one of them, so
+/// duplicates never reach it. Key columns the records leave out (like `chain`)
+/// take the same default in every row, so only the ones they fill are compared;
+/// records filling none of them (a generated `id`) are all kept.
+fn last_per_key<'a>(run: &[&'a Map<String, Value>], primary_key: &[String]) -> Vec<&'a Map<String, Value>> {
+    let filled = primary_key.iter().filter(|column| run[0].contains_key(column.as_str())).collect::<Vec<_>>();
+    if filled.is_empty() {
+        return run.to_vec();
+    }
+    let key = |record: &Map<String, Value>| filled.iter().map(|column| record[column.as_str()].to_string()).collect::<Vec<_>>();
+    let last = run.iter().enumerate().map(|(i, record)| (key(record), i)).collect::<HashMap<_, _>>();
+    run.iter()
+        .enumerate()
+        .filter(|(i, record)| last[&key(record)] == *i)
+        .map(|(_, record)| *record)
+        .collect()
+}
+
+/// `INSERT OR REPLACE` of `rows` rows filling the columns of `record`
+fn insert_sql(table_name: &str, record: &Map<String, Value>, rows: usize) -> String {
+    let columns = record.keys().map(|s| s.as_str()).collect::<Vec<_>>().join(", ");
+    let values = (0..rows)
+        .map(|row| {
+            let placeholders = (1..=record.len()).map(|i| format!("${}", row * record.len() + i));
+            format!("({})", placeholders.collect::<Vec<_>>().join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("INSERT OR REPLACE INTO {} ({}) VALUES {}", table_name, columns, values)
+}
+
+/// Converts a DuckDB value to JSON; `select_list` casts other types to text first
+pub(crate) fn json_value(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Boolean(b) => Value::Bool(b),
+        ValueRef::TinyInt(n) => n.into(),
+        ValueRef::SmallInt(n) => n.into(),
+        ValueRef::Int(n) => n.into(),
+        ValueRef::BigInt(n) => n.into(),
+        ValueRef::UTinyInt(n) => n.into(),
+        ValueRef::USmallInt(n) => n.into(),
+        ValueRef::UInt(n) => n.into(),
+        ValueRef::UBigInt(n) => n.into(),
+        ValueRef::Float(n) => Number::from_f64(n.into()).map_or(Value::Null, Value::Number),
+        ValueRef::Double(n) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+        ValueRef::Text(bytes) => Value::String(String::from_utf8_lossy(bytes).into_owned()),
+        _ => Value::Null,
+    }
+}
+
+/// Column names of `table` in order, each with whether its type is JSON-native
+pub(crate) fn table_columns(conn: &Connection, table: &str) -> Result<Vec<(String, bool)>> {
+    conn.prepare("SELECT column_name, data_type IN ('BOOLEAN', 'TINYINT', 'SMALLINT', 'INTEGER', 'BIGINT',
+                    'UTINYINT', 'USMALLINT', 'UINTEGER', 'UBIGINT', 'FLOAT', 'DOUBLE')
+                  FROM information_schema.columns WHERE table_name = $1 ORDER BY ordinal_position")?
+        .query_map(params![table], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+}
+
+/// Select list for `columns` that keeps JSON-native types as they are and
+/// renders everything else (timestamps, decimals, ...) as DuckDB's text form
+pub(crate) fn select_list(columns: &[(String, bool)]) -> String {
+    columns.iter()
+        .map(|(name, native)| if *native {
+            format!("\"{}\"", name)
+        } else {
+            format!("CAST(\"{}\" AS VARCHAR)", name)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Condition on one column of a `query_records` query
+///
+/// Values are bound as text and cast by DuckDB to the column's type.
+#[derive(Debug, Clone, Copy)]
+pub enum Filter<'a> {
+    /// Column equals the value
+    Equals(&'a str, &'a str),
+    /// Column matches a `LIKE` pattern
+    Like(&'a str, &'a str),
+    /// Column is greater than or equal to the value
+    AtLeast(&'a str, &'a str),
+}
+
+impl<'a> Filter<'a> {
+    fn column(&self) -> &'a str {
+        match self {
+            Filter::Equals(column, _) | Filter::Like(column, _) | Filter::AtLeast(column, _) => column,
+        }
+    }
+
+    fn value(&self) -> &'a str {
+        match self {
+            Filter::Equals(_, value) | Filter::Like(_, value) | Filter::AtLeast(_, value) => value,
+        }
+    }
+
+    fn operator(&self) -> &'static str {
+        match self {
+            Filter::Equals(..) => "=",
+            Filter::Like(..) => "LIKE",
+            Filter::AtLeast(..) => ">=",
+        }
+    }
+}
+
+/// Reads rows of `table` matching every filter as JSON objects keyed by column name
+///
+/// Filters on columns the table doesn't have fail with `InvalidColumnName`, and
+/// a table that doesn't exist fails like any query.
+pub fn query_records(
+    conn: &Connection,
+    table: &str,
+    filters: &[Filter],
+    limit: Option<usize>,
+) -> Result<Vec<Value>> {
+    query_records_page(conn, table, filters, limit, 0)
+}
+
+/// Like `query_records`, skipping the first `offset` matching rows
+///
+/// Rows come in storage order, which is stable while the table isn't written,
+/// so consecutive offsets page through the rows without repeats.
+pub fn query_records_page(
+    conn: &Connection,
+    table: &str,
+    filters: &[Filter],
+    limit: Option<usize>,
+    offset: usize,
+) -> Result<Vec<Value>> {
+    let columns = table_columns(conn, table)?;
+    let mut sql = format!(
+        "SELECT {} FROM \"{}\"{}",
+        select_list(&columns), table.replace('"', "\"\""), where_clause(&columns, filters)?
+    );
+    if let Some(limit) = limit {
+        sql.push_str(&format!(" LIMIT {}", limit));
+    }
+    if offset > 0 {
+        sql.push_str(&format!(" OFFSET {}", offset));
+    }
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(params_from_iter(filters.iter().map(Filter::value)))?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut record = Map::new();
+        for (i, (name, _)) in columns.iter().enumerate() {
+            record.insert(name.clone(), json_value(row.get_ref(i)?));
+        }
+        records.push(Value::Object(record));
+    }
+    Ok(records)
+}
+
+/// Number of rows of `table` matching every filter, checked like `query_records`
+pub fn count_records(conn: &Connection, table: &str, filters: &[Filter]) -> Result<u64> {
+    let columns = table_columns(conn, table)?;
+    let sql = format!(
+        "SELECT COUNT(*) FROM \"{}\"{}",
+        table.replace('"', "\"\""), where_clause(&columns, filters)?
+    );
+    conn.query_row(&sql, params_from_iter(filters.iter().map(Filter::value)), |row| row.get(0))
+}
+
+/// `WHERE` clause binding each filter's value as a positional parameter
+fn where_clause(columns: &[(String, bool)], filters: &[Filter]) -> Result<String> {
+    let mut conditions = Vec::with_capacity(filters.len());
+    for (i, filter) in filters.iter().enumerate() {
+        if !columns.iter().any(|(name, _)| name == filter.column()) {
+            return Err(Error::InvalidColumnName(filter.column().to_string()));
+        }
+        conditions.push(format!("\"{}\" {} ${}", filter.column(), filter.operator(), i + 1));
+    }
+    Ok(if conditions.is_empty() { String::new() } else { format!(" WHERE {}", conditions.join(" AND ")) })
+}
+
+/// First keyword of `sql`, uppercased, skipping whitespace, comments and opening parentheses
+fn leading_keyword(sql: &str) -> String {
+    let mut rest = sql;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else {
+            break;
+        }
+    }
+    rest.chars().take_while(|c| c.is_ascii_alphabetic()).collect::<String>().to_ascii_uppercase()
+}
+
+/// Trims `sql` and its trailing semicolon, failing with `InvalidQuery` unless it starts with `SELECT` or `WITH`
+pub(crate) fn check_read_query(sql: &str) -> Result<&str> {
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    if matches!(leading_keyword(sql).as_str(), "SELECT" | "WITH") {
+        Ok(sql)
+    } else {
+        Err(Error::InvalidQuery)
+    }
+}
+
+/// Runs a user-supplied `SELECT` and returns its rows as JSON objects keyed by column name
+///
+/// Statements starting with anything but `SELECT` or `WITH` fail with
+/// `InvalidQuery`, and several statements at once fail to prepare. The query
+/// also runs in a transaction that is always rolled back, so nothing it does
+/// is kept. Column types other than booleans and numbers are returned as text.
+pub fn run_read_query(conn: &Connection, sql: &str) -> Result<Vec<Value>> {
+    let sql = check_read_query(sql)?;
+    let tx = conn.unchecked_transaction()?;
+    let columns: Vec<(String, bool)> = tx
+        .prepare(&format!("DESCRIBE {}", sql))?
+        .query_map([], |row| {
+            let (name, data_type): (String, String) = (row.get(0)?, row.get(1)?);
+            let native = matches!(data_type.as_str(), "BOOLEAN" | "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT"
+                | "UTINYINT" | "USMALLINT" | "UINTEGER" | "UBIGINT" | "FLOAT" | "DOUBLE");
+            Ok((name, native))
+        })?
+        .collect::<Result<_>>()?;
+
+    // Columns are selected by position, since joins can repeat a name
+    let select = columns.iter().enumerate()
+        .map(|(i, (_, native))| if *native { format!("#{}", i + 1) } else { format!("CAST(#{} AS VARCHAR)", i + 1) })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let wrapped = format!("SELECT {} FROM ({}) AS query", select, sql);
+    trace!(sql = %wrapped);
+
+    let mut stmt = tx.prepare(&wrapped)?;
+    let mut rows = stmt.query([])?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut record = Map::new();
+        for (i, (name, _)) in columns.iter().enumerate() {
+            record.insert(name.clone(), json_value(row.get_ref(i)?));
+        }
+        records.push(Value::Object(record));
+    }
+    Ok(records)
+}
+
+/// Investigation case from `cases`, with the number of records attached to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub id: i64,
+    pub name: String,
+    pub created_at: Option<String>,
+    pub records: u64,
+}
+
+/// Id of the case named `name`, if it exists
+pub fn find_case(conn: &Connection, name: &str) -> Result<Option<i64>> {
+    conn.query_row("SELECT id FROM cases WHERE name = $1", params![name], |row| row.get(0))
+        .optional()
+}
+
+/// Id of the case named `name`, creating the case if it doesn't exist
+pub fn open_case(conn: &Connection, name: &str) -> Result<i64> {
+    if let Some(id) = find_case(conn, name)? {
+        return Ok(id);
+    }
+    conn.query_row("INSERT INTO cases (name) VALUES ($1) RETURNING id", params![name], |row| row.get(0))
+}
+
+/// Every case, oldest first
+pub fn list_cases(conn: &Connection) -> Result<Vec<Case>> {
+    conn.prepare(
+        "SELECT c.id, c.name, CAST(c.created_at AS VARCHAR), COUNT(r.case_id)
+         FROM cases c
+         LEFT JOIN (
+             SELECT case_id FROM ethereum_accounts
+             UNION ALL SELECT case_id FROM ethereum_transactions
+             UNION ALL SELECT case_id FROM urlscan_domain_data
+         ) r ON r.case_id = c.id
+         GROUP BY c.id, c.name, c.created_at
+         ORDER BY c.id",
+    )?
+    .query_map([], |row| Ok(Case {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        records: row.get(3)?,
+    }))?
+    .collect()
+}
+
+/// Kind of entity a tag is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityType {
+    Address,
+    Domain,
+}
+
+impl EntityType {
+    pub const ALL: [EntityType; 2] = [EntityType::Address, EntityType::Domain];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityType::Address => "address",
+            EntityType::Domain => "domain",
+        }
+    }
+}
+
+impl fmt::Display for EntityType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Label from `entity_tags` on an address or domain
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub entity_id: String,
+    pub tag: String,
+    pub note: Option<String>,
+    pub created_at: Option<String>,
+}
+
+/// Tags `entity_id` with `tag`, or replaces the note of a tag it already has
+///
+/// Entity ids and tags are trimmed and lowercased, so `Suspect` and `suspect`
+/// are the same tag; an empty note is stored as none.
+pub fn add_tag(conn: &Connection, entity_type: EntityType, entity_id: &str, tag: &str, note: Option<&str>) -> Result<()> {
+    let note = note.map(str::trim).filter(|note| !note.is_empty());
+    conn.execute(
+        "INSERT INTO entity_tags (entity_type, entity_id, tag, note) VALUES ($1, lower(trim($2)), lower(trim($3)), $4)
+         ON CONFLICT (entity_type, entity_id, tag) DO UPDATE SET note = excluded.note",
+        params![entity_type.as_str(), entity_id, tag, note],
+    )?;
+    Ok(())
+}
+
+/// Tags of one entity, or of every entity of the type without one, oldest first
+pub fn list_tags(conn: &Connection, entity_type: EntityType, entity_id: Option<&str>) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(
+        "SELECT entity_id, tag, note, CAST(created_at AS VARCHAR) FROM entity_tags
+         WHERE entity_type = $1 AND ($2 IS NULL OR entity_id = lower(trim($2)))
+         ORDER BY created_at, id",
+    )?;
+    let tags = stmt.query_map(params![entity_type.as_str(), entity_id], |row| Ok(Tag {
+        entity_id: row.get(0)?,
+        tag: row.get(1)?,
+        note: row.get(2)?,
+        created_at: row.get(3)?,
+    }))?
+    .collect();
+    tags
+}
+
+/// Removes `tag` from `entity_id`, returning whether it had it
+pub fn remove_tag(conn: &Connection, entity_type: EntityType, entity_id: &str, tag: &str) -> Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM entity_tags WHERE entity_type = $1 AND entity_id = lower(trim($2)) AND tag = lower(trim($3))",
+        params![entity_type.as_str(), entity_id, tag],
+    )?;
+    Ok(removed > 0)
+}
+
+/// SQL condition limiting rows to one case; every row matches without one
+pub(crate) fn case_condition(case_id: Option<i64>) -> String {
+    case_id.map_or_else(|| "TRUE".to_string(), |id| format!("case_id = {}", id))
+}
+
+/// Saved account from `ethereum_accounts`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredAccount {
+    pub chain: String,
+    pub address: String,
+    pub account_type: Option<String>,
+    pub created_timestamp: Option<String>,
+    pub last_active_timestamp: Option<String>,
+    pub creator_address: Option<String>,
+}
+
+/// Saved transaction from `ethereum_transactions`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredTransaction {
+    pub transaction_hash: String,
+    pub block_number: Option<i64>,
+    pub timestamp: Option<String>,
+    pub from_address: Option<String>,
+    pub to_address: Option<String>,
+    pub value: Option<f64>,
+}
+
+/// Scanned domain from `urlscan_domain_data`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedDomain {
+    pub domain: String,
+    pub uuid: String,
+    pub status: Option<String>,
+    pub title: Option<String>,
+    pub ip: Option<String>,
+    pub verdict_score: Option<i64>,
+    pub verdict_brands: Option<String>,
+    pub created_at: Option<String>,
+}
+
+/// Saved accounts in a case (or all of them), most recently active first
+pub fn list_accounts(conn: &Connection, case_id: Option<i64>) -> Result<Vec<StoredAccount>> {
+    conn.prepare(&format!(
+        "SELECT chain, address, type, CAST(created_timestamp AS VARCHAR),
+                CAST(last_active_timestamp AS VARCHAR), creator_address
+         FROM ethereum_accounts WHERE {}
+         ORDER BY last_active_timestamp DESC NULLS LAST, address",
+        case_condition(case_id)
+    ))?
+    .query_map([], |row| Ok(StoredAccount {
+        chain: row.get(0)?,
+        address: row.get(1)?,
+        account_type: row.get(2)?,
+        created_timestamp: row.get(3)?,
+        last_active_timestamp: row.get(4)?,
+        creator_address: row.get(5)?,
+    }))?
+    .collect()
+}
+
+/// SQL condition matching transactions sent from or to `$2` on chain `$1`
+///
+/// Addresses are hex, so they are compared case-insensitively.
+const ADDRESS_TRANSACTIONS: &str =
+    "chain = $1 AND (lower(from_address) = lower($2) OR lower(to_address) = lower($2))";
+
+/// Number of saved transactions sent from or to `address` in a case (or in any)
+pub fn count_transactions(conn: &Connection, chain: &str, address: &str, case_id: Option<i64>) -> Result<u64> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM ethereum_transactions WHERE {} AND {}",
+            ADDRESS_TRANSACTIONS, case_condition(case_id)
+        ),
+        params![chain, address],
+        |row| row.get(0),
+    )
+}
+
+/// Timestamps of the first and last saved transaction sent from or to `address`
+/// in a case (or in any)
+pub fn transaction_span(conn: &Connection, chain: &str, address: &str, case_id: Option<i64>) -> Result<(Option<String>, Option<String>)> {
+    conn.query_row(
+        &format!(
+            "SELECT CAST(MIN(timestamp) AS VARCHAR), CAST(MAX(timestamp) AS VARCHAR)
+             FROM ethereum_transactions WHERE {} AND {}",
+            ADDRESS_TRANSACTIONS, case_condition(case_id)
+        ),
+        params![chain, address],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+/// Block up to which every transaction of `address` is stored, as recorded by
+/// `advance_sync_mark`; `None` before its first complete pull
+pub fn sync_mark(conn: &Connection, chain: &str, address: &str) -> Result<Option<u64>> {
+    let mark: Option<i64> = conn
+        .query_row(
+            "SELECT last_block FROM sync_state WHERE chain = $1 AND address = lower($2)",
+            params![chain, address],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(mark.map(|block| block as u64))
+}
+
+/// Records that a complete pull of `address` from `from_block` up to the latest
+/// block has been saved, moving its sync mark to the newest stored block
+///
+/// Only a pull that started at block 0 or at or below the current mark leaves
+/// no gap behind it; any other pull, or one for an address with no stored
+/// transactions, leaves the mark alone. The mark never moves back. Returns
+/// whether it moved.
+pub fn advance_sync_mark(conn: &Connection, chain: &str, address: &str, from_block: u64) -> Result<bool> {
+    let current = sync_mark(conn, chain, address)?;
+    if from_block > 0 && current.is_none_or(|mark| from_block > mark) {
+        return Ok(false);
+    }
+    let newest: Option<i64> = conn.query_row(
+        &format!("SELECT MAX(block_number) FROM ethereum_transactions WHERE {}", ADDRESS_TRANSACTIONS),
+        params![chain, address],
+        |row| row.get(0),
+    )?;
+    let Some(newest) = newest.map(|block| block as u64).filter(|newest| current.is_none_or(|mark| *newest > mark)) else {
+        return Ok(false);
+    };
+    conn.execute(
+        "INSERT INTO sync_state (chain, address, last_block, synced_at) VALUES ($1, lower($2), $3, CURRENT_TIMESTAMP)
+         ON CONFLICT (chain, address) DO UPDATE SET last_block = excluded.last_block, synced_at = excluded.synced_at",
+        params![chain, address, newest as i64],
+    )?;
+    Ok(true)
+}
+
+/// Saved transactions sent from or to `address` in a case (or in any), newest first
+///
+/// Returns at most `limit` transactions after skipping the newest `offset`.
+pub fn get_transactions_for_address(
+    conn: &Connection,
+    chain: &str,
+    address: &str,
+    case_id: Option<i64>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<StoredTransaction>> {
+    conn.prepare(&format!(
+        "SELECT transaction_hash, block_number, CAST(timestamp AS VARCHAR), from_address, to_address, value
+         FROM ethereum_transactions WHERE {} AND {}
+         ORDER BY block_number DESC NULLS LAST, position DESC LIMIT {} OFFSET {}",
+        ADDRESS_TRANSACTIONS, case_condition(case_id), limit, offset
+    ))?
+    .query_map(params![chain, address], |row| Ok(StoredTransaction {
+        transaction_hash: row.get(0)?,
+        block_number: row.get(1)?,
+        timestamp: row.get(2)?,
+        from_address: row.get(3)?,
+        to_address: row.get(4)?,
+        value: row.get(5)?,
+    }))?
+    .collect()
+}
+
+/// Scanned domains in a case (or all of them), most recent scan first
+pub fn list_scanned_domains(conn: &Connection, case_id: Option<i64>) -> Result<Vec<ScannedDomain>> {
+    conn.prepare(&format!(
+        "SELECT domain, uuid, status, title, ip, verdict_score, verdict_brands, CAST(created_at AS VARCHAR)
+         FROM urlscan_domain_data WHERE {} ORDER BY created_at DESC, id DESC",
+        case_condition(case_id)
+    ))?
+    .query_map([], |row| Ok(ScannedDomain {
+        domain: row.get(0)?,
+        uuid: row.get(1)?,
+        status: row.get(2)?,
+        title: row.get(3)?,
+        ip: row.get(4)?,
+        verdict_score: row.get(5)?,
+        verdict_brands: row.get(6)?,
+        created_at: row.get(7)?,
+    }))?
+    .collect()
+}
+
+/// Removes the saved NFT holdings of `owner` on `chain`, returning how many rows went
+///
+/// Holdings are a snapshot, so they are cleared before a new pull rather than
+/// keeping tokens the owner has since sold.
+pub fn clear_nft_holdings(conn: &Connection, chain: &str, owner: &str) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM nft_holdings WHERE chain = $1 AND lower(owner_address) = lower($2)",
+        params![chain, owner],
+    )
+}
+
+/// Scan whose DOM snapshot contains a searched string
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomMatch {
+    pub uuid: String,
+    pub domain: Option<String>,
+    pub scanned_at: Option<String>,
+    /// The first match with up to `context` characters on each side, on one line
+    pub snippet: String,
+}
+
+/// Text around the first case-insensitive match of `needle` in `dom`
+///
+/// Matching ignores ASCII case only, so offsets stay valid in the original text.
+fn dom_snippet(dom: &str, needle: &str, context: usize) -> Option<String> {
+    let start = dom.to_ascii_lowercase().find(&needle.to_ascii_lowercase())?;
+    let before = dom[..start].char_indices().rev().nth(context.saturating_sub(1)).map_or(0, |(i, _)| i);
+    let after = &dom[start + needle.len()..];
+    let end = start + needle.len() + after.char_indices().nth(context).map_or(after.len(), |(i, _)| i);
+    Some(dom[before..end].split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Scans whose DOM snapshot contains `needle`, ignoring case, most recent first
+///
+/// Snapshots kept in the database are matched by DuckDB, which only returns
+/// the snippet. Snapshots stored as compressed files are read one at a time;
+/// unreadable files are skipped with a warning. This is a plain substring
+/// search rather than DuckDB's `fts` extension, since wallet addresses and kit
+/// fingerprints are often fragments that a word index wouldn't match.
+pub fn search_doms(
+    conn: &Connection,
+    needle: &str,
+    context: usize,
+    limit: usize,
+    case_id: Option<i64>,
+) -> Result<Vec<DomMatch>> {
+    if needle.is_empty() {
+        return Ok(Vec::new());
+    }
+    let scans = format!(
+        "LEFT JOIN urlscan_domain_data d ON d.uuid = s.uuid WHERE {}",
+        case_condition(case_id).replace("case_id", "d.case_id")
+    );
+
+    let mut matches: Vec<DomMatch> = conn.prepare(&format!(
+        "SELECT s.uuid, d.domain, CAST(d.created_at AS VARCHAR),
+                substr(s.dom, greatest(1, s.position - $2), least(s.position - 1, $2) + length($1) + $2)
+         FROM (SELECT uuid, dom, strpos(lower(dom), lower($1)) AS position
+               FROM urlscan_dom_snapshot WHERE dom IS NOT NULL) s
+         {} AND s.position > 0",
+        scans
+    ))?
+    .query_map(params![needle, context as i64], |row| Ok(DomMatch {
+        uuid: row.get(0)?,
+        domain: row.get(1)?,
+        scanned_at: row.get(2)?,
+        snippet: row.get::<_, String>(3)?.split_whitespace().collect::<Vec<_>>().join(" "),
+    }))?
+    .collect::<Result<_>>()?;
+
+    let files: Vec<(String, String, Option<String>, Option<String>)> = conn.prepare(&format!(
+        "SELECT s.uuid, s.dom_path, d.domain, CAST(d.created_at AS VARCHAR)
+         FROM urlscan_dom_snapshot s {} AND s.dom IS NULL AND s.dom_path IS NOT NULL",
+        scans
+    ))?
+    .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+    .collect::<Result<_>>()?;
+
+    for (uuid, path, domain, scanned_at) in files {
+        let mut dom = String::new();
+        if let Err(e) = File::open(&path).and_then(|file| GzDecoder::new(file).read_to_string(&mut dom)) {
+            warn!(%uuid, %path, "Skipping unreadable DOM snapshot: {}", e);
+            continue;
+        }
+        if let Some(snippet) = dom_snippet(&dom, needle, context) {
+            matches.push(DomMatch { uuid, domain, scanned_at, snippet });
+        }
+    }
+
+    matches.sort_by(|a, b| b.scanned_at.cmp(&a.scanned_at));
+    matches.truncate(limit);
+    Ok(matches)
+}
+
+/// Logged query from `query_history`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub queried_at: Option<String>,
+    pub operation: String,
+    pub target: String,
+    pub result_count: Option<i64>,
+}
+
+/// Logs a completed query, such as `ethereum transactions` for an address
+pub fn record_query(
+    conn: &Connection,
+    operation: &str,
+    target: &str,
+    result_count: usize,
+    case_id: Option<i64>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO query_history (operation, target, result_count, case_id) VALUES ($1, $2, $3, $4)",
+        params![operation, target, result_count as i64, case_id],
+    )?;
+    Ok(())
+}
+
+/// The last `limit` queries in a case (or in any), newest first
+pub fn list_history(conn: &Connection, case_id: Option<i64>, limit: usize) -> Result<Vec<HistoryEntry>> {
+    conn.prepare(&format!(
+        "SELECT CAST(queried_at AS VARCHAR), operation, target, result_count
+         FROM query_history WHERE {} ORDER BY queried_at DESC, id DESC LIMIT {}",
+        case_condition(case_id), limit
+    ))?
+    .query_map([], |row| Ok(HistoryEntry {
+        queried_at: row.get(0)?,
+        operation: row.get(1)?,
+        target: row.get(2)?,
+        result_count: row.get(3)?,
+    }))?
+    .collect()
+}
+
+/// Stored screenshot of a URLScan scan and the path it was saved to
+///
+/// Either is `None` when the download failed or, for the image, when it was
+/// saved before screenshots were kept in the database. Fails with
+/// `QueryReturnedNoRows` if there is no scan with `uuid`.
+pub fn read_screenshot(conn: &Connection, uuid: &str) -> Result<(Option<Vec<u8>>, Option<String>)> {
+    conn.query_row(
+        "SELECT screenshot, screenshot_path FROM urlscan_domain_data WHERE uuid = $1",
+        params![uuid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+/// Reads a value from the `fragarach_meta` table
+pub fn read_meta(conn: &Connection, key: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM fragarach_meta WHERE key = $1",
+        params![key],
+        |row| row.get(0),
+    ).optional()
+}
+
+/// Writes a value to the `fragarach_meta` table, replacing any existing one
+pub fn write_meta(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO fragarach_meta (key, value) VALUES ($1, $2)",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// Tables `purge_older_than` can clear, each with the column that dates its rows
+///
+/// Every row is dated by when it was stored; for transactions and transfers
+/// that is `saved_at`, as their `timestamp` is the block time. Accounts, cases,
+/// tags, the watchlist and sync marks aren't records that age, so they are
+/// never purged.
+pub const PURGEABLE_TABLES: &[(&str, &str)] = &[
+    ("ethereum_transactions", "saved_at"),
+    ("token_transfers", "saved_at"),
+    ("nft_transfers", "saved_at"),
+    ("nft_holdings", "queried_at"),
+    ("ens_names", "resolved_at"),
+    ("urlscan_domain_data", "created_at"),
+    ("urlscan_scan_data", "created_at"),
+    ("urlscan_dom_snapshot", "created_at"),
+    ("domain_whois", "looked_up_at"),
+    ("domain_dns", "resolved_at"),
+    ("virustotal_domain_data", "looked_up_at"),
+    ("virustotal_ip_data", "looked_up_at"),
+    ("shodan_host_data", "looked_up_at"),
+    ("query_history", "queried_at"),
+    ("export_log", "created_at"),
+];
+
+/// `WHERE` condition matching rows of `table` older than `$1` days
+///
+/// Fails with `InvalidParameterName` for tables not in `PURGEABLE_TABLES`.
+fn older_than_condition(table: &str) -> Result<String> {
+    let (_, column) = PURGEABLE_TABLES.iter()
+        .find(|(name, _)| *name == table)
+        .ok_or_else(|| Error::InvalidParameterName(table.to_string()))?;
+    Ok(format!("\"{}\" < CAST(current_timestamp AS TIMESTAMP) - to_days(CAST($1 AS INTEGER))", column))
+}
+
+/// Number of rows `purge_older_than` would delete from `table`, without deleting them
+///
+/// For `urlscan_domain_data` this counts the scans; their scan data and DOM
+/// snapshots go with them.
+pub fn count_older_than(conn: &Connection, table: &str, days: u32) -> Result<u64> {
+    let sql = format!("SELECT COUNT(*) FROM {} WHERE {}", table, older_than_condition(table)?);
+    conn.query_row(&sql, params![days], |row| row.get(0))
+}
+
+/// Deletes rows of `table` older than `days` days, returning how many went
+///
+/// Purging `urlscan_domain_data` also deletes the scan data and DOM snapshots
+/// of the scans it removes. DOM snapshot and screenshot files of deleted rows
+/// are removed once the deletion is committed; files that can't be removed
+/// are left with a warning. Sync marks are kept, so transactions saved before
+/// the cutoff aren't pulled again by incremental syncs.
+pub fn purge_older_than(conn: &Connection, table: &str, days: u32) -> Result<usize> {
+    let condition = older_than_condition(table)?;
+    let tx = conn.unchecked_transaction()?;
+
+    let mut files: Vec<String> = Vec::new();
+    if table == "urlscan_domain_data" {
+        let scans = format!("SELECT uuid FROM urlscan_domain_data WHERE {}", condition);
+        files.extend(tx.prepare(&format!(
+            "SELECT screenshot_path FROM urlscan_domain_data WHERE {} AND screenshot_path IS NOT NULL
+             UNION ALL SELECT dom_path FROM urlscan_dom_snapshot WHERE uuid IN ({}) AND dom_path IS NOT NULL",
+            condition, scans
+        ))?.query_map(params![days], |row| row.get(0))?.collect::<Result<Vec<_>>>()?);
+        tx.execute(&format!("DELETE FROM urlscan_scan_data WHERE uuid IN ({})", scans), params![days])?;
+        tx.execute(&format!("DELETE FROM urlscan_dom_snapshot WHERE uuid IN ({})", scans), params![days])?;
+    } else if table == "urlscan_dom_snapshot" {
+        files.extend(tx.prepare(&format!(
+            "SELECT dom_path FROM urlscan_dom_snapshot WHERE {} AND dom_path IS NOT NULL", condition
+        ))?.query_map(params![days], |row| row.get(0))?.collect::<Result<Vec<_>>>()?);
+    }
+
+    let deleted = tx.execute(&format!("DELETE FROM {} WHERE {}", table, condition), params![days])?;
+    tx.commit()?;
+    debug!(table, days, deleted, files = files.len(), "Purged old records");
+
+    for path in files {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => warn!(%path, "Couldn't remove purged file: {}", e),
+            _ => {}
+        }
+    }
+    Ok(deleted)
+}
+
+/// Writes everything to the database file and returns the space of deleted rows to DuckDB
+///
+/// Freed blocks are reused by later writes; the file itself only shrinks when
+/// they are at its end.
+pub fn compact(conn: &Connection) -> Result<()> {
+    conn.execute_batch("VACUUM; CHECKPOINT;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::migrations::{self, test_db};
+    use serde_json::json;
+
+    fn transaction() -> Value {
+        json!({
+            "transaction_hash": "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
+            "base_fee_per_gas": null,
+            "block_number": 46147,
+            "contract_address": null,
+            "fees_burned": 0,
+            "from_address": "0xA1E4380A3B1f749673E270229993eE55F35663b4",
+            "gas_limit": 21000,
+            "gas_price": 50000000000000_u64,
+            "gas_used": 21000,
+            "input": "0x",
+            "internal_transaction_count": 0,
+            "log_count": 0,
+            "nonce": 0,
+            "position": 0,
+            "timestamp": "2015-08-07T03:30:33Z",
+            "to_address": "0x5DF9B87991262F6BA471F09758CDE1c0FC1De734",
+            "transaction_fee": 1.05e18,
+            "type": 0,
+            "value": 31337
+        })
+    }
+
+    #[test]
+    fn transaction_round_trips_with_native_types() {
+        let conn = test_db();
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+
+        let (block_number, value, from_address): (i64, f64, String) = conn
+            .query_row(
+                "SELECT block_number, value, from_address FROM ethereum_transactions",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(block_number, 46147);
+        assert_eq!(value, 31337.0);
+        assert_eq!(from_address, "0xA1E4380A3B1f749673E270229993eE55F35663b4");
+    }
+
+    fn synthetic_transactions(count: usize) -> Vec<Value> {
+        (0..count)
+            .map(|i| {
+                let mut tx = transaction();
+                tx["transaction_hash"] = json!(format!("0x{:064x}", i));
+                tx["block_number"] = json!(46147 + i);
+                tx
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bulk_saves_upsert_thousands_of_records() {
+        let conn = test_db();
+        let records = synthetic_transactions(5000);
+        save_records(&conn, &records, "ethereum_transactions").unwrap();
+        assert_eq!(count_records(&conn, "ethereum_transactions", &[]).unwrap(), 5000);
+
+        // Saving again replaces rather than duplicates, and the later copy wins
+        // even within one statement
+        let mut changed = records[..10].to_vec();
+        changed.iter_mut().for_each(|tx| tx["value"] = json!(1));
+        changed.push(records[0].clone());
+        save_records(&conn, &changed, "ethereum_transactions").unwrap();
+        assert_eq!(count_records(&conn, "ethereum_transactions", &[]).unwrap(), 5000);
+        let ones: i64 = conn
+            .query_row("SELECT COUNT(*) FROM ethereum_transactions WHERE value = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(ones, 9);
+    }
+
+    /// The insert path `save_records` replaced: one statement per record
+    fn save_row_by_row(conn: &Connection, data: &[Value], table_name: &str) -> Result<()> {
+        let tx = conn.unchecked_transaction()?;
+        for record in data.iter().map(|record| record.as_object().unwrap()) {
+            tx.prepare_cached(&insert_sql(table_name, record, 1))?
+                .execute(params_from_iter(record.values().map(sql_value)))?;
+        }
+        tx.commit()
+    }
+
+    /// Times both insert paths; run with `cargo test -- --ignored --nocapture`
+    #[test]
+    #[ignore = "benchmark"]
+    fn multi_row_inserts_beat_one_row_per_statement() {
+        let records = synthetic_transactions(5000);
+        let time = |save: fn(&Connection, &[Value], &str) -> Result<()>| {
+            let conn = test_db();
+            let started = std::time::Instant::now();
+            save(&conn, &records, "ethereum_transactions").unwrap();
+            let elapsed = started.elapsed();
+            assert_eq!(count_records(&conn, "ethereum_transactions", &[]).unwrap(), 5000);
+            elapsed
+        };
+
+        let row_by_row = time(save_row_by_row);
+        let bulk = time(save_records);
+        println!("5000 records: bulk {:?}, row by row {:?}", bulk, row_by_row);
+    }
+
+    #[test]
+    fn failed_record_rolls_back_the_whole_batch() {
+        let conn = test_db();
+        let mut records = synthetic_transactions(5);
+        records[3]["block_number"] = json!("not a block");
+        assert!(save_records(&conn, &records, "ethereum_transactions").is_err());
+        assert_eq!(count_records(&conn, "ethereum_transactions", &[]).unwrap(), 0);
+
+        records[3]["block_number"] = json!(46150);
+        save_records(&conn, &records, "ethereum_transactions").unwrap();
+        assert_eq!(count_records(&conn, "ethereum_transactions", &[]).unwrap(), 5);
+    }
+
+    #[test]
+    fn account_address_is_stored_without_quotes() {
+        let conn = test_db();
+        let account = json!({
+            "address": "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045",
+            "created_timestamp": "2015-08-08T16:54:15Z",
+            "creator_address": null,
+            "last_active_timestamp": "2024-05-01T12:00:00Z",
+            "type": "wallet"
+        });
+        save_records(&conn, &[account], "ethereum_accounts").unwrap();
+
+        let (address, account_type): (String, String) = conn
+            .query_row("SELECT address, type FROM ethereum_accounts", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(address, "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045");
+        assert_eq!(account_type, "wallet");
+    }
+
+    #[test]
+    fn query_records_filters_and_limits() {
+        let conn = test_db();
+        let accounts = ["0xaaa", "0xbbb", "0xccc"].map(|address| json!({
+            "address": address,
+            "created_timestamp": "2015-08-08T16:54:15Z",
+            "type": if address == "0xbbb" { "contract" } else { "wallet" }
+        }));
+        save_records(&conn, &accounts, "ethereum_accounts").unwrap();
+
+        let contracts = query_records(&conn, "ethereum_accounts", &[Filter::Equals("type", "contract")], None).unwrap();
+        assert_eq!(contracts.len(), 1);
+        assert_eq!(contracts[0]["address"], "0xbbb");
+        assert_eq!(contracts[0]["chain"], "ethereum");
+        assert_eq!(contracts[0]["created_timestamp"], "2015-08-08 16:54:15");
+        assert_eq!(contracts[0]["creator_address"], Value::Null);
+
+        let limited = query_records(&conn, "ethereum_accounts", &[Filter::Equals("type", "wallet")], Some(1)).unwrap();
+        assert_eq!(limited.len(), 1);
+    }
+
+    #[test]
+    fn query_records_pages_through_matching_rows() {
+        let conn = test_db();
+        let accounts: Vec<Value> = (0..5).map(|i| json!({ "address": format!("0x{}", i), "type": "wallet" })).collect();
+        save_records(&conn, &accounts, "ethereum_accounts").unwrap();
+        let wallets = [Filter::Equals("type", "wallet")];
+
+        assert_eq!(count_records(&conn, "ethereum_accounts", &wallets).unwrap(), 5);
+        let pages: Vec<Vec<Value>> = [0, 2, 4]
+            .map(|offset| query_records_page(&conn, "ethereum_accounts", &wallets, Some(2), offset).unwrap())
+            .into();
+        let addresses: Vec<&Value> = pages.iter().flatten().map(|record| &record["address"]).collect();
+        assert_eq!(addresses, ["0x0", "0x1", "0x2", "0x3", "0x4"]);
+        assert_eq!(pages[2].len(), 1);
+
+        assert!(matches!(
+            count_records(&conn, "ethereum_accounts", &[Filter::Equals("nope", "x")]),
+            Err(Error::InvalidColumnName(_))
+        ));
+    }
+
+    #[test]
+    fn query_records_keeps_numeric_types() {
+        let conn = test_db();
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+
+        let records = query_records(&conn, "ethereum_transactions", &[Filter::Equals("block_number", "46147")], None).unwrap();
+        assert_eq!(records[0]["block_number"], 46147);
+        assert_eq!(records[0]["value"], 31337.0);
+    }
+
+    #[test]
+    fn query_records_matches_patterns_and_thresholds() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO urlscan_domain_data (domain, uuid, verdict_score) VALUES
+                ('login.example.com', 'u1', 0), ('example.org', 'u2', 75), ('other.net', 'u3', 100)",
+        ).unwrap();
+
+        let uuids = |filters: &[Filter]| -> Vec<Value> {
+            query_records(&conn, "urlscan_domain_data", filters, None).unwrap()
+                .into_iter().map(|r| r["uuid"].clone()).collect()
+        };
+        assert_eq!(uuids(&[Filter::Like("domain", "%example%")]), vec!["u1", "u2"]);
+        assert_eq!(uuids(&[Filter::AtLeast("verdict_score", "75")]), vec!["u2", "u3"]);
+        assert_eq!(uuids(&[Filter::Like("domain", "%example%"), Filter::AtLeast("verdict_score", "50")]), vec!["u2"]);
+    }
+
+    #[test]
+    fn read_query_joins_tables_with_text_timestamps() {
+        let conn = test_db();
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+        save_records(&conn, &[json!({ "address": "0xA1E4380A3B1f749673E270229993eE55F35663b4", "type": "eoa" })], "ethereum_accounts").unwrap();
+
+        let rows = run_read_query(&conn, "
+            -- senders with their account type
+            SELECT a.type, t.timestamp, t.value
+            FROM ethereum_transactions t JOIN ethereum_accounts a ON a.address = t.from_address;
+        ").unwrap();
+        assert_eq!(rows, vec![json!({ "type": "eoa", "timestamp": "2015-08-07 03:30:33", "value": 31337.0 })]);
+    }
+
+    #[test]
+    fn read_query_rejects_writes() {
+        let conn = test_db();
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+
+        for sql in ["DROP TABLE ethereum_transactions", "/* SELECT */ DELETE FROM ethereum_transactions", "SELECT 1; DROP TABLE ethereum_transactions"] {
+            assert!(run_read_query(&conn, sql).is_err(), "{} should be rejected", sql);
+        }
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM ethereum_transactions", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn dom_search_finds_inline_and_file_snapshots() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let conn = test_db();
+        let dir = std::env::temp_dir().join(format!("fragarach-dom-search-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("b.html.gz");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), flate2::Compression::default());
+        encoder.write_all(b"<p>Send to\n  0xDEADbeef00 now</p>").unwrap();
+        encoder.finish().unwrap();
+
+        conn.execute_batch(
+            "INSERT INTO urlscan_domain_data (domain, uuid, created_at) VALUES
+                ('a.example', 'a', '2026-01-01'), ('b.example', 'b', '2026-02-01'), ('c.example', 'c', '2026-03-01');
+             INSERT INTO urlscan_dom_snapshot (uuid, dom) VALUES
+                ('a', '<div>wallet: 0xdeadBEEF00</div>'), ('c', '<div>nothing here</div>');"
+        ).unwrap();
+        conn.execute(
+            "INSERT INTO urlscan_dom_snapshot (uuid, dom_path) VALUES ('b', $1)",
+            params![path.display().to_string()],
+        ).unwrap();
+
+        let matches = search_doms(&conn, "0xdeadbeef00", 8, 10, None).unwrap();
+        let found: Vec<(&str, Option<&str>, &str)> = matches.iter()
+            .map(|m| (m.uuid.as_str(), m.domain.as_deref(), m.snippet.as_str()))
             .collect();
-        
-        // Convert values to a slice of references that implement ToSql
-        let param_refs: Vec<&(dyn ToSql)> = values.iter()
-            .map(|s| s as &(dyn ToSql))
+        assert_eq!(found, [
+            ("b", Some("b.example"), "nd to 0xDEADbeef00 now</p>"),
+            ("a", Some("a.example"), "wallet: 0xdeadBEEF00</div>"),
+        ]);
+        assert_eq!(search_doms(&conn, "0xdeadbeef00", 8, 1, None).unwrap().len(), 1);
+        assert!(search_doms(&conn, "absent", 8, 10, None).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn query_records_rejects_unknown_filter_column() {
+        let conn = test_db();
+        let err = query_records(&conn, "ethereum_accounts", &[Filter::Equals("address; DROP TABLE x", "0x")], None).unwrap_err();
+        assert!(matches!(err, Error::InvalidColumnName(_)));
+    }
+
+    #[test]
+    fn transactions_for_address_page_newest_first() {
+        let conn = test_db();
+        let transactions: Vec<Value> = (1..=5)
+            .map(|block| {
+                let mut tx = transaction();
+                tx["transaction_hash"] = json!(format!("0x{}", block));
+                tx["block_number"] = json!(block);
+                tx
+            })
             .collect();
-        
-        conn.execute(&sql, param_refs.as_slice())?;
+        save_records(&conn, &transactions, "ethereum_transactions").unwrap();
+
+        let address = "0xa1e4380a3b1f749673e270229993ee55f35663b4";
+        assert_eq!(count_transactions(&conn, "ethereum", address, None).unwrap(), 5);
+        assert_eq!(count_transactions(&conn, "polygon", address, None).unwrap(), 0);
+        assert_eq!(transaction_span(&conn, "polygon", address, None).unwrap(), (None, None));
+        let (first, last) = transaction_span(&conn, "ethereum", address, None).unwrap();
+        assert_eq!((first.as_deref(), last.as_deref()), (Some("2015-08-07 03:30:33"), Some("2015-08-07 03:30:33")));
+
+        let page = get_transactions_for_address(&conn, "ethereum", address, None, 2, 2).unwrap();
+        let blocks: Vec<_> = page.iter().map(|tx| tx.block_number).collect();
+        assert_eq!(blocks, vec![Some(3), Some(2)]);
+        assert_eq!(page[0].timestamp.as_deref(), Some("2015-08-07 03:30:33"));
     }
-    
-    Ok(())
-} 
\ No newline at end of file
+
+    #[test]
+    fn sync_marks_only_advance_past_complete_pulls() {
+        let conn = test_db();
+        let address = "0xa1e4380a3b1f749673e270229993ee55f35663b4";
+        let save_block = |block: u64| {
+            let mut tx = transaction();
+            tx["transaction_hash"] = json!(format!("0x{}", block));
+            tx["block_number"] = json!(block);
+            save_records(&conn, &[tx], "ethereum_transactions").unwrap();
+        };
+
+        // Nothing is known about an address before a pull from block 0
+        save_block(100);
+        assert!(!advance_sync_mark(&conn, "ethereum", address, 50).unwrap());
+        assert_eq!(sync_mark(&conn, "ethereum", address).unwrap(), None);
+        assert!(advance_sync_mark(&conn, "ethereum", address, 0).unwrap());
+        assert_eq!(sync_mark(&conn, "ethereum", address).unwrap(), Some(100));
+
+        // A pull starting past the mark could have skipped blocks in between
+        save_block(300);
+        assert!(!advance_sync_mark(&conn, "ethereum", address, 200).unwrap());
+        assert!(advance_sync_mark(&conn, "ethereum", address, 100).unwrap());
+        assert_eq!(sync_mark(&conn, "ethereum", "0xA1E4380A3B1F749673E270229993EE55F35663B4").unwrap(), Some(300));
+        assert_eq!(sync_mark(&conn, "polygon", address).unwrap(), None);
+    }
+
+    #[test]
+    fn tags_outlive_requeried_records() {
+        let conn = test_db();
+        let address = "0xa1e4380a3b1f749673e270229993ee55f35663b4";
+        save_records(&conn, &[json!({ "address": address, "type": "eoa" })], "ethereum_accounts").unwrap();
+        add_tag(&conn, EntityType::Address, "0xA1E4380A3B1F749673E270229993EE55F35663B4", " Suspect ", Some("funds from the drainer")).unwrap();
+        add_tag(&conn, EntityType::Address, address, "exchange", Some("  ")).unwrap();
+        add_tag(&conn, EntityType::Domain, "phish.example", "suspect", None).unwrap();
+
+        // Re-querying replaces the account row, not its tags
+        save_records(&conn, &[json!({ "address": address, "type": "contract" })], "ethereum_accounts").unwrap();
+        add_tag(&conn, EntityType::Address, address, "suspect", Some("confirmed")).unwrap();
+
+        let tags = list_tags(&conn, EntityType::Address, Some(address)).unwrap();
+        let summary: Vec<_> = tags.iter().map(|t| (t.tag.as_str(), t.note.as_deref())).collect();
+        assert_eq!(summary, [("suspect", Some("confirmed")), ("exchange", None)]);
+        assert_eq!(list_tags(&conn, EntityType::Domain, None).unwrap()[0].entity_id, "phish.example");
+
+        assert!(remove_tag(&conn, EntityType::Address, address, "SUSPECT").unwrap());
+        assert!(!remove_tag(&conn, EntityType::Address, address, "suspect").unwrap());
+        assert_eq!(list_tags(&conn, EntityType::Address, Some(address)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn records_keep_their_case_and_filter_by_it() {
+        let conn = test_db();
+        let acme = open_case(&conn, "acme").unwrap();
+        assert_eq!(open_case(&conn, "acme").unwrap(), acme);
+        let other = open_case(&conn, "other").unwrap();
+
+        save_records(&conn, &[json!({ "address": "0xaaa", "case_id": acme })], "ethereum_accounts").unwrap();
+        save_records(&conn, &[json!({ "address": "0xbbb", "case_id": other })], "ethereum_accounts").unwrap();
+        save_records(&conn, &[json!({ "address": "0xccc" })], "ethereum_accounts").unwrap();
+        // Saving again outside a case leaves the record in its case
+        save_records(&conn, &[json!({ "address": "0xaaa", "type": "wallet" })], "ethereum_accounts").unwrap();
+
+        let in_acme: Vec<_> = list_accounts(&conn, Some(acme)).unwrap().into_iter().map(|a| a.address).collect();
+        assert_eq!(in_acme, vec!["0xaaa"]);
+        assert_eq!(list_accounts(&conn, None).unwrap().len(), 3);
+
+        let cases: Vec<_> = list_cases(&conn).unwrap().into_iter().map(|c| (c.name, c.records)).collect();
+        assert_eq!(cases, vec![("acme".to_string(), 1), ("other".to_string(), 1)]);
+        assert_eq!(find_case(&conn, "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn screenshot_round_trips_and_missing_one_is_none() {
+        let conn = test_db();
+        let png = vec![0x89, b'P', b'N', b'G', 0, 1, 2];
+        conn.execute(
+            "INSERT INTO urlscan_domain_data (domain, uuid, screenshot_path, screenshot) VALUES ('a.com', 'u1', 'u1.png', $1)",
+            params![png],
+        ).unwrap();
+        conn.execute_batch("INSERT INTO urlscan_domain_data (domain, uuid) VALUES ('b.com', 'u2')").unwrap();
+
+        assert_eq!(read_screenshot(&conn, "u1").unwrap(), (Some(png), Some("u1.png".to_string())));
+        assert_eq!(read_screenshot(&conn, "u2").unwrap(), (None, None));
+        assert!(matches!(read_screenshot(&conn, "u3"), Err(Error::QueryReturnedNoRows)));
+    }
+
+    #[test]
+    fn json_null_is_stored_as_sql_null() {
+        let conn = test_db();
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+
+        let contract_is_null: bool = conn
+            .query_row("SELECT contract_address IS NULL FROM ethereum_transactions", [], |row| row.get(0))
+            .unwrap();
+        assert!(contract_is_null);
+    }
+
+    #[test]
+    fn chain_migration_keeps_rows_and_allows_same_address_per_chain() {
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in migrations::MIGRATIONS.iter().filter(|m| m.version < 6) {
+            migrations::apply(&conn, migration).unwrap();
+        }
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+        save_records(&conn, &[json!({ "address": "0xabc", "type": "wallet" })], "ethereum_accounts").unwrap();
+
+        for migration in migrations::MIGRATIONS.iter().filter(|m| m.version >= 6) {
+            migrations::apply(&conn, migration).unwrap();
+        }
+        save_records(&conn, &[json!({ "chain": "polygon", "address": "0xabc", "type": "wallet" })], "ethereum_accounts").unwrap();
+
+        let chains: Vec<String> = conn
+            .prepare("SELECT chain FROM ethereum_accounts WHERE address = '0xabc' ORDER BY chain").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(chains, vec!["ethereum", "polygon"]);
+
+        let (chain, block_number): (String, i64) = conn
+            .query_row("SELECT chain, block_number FROM ethereum_transactions", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((chain.as_str(), block_number), ("ethereum", 46147));
+    }
+
+    #[test]
+    fn unquote_migration_cleans_legacy_rows() {
+        let conn = test_db();
+        conn.execute_batch(
+            r#"INSERT INTO ethereum_transactions (transaction_hash, from_address, contract_address)
+               VALUES ('"0xabc"', '"0xdef"', 'null')"#,
+        )
+        .unwrap();
+
+        let unquote = migrations::MIGRATIONS.iter().find(|m| m.version == 4).unwrap();
+        conn.execute_batch(unquote.sql).unwrap();
+
+        let (hash, from, contract): (String, String, Option<String>) = conn
+            .query_row(
+                "SELECT transaction_hash, from_address, contract_address FROM ethereum_transactions",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(hash, "0xabc");
+        assert_eq!(from, "0xdef");
+        assert_eq!(contract, None);
+    }
+
+    #[test]
+    fn token_transfers_in_one_transaction_are_kept_apart() {
+        let conn = test_db();
+        let transfer = |log_index: u64, amount: &str| json!({
+            "chain": "ethereum",
+            "transaction_hash": "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
+            "log_index": log_index,
+            "contract_address": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "token_symbol": "USDC",
+            "token_decimals": 6,
+            "raw_amount": amount,
+            "amount": 1.5
+        });
+        save_records(&conn, &[transfer(3, "1500000"), transfer(4, "115792089237316195423570985008687907853269984665640564039457584007913129639935")], "token_transfers").unwrap();
+        save_records(&conn, &[transfer(3, "1500000")], "token_transfers").unwrap();
+
+        let records = query_records(&conn, "token_transfers", &[], None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().any(|r| r["raw_amount"].as_str().unwrap().len() == 78));
+    }
+
+    #[test]
+    fn nft_batch_transfers_keep_each_token_id() {
+        let conn = test_db();
+        let transfer = |token_id: &str| json!({
+            "transaction_hash": "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
+            "log_index": 7,
+            "contract_address": "0x76be3b62873462d2142405439777e971754e8e77",
+            "token_id": token_id,
+            "quantity": "1"
+        });
+        let large_id = "57896044618658097711785492504343953926634992332820282019728792003956564819968";
+        save_records(&conn, &[transfer("10"), transfer(large_id)], "nft_transfers").unwrap();
+
+        let records = query_records(&conn, "nft_transfers", &[Filter::Equals("token_id", large_id)], None).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(query_records(&conn, "nft_transfers", &[], None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cleared_holdings_only_affect_one_owner() {
+        let conn = test_db();
+        let holding = |owner: &str, token_id: &str| json!({
+            "chain": "ethereum",
+            "owner_address": owner,
+            "contract_address": "0xd07dc4262bcdbf85190c01c996b4c06a461d2430",
+            "token_id": token_id,
+            "balance": "3",
+            "token_standard": "ERC1155"
+        });
+        save_records(&conn, &[holding("0xabc", "1"), holding("0xabc", "2"), holding("0xdef", "1")], "nft_holdings").unwrap();
+
+        assert_eq!(clear_nft_holdings(&conn, "ethereum", "0xABC").unwrap(), 2);
+        let left = query_records(&conn, "nft_holdings", &[], None).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!((left[0]["owner_address"].as_str(), left[0]["balance"].as_str()), (Some("0xdef"), Some("3")));
+    }
+
+    #[test]
+    fn history_lists_newest_queries_first() {
+        let conn = test_db();
+        record_query(&conn, "ethereum account", "0xa1e4380a3b1f749673e270229993ee55f35663b4", 1, None).unwrap();
+        record_query(&conn, "urlscan scan", "example.com", 1, Some(1)).unwrap();
+        record_query(&conn, "ethereum transactions", "0xa1e4380a3b1f749673e270229993ee55f35663b4", 250, None).unwrap();
+
+        let history = list_history(&conn, None, 2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].operation, "ethereum transactions");
+        assert_eq!(history[0].result_count, Some(250));
+        assert_eq!(history[1].target, "example.com");
+
+        assert_eq!(list_history(&conn, Some(1), 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn purging_old_scans_takes_their_snapshots_and_files() {
+        let conn = test_db();
+        let dir = std::env::temp_dir().join(format!("fragarach-purge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("old.html.gz");
+        std::fs::write(&path, b"").unwrap();
+
+        conn.execute(
+            "INSERT INTO urlscan_domain_data (domain, uuid, created_at) VALUES
+                ('old.example', 'old', current_timestamp::TIMESTAMP - INTERVAL 40 DAY),
+                ('new.example', 'new', current_timestamp::TIMESTAMP - INTERVAL 2 DAY)",
+            [],
+        ).unwrap();
+        conn.execute(
+            "INSERT INTO urlscan_dom_snapshot (uuid, dom_path) VALUES ('old', $1)",
+            params![path.display().to_string()],
+        ).unwrap();
+        conn.execute_batch(
+            "INSERT INTO urlscan_dom_snapshot (uuid, dom) VALUES ('new', '<p></p>');
+             INSERT INTO urlscan_scan_data (uuid) VALUES ('old'), ('new');"
+        ).unwrap();
+
+        assert_eq!(count_older_than(&conn, "urlscan_domain_data", 30).unwrap(), 1);
+        assert_eq!(count_older_than(&conn, "urlscan_domain_data", 1).unwrap(), 2);
+        assert_eq!(purge_older_than(&conn, "urlscan_domain_data", 30).unwrap(), 1);
+
+        for table in ["urlscan_domain_data", "urlscan_dom_snapshot", "urlscan_scan_data"] {
+            let left = query_records(&conn, table, &[], None).unwrap();
+            assert_eq!(left.len(), 1, "{}", table);
+            assert_eq!(left[0]["uuid"], "new");
+        }
+        assert!(!path.exists());
+        compact(&conn).unwrap();
+
+        assert!(matches!(count_older_than(&conn, "cases", 30), Err(Error::InvalidParameterName(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn transactions_are_purged_by_when_they_were_saved() {
+        let conn = test_db();
+        // Mined years ago but saved just now
+        save_records(&conn, &[transaction()], "ethereum_transactions").unwrap();
+        assert_eq!(count_older_than(&conn, "ethereum_transactions", 30).unwrap(), 0);
+
+        conn.execute_batch("UPDATE ethereum_transactions SET saved_at = current_timestamp::TIMESTAMP - INTERVAL 40 DAY").unwrap();
+        assert_eq!(purge_older_than(&conn, "ethereum_transactions", 30).unwrap(), 1);
+        assert_eq!(count_records(&conn, "ethereum_transactions", &[]).unwrap(), 0);
+    }
+}
@@ -1,5 +1,5 @@
 /// DuckDB schema initialization and management
-/// 
+///
 /// # Tables
 /// Creates the following tables:
 /// - ethereum_accounts
@@ -7,108 +7,67 @@
 /// - urlscan_domain_data
 /// - urlscan_dom_snapshot
 /// - urlscan_scan_data
-/// 
+/// - domain_whois
+/// - domain_dns
+/// - virustotal_domain_data
+/// - virustotal_ip_data
+/// - shodan_host_data
+/// - ens_names
+/// - token_transfers
+/// - nft_transfers
+/// - nft_holdings
+/// - query_history
+/// - cases
+/// - export_log
+/// - fragarach_meta
+/// - schema_migrations
+///
 /// # Schema Version
-/// Current schema version: 1.1
+/// Tracked in `fragarach_meta` and advanced through `helpers::migrations`,
+/// which logs each applied step in `schema_migrations`
+use crate::helpers::migrations;
+use tracing::{debug, info};
 use duckdb::{Connection, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
 
+/// Creates the schema in a new database, or applies the migrations an older one is missing
 pub fn setup_database_schema(conn: &Connection) -> Result<()> {
-    println!("Setting up ethereum_accounts table...");
-    conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS ethereum_accounts (
-            address VARCHAR PRIMARY KEY,
-            created_timestamp TIMESTAMP,
-            creator_address VARCHAR,
-            last_active_timestamp TIMESTAMP,
-            type VARCHAR
-        )"
-    )?;
-    println!("ethereum_accounts table created successfully.");
+    debug!("Setting up fragarach_meta table");
+    migrations::ensure_meta_table(conn)?;
 
-    println!("Setting up ethereum_transactions table...");
-    conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS ethereum_transactions (
-            transaction_hash VARCHAR PRIMARY KEY,
-            base_fee_per_gas DOUBLE,
-            block_number BIGINT,
-            contract_address VARCHAR,
-            fees_burned DOUBLE,
-            fees_rewarded DOUBLE,
-            fees_saved DOUBLE,
-            from_address VARCHAR,
-            gas_limit DOUBLE,
-            gas_price DOUBLE,
-            gas_used DOUBLE,
-            input TEXT,
-            internal_failed_transaction_count INTEGER,
-            internal_transaction_count INTEGER,
-            log_count INTEGER,
-            max_fee_per_gas DOUBLE,
-            max_priority_fee_per_gas DOUBLE,
-            nonce BIGINT,
-            output TEXT,
-            position INTEGER,
-            timestamp TIMESTAMP,
-            to_address VARCHAR,
-            transaction_fee DOUBLE,
-            type INTEGER,
-            value DOUBLE
-        )"
-    )?;
-    println!("ethereum_transactions table created successfully.");
+    for migration in migrations::pending(conn)? {
+        info!(version = migration.version, "Applying migration: {}", migration.description);
+        migrations::apply(conn, migration)?;
+    }
 
-    println!("Setting up urlscan tables...");
-    conn.execute_batch(
-        "CREATE SEQUENCE IF NOT EXISTS urlscan_domain_seq START 1;
-         CREATE TABLE IF NOT EXISTS urlscan_domain_data (
-            id BIGINT PRIMARY KEY DEFAULT nextval('urlscan_domain_seq'),
-            domain VARCHAR,
-            uuid VARCHAR UNIQUE,
-            result_url VARCHAR,
-            api_url VARCHAR,
-            visibility VARCHAR,
-            useragent VARCHAR,
-            country VARCHAR,
-            screenshot_path VARCHAR,
-            asn VARCHAR,
-            ip VARCHAR,
-            title VARCHAR,
-            verdict_score INTEGER,
-            verdict_brands TEXT,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )"
-    )?;
-    println!("urlscan_domain_data table created successfully.");
+    debug!(version = migrations::current_version(conn)?, "Database schema is up to date");
 
-    conn.execute_batch(
-        "CREATE SEQUENCE IF NOT EXISTS urlscan_dom_seq START 1;
-         CREATE TABLE IF NOT EXISTS urlscan_dom_snapshot (
-            id BIGINT PRIMARY KEY DEFAULT nextval('urlscan_dom_seq'),
-            uuid VARCHAR UNIQUE,
-            dom TEXT,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )"
-    )?;
-    println!("urlscan_dom_snapshot table created successfully.");
+    Ok(())
+}
 
-    conn.execute_batch(
-        "CREATE SEQUENCE IF NOT EXISTS urlscan_scan_seq START 1;
-         CREATE TABLE IF NOT EXISTS urlscan_scan_data (
-            id BIGINT PRIMARY KEY DEFAULT nextval('urlscan_scan_seq'),
-            uuid VARCHAR UNIQUE,
-            ip VARCHAR,
-            data_links TEXT,
-            page_asn VARCHAR,
-            page_ip VARCHAR,
-            page_country VARCHAR,
-            page_title VARCHAR,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )"
-    )?;
-    println!("urlscan_scan_data table created successfully.");
+/// Whether the database already contains tables other than the metadata table
+pub fn has_user_tables(conn: &Connection) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM information_schema.tables WHERE table_name NOT IN ('fragarach_meta', 'schema_migrations')",
+        [],
+        |row| row.get(0),
+    )
+}
 
-    // Final confirmation
-    println!("All URLScan tables created successfully.");
+/// Copies the database file next to itself before a migration
+///
+/// The backup is named after the schema version it was taken at, e.g.
+/// `fragarach.duckdb.v1.bak`.
+pub fn backup_database(conn: &Connection, db_path: &Path, version: u32) -> std::io::Result<PathBuf> {
+    // Flush the write-ahead log so the copied file is complete
+    conn.execute_batch("CHECKPOINT")
+        .map_err(std::io::Error::other)?;
 
-    Ok(())
-} 
\ No newline at end of file
+    let mut backup = db_path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", version));
+    let backup = PathBuf::from(backup);
+
+    fs::copy(db_path, &backup)?;
+    Ok(backup)
+}
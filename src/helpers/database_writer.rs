@@ -0,0 +1,286 @@
+/// Dedicated database task owning the DuckDB connection
+///
+/// `duckdb::Connection` is not `Sync`, so instead of sharing it, a single
+/// thread owns it and executes commands received over a bounded mpsc channel.
+/// Callers hold a cheap, cloneable `DatabaseHandle` and await replies on
+/// oneshot channels.
+///
+/// # Guarantees
+/// - Ordering: commands run one at a time in the order they were queued
+/// - Backpressure: sending waits once the queue holds `queue_capacity` commands
+/// - Shutdown: commands queued before shutdown are drained before the
+///   connection is closed
+///
+/// The handle also carries the session's active investigation case, which
+/// `save_records` attaches to every record it saves.
+use crate::error::FragarachError;
+use crate::helpers::{database_operations, database_setup};
+use duckdb::Connection;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tokio::sync::{mpsc, oneshot};
+
+type Job = Box<dyn FnOnce(&Connection) + Send>;
+
+enum Command {
+    Run(Job),
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// Cloneable async handle for issuing database commands
+#[derive(Clone)]
+pub struct DatabaseHandle {
+    tx: mpsc::Sender<Command>,
+    active_case: Arc<Mutex<Option<i64>>>,
+}
+
+/// Owner of the database thread
+pub struct DatabaseWriter {
+    handle: DatabaseHandle,
+    thread: JoinHandle<()>,
+}
+
+impl DatabaseWriter {
+    /// Moves the connection onto a dedicated thread
+    pub fn spawn(conn: Connection, queue_capacity: usize) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Command>(queue_capacity.max(1));
+
+        let thread = thread::spawn(move || {
+            while let Some(command) = rx.blocking_recv() {
+                match command {
+                    Command::Run(job) => job(&conn),
+                    Command::Shutdown(done) => {
+                        // Refuse new commands, then drain what is already queued
+                        rx.close();
+                        while let Some(command) = rx.blocking_recv() {
+                            if let Command::Run(job) = command {
+                                job(&conn);
+                            }
+                        }
+                        let _ = done.send(());
+                        break;
+                    }
+                }
+            }
+        });
+
+        DatabaseWriter {
+            handle: DatabaseHandle { tx, active_case: Arc::default() },
+            thread,
+        }
+    }
+
+    pub fn handle(&self) -> DatabaseHandle {
+        self.handle.clone()
+    }
+
+    /// Drains queued commands and closes the connection
+    pub async fn shutdown(self) -> Result<(), FragarachError> {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.handle.tx.send(Command::Shutdown(done_tx)).await.is_ok() {
+            let _ = done_rx.await;
+        }
+
+        tokio::task::spawn_blocking(move || self.thread.join())
+            .await
+            .map_err(|_| FragarachError::DatabaseClosed)?
+            .map_err(|_| FragarachError::DatabaseClosed)
+    }
+}
+
+impl DatabaseHandle {
+    /// Runs a closure against the connection and returns its result
+    pub async fn call<F, T, E>(&self, f: F) -> Result<T, FragarachError>
+    where
+        F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: Into<FragarachError> + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let job: Job = Box::new(move |conn| {
+            let _ = reply_tx.send(f(conn));
+        });
+
+        self.tx
+            .send(Command::Run(job))
+            .await
+            .map_err(|_| FragarachError::DatabaseClosed)?;
+
+        reply_rx.await
+            .map_err(|_| FragarachError::DatabaseClosed)?
+            .map_err(Into::into)
+    }
+
+    /// Persists API records into the given table, attached to the active case
+    pub async fn save_records(&self, mut data: Vec<Value>, table: &'static str) -> Result<(), FragarachError> {
+        if let Some(case_id) = self.active_case() {
+            for record in data.iter_mut().filter_map(Value::as_object_mut) {
+                record.insert("case_id".to_string(), case_id.into());
+            }
+        }
+        self.call(move |conn| database_operations::save_records(conn, &data, table)).await
+    }
+
+    /// Logs a completed query in `query_history`, attached to the active case
+    pub async fn record_query(&self, operation: String, target: String, result_count: usize) -> Result<(), FragarachError> {
+        let case_id = self.active_case();
+        self.call(move |conn| database_operations::record_query(conn, &operation, &target, result_count, case_id)).await
+    }
+
+    /// Id of the case records are currently attached to
+    pub fn active_case(&self) -> Option<i64> {
+        *self.active_case.lock().unwrap()
+    }
+
+    /// Attaches records saved from now on to a case, or to none
+    pub fn set_active_case(&self, case_id: Option<i64>) {
+        *self.active_case.lock().unwrap() = case_id;
+    }
+
+    /// Makes the case named `name` active, creating it if needed, and returns its id
+    pub async fn open_case(&self, name: String) -> Result<i64, FragarachError> {
+        let id = self.call(move |conn| database_operations::open_case(conn, &name)).await?;
+        self.set_active_case(Some(id));
+        Ok(id)
+    }
+
+    /// Applies any pending schema migrations
+    pub async fn setup_schema(&self) -> Result<(), FragarachError> {
+        self.call(database_setup::setup_database_schema).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::migrations::test_db;
+    use serde_json::json;
+    use std::sync::mpsc as std_mpsc;
+    use tokio::task::JoinHandle as TaskHandle;
+
+    fn transaction(i: usize) -> Value {
+        json!({
+            "transaction_hash": format!("0x{:064x}", i),
+            "block_number": 46147 + i,
+            "from_address": "0xa1e4380a3b1f749673e270229993ee55f35663b4",
+            "to_address": "0x5df9b87991262f6ba471f09758cde1c0fc1de734",
+            "value": 31337
+        })
+    }
+
+    /// Occupies the database thread until the returned sender is used or dropped
+    async fn block_writer(handle: &DatabaseHandle) -> (std_mpsc::Sender<()>, TaskHandle<Result<(), FragarachError>>) {
+        let (started_tx, started_rx) = oneshot::channel();
+        let (release_tx, release_rx) = std_mpsc::channel::<()>();
+        let handle = handle.clone();
+        let blocked = tokio::spawn(async move {
+            handle.call(move |_| {
+                let _ = started_tx.send(());
+                let _ = release_rx.recv();
+                Ok::<_, FragarachError>(())
+            }).await
+        });
+        started_rx.await.unwrap();
+        (release_tx, blocked)
+    }
+
+    /// Waits until `queued` commands sit in the channel
+    async fn wait_until_queued(handle: &DatabaseHandle, queued: usize) {
+        while handle.tx.max_capacity() - handle.tx.capacity() < queued {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Queues one `save_records` per transaction, each only after the previous is queued
+    async fn queue_saves(handle: &DatabaseHandle, count: usize) -> Vec<TaskHandle<Result<(), FragarachError>>> {
+        let mut saves = Vec::new();
+        for i in 0..count {
+            let writer = handle.clone();
+            saves.push(tokio::spawn(async move {
+                writer.save_records(vec![transaction(i)], "ethereum_transactions").await
+            }));
+            wait_until_queued(handle, i + 1).await;
+        }
+        saves
+    }
+
+    fn saved_hashes(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn.prepare("SELECT transaction_hash FROM ethereum_transactions ORDER BY rowid").unwrap();
+        stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+    }
+
+    #[tokio::test]
+    async fn queued_saves_land_in_send_order() {
+        let conn = test_db();
+        let reader = conn.try_clone().unwrap();
+        let writer = DatabaseWriter::spawn(conn, 16);
+        let handle = writer.handle();
+
+        let (release, blocked) = block_writer(&handle).await;
+        let saves = queue_saves(&handle, 10).await;
+        release.send(()).unwrap();
+        blocked.await.unwrap().unwrap();
+        for save in saves {
+            save.await.unwrap().unwrap();
+        }
+
+        let expected: Vec<String> = (0..10).map(|i| format!("0x{:064x}", i)).collect();
+        assert_eq!(saved_hashes(&reader), expected);
+        writer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_commits_writes_queued_before_it() {
+        let conn = test_db();
+        let reader = conn.try_clone().unwrap();
+        let writer = DatabaseWriter::spawn(conn, 16);
+        let handle = writer.handle();
+
+        let (release, blocked) = block_writer(&handle).await;
+        let saves = queue_saves(&handle, 5).await;
+        let shutdown = tokio::spawn(writer.shutdown());
+        wait_until_queued(&handle, 6).await;
+        release.send(()).unwrap();
+        shutdown.await.unwrap().unwrap();
+
+        assert_eq!(saved_hashes(&reader).len(), 5);
+        blocked.await.unwrap().unwrap();
+        for save in saves {
+            save.await.unwrap().unwrap();
+        }
+        assert!(matches!(
+            handle.save_records(vec![transaction(5)], "ethereum_transactions").await,
+            Err(FragarachError::DatabaseClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn full_queue_holds_back_the_next_send() {
+        let conn = test_db();
+        let reader = conn.try_clone().unwrap();
+        let writer = DatabaseWriter::spawn(conn, 1);
+        let handle = writer.handle();
+
+        let (release, blocked) = block_writer(&handle).await;
+        let first = queue_saves(&handle, 1).await.remove(0);
+        // Resolves as soon as the command is queued, not when it has run
+        let tx = handle.tx.clone();
+        let second = tokio::spawn(async move {
+            let job: Job = Box::new(|_| {});
+            tx.send(Command::Run(job)).await.is_ok()
+        });
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!second.is_finished());
+
+        // The slot frees once the writer takes the first save off the queue
+        release.send(()).unwrap();
+        blocked.await.unwrap().unwrap();
+        first.await.unwrap().unwrap();
+        assert!(second.await.unwrap());
+        assert_eq!(saved_hashes(&reader).len(), 1);
+        writer.shutdown().await.unwrap();
+    }
+}
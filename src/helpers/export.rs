@@ -0,0 +1,504 @@
+/// Table exports to local files and S3
+///
+/// # Targets
+/// - Local file under the exports directory
+/// - S3 bucket via DuckDB's `httpfs` extension
+///
+/// # Formats
+/// - Parquet
+/// - CSV: header row, fields containing delimiters or quotes are quoted
+/// - JSON: array of objects keyed by column name (local files only)
+///
+/// Empty tables produce a header-only CSV file or `[]`.
+///
+/// # Filters
+/// Exports can be restricted to one address (Ethereum tables) or one domain
+/// (URLScan tables); see `filter_kind`. They can also be restricted to the
+/// records of one investigation case.
+///
+/// Local exports never replace an existing file unless asked to.
+///
+/// Every completed export is recorded in the `export_log` table with its
+/// destination URI, filter and row count. S3 uploads are verified by reading the
+/// object back and comparing row counts.
+use crate::error::FragarachError;
+use crate::helpers::database_operations::{self, case_condition, json_value, select_list, table_columns};
+use duckdb::{params, Connection};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Tables that can be exported
+pub const EXPORTABLE_TABLES: &[&str] = &[
+    "ethereum_accounts",
+    "ethereum_transactions",
+    "urlscan_domain_data",
+    "urlscan_dom_snapshot",
+    "urlscan_scan_data",
+    "domain_whois",
+    "domain_dns",
+    "virustotal_domain_data",
+    "virustotal_ip_data",
+    "shodan_host_data",
+    "ens_names",
+    "token_transfers",
+    "nft_transfers",
+    "nft_holdings",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "parquet" => Ok(ExportFormat::Parquet),
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown export format '{}' (expected parquet, csv or json)", s)),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn copy_options(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "FORMAT PARQUET",
+            ExportFormat::Csv => "FORMAT CSV, HEADER",
+            // Written row by row in `write_json`; DuckDB's JSON extension isn't bundled
+            ExportFormat::Json => unreachable!("JSON exports don't use COPY"),
+        }
+    }
+
+    fn reader(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "read_parquet",
+            ExportFormat::Csv => "read_csv_auto",
+            ExportFormat::Json => unreachable!("JSON exports don't use COPY"),
+        }
+    }
+}
+
+/// Credentials for the S3 export target
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub region: String,
+    pub endpoint: Option<String>,
+}
+
+/// Quotes a value as a SQL string literal
+fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn check_table(table: &str) -> Result<(), FragarachError> {
+    if EXPORTABLE_TABLES.contains(&table) {
+        Ok(())
+    } else {
+        Err(FragarachError::UnknownTable(table.to_string()))
+    }
+}
+
+/// What an export of `table` can be filtered by: `address` or `domain`
+pub fn filter_kind(table: &str) -> &'static str {
+    match table {
+        "ethereum_accounts" | "ethereum_transactions" | "token_transfers" | "nft_transfers" | "nft_holdings" | "ens_names" => "address",
+        _ => "domain",
+    }
+}
+
+/// Query selecting the rows of `table` to export
+fn source_query(table: &str, filter: Option<&str>, case_id: Option<i64>) -> String {
+    let mut conditions = Vec::new();
+
+    if let Some(value) = filter {
+        // Addresses are hex, so compare them case-insensitively
+        let value = sql_literal(value);
+        conditions.push(match table {
+            "ethereum_accounts" | "ens_names" => format!("lower(address) = lower({})", value),
+            "ethereum_transactions" | "token_transfers" | "nft_transfers" => format!("(lower(from_address) = lower({0}) OR lower(to_address) = lower({0}))", value),
+            "nft_holdings" => format!("lower(owner_address) = lower({})", value),
+            "virustotal_ip_data" => format!("ip = {}", value),
+            "shodan_host_data" => format!("ip = {}", value),
+            "urlscan_domain_data" | "domain_whois" | "domain_dns" | "virustotal_domain_data" => format!("domain = {}", value),
+            _ => format!("uuid IN (SELECT uuid FROM urlscan_domain_data WHERE domain = {})", value),
+        });
+    }
+
+    if case_id.is_some() {
+        // DOM snapshots and scan data belong to the case of their scan
+        conditions.push(match table {
+            "urlscan_dom_snapshot" | "urlscan_scan_data" => format!(
+                "uuid IN (SELECT uuid FROM urlscan_domain_data WHERE {})", case_condition(case_id)
+            ),
+            _ => case_condition(case_id),
+        });
+    }
+
+    if conditions.is_empty() {
+        format!("SELECT * FROM {}", table)
+    } else {
+        format!("SELECT * FROM {} WHERE {}", table, conditions.join(" AND "))
+    }
+}
+
+fn count_rows(conn: &Connection, query: &str) -> duckdb::Result<u64> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM ({})", query), [], |row| row.get(0))
+}
+
+fn log_export(
+    conn: &Connection,
+    table: &str,
+    format: ExportFormat,
+    destination: &str,
+    filter: Option<&str>,
+    case_id: Option<i64>,
+    rows: u64,
+) -> duckdb::Result<()> {
+    conn.execute(
+        "INSERT INTO export_log (table_name, format, destination, filter, case_id, row_count) VALUES ($1, $2, $3, $4, $5, $6)",
+        params![table, format.extension(), destination, filter, case_id, rows],
+    )?;
+    Ok(())
+}
+
+/// Writes the rows selected by `query` from `table` as a JSON array of objects,
+/// returning the number of rows written
+fn write_json(conn: &Connection, table: &str, query: &str, out: &Path) -> Result<u64, FragarachError> {
+    let columns = table_columns(conn, table)?;
+    let mut writer = BufWriter::new(File::create(out)?);
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM ({})", select_list(&columns), query))?;
+    let mut rows = stmt.query([])?;
+    let mut written = 0;
+
+    writer.write_all(b"[")?;
+    while let Some(row) = rows.next()? {
+        // Objects are written by hand to keep keys in column order
+        writer.write_all(if written == 0 { b"\n{" } else { b",\n{" })?;
+        for (i, (name, _)) in columns.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+            write!(writer, "{}:{}", Value::String(name.clone()), json_value(row.get_ref(i)?))?;
+        }
+        writer.write_all(b"}")?;
+        written += 1;
+    }
+    writer.write_all(if written == 0 { b"]\n" } else { b"\n]\n" })?;
+    writer.flush()?;
+
+    Ok(written)
+}
+
+/// Writes a table to a local file, returning the number of rows exported
+///
+/// Fails with `ExportExists` if `out` exists and `overwrite` is false.
+pub fn export_table_local(
+    conn: &Connection,
+    table: &str,
+    format: ExportFormat,
+    filter: Option<&str>,
+    case_id: Option<i64>,
+    out: &Path,
+    overwrite: bool,
+) -> Result<u64, FragarachError> {
+    check_table(table)?;
+    if out.exists() && !overwrite {
+        return Err(FragarachError::ExportExists(out.to_path_buf()));
+    }
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let query = source_query(table, filter, case_id);
+    let destination = out.display().to_string();
+    let rows = match format {
+        ExportFormat::Json => write_json(conn, table, &query, out)?,
+        _ => {
+            conn.execute_batch(&format!(
+                "COPY ({}) TO {} ({})",
+                query, sql_literal(&destination), format.copy_options()
+            ))?;
+            count_rows(conn, &query)?
+        }
+    };
+
+    log_export(conn, table, format, &destination, filter, case_id, rows)?;
+    Ok(rows)
+}
+
+/// Writes the rows of a read-only query to a CSV file, returning the number of rows exported
+///
+/// The query is checked like `database_operations::run_read_query` and logged
+/// in `export_log` under the table name `query`, with the SQL as its filter.
+pub fn export_query_csv(conn: &Connection, sql: &str, out: &Path, overwrite: bool) -> Result<u64, FragarachError> {
+    let sql = database_operations::check_read_query(sql)?;
+    if out.exists() && !overwrite {
+        return Err(FragarachError::ExportExists(out.to_path_buf()));
+    }
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let destination = out.display().to_string();
+    let rows = {
+        // Rolled back on drop, like `run_read_query`
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(&format!(
+            "COPY ({}) TO {} ({})",
+            sql, sql_literal(&destination), ExportFormat::Csv.copy_options()
+        ))?;
+        count_rows(&tx, sql)?
+    };
+
+    log_export(conn, "query", ExportFormat::Csv, &destination, Some(sql), None, rows)?;
+    Ok(rows)
+}
+
+/// Writes a table to a Parquet file for pandas/Polars, returning the number of rows exported
+pub fn export_table_parquet(
+    conn: &Connection,
+    table: &str,
+    out: &Path,
+    filter: Option<&str>,
+    case_id: Option<i64>,
+    overwrite: bool,
+) -> Result<u64, FragarachError> {
+    export_table_local(conn, table, ExportFormat::Parquet, filter, case_id, out, overwrite)
+}
+
+/// Loads httpfs and registers the S3 credentials as a DuckDB secret
+///
+/// The statement embeds the secret, so it is never printed or logged.
+fn configure_s3(conn: &Connection, credentials: &S3Credentials) -> Result<(), FragarachError> {
+    conn.execute_batch("INSTALL httpfs; LOAD httpfs;")
+        .map_err(|source| FragarachError::ExtensionUnavailable { name: "httpfs", source })?;
+
+    let mut secret = format!(
+        "CREATE OR REPLACE TEMPORARY SECRET fragarach_s3 (TYPE S3, KEY_ID {}, SECRET {}, REGION {}",
+        sql_literal(&credentials.access_key_id),
+        sql_literal(&credentials.secret_access_key),
+        sql_literal(&credentials.region),
+    );
+    if let Some(endpoint) = &credentials.endpoint {
+        secret.push_str(&format!(", ENDPOINT {}", sql_literal(endpoint)));
+    }
+    secret.push(')');
+
+    conn.execute_batch(&secret)?;
+    Ok(())
+}
+
+/// Writes a table to `<prefix>/<table>.<ext>` on S3 and verifies the upload
+///
+/// Returns the destination URI and the number of rows exported.
+pub fn export_table_s3(
+    conn: &Connection,
+    table: &str,
+    format: ExportFormat,
+    filter: Option<&str>,
+    case_id: Option<i64>,
+    prefix: &str,
+    credentials: &S3Credentials,
+) -> Result<(String, u64), FragarachError> {
+    check_table(table)?;
+    if let ExportFormat::Json = format {
+        return Err(FragarachError::UnsupportedFormat { format: "JSON", target: "S3" });
+    }
+    configure_s3(conn, credentials)?;
+
+    let query = source_query(table, filter, case_id);
+    let destination = format!("{}/{}.{}", prefix.trim_end_matches('/'), table, format.extension());
+    conn.execute_batch(&format!(
+        "COPY ({}) TO {} ({})",
+        query, sql_literal(&destination), format.copy_options()
+    ))?;
+
+    // Read the object back to confirm the upload is complete
+    let expected = count_rows(conn, &query)?;
+    let found: u64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM {}({})", format.reader(), sql_literal(&destination)),
+        [],
+        |row| row.get(0),
+    )?;
+    if found != expected {
+        return Err(FragarachError::VerificationFailed { expected, found });
+    }
+
+    log_export(conn, table, format, &destination, filter, case_id, expected)?;
+    Ok((destination, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::migrations::test_db;
+
+    fn export_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("fragarach-export-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn insert_transactions(conn: &Connection) {
+        conn.execute_batch(
+            "INSERT INTO ethereum_transactions (transaction_hash, from_address, to_address) VALUES
+                ('0x01', '0xAAAA', '0xbbbb'),
+                ('0x02', '0xcccc', '0xaaaa'),
+                ('0x03', '0xbbbb', '0xcccc');",
+        ).unwrap();
+    }
+
+    fn insert_whois(conn: &Connection) {
+        conn.execute_batch(
+            "INSERT INTO domain_whois (domain, registrar, registrant, looked_up_at) VALUES
+                ('example.com', 'Example Registrar', 'Acme, Inc.', '2024-01-01 00:00:00'),
+                ('example.org', 'Example Registrar', NULL, '2024-01-01 00:00:00');",
+        ).unwrap();
+    }
+
+    #[test]
+    fn exports_report_and_log_the_rows_written() {
+        let conn = test_db();
+        insert_transactions(&conn);
+        let dir = export_dir("count");
+        let out = dir.join("transactions.csv");
+
+        let rows = export_table_local(&conn, "ethereum_transactions", ExportFormat::Csv, None, None, &out, false).unwrap();
+        assert_eq!(rows, 3);
+        assert_eq!(fs::read_to_string(&out).unwrap().lines().count(), 4);
+        let logged: u64 = conn
+            .query_row("SELECT row_count FROM export_log WHERE table_name = 'ethereum_transactions'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(logged, 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn address_filter_matches_either_side_in_any_case() {
+        let conn = test_db();
+        insert_transactions(&conn);
+        let count = |filter| count_rows(&conn, &source_query("ethereum_transactions", Some(filter), None)).unwrap();
+        assert_eq!(count("0xaaaa"), 2);
+        assert_eq!(count("0xCCCC"), 2);
+        assert_eq!(count("0xdddd"), 0);
+    }
+
+    #[test]
+    fn domain_filter_selects_one_domain() {
+        let conn = test_db();
+        insert_whois(&conn);
+        let dir = export_dir("domain");
+        let out = dir.join("whois.csv");
+
+        let rows = export_table_local(&conn, "domain_whois", ExportFormat::Csv, Some("example.org"), None, &out, false).unwrap();
+        assert_eq!(rows, 1);
+        let contents = fs::read_to_string(&out).unwrap();
+        assert!(contents.contains("example.org") && !contents.contains("example.com"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn existing_export_is_kept_unless_overwritten() {
+        let conn = test_db();
+        insert_transactions(&conn);
+        let dir = export_dir("exists");
+        let out = dir.join("transactions.csv");
+        fs::write(&out, "earlier export\n").unwrap();
+
+        let result = export_table_local(&conn, "ethereum_transactions", ExportFormat::Csv, None, None, &out, false);
+        assert!(matches!(result, Err(FragarachError::ExportExists(path)) if path == out));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "earlier export\n");
+
+        let rows = export_table_local(&conn, "ethereum_transactions", ExportFormat::Csv, None, None, &out, true).unwrap();
+        assert_eq!(rows, 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn empty_tables_export_a_header_or_an_empty_array() {
+        let conn = test_db();
+        let dir = export_dir("empty");
+
+        let csv = dir.join("ens.csv");
+        assert_eq!(export_table_local(&conn, "ens_names", ExportFormat::Csv, None, None, &csv, false).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&csv).unwrap(), "ens_name,address,case_id,resolved_at\n");
+
+        let json = dir.join("ens.json");
+        assert_eq!(export_table_local(&conn, "ens_names", ExportFormat::Json, None, None, &json, false).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&json).unwrap(), "[]\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_fields_with_commas_or_quotes_are_quoted() {
+        let conn = test_db();
+        conn.execute_batch(
+            "INSERT INTO ens_names (ens_name, address, resolved_at) VALUES
+                ('acme, inc.eth', '0xaaaa', '2024-01-01 00:00:00'),
+                ('say \"gm\".eth', '0xbbbb', '2024-01-01 00:00:00');",
+        ).unwrap();
+        let dir = export_dir("csv");
+        let out = dir.join("ens.csv");
+
+        export_table_local(&conn, "ens_names", ExportFormat::Csv, None, None, &out, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "ens_name,address,case_id,resolved_at\n\
+             \"acme, inc.eth\",0xaaaa,,2024-01-01 00:00:00\n\
+             \"say \"\"gm\"\".eth\",0xbbbb,,2024-01-01 00:00:00\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_keys_follow_column_order() {
+        let conn = test_db();
+        insert_whois(&conn);
+        let dir = export_dir("json");
+        let out = dir.join("whois.json");
+
+        export_table_local(&conn, "domain_whois", ExportFormat::Json, Some("example.com"), None, &out, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "[\n{\"domain\":\"example.com\",\"registrar\":\"Example Registrar\",\"registrant\":\"Acme, Inc.\",\
+             \"registered\":null,\"expires\":null,\"updated\":null,\"nameservers\":null,\"case_id\":null,\
+             \"looked_up_at\":\"2024-01-01 00:00:00\",\"registrant_country\":null,\"source\":null,\"raw\":null}\n]\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parquet_export_creates_missing_directories() {
+        let conn = test_db();
+        insert_transactions(&conn);
+        let dir = export_dir("parquet");
+        let out = dir.join("cases").join("acme").join("transactions.parquet");
+
+        let rows = export_table_parquet(&conn, "ethereum_transactions", &out, Some("0xaaaa"), None, false).unwrap();
+        assert_eq!(rows, 2);
+        let found: u64 = conn
+            .query_row(&format!("SELECT COUNT(*) FROM read_parquet({})", sql_literal(&out.display().to_string())), [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(found, rows);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
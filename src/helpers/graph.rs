@@ -0,0 +1,315 @@
+/// Address interaction graphs for Gephi, Maltego and Graphviz
+///
+/// Builds a directed graph from stored `ethereum_transactions`: the seed
+/// addresses and their direct counterparties (one hop) become nodes, and each
+/// sender → recipient pair becomes one edge weighted by its transaction count
+/// and summed value, with the first and last time it was seen.
+///
+/// # Formats
+/// - DOT, for Graphviz and Gephi
+/// - GraphML, for Gephi, Maltego and yEd
+///
+/// Nodes carry whether the address is a seed and, when it is stored in
+/// `ethereum_accounts`, its account type. Addresses are lowercased.
+use crate::error::FragarachError;
+use duckdb::{params, Connection, Result};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    GraphMl,
+}
+
+impl GraphFormat {
+    pub const ALL: [GraphFormat; 2] = [GraphFormat::Dot, GraphFormat::GraphMl];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::GraphMl => "graphml",
+        }
+    }
+}
+
+impl fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GraphFormat::Dot => "DOT",
+            GraphFormat::GraphMl => "GraphML",
+        })
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dot" | "gv" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::GraphMl),
+            _ => Err(format!("unknown graph format '{}' (expected dot or graphml)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Node {
+    pub seed: bool,
+    /// Whether the address is stored in `ethereum_accounts`
+    pub known: bool,
+    pub account_type: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub transactions: u64,
+    pub value: f64,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Graph {
+    /// Nodes by address, sorted so output is stable
+    pub nodes: BTreeMap<String, Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Builds the graph of transactions sent or received by any of `seeds`
+pub fn build(conn: &Connection, seeds: &[String]) -> Result<Graph> {
+    let seeds: Vec<String> = seeds.iter().map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect();
+    let mut graph = Graph::default();
+    if seeds.is_empty() {
+        return Ok(graph);
+    }
+    let seed_list = seeds.iter().map(|s| format!("'{}'", s.replace('\'', "''"))).collect::<Vec<_>>().join(", ");
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT lower(from_address) AS src, lower(to_address) AS dst, COUNT(*), COALESCE(SUM(value), 0),
+                CAST(MIN(timestamp) AS VARCHAR), CAST(MAX(timestamp) AS VARCHAR)
+         FROM ethereum_transactions
+         WHERE (lower(from_address) IN ({0}) OR lower(to_address) IN ({0}))
+           AND from_address IS NOT NULL AND to_address IS NOT NULL
+         GROUP BY src, dst ORDER BY src, dst",
+        seed_list
+    ))?;
+    graph.edges = stmt
+        .query_map([], |row| Ok(Edge {
+            from: row.get(0)?,
+            to: row.get(1)?,
+            transactions: row.get(2)?,
+            value: row.get(3)?,
+            first_seen: row.get(4)?,
+            last_seen: row.get(5)?,
+        }))?
+        .collect::<Result<_>>()?;
+
+    for address in seeds.iter().chain(graph.edges.iter().flat_map(|e| [&e.from, &e.to])) {
+        graph.nodes.entry(address.clone()).or_default();
+    }
+    for seed in &seeds {
+        if let Some(node) = graph.nodes.get_mut(seed) {
+            node.seed = true;
+        }
+    }
+
+    let mut stmt = conn.prepare("SELECT type FROM ethereum_accounts WHERE lower(address) = $1 ORDER BY chain LIMIT 1")?;
+    for (address, node) in graph.nodes.iter_mut() {
+        if let Some(row) = stmt.query(params![address])?.next()? {
+            node.known = true;
+            node.account_type = row.get(0)?;
+        }
+    }
+
+    Ok(graph)
+}
+
+/// Quotes a DOT identifier or attribute value
+fn dot_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Escapes text for a GraphML element or attribute
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+pub fn to_dot(graph: &Graph) -> String {
+    let mut out = String::from("digraph transactions {\n");
+    for (address, node) in &graph.nodes {
+        let _ = write!(out, "  {} [seed={}, known={}", dot_quote(address), node.seed, node.known);
+        if let Some(kind) = &node.account_type {
+            let _ = write!(out, ", account_type={}", dot_quote(kind));
+        }
+        if node.seed {
+            out.push_str(", shape=doublecircle");
+        }
+        out.push_str("];\n");
+    }
+    for edge in &graph.edges {
+        let _ = write!(
+            out,
+            "  {} -> {} [weight={}, label=\"{}\", value={}",
+            dot_quote(&edge.from), dot_quote(&edge.to), edge.transactions, edge.transactions, edge.value
+        );
+        for (key, seen) in [("first_seen", &edge.first_seen), ("last_seen", &edge.last_seen)] {
+            if let Some(seen) = seen {
+                let _ = write!(out, ", {}={}", key, dot_quote(seen));
+            }
+        }
+        out.push_str("];\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+pub fn to_graphml(graph: &Graph) -> String {
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        "  <key id=\"seed\" for=\"node\" attr.name=\"seed\" attr.type=\"boolean\"/>\n",
+        "  <key id=\"known\" for=\"node\" attr.name=\"known\" attr.type=\"boolean\"/>\n",
+        "  <key id=\"account_type\" for=\"node\" attr.name=\"account_type\" attr.type=\"string\"/>\n",
+        "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"long\"/>\n",
+        "  <key id=\"value\" for=\"edge\" attr.name=\"value\" attr.type=\"double\"/>\n",
+        "  <key id=\"first_seen\" for=\"edge\" attr.name=\"first_seen\" attr.type=\"string\"/>\n",
+        "  <key id=\"last_seen\" for=\"edge\" attr.name=\"last_seen\" attr.type=\"string\"/>\n",
+        "  <graph id=\"transactions\" edgedefault=\"directed\">\n",
+    ));
+    for (address, node) in &graph.nodes {
+        let _ = write!(
+            out,
+            "    <node id=\"{}\"><data key=\"seed\">{}</data><data key=\"known\">{}</data>",
+            xml_escape(address), node.seed, node.known
+        );
+        if let Some(kind) = &node.account_type {
+            let _ = write!(out, "<data key=\"account_type\">{}</data>", xml_escape(kind));
+        }
+        out.push_str("</node>\n");
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let _ = write!(
+            out,
+            "    <edge id=\"e{}\" source=\"{}\" target=\"{}\"><data key=\"weight\">{}</data><data key=\"value\">{}</data>",
+            i, xml_escape(&edge.from), xml_escape(&edge.to), edge.transactions, edge.value
+        );
+        for (key, seen) in [("first_seen", &edge.first_seen), ("last_seen", &edge.last_seen)] {
+            if let Some(seen) = seen {
+                let _ = write!(out, "<data key=\"{}\">{}</data>", key, xml_escape(seen));
+            }
+        }
+        out.push_str("</edge>\n");
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+/// Writes the graph around `seeds` to `out`, returning its node and edge counts
+///
+/// Fails with `ExportExists` if `out` exists and `overwrite` is false. The
+/// export is logged in `export_log` under the table name `graph`, with the
+/// seeds as its filter.
+pub fn export_graph(
+    conn: &Connection,
+    seeds: &[String],
+    format: GraphFormat,
+    out: &Path,
+    overwrite: bool,
+) -> Result<(usize, usize), FragarachError> {
+    if out.exists() && !overwrite {
+        return Err(FragarachError::ExportExists(out.to_path_buf()));
+    }
+    let graph = build(conn, seeds)?;
+    let rendered = match format {
+        GraphFormat::Dot => to_dot(&graph),
+        GraphFormat::GraphMl => to_graphml(&graph),
+    };
+
+    if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(out, rendered)?;
+
+    conn.execute(
+        "INSERT INTO export_log (table_name, format, destination, filter, row_count) VALUES ('graph', $1, $2, $3, $4)",
+        params![format.extension(), out.display().to_string(), seeds.join(","), graph.edges.len() as u64],
+    )?;
+    Ok((graph.nodes.len(), graph.edges.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::database_operations::save_records;
+    use crate::helpers::migrations;
+    use serde_json::json;
+
+    fn seeded_db() -> Connection {
+        let conn = migrations::test_db();
+        let tx = |hash: &str, from: &str, to: &str, value: f64, timestamp: &str| json!({
+            "transaction_hash": hash, "from_address": from, "to_address": to, "value": value, "timestamp": timestamp
+        });
+        save_records(&conn, &[
+            tx("0x01", "0xAAA", "0xbbb", 1.5, "2026-01-01T00:00:00Z"),
+            tx("0x02", "0xaaa", "0xbbb", 2.5, "2026-01-03T00:00:00Z"),
+            tx("0x03", "0xccc", "0xaaa", 4.0, "2026-01-02T00:00:00Z"),
+            // Two hops from the seed, so left out
+            tx("0x04", "0xbbb", "0xddd", 9.0, "2026-01-04T00:00:00Z"),
+        ], "ethereum_transactions").unwrap();
+        save_records(&conn, &[json!({ "address": "0xccc", "type": "contract" })], "ethereum_accounts").unwrap();
+        conn
+    }
+
+    #[test]
+    fn graph_aggregates_direct_counterparties() {
+        let conn = seeded_db();
+        let graph = build(&conn, &["0xAAA".to_string()]).unwrap();
+
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), ["0xaaa", "0xbbb", "0xccc"]);
+        assert!(graph.nodes["0xaaa"].seed);
+        assert_eq!(graph.nodes["0xccc"], Node { seed: false, known: true, account_type: Some("contract".to_string()) });
+        assert_eq!(graph.edges[0], Edge {
+            from: "0xaaa".to_string(),
+            to: "0xbbb".to_string(),
+            transactions: 2,
+            value: 4.0,
+            first_seen: Some("2026-01-01 00:00:00".to_string()),
+            last_seen: Some("2026-01-03 00:00:00".to_string()),
+        });
+        assert_eq!(graph.edges.len(), 2);
+    }
+
+    #[test]
+    fn graph_exports_as_dot_and_graphml() {
+        let conn = seeded_db();
+        let dir = std::env::temp_dir().join(format!("fragarach-graph-{}", std::process::id()));
+        let seeds = ["0xaaa".to_string()];
+
+        let dot = dir.join("graph.dot");
+        assert_eq!(export_graph(&conn, &seeds, GraphFormat::Dot, &dot, false).unwrap(), (3, 2));
+        let text = fs::read_to_string(&dot).unwrap();
+        assert!(text.starts_with("digraph transactions {"));
+        assert!(text.contains("\"0xccc\" [seed=false, known=true, account_type=\"contract\"];"));
+        assert!(text.contains("\"0xaaa\" -> \"0xbbb\" [weight=2, label=\"2\", value=4, first_seen=\"2026-01-01 00:00:00\""));
+        assert!(matches!(
+            export_graph(&conn, &seeds, GraphFormat::Dot, &dot, false),
+            Err(FragarachError::ExportExists(_))
+        ));
+
+        let graphml = dir.join("graph.graphml");
+        export_graph(&conn, &seeds, GraphFormat::GraphMl, &graphml, false).unwrap();
+        let text = fs::read_to_string(&graphml).unwrap();
+        assert!(text.contains("<edge id=\"e1\" source=\"0xccc\" target=\"0xaaa\"><data key=\"weight\">1</data>"));
+
+        let logged: u64 = conn.query_row("SELECT COUNT(*) FROM export_log WHERE table_name = 'graph'", [], |row| row.get(0)).unwrap();
+        assert_eq!(logged, 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,659 @@
+/// Ordered schema migrations for the DuckDB database
+///
+/// The applied schema version is stored under the `schema_version` key of the
+/// `fragarach_meta` table. Each migration runs inside a transaction and bumps
+/// the version on success, so a failed step leaves the database untouched.
+/// Every applied migration is also recorded in `schema_migrations` with the
+/// time it ran, so `history` shows how a database reached its version.
+///
+/// # Adding a Migration
+/// Append a new entry to `MIGRATIONS` with the next version number. Never edit
+/// or reorder migrations that have already shipped.
+use duckdb::{params, Connection, OptionalExt, Result};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial Ethereum and URLScan tables",
+        sql: "CREATE TABLE IF NOT EXISTS ethereum_accounts (
+                address VARCHAR PRIMARY KEY,
+                created_timestamp TIMESTAMP,
+                creator_address VARCHAR,
+                last_active_timestamp TIMESTAMP,
+                type VARCHAR
+            );
+            CREATE TABLE IF NOT EXISTS ethereum_transactions (
+                transaction_hash VARCHAR PRIMARY KEY,
+                base_fee_per_gas DOUBLE,
+                block_number BIGINT,
+                contract_address VARCHAR,
+                fees_burned DOUBLE,
+                fees_rewarded DOUBLE,
+                fees_saved DOUBLE,
+                from_address VARCHAR,
+                gas_limit DOUBLE,
+                gas_price DOUBLE,
+                gas_used DOUBLE,
+                input TEXT,
+                internal_failed_transaction_count INTEGER,
+                internal_transaction_count INTEGER,
+                log_count INTEGER,
+                max_fee_per_gas DOUBLE,
+                max_priority_fee_per_gas DOUBLE,
+                nonce BIGINT,
+                output TEXT,
+                position INTEGER,
+                timestamp TIMESTAMP,
+                to_address VARCHAR,
+                transaction_fee DOUBLE,
+                type INTEGER,
+                value DOUBLE
+            );
+            CREATE SEQUENCE IF NOT EXISTS urlscan_domain_seq START 1;
+            CREATE TABLE IF NOT EXISTS urlscan_domain_data (
+                id BIGINT PRIMARY KEY DEFAULT nextval('urlscan_domain_seq'),
+                domain VARCHAR,
+                uuid VARCHAR UNIQUE,
+                result_url VARCHAR,
+                api_url VARCHAR,
+                visibility VARCHAR,
+                useragent VARCHAR,
+                country VARCHAR,
+                screenshot_path VARCHAR,
+                asn VARCHAR,
+                ip VARCHAR,
+                title VARCHAR,
+                verdict_score INTEGER,
+                verdict_brands TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE SEQUENCE IF NOT EXISTS urlscan_dom_seq START 1;
+            CREATE TABLE IF NOT EXISTS urlscan_dom_snapshot (
+                id BIGINT PRIMARY KEY DEFAULT nextval('urlscan_dom_seq'),
+                uuid VARCHAR UNIQUE,
+                dom TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE SEQUENCE IF NOT EXISTS urlscan_scan_seq START 1;
+            CREATE TABLE IF NOT EXISTS urlscan_scan_data (
+                id BIGINT PRIMARY KEY DEFAULT nextval('urlscan_scan_seq'),
+                uuid VARCHAR UNIQUE,
+                ip VARCHAR,
+                data_links TEXT,
+                page_asn VARCHAR,
+                page_ip VARCHAR,
+                page_country VARCHAR,
+                page_title VARCHAR,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 2,
+        description: "Export log",
+        sql: "CREATE SEQUENCE IF NOT EXISTS export_log_seq START 1;
+            CREATE TABLE IF NOT EXISTS export_log (
+                id BIGINT PRIMARY KEY DEFAULT nextval('export_log_seq'),
+                table_name VARCHAR,
+                format VARCHAR,
+                destination VARCHAR,
+                row_count BIGINT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 3,
+        description: "Export filters in export log",
+        sql: "ALTER TABLE export_log ADD COLUMN IF NOT EXISTS filter VARCHAR;",
+    },
+    Migration {
+        version: 4,
+        description: "Unquote Ethereum text values saved as JSON",
+        // Older builds stored JSON-encoded text: strings kept their quotes and
+        // nulls became the string 'null'
+        sql: r#"UPDATE ethereum_accounts SET
+                address = regexp_replace(NULLIF(address, 'null'), '^"(.*)"$', '\1'),
+                creator_address = regexp_replace(NULLIF(creator_address, 'null'), '^"(.*)"$', '\1'),
+                type = regexp_replace(NULLIF(type, 'null'), '^"(.*)"$', '\1');
+            UPDATE ethereum_transactions SET
+                transaction_hash = regexp_replace(NULLIF(transaction_hash, 'null'), '^"(.*)"$', '\1'),
+                contract_address = regexp_replace(NULLIF(contract_address, 'null'), '^"(.*)"$', '\1'),
+                from_address = regexp_replace(NULLIF(from_address, 'null'), '^"(.*)"$', '\1'),
+                input = regexp_replace(NULLIF(input, 'null'), '^"(.*)"$', '\1'),
+                output = regexp_replace(NULLIF(output, 'null'), '^"(.*)"$', '\1'),
+                to_address = regexp_replace(NULLIF(to_address, 'null'), '^"(.*)"$', '\1');"#,
+    },
+    Migration {
+        version: 5,
+        description: "URLScan scan status",
+        sql: "ALTER TABLE urlscan_domain_data ADD COLUMN IF NOT EXISTS status VARCHAR;
+            ALTER TABLE urlscan_domain_data ADD COLUMN IF NOT EXISTS completed_at TIMESTAMP;
+            UPDATE urlscan_domain_data SET status = CASE WHEN screenshot_path IS NULL THEN 'pending' ELSE 'complete' END;",
+    },
+    Migration {
+        version: 6,
+        description: "Chain column in Ethereum tables",
+        // DuckDB can't change a primary key in place, so the tables are rebuilt
+        sql: "CREATE TABLE ethereum_accounts_v6 (
+                chain VARCHAR NOT NULL DEFAULT 'ethereum',
+                address VARCHAR,
+                created_timestamp TIMESTAMP,
+                creator_address VARCHAR,
+                last_active_timestamp TIMESTAMP,
+                type VARCHAR,
+                PRIMARY KEY (chain, address)
+            );
+            INSERT INTO ethereum_accounts_v6 BY NAME SELECT * FROM ethereum_accounts;
+            DROP TABLE ethereum_accounts;
+            ALTER TABLE ethereum_accounts_v6 RENAME TO ethereum_accounts;
+            CREATE TABLE ethereum_transactions_v6 (
+                chain VARCHAR NOT NULL DEFAULT 'ethereum',
+                transaction_hash VARCHAR,
+                base_fee_per_gas DOUBLE,
+                block_number BIGINT,
+                contract_address VARCHAR,
+                fees_burned DOUBLE,
+                fees_rewarded DOUBLE,
+                fees_saved DOUBLE,
+                from_address VARCHAR,
+                gas_limit DOUBLE,
+                gas_price DOUBLE,
+                gas_used DOUBLE,
+                input TEXT,
+                internal_failed_transaction_count INTEGER,
+                internal_transaction_count INTEGER,
+                log_count INTEGER,
+                max_fee_per_gas DOUBLE,
+                max_priority_fee_per_gas DOUBLE,
+                nonce BIGINT,
+                output TEXT,
+                position INTEGER,
+                timestamp TIMESTAMP,
+                to_address VARCHAR,
+                transaction_fee DOUBLE,
+                type INTEGER,
+                value DOUBLE,
+                PRIMARY KEY (chain, transaction_hash)
+            );
+            INSERT INTO ethereum_transactions_v6 BY NAME SELECT * FROM ethereum_transactions;
+            DROP TABLE ethereum_transactions;
+            ALTER TABLE ethereum_transactions_v6 RENAME TO ethereum_transactions;",
+    },
+    Migration {
+        version: 7,
+        description: "Screenshot images in URLScan domain data",
+        sql: "ALTER TABLE urlscan_domain_data ADD COLUMN IF NOT EXISTS screenshot BLOB;",
+    },
+    Migration {
+        version: 8,
+        description: "Investigation cases",
+        // Records saved outside any case keep a NULL case_id
+        sql: "CREATE SEQUENCE IF NOT EXISTS cases_seq START 1;
+            CREATE TABLE IF NOT EXISTS cases (
+                id BIGINT PRIMARY KEY DEFAULT nextval('cases_seq'),
+                name VARCHAR UNIQUE NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            ALTER TABLE ethereum_accounts ADD COLUMN IF NOT EXISTS case_id BIGINT;
+            ALTER TABLE ethereum_transactions ADD COLUMN IF NOT EXISTS case_id BIGINT;
+            ALTER TABLE urlscan_domain_data ADD COLUMN IF NOT EXISTS case_id BIGINT;
+            ALTER TABLE export_log ADD COLUMN IF NOT EXISTS case_id BIGINT;",
+    },
+    Migration {
+        version: 9,
+        description: "DOM snapshot files",
+        sql: "ALTER TABLE urlscan_dom_snapshot ADD COLUMN IF NOT EXISTS dom_path VARCHAR;",
+    },
+    Migration {
+        version: 10,
+        description: "Resolved ENS names",
+        sql: "CREATE TABLE IF NOT EXISTS ens_names (
+                ens_name VARCHAR PRIMARY KEY,
+                address VARCHAR,
+                case_id BIGINT,
+                resolved_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 11,
+        description: "ERC-20 token transfers",
+        // One transaction can emit several transfers, told apart by log index
+        sql: "CREATE TABLE IF NOT EXISTS token_transfers (
+                chain VARCHAR NOT NULL DEFAULT 'ethereum',
+                transaction_hash VARCHAR,
+                log_index INTEGER,
+                block_number BIGINT,
+                timestamp TIMESTAMP,
+                contract_address VARCHAR,
+                token_symbol VARCHAR,
+                token_decimals INTEGER,
+                from_address VARCHAR,
+                to_address VARCHAR,
+                raw_amount VARCHAR,
+                amount DOUBLE,
+                case_id BIGINT,
+                PRIMARY KEY (chain, transaction_hash, log_index)
+            );",
+    },
+    Migration {
+        version: 12,
+        description: "NFT transfers",
+        // Token IDs are uint256, so they're kept as text; an ERC-1155 batch
+        // transfer logs several token IDs under one log index
+        sql: "CREATE TABLE IF NOT EXISTS nft_transfers (
+                chain VARCHAR NOT NULL DEFAULT 'ethereum',
+                transaction_hash VARCHAR,
+                log_index INTEGER,
+                block_number BIGINT,
+                timestamp TIMESTAMP,
+                contract_address VARCHAR,
+                token_id VARCHAR,
+                from_address VARCHAR,
+                to_address VARCHAR,
+                quantity VARCHAR,
+                case_id BIGINT,
+                PRIMARY KEY (chain, transaction_hash, log_index, token_id)
+            );",
+    },
+    Migration {
+        version: 13,
+        description: "Query history",
+        sql: "CREATE SEQUENCE IF NOT EXISTS query_history_seq START 1;
+            CREATE TABLE IF NOT EXISTS query_history (
+                id BIGINT PRIMARY KEY DEFAULT nextval('query_history_seq'),
+                queried_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                operation VARCHAR NOT NULL,
+                target VARCHAR NOT NULL,
+                result_count BIGINT,
+                case_id BIGINT
+            );",
+    },
+    Migration {
+        version: 14,
+        description: "Domain registration data",
+        // Dates are kept as the ISO 8601 text RDAP reports; nameservers as a JSON array
+        sql: "CREATE TABLE IF NOT EXISTS domain_whois (
+                domain VARCHAR PRIMARY KEY,
+                registrar VARCHAR,
+                registrant VARCHAR,
+                registered VARCHAR,
+                expires VARCHAR,
+                updated VARCHAR,
+                nameservers TEXT,
+                case_id BIGINT,
+                looked_up_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 15,
+        description: "Domain DNS records",
+        sql: "CREATE TABLE IF NOT EXISTS domain_dns (
+                domain VARCHAR NOT NULL,
+                record_type VARCHAR NOT NULL,
+                value VARCHAR NOT NULL,
+                ttl BIGINT,
+                case_id BIGINT,
+                resolved_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (domain, record_type, value)
+            );",
+    },
+    Migration {
+        version: 16,
+        description: "NULL instead of N/A for missing scan page details",
+        sql: "UPDATE urlscan_domain_data SET
+                asn = NULLIF(asn, 'N/A'),
+                ip = NULLIF(ip, 'N/A'),
+                title = NULLIF(title, 'N/A')
+            WHERE 'N/A' IN (asn, ip, title);",
+    },
+    Migration {
+        version: 17,
+        description: "Resolved IPs, page links and raw result of scans",
+        sql: "ALTER TABLE urlscan_scan_data ADD COLUMN IF NOT EXISTS resolved_ips TEXT;
+            ALTER TABLE urlscan_scan_data ADD COLUMN IF NOT EXISTS page_links TEXT;
+            ALTER TABLE urlscan_scan_data ADD COLUMN IF NOT EXISTS raw_result TEXT;",
+    },
+    Migration {
+        version: 18,
+        description: "NFT collections, token standards and holdings",
+        sql: "ALTER TABLE nft_transfers ADD COLUMN IF NOT EXISTS collection_name VARCHAR;
+            ALTER TABLE nft_transfers ADD COLUMN IF NOT EXISTS token_standard VARCHAR;
+            CREATE TABLE IF NOT EXISTS nft_holdings (
+                chain VARCHAR NOT NULL DEFAULT 'ethereum',
+                owner_address VARCHAR,
+                contract_address VARCHAR,
+                token_id VARCHAR,
+                balance VARCHAR,
+                collection_name VARCHAR,
+                token_standard VARCHAR,
+                case_id BIGINT,
+                queried_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (chain, owner_address, contract_address, token_id)
+            );",
+    },
+    Migration {
+        version: 19,
+        description: "Full URL submitted for each scan",
+        sql: "ALTER TABLE urlscan_domain_data ADD COLUMN IF NOT EXISTS url VARCHAR;
+            UPDATE urlscan_domain_data SET url = domain WHERE url IS NULL;",
+    },
+    Migration {
+        version: 20,
+        description: "Watchlist of addresses and domains",
+        sql: "CREATE SEQUENCE IF NOT EXISTS watchlist_seq START 1;
+            CREATE TABLE IF NOT EXISTS watchlist (
+                id BIGINT PRIMARY KEY DEFAULT nextval('watchlist_seq'),
+                item_type VARCHAR NOT NULL,
+                value VARCHAR NOT NULL,
+                chain VARCHAR,
+                added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                last_checked_at TIMESTAMP,
+                last_scan_uuid VARCHAR,
+                last_malicious BOOLEAN,
+                case_id BIGINT
+            );",
+    },
+    Migration {
+        version: 21,
+        description: "Transaction sync high-water marks",
+        sql: "CREATE TABLE IF NOT EXISTS sync_state (
+                chain VARCHAR NOT NULL,
+                address VARCHAR NOT NULL,
+                last_block BIGINT NOT NULL,
+                synced_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (chain, address)
+            );",
+    },
+    Migration {
+        version: 22,
+        description: "Tags on addresses and domains",
+        sql: "CREATE SEQUENCE IF NOT EXISTS entity_tags_seq START 1;
+            CREATE TABLE IF NOT EXISTS entity_tags (
+                id BIGINT PRIMARY KEY DEFAULT nextval('entity_tags_seq'),
+                entity_type VARCHAR NOT NULL,
+                entity_id VARCHAR NOT NULL,
+                tag VARCHAR NOT NULL,
+                note VARCHAR,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (entity_type, entity_id, tag)
+            );",
+    },
+    Migration {
+        version: 23,
+        description: "VirusTotal domain reports",
+        // Categories as a JSON object of vendor to category; resolutions as a JSON array
+        sql: "CREATE TABLE IF NOT EXISTS virustotal_domain_data (
+                domain VARCHAR PRIMARY KEY,
+                reputation BIGINT,
+                categories TEXT,
+                malicious INTEGER,
+                suspicious INTEGER,
+                harmless INTEGER,
+                undetected INTEGER,
+                resolutions TEXT,
+                case_id BIGINT,
+                looked_up_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 24,
+        description: "VirusTotal reports of IPs and detection ratios",
+        // Scans report their domain next to the lookups in `virustotal_domain_data`,
+        // and the IPs the page contacted in a table of the same shape
+        sql: "ALTER TABLE virustotal_domain_data ADD COLUMN IF NOT EXISTS detection_ratio VARCHAR;
+            CREATE TABLE IF NOT EXISTS virustotal_ip_data (
+                ip VARCHAR PRIMARY KEY,
+                reputation BIGINT,
+                categories TEXT,
+                malicious INTEGER,
+                suspicious INTEGER,
+                harmless INTEGER,
+                undetected INTEGER,
+                detection_ratio VARCHAR,
+                case_id BIGINT,
+                looked_up_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 25,
+        description: "Shodan data of IP addresses",
+        // Ports and host names as JSON arrays; `found` is false when Shodan had nothing on the IP
+        sql: "CREATE TABLE IF NOT EXISTS shodan_host_data (
+                ip VARCHAR PRIMARY KEY,
+                found BOOLEAN NOT NULL,
+                org VARCHAR,
+                isp VARCHAR,
+                asn VARCHAR,
+                country VARCHAR,
+                ports TEXT,
+                hostnames TEXT,
+                last_update VARCHAR,
+                case_id BIGINT,
+                looked_up_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );",
+    },
+    Migration {
+        version: 26,
+        description: "Registrant country and raw response of registration lookups",
+        // `source` is rdap or whois; rows from before this migration came from RDAP
+        sql: "ALTER TABLE domain_whois ADD COLUMN IF NOT EXISTS registrant_country VARCHAR;
+            ALTER TABLE domain_whois ADD COLUMN IF NOT EXISTS source VARCHAR;
+            ALTER TABLE domain_whois ADD COLUMN IF NOT EXISTS raw TEXT;
+            UPDATE domain_whois SET source = 'rdap' WHERE source IS NULL;",
+    },
+    Migration {
+        version: 27,
+        description: "Save time of transactions and transfers",
+        // Their `timestamp` is the block time, so purging old records needs
+        // when they were saved; rows stored before this count as saved now
+        sql: "ALTER TABLE ethereum_transactions ADD COLUMN IF NOT EXISTS saved_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+            ALTER TABLE token_transfers ADD COLUMN IF NOT EXISTS saved_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;
+            ALTER TABLE nft_transfers ADD COLUMN IF NOT EXISTS saved_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP;",
+    },
+];
+
+/// Schema version this binary expects
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Creates the metadata and migration history tables if they don't exist
+pub fn ensure_meta_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS fragarach_meta (
+            key VARCHAR PRIMARY KEY,
+            value VARCHAR
+        );
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            description VARCHAR,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )"
+    )
+}
+
+/// Schema version recorded in the database, or 0 for unversioned databases
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    let has_meta: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM information_schema.tables WHERE table_name = 'fragarach_meta'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_meta {
+        return Ok(0);
+    }
+
+    let version: Option<String> = conn
+        .query_row(
+            "SELECT value FROM fragarach_meta WHERE key = 'schema_version'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(version.and_then(|v| v.parse().ok()).unwrap_or(0))
+}
+
+/// Migrations newer than the database's recorded version
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let version = current_version(conn)?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > version).collect())
+}
+
+/// Applies a single migration and records its version atomically
+pub fn apply(conn: &Connection, migration: &Migration) -> Result<()> {
+    ensure_meta_table(conn)?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(migration.sql)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO fragarach_meta (key, value) VALUES ('schema_version', $1)",
+        params![migration.version.to_string()],
+    )?;
+    tx.execute(
+        "INSERT OR REPLACE INTO schema_migrations (version, description) VALUES ($1, $2)",
+        params![migration.version, migration.description],
+    )?;
+    tx.commit()
+}
+
+/// Migrations recorded as applied, oldest first, with the time each ran
+///
+/// Databases migrated before the history table existed only list the
+/// migrations applied since.
+pub fn history(conn: &Connection) -> Result<Vec<(u32, String, String)>> {
+    ensure_meta_table(conn)?;
+    let mut stmt = conn.prepare(
+        "SELECT version, description, CAST(applied_at AS VARCHAR) FROM schema_migrations ORDER BY version"
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    rows.collect()
+}
+
+/// In-memory database with every migration applied, for tests
+#[cfg(test)]
+pub(crate) fn test_db() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    for migration in MIGRATIONS {
+        apply(&conn, migration).unwrap();
+    }
+    conn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrate_to(conn: &Connection, version: u32) {
+        for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+            apply(conn, migration).unwrap();
+        }
+    }
+
+    #[test]
+    fn only_newer_migrations_are_pending() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert_eq!(pending(&conn).unwrap().len(), MIGRATIONS.len());
+
+        migrate_to(&conn, 5);
+        assert_eq!(current_version(&conn).unwrap(), 5);
+        let versions: Vec<u32> = pending(&conn).unwrap().iter().map(|m| m.version).collect();
+        assert_eq!(versions, (6..=latest_version()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chain_migration_keeps_existing_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 5);
+        conn.execute_batch("INSERT INTO ethereum_accounts (address, type) VALUES ('0xabc', 'eoa')").unwrap();
+
+        for migration in pending(&conn).unwrap() {
+            apply(&conn, migration).unwrap();
+        }
+
+        let (chain, kind): (String, String) = conn
+            .query_row("SELECT chain, type FROM ethereum_accounts WHERE address = '0xabc'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((chain.as_str(), kind.as_str()), ("ethereum", "eoa"));
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn version_one_database_upgrades_with_data_intact() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 1);
+        conn.execute_batch(
+            "INSERT INTO ethereum_transactions (transaction_hash, from_address, to_address, value)
+             VALUES ('0xfeed', '0xabc', '0xdef', 1.5)"
+        ).unwrap();
+
+        for migration in pending(&conn).unwrap() {
+            apply(&conn, migration).unwrap();
+        }
+
+        let (from, value, chain, case_id): (String, f64, String, Option<i64>) = conn
+            .query_row(
+                "SELECT from_address, value, chain, case_id FROM ethereum_transactions WHERE transaction_hash = '0xfeed'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((from.as_str(), value, chain.as_str(), case_id), ("0xabc", 1.5, "ethereum", None));
+
+        let recorded: Vec<u32> = history(&conn).unwrap().into_iter().map(|(version, _, _)| version).collect();
+        assert_eq!(recorded, (1..=latest_version()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn virustotal_lookups_keep_their_rows_when_scans_join_them() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 23);
+        conn.execute_batch(
+            "INSERT INTO virustotal_domain_data (domain, malicious, suspicious, harmless, undetected, resolutions)
+                VALUES ('looked.example', 1, 0, 2, 3, '[]')"
+        ).unwrap();
+
+        for migration in pending(&conn).unwrap() {
+            apply(&conn, migration).unwrap();
+        }
+
+        let lookup: (i32, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT malicious, resolutions, detection_ratio FROM virustotal_domain_data WHERE domain = 'looked.example'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(lookup, (1, Some("[]".to_string()), None));
+        conn.execute_batch(
+            "INSERT INTO virustotal_ip_data (ip, malicious, suspicious, harmless, undetected, detection_ratio)
+                VALUES ('192.0.2.1', 0, 0, 8, 2, '0/10')"
+        ).unwrap();
+    }
+
+    #[test]
+    fn failed_migration_leaves_version_unchanged() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 2);
+
+        let broken = Migration {
+            version: 3,
+            description: "Broken",
+            sql: "CREATE TABLE half_done (id INTEGER); ALTER TABLE missing ADD COLUMN x INTEGER;",
+        };
+        assert!(apply(&conn, &broken).is_err());
+
+        assert_eq!(current_version(&conn).unwrap(), 2);
+        assert_eq!(history(&conn).unwrap().len(), 2);
+        let half_done: bool = conn
+            .query_row("SELECT COUNT(*) > 0 FROM information_schema.tables WHERE table_name = 'half_done'", [], |row| row.get(0))
+            .unwrap();
+        assert!(!half_done);
+    }
+}
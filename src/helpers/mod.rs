@@ -3,5 +3,17 @@
 /// # Modules
 /// - `database_setup`: Database schema initialization
 /// - `database_operations`: Database storage operations
+/// - `database_writer`: Task owning the database connection
+/// - `export`: Table exports to local files and S3
+/// - `graph`: Address interaction graphs as DOT and GraphML
+/// - `migrations`: Ordered schema migrations
+/// - `report`: Investigation reports in Markdown and HTML
+/// - `watchlist`: Addresses and domains re-checked on every watchlist run
 pub mod database_setup;
-pub mod database_operations;
\ No newline at end of file
+pub mod database_operations;
+pub mod database_writer;
+pub mod export;
+pub mod graph;
+pub mod migrations;
+pub mod report;
+pub mod watchlist;
\ No newline at end of file
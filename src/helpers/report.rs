@@ -0,0 +1,494 @@
+/// Investigation reports rendered from the local database
+///
+/// A report covers one address, one domain, or everything attached to an
+/// investigation case, and only reads what is already stored: no API calls
+/// are made.
+///
+/// # Contents
+/// - Addresses: account details, transaction statistics, top counterparties,
+///   token and NFT activity and ENS names
+/// - Domains: every scan with its verdict and screenshot, WHOIS and DNS records
+/// - Generation time and the Fragarach version that produced the report
+///
+/// # Templates
+/// Reports are rendered with MiniJinja from the built-in `templates/report.md`
+/// and `templates/report.html`. A file of the same name in the configured
+/// report template directory replaces the built-in layout; the data passed to
+/// it is the serialized `Report`. Two filters are available: `or_dash` shows
+/// `-` for missing values and `cell` makes a value safe for a Markdown table.
+use crate::error::FragarachError;
+use duckdb::{params, Connection, OptionalExt, Result};
+use minijinja::{Environment, Value as TemplateValue};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Counterparties listed per address
+const TOP_COUNTERPARTIES: usize = 10;
+
+const MARKDOWN_TEMPLATE: &str = include_str!("../templates/report.md");
+const HTML_TEMPLATE: &str = include_str!("../templates/report.html");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub const ALL: [ReportFormat; 2] = [ReportFormat::Markdown, ReportFormat::Html];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    /// Name of the template file rendering this format
+    fn template(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "report.md",
+            ReportFormat::Html => "report.html",
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ReportFormat::Markdown => "Markdown",
+            ReportFormat::Html => "HTML",
+        })
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(format!("unknown report format '{}' (expected markdown or html)", s)),
+        }
+    }
+}
+
+/// What a report is about
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportSubject {
+    Address(String),
+    Domain(String),
+    /// Every address and domain saved in the case, by case name
+    Case(String),
+}
+
+impl ReportSubject {
+    /// Title of the report and stem of its default file name
+    pub fn name(&self) -> String {
+        match self {
+            ReportSubject::Address(address) => address.to_lowercase(),
+            ReportSubject::Domain(domain) => domain.to_lowercase(),
+            ReportSubject::Case(name) => format!("case {}", name),
+        }
+    }
+
+    /// Default file name of the report, without characters unsafe in paths
+    pub fn file_name(&self, format: ReportFormat) -> String {
+        let stem: String = self.name()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        format!("{}.{}", stem, format.extension())
+    }
+}
+
+/// Everything a report template is rendered from
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub title: String,
+    pub generated_at: String,
+    pub version: &'static str,
+    pub addresses: Vec<AddressSection>,
+    pub domains: Vec<DomainSection>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressSection {
+    pub address: String,
+    pub ens_names: Vec<String>,
+    pub accounts: Vec<AccountDetails>,
+    pub transactions: TransactionStats,
+    pub counterparties: Vec<Counterparty>,
+    pub token_transfers: u64,
+    pub nft_transfers: u64,
+    pub nft_holdings: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountDetails {
+    pub chain: String,
+    pub account_type: Option<String>,
+    pub created: Option<String>,
+    pub last_active: Option<String>,
+    pub creator: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TransactionStats {
+    pub count: u64,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub sent: u64,
+    pub received: u64,
+    pub value_sent: Option<f64>,
+    pub value_received: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Counterparty {
+    pub address: String,
+    pub transactions: u64,
+    pub value: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DomainSection {
+    pub domain: String,
+    pub scans: Vec<ScanSummary>,
+    pub whois: Option<WhoisSummary>,
+    pub dns: Vec<DnsRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanSummary {
+    pub uuid: String,
+    pub scanned_at: Option<String>,
+    pub status: Option<String>,
+    pub title: Option<String>,
+    pub ip: Option<String>,
+    pub verdict_score: Option<i64>,
+    pub verdict_brands: Option<String>,
+    pub screenshot_path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WhoisSummary {
+    pub registrar: Option<String>,
+    pub registered: Option<String>,
+    pub expires: Option<String>,
+    pub nameservers: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DnsRecord {
+    pub record_type: String,
+    pub value: String,
+}
+
+fn address_section(conn: &Connection, address: &str) -> Result<AddressSection> {
+    let address = address.to_lowercase();
+
+    let ens_names = conn
+        .prepare("SELECT ens_name FROM ens_names WHERE lower(address) = $1 ORDER BY ens_name")?
+        .query_map(params![address], |row| row.get(0))?
+        .collect::<Result<_>>()?;
+
+    let accounts = conn
+        .prepare(
+            "SELECT chain, type, CAST(created_timestamp AS VARCHAR), CAST(last_active_timestamp AS VARCHAR), creator_address
+             FROM ethereum_accounts WHERE lower(address) = $1 ORDER BY chain"
+        )?
+        .query_map(params![address], |row| Ok(AccountDetails {
+            chain: row.get(0)?,
+            account_type: row.get(1)?,
+            created: row.get(2)?,
+            last_active: row.get(3)?,
+            creator: row.get(4)?,
+        }))?
+        .collect::<Result<_>>()?;
+
+    let transactions = conn.query_row(
+        "SELECT COUNT(*), CAST(MIN(timestamp) AS VARCHAR), CAST(MAX(timestamp) AS VARCHAR),
+                COUNT(*) FILTER (WHERE lower(from_address) = $1),
+                COUNT(*) FILTER (WHERE lower(to_address) = $1),
+                SUM(value) FILTER (WHERE lower(from_address) = $1),
+                SUM(value) FILTER (WHERE lower(to_address) = $1)
+         FROM ethereum_transactions WHERE lower(from_address) = $1 OR lower(to_address) = $1",
+        params![address],
+        |row| Ok(TransactionStats {
+            count: row.get(0)?,
+            first: row.get(1)?,
+            last: row.get(2)?,
+            sent: row.get(3)?,
+            received: row.get(4)?,
+            value_sent: row.get(5)?,
+            value_received: row.get(6)?,
+        }),
+    )?;
+
+    let counterparties = conn
+        .prepare(&format!(
+            "SELECT counterparty, COUNT(*) AS transactions, SUM(value)
+             FROM (
+                 SELECT CASE WHEN lower(from_address) = $1 THEN lower(to_address) ELSE lower(from_address) END AS counterparty, value
+                 FROM ethereum_transactions WHERE lower(from_address) = $1 OR lower(to_address) = $1
+             )
+             WHERE counterparty IS NOT NULL AND counterparty <> $1
+             GROUP BY counterparty ORDER BY transactions DESC, counterparty LIMIT {}",
+            TOP_COUNTERPARTIES
+        ))?
+        .query_map(params![address], |row| Ok(Counterparty {
+            address: row.get(0)?,
+            transactions: row.get(1)?,
+            value: row.get(2)?,
+        }))?
+        .collect::<Result<_>>()?;
+
+    let count = |sql: &str| conn.query_row(sql, params![address], |row| row.get::<_, u64>(0));
+    Ok(AddressSection {
+        token_transfers: count("SELECT COUNT(*) FROM token_transfers WHERE lower(from_address) = $1 OR lower(to_address) = $1")?,
+        nft_transfers: count("SELECT COUNT(*) FROM nft_transfers WHERE lower(from_address) = $1 OR lower(to_address) = $1")?,
+        nft_holdings: count("SELECT COUNT(*) FROM nft_holdings WHERE lower(owner_address) = $1")?,
+        address,
+        ens_names,
+        accounts,
+        transactions,
+        counterparties,
+    })
+}
+
+fn domain_section(conn: &Connection, domain: &str) -> Result<DomainSection> {
+    let domain = domain.to_lowercase();
+
+    let scans = conn
+        .prepare(
+            "SELECT uuid, CAST(created_at AS VARCHAR), status, title, ip, verdict_score, verdict_brands, screenshot_path
+             FROM urlscan_domain_data WHERE lower(domain) = $1 ORDER BY created_at DESC, id DESC"
+        )?
+        .query_map(params![domain], |row| Ok(ScanSummary {
+            uuid: row.get(0)?,
+            scanned_at: row.get(1)?,
+            status: row.get(2)?,
+            title: row.get(3)?,
+            ip: row.get(4)?,
+            verdict_score: row.get(5)?,
+            verdict_brands: row.get(6)?,
+            screenshot_path: row.get(7)?,
+        }))?
+        .collect::<Result<_>>()?;
+
+    let whois = conn
+        .query_row(
+            "SELECT registrar, registered, expires, nameservers FROM domain_whois WHERE lower(domain) = $1",
+            params![domain],
+            |row| Ok(WhoisSummary {
+                registrar: row.get(0)?,
+                registered: row.get(1)?,
+                expires: row.get(2)?,
+                nameservers: row.get(3)?,
+            }),
+        )
+        .optional()?;
+
+    let dns = conn
+        .prepare("SELECT record_type, value FROM domain_dns WHERE lower(domain) = $1 ORDER BY record_type, value")?
+        .query_map(params![domain], |row| Ok(DnsRecord { record_type: row.get(0)?, value: row.get(1)? }))?
+        .collect::<Result<_>>()?;
+
+    Ok(DomainSection { domain, scans, whois, dns })
+}
+
+/// Collects the stored data about `subject`
+///
+/// A case that doesn't exist fails with `QueryReturnedNoRows`; an address or
+/// domain with nothing stored gives a report with empty sections.
+pub fn gather(conn: &Connection, subject: &ReportSubject, generated_at: String) -> Result<Report> {
+    let (addresses, domains) = match subject {
+        ReportSubject::Address(address) => (vec![address.clone()], Vec::new()),
+        ReportSubject::Domain(domain) => (Vec::new(), vec![domain.clone()]),
+        ReportSubject::Case(name) => {
+            let case_id: i64 = conn.query_row("SELECT id FROM cases WHERE name = $1", params![name], |row| row.get(0))?;
+            let addresses = conn
+                .prepare("SELECT DISTINCT lower(address) AS a FROM ethereum_accounts WHERE case_id = $1 ORDER BY a")?
+                .query_map(params![case_id], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+            let domains = conn
+                .prepare("SELECT DISTINCT lower(domain) AS d FROM urlscan_domain_data WHERE case_id = $1 ORDER BY d")?
+                .query_map(params![case_id], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+            (addresses, domains)
+        }
+    };
+
+    Ok(Report {
+        title: subject.name(),
+        generated_at,
+        version: env!("CARGO_PKG_VERSION"),
+        addresses: addresses.iter().map(|a| address_section(conn, a)).collect::<Result<_>>()?,
+        domains: domains.iter().map(|d| domain_section(conn, d)).collect::<Result<_>>()?,
+    })
+}
+
+/// Shows `-` for a missing value
+fn or_dash(value: TemplateValue) -> TemplateValue {
+    if value.is_none() || value.is_undefined() {
+        TemplateValue::from("-")
+    } else {
+        value
+    }
+}
+
+/// Keeps a value on one line of a Markdown table cell
+fn cell(value: TemplateValue) -> String {
+    let text = if value.is_none() || value.is_undefined() { "-".to_string() } else { value.to_string() };
+    text.split_whitespace().collect::<Vec<_>>().join(" ").replace('|', "\\|")
+}
+
+/// Renders a report, using the template in `template_dir` when it has one for `format`
+pub fn render(report: &Report, format: ReportFormat, template_dir: Option<&Path>) -> Result<String, FragarachError> {
+    let name = format.template();
+    let custom = template_dir.map(|dir| dir.join(name)).filter(|path| path.is_file());
+    let source = match custom {
+        Some(path) => fs::read_to_string(path)?,
+        None => match format {
+            ReportFormat::Markdown => MARKDOWN_TEMPLATE.to_string(),
+            ReportFormat::Html => HTML_TEMPLATE.to_string(),
+        },
+    };
+
+    let mut env = Environment::new();
+    env.add_filter("or_dash", or_dash);
+    env.add_filter("cell", cell);
+    env.add_template_owned(name, source)?;
+    Ok(env.get_template(name)?.render(report)?)
+}
+
+/// Current time as `YYYY-MM-DD HH:MM:SS UTC`, formatted by DuckDB
+fn now_utc(conn: &Connection) -> Result<String> {
+    let micros = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as i64).unwrap_or(0);
+    let time: String = conn.query_row(
+        "SELECT strftime(make_timestamp($1), '%Y-%m-%d %H:%M:%S')",
+        params![micros],
+        |row| row.get(0),
+    )?;
+    Ok(format!("{} UTC", time))
+}
+
+/// Gathers, renders and writes a report to `out`, returning the path written
+///
+/// Fails with `ExportExists` if `out` exists and `overwrite` is false.
+pub fn write_report(
+    conn: &Connection,
+    subject: &ReportSubject,
+    format: ReportFormat,
+    template_dir: Option<&Path>,
+    out: &Path,
+    overwrite: bool,
+) -> Result<PathBuf, FragarachError> {
+    if out.exists() && !overwrite {
+        return Err(FragarachError::ExportExists(out.to_path_buf()));
+    }
+    let report = gather(conn, subject, now_utc(conn)?)?;
+    let rendered = render(&report, format, template_dir)?;
+
+    if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(out, rendered)?;
+    Ok(out.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::database_operations::{open_case, save_records};
+    use crate::helpers::migrations;
+    use serde_json::json;
+
+    const VICTIM: &str = "0xa1e4380a3b1f749673e270229993ee55f35663b4";
+    const SCAMMER: &str = "0x5df9b87991262f6ba471f09758cde1c0fc1de734";
+
+    fn seeded_db() -> Connection {
+        let conn = migrations::test_db();
+        let case_id = open_case(&conn, "acme phish").unwrap();
+
+        let tx = |hash: &str, from: &str, to: &str, value: f64, timestamp: &str| json!({
+            "transaction_hash": hash, "from_address": from, "to_address": to, "value": value, "timestamp": timestamp
+        });
+        save_records(&conn, &[
+            tx("0x01", VICTIM, SCAMMER, 2.0, "2026-03-01T10:00:00Z"),
+            tx("0x02", VICTIM, SCAMMER, 3.0, "2026-03-02T10:00:00Z"),
+            tx("0x03", "0xfeed", VICTIM, 10.0, "2026-02-01T10:00:00Z"),
+        ], "ethereum_transactions").unwrap();
+        save_records(&conn, &[json!({ "address": VICTIM, "type": "eoa", "case_id": case_id })], "ethereum_accounts").unwrap();
+        save_records(&conn, &[json!({ "ens_name": "victim.eth", "address": VICTIM })], "ens_names").unwrap();
+        conn.execute(
+            "INSERT INTO urlscan_domain_data (domain, uuid, status, title, verdict_score, verdict_brands, screenshot_path, case_id)
+             VALUES ('login-example.com', 'b5f3', 'complete', 'Sign in | Microsoft', 100, '[\"Microsoft\"]', '/screens/b5f3.png', $1)",
+            params![case_id],
+        ).unwrap();
+        save_records(&conn, &[json!({ "domain": "login-example.com", "record_type": "A", "value": "185.199.110.153" })], "domain_dns").unwrap();
+        conn
+    }
+
+    #[test]
+    fn address_report_summarizes_transactions_and_counterparties() {
+        let conn = seeded_db();
+        let report = gather(&conn, &ReportSubject::Address(VICTIM.to_uppercase().replace("0X", "0x")), "now".to_string()).unwrap();
+
+        let section = &report.addresses[0];
+        assert_eq!(section.ens_names, ["victim.eth"]);
+        assert_eq!(section.accounts[0].account_type.as_deref(), Some("eoa"));
+        assert_eq!((section.transactions.count, section.transactions.sent, section.transactions.received), (3, 2, 1));
+        assert_eq!((section.transactions.value_sent, section.transactions.value_received), (Some(5.0), Some(10.0)));
+        assert_eq!(section.transactions.first.as_deref(), Some("2026-02-01 10:00:00"));
+        let top: Vec<(&str, u64)> = section.counterparties.iter().map(|c| (c.address.as_str(), c.transactions)).collect();
+        assert_eq!(top, [(SCAMMER, 2), ("0xfeed", 1)]);
+    }
+
+    #[test]
+    fn case_report_renders_markdown_and_html() {
+        let conn = seeded_db();
+        let report = gather(&conn, &ReportSubject::Case("acme phish".to_string()), "2026-10-17 09:00:00 UTC".to_string()).unwrap();
+        assert_eq!(report.addresses.len(), 1);
+        assert_eq!(report.domains.len(), 1);
+
+        let markdown = render(&report, ReportFormat::Markdown, None).unwrap();
+        assert!(markdown.contains("# Investigation Report: case acme phish"));
+        assert!(markdown.contains("2026-10-17 09:00:00 UTC"));
+        assert!(markdown.contains(env!("CARGO_PKG_VERSION")));
+        assert!(markdown.contains("| Sign in \\| Microsoft |"));
+        assert!(markdown.contains("![Screenshot of scan b5f3](/screens/b5f3.png)"));
+        assert!(markdown.contains("| A | 185.199.110.153 |"));
+
+        let html = render(&report, ReportFormat::Html, None).unwrap();
+        assert!(html.contains("<img src=\"&#x2f;screens&#x2f;b5f3.png\""));
+        assert!(html.contains("&quot;Microsoft&quot;"));
+
+        assert!(gather(&conn, &ReportSubject::Case("missing".to_string()), String::new()).is_err());
+    }
+
+    #[test]
+    fn custom_template_replaces_the_layout() {
+        let conn = seeded_db();
+        let dir = std::env::temp_dir().join(format!("fragarach-report-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("report.md"), "{% for d in domains %}{{ d.domain }}: {{ d.scans[0].verdict_score }}{% endfor %}").unwrap();
+
+        let out = dir.join("reports").join(ReportSubject::Domain("login-example.com".to_string()).file_name(ReportFormat::Markdown));
+        let subject = ReportSubject::Domain("login-example.com".to_string());
+        write_report(&conn, &subject, ReportFormat::Markdown, Some(&dir), &out, false).unwrap();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "login-example.com: 100");
+        assert!(matches!(
+            write_report(&conn, &subject, ReportFormat::Markdown, Some(&dir), &out, false),
+            Err(FragarachError::ExportExists(_))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+/// Addresses and domains re-checked on every watchlist run
+///
+/// Items live in `watchlist`, one per address or domain, in the case that was
+/// active when they were added. A run pulls each address's transactions from
+/// the day before `last_checked_at` onwards and looks each domain up in the
+/// URLScan search, remembering the newest scan and its verdict so the next run
+/// can tell what changed. Addresses are lowercased and domains reduced to
+/// their host name before they are stored.
+use crate::helpers::database_operations::case_condition;
+use duckdb::{params, Connection, Result};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchKind {
+    Address,
+    Domain,
+}
+
+impl WatchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchKind::Address => "address",
+            WatchKind::Domain => "domain",
+        }
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WatchKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "address" => Ok(WatchKind::Address),
+            "domain" => Ok(WatchKind::Domain),
+            _ => Err(format!("unknown watchlist item type '{}' (expected address or domain)", s)),
+        }
+    }
+}
+
+/// Watched address or domain, with what the last run saw
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WatchItem {
+    pub id: i64,
+    pub kind: WatchKind,
+    pub value: String,
+    /// Chain of a watched address; `None` for domains
+    pub chain: Option<String>,
+    pub added_at: Option<String>,
+    pub last_checked_at: Option<String>,
+    /// Newest URLScan scan of a watched domain at the last run
+    pub last_scan_uuid: Option<String>,
+    /// Verdict of that scan, `None` when it had none
+    pub last_malicious: Option<bool>,
+    /// First day (`YYYY-MM-DD`) the next run pulls for a watched address, or
+    /// `None` before its first run
+    ///
+    /// The day before the last check, so transactions indexed late or stamped
+    /// in another time zone are still picked up.
+    #[serde(skip)]
+    pub pull_from: Option<String>,
+}
+
+/// Adds an item to the watchlist, returning `false` if it was already there
+pub fn add(conn: &Connection, kind: WatchKind, value: &str, chain: Option<&str>, case_id: Option<i64>) -> Result<bool> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM watchlist WHERE item_type = $1 AND value = $2",
+        params![kind.as_str(), value],
+        |row| row.get(0),
+    )?;
+    if exists {
+        return Ok(false);
+    }
+    conn.execute(
+        "INSERT INTO watchlist (item_type, value, chain, case_id) VALUES ($1, $2, $3, $4)",
+        params![kind.as_str(), value, chain, case_id],
+    )?;
+    Ok(true)
+}
+
+/// Removes every item watching `value`, returning how many there were
+pub fn remove(conn: &Connection, value: &str) -> Result<usize> {
+    conn.execute("DELETE FROM watchlist WHERE lower(value) = lower($1)", params![value.trim()])
+}
+
+/// Items in a case (or all of them), in the order they were added
+pub fn list(conn: &Connection, case_id: Option<i64>) -> Result<Vec<WatchItem>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, item_type, value, chain, CAST(added_at AS VARCHAR), CAST(last_checked_at AS VARCHAR),
+                last_scan_uuid, last_malicious, strftime(last_checked_at - INTERVAL 1 DAY, '%Y-%m-%d')
+         FROM watchlist WHERE {} ORDER BY id",
+        case_condition(case_id)
+    ))?;
+    let items = stmt.query_map([], |row| {
+        let kind: String = row.get(1)?;
+        Ok(WatchItem {
+            id: row.get(0)?,
+            kind: if kind == "domain" { WatchKind::Domain } else { WatchKind::Address },
+            value: row.get(2)?,
+            chain: row.get(3)?,
+            added_at: row.get(4)?,
+            last_checked_at: row.get(5)?,
+            last_scan_uuid: row.get(6)?,
+            last_malicious: row.get(7)?,
+            pull_from: row.get(8)?,
+        })
+    })?
+    .collect();
+    items
+}
+
+/// Records a successful check of a watched address
+pub fn mark_address_checked(conn: &Connection, id: i64) -> Result<usize> {
+    conn.execute("UPDATE watchlist SET last_checked_at = CURRENT_TIMESTAMP WHERE id = $1", params![id])
+}
+
+/// Records a successful check of a watched domain and the newest scan it found
+///
+/// Without a scan, the one remembered from an earlier run is kept.
+pub fn mark_domain_checked(conn: &Connection, id: i64, newest: Option<(&str, Option<bool>)>) -> Result<usize> {
+    match newest {
+        Some((uuid, malicious)) => conn.execute(
+            "UPDATE watchlist SET last_checked_at = CURRENT_TIMESTAMP, last_scan_uuid = $2, last_malicious = $3 WHERE id = $1",
+            params![id, uuid, malicious],
+        ),
+        None => mark_address_checked(conn, id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::migrations::test_db;
+
+    #[test]
+    fn items_are_listed_per_case_and_added_once() {
+        let conn = test_db();
+        assert!(add(&conn, WatchKind::Address, "0xabc", Some("ethereum"), Some(1)).unwrap());
+        assert!(add(&conn, WatchKind::Domain, "phish.example", None, Some(2)).unwrap());
+        assert!(!add(&conn, WatchKind::Address, "0xabc", Some("ethereum"), Some(1)).unwrap());
+
+        let all = list(&conn, None).unwrap();
+        assert_eq!(all.iter().map(|item| item.value.as_str()).collect::<Vec<_>>(), ["0xabc", "phish.example"]);
+        let case_two = list(&conn, Some(2)).unwrap();
+        assert_eq!(case_two.len(), 1);
+        assert_eq!(case_two[0].kind, WatchKind::Domain);
+        assert_eq!(case_two[0].last_checked_at, None);
+
+        assert_eq!(remove(&conn, "PHISH.example").unwrap(), 1);
+        assert_eq!(list(&conn, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn checks_remember_the_newest_scan_and_the_pull_start() {
+        let conn = test_db();
+        add(&conn, WatchKind::Domain, "phish.example", None, None).unwrap();
+        let item = list(&conn, None).unwrap().remove(0);
+        assert_eq!(item.pull_from, None);
+
+        mark_domain_checked(&conn, item.id, Some(("b5f3e2a1", Some(true)))).unwrap();
+        mark_domain_checked(&conn, item.id, None).unwrap();
+        let item = list(&conn, None).unwrap().remove(0);
+        assert_eq!(item.last_scan_uuid.as_deref(), Some("b5f3e2a1"));
+        assert_eq!(item.last_malicious, Some(true));
+        assert!(item.last_checked_at.is_some());
+
+        conn.execute("UPDATE watchlist SET last_checked_at = TIMESTAMP '2024-03-01 00:30:00'", []).unwrap();
+        assert_eq!(list(&conn, None).unwrap()[0].pull_from.as_deref(), Some("2024-02-29"));
+    }
+}
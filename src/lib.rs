@@ -0,0 +1,20 @@
+//! Fragarach collection logic, usable without the command-line interface
+//!
+//! # Modules
+//! - `api`: Transpose, Etherscan, URLScan, VirusTotal, Shodan, RDAP and DNS clients
+//! - `config`: Settings from `fragarach.toml`, `.env`, the environment and the OS keyring
+//! - `error`: `FragarachError`, returned by the API, config and helper modules
+//! - `helpers`: DuckDB schema, storage and exports
+//!
+//! Nothing here prompts or prints; results are returned to the caller. The
+//! main entry points are:
+//! - `api::transpose::query_ethereum_transactions` and `query_ethereum_account`,
+//!   or the same calls on `api::source::DataSource` to pick Transpose or Etherscan
+//! - `api::urlscan::scan_domain`, which returns the scan without storing it
+//! - `helpers::database_setup::setup_database_schema`
+//! - `helpers::database_operations::save_records`
+
+pub mod api;
+pub mod config;
+pub mod error;
+pub mod helpers;
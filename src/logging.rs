@@ -0,0 +1,79 @@
+/// Diagnostic logging for the API and database layers
+///
+/// Messages from the layers below the CLI (schema setup, retries, scan
+/// progress) are `tracing` events rather than prints, so they can be silenced,
+/// raised or captured without touching the menu output.
+///
+/// # Levels
+/// - Default: `info`, or `warn` while quiet mode keeps stdout for results
+/// - `-v` / `--verbose`: `debug`; `-vv`: `trace`, plus debug events of the
+///   libraries underneath
+/// - `FRAGARACH_LOG`: a filter such as `debug` or `fragarach=trace,reqwest=info`,
+///   overriding both
+///
+/// # Destinations
+/// Console logs go to stderr, so they never mix with piped or JSON results.
+/// With `--log-file` or `FRAGARACH_LOG_FILE`, every event at `debug` or above
+/// (or as `FRAGARACH_LOG` says) is also appended to that file with timestamps,
+/// keeping a record of the investigation session.
+use crate::output;
+use std::fs::OpenOptions;
+use std::path::Path;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Console filter for the number of `--verbose` flags
+fn console_directives(verbosity: u8) -> &'static str {
+    match verbosity {
+        0 if output::is_quiet() => "warn",
+        0 => "fragarach=info,warn",
+        1 => "fragarach=debug,warn",
+        // Includes the HTTP client's connection handling
+        _ => "fragarach=trace,debug",
+    }
+}
+
+/// Installs the global subscriber; keep the returned guard alive so the log file is flushed
+pub fn init(verbosity: u8, custom_filter: Option<&str>, log_file: Option<&Path>) -> Result<Option<WorkerGuard>, String> {
+    // A filter that doesn't parse falls back to the defaults rather than stopping the run
+    let custom = custom_filter.filter(|directives| EnvFilter::try_new(directives).is_ok());
+    let filter = |default: &str| EnvFilter::new(custom.unwrap_or(default));
+
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(!output::is_plain())
+        .with_target(false)
+        .without_time()
+        .with_filter(filter(console_directives(verbosity)));
+
+    let (file, guard) = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Cannot open log file {}: {}", path.display(), e))?;
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(filter("fragarach=debug,warn"));
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+
+    if let Some(directives) = custom_filter.filter(|_| custom.is_none()) {
+        tracing::warn!("Ignoring FRAGARACH_LOG={:?}: not a valid log filter", directives);
+    }
+    Ok(guard)
+}
@@ -1,52 +1,31 @@
-/// Main entry point for the Fragarach OSINT Framework
-/// 
-/// # Architecture
-/// The application follows a modular architecture with the following components:
-/// - API integrations (Transpose, URLScan)
-/// - CLI interface
-/// - Configuration management
-/// - Database connections (DuckDB)
-/// 
-/// # Database Initialization
-/// - Creates DuckDB database if it doesn't exist
-/// 
-/// # Error Handling
-/// Implements comprehensive error handling for database connections and schema setup
-mod api;
+/// Fragarach command-line entry point
+///
+/// The collection logic lives in the library crate (`fragarach::api`,
+/// `fragarach::config`, `fragarach::helpers`); the binary adds:
+/// - CLI interface (interactive menu, clap subcommands and JSON pipe mode)
+/// - Diagnostic logging (`logging`), on stderr and optionally to a file
+/// - Terminal output modes (`output`)
+///
+/// `main` parses the arguments and hands them to `cli::run`.
 mod cli;
-mod config;
-mod helpers;
+mod logging;
+mod output;
 
-use config::Config;
-use duckdb::Connection;
-use std::fs;
-use std::path::Path;
-
-#[tokio::main]
-/// Initializes the application, sets up database connections, and launches the CLI interface
-///
-/// # Errors
-/// Returns an error if:
-/// - Database directory creation fails
-/// - Database connection fails
-/// - Schema setup fails
-/// - CLI execution fails
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut config = Config::new();
+// Lets the binary's modules reach library modules through `crate::` paths
+use fragarach::{api, config, error, helpers};
 
-    // Create the data directory if it doesn't exist
-    fs::create_dir_all("data")?;
-    
-    // Initialize DuckDB connection
-    let db_path = Path::new("data/fragarach.duckdb");
-    let conn = Connection::open(db_path)?;
+use clap::Parser;
+use cli::args::Args;
+use colored::*;
 
-    // Initialize schema
-    if let Err(e) = helpers::database_setup::setup_database_schema(&conn) {
-        eprintln!("Error setting up database schema: {}", e);
+#[tokio::main]
+/// Runs the application, reporting any error on stderr with exit code 1
+async fn main() {
+    if let Err(e) = cli::run(Args::parse()).await {
+        eprintln!("{} {}", "✘ Error:".bright_red(), e);
+        if let Some(hint) = e.downcast_ref::<error::FragarachError>().and_then(|e| e.hint()) {
+            eprintln!("  {}", hint);
+        }
+        std::process::exit(1);
     }
-
-    cli::run_cli(&mut config, &conn).await?;
-
-    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,59 @@
+/// Process-wide output mode
+///
+/// Pipe mode reserves stdout for protocol messages, so informational messages
+/// from the CLI go through `status`, which drops them while quiet mode is on.
+/// The API and database layers log through `tracing` instead (see `logging`),
+/// which quiet mode limits to warnings on stderr. Interactive prompts and menus
+/// are never shown in quiet mode and don't need to check it.
+///
+/// Plain mode (`--no-color` or `NO_COLOR`) turns off colors and animations, so
+/// output redirected to a file or log stays readable.
+///
+/// Progress bars are only drawn for the interactive menu; headless commands
+/// print progress as plain lines that read well in logs.
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static PROGRESS_BARS: AtomicBool = AtomicBool::new(false);
+
+/// Suppresses informational output for the rest of the process
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Turns off colors and animations for the rest of the process
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+    if plain {
+        colored::control::set_override(false);
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Allows progress bars for the rest of the process
+pub fn set_progress_bars(enabled: bool) {
+    PROGRESS_BARS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether progress bars are allowed and neither plain nor quiet mode is on
+pub fn progress_bars() -> bool {
+    PROGRESS_BARS.load(Ordering::Relaxed) && !is_plain() && !is_quiet()
+}
+
+/// Prints an informational line unless quiet mode is on
+pub fn status(message: impl Display) {
+    if !is_quiet() {
+        println!("{}", message);
+    }
+}
@@ -0,0 +1,15 @@
+-- ENS Name Resolution
+-- 
+-- Resolves an ENS name to the address it points at. ENS lives on Ethereum
+-- mainnet, so the schema is fixed regardless of the chain being queried.
+-- 
+-- Parameters:
+-- {{ens_name}} - Lowercase ENS name, e.g. vitalik.eth
+-- 
+-- Returns:
+-- - ens_name: The queried name
+-- - resolved_address: The address the name resolves to (NULL if unset)
+SELECT ens_name, resolved_address
+FROM ethereum.ens_names
+WHERE ens_name = '{{ens_name}}'
+LIMIT 1
\ No newline at end of file
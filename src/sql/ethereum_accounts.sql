@@ -3,7 +3,8 @@
 -- Retrieves detailed information about an Ethereum account/address
 -- 
 -- Parameters:
--- {{address}} - The Ethereum address to query
+-- {{chain}} - Transpose chain schema, e.g. ethereum or polygon
+-- {{address}} - The address to query
 -- 
 -- Returns:
 -- - address: The account's Ethereum address
@@ -12,5 +13,5 @@
 -- - last_active_timestamp: Last on-chain activity timestamp
 -- - type: Account type (EOA/Contract)
 SELECT *
-FROM ethereum.accounts
+FROM {{chain}}.accounts
 WHERE address = '{{address}}'
\ No newline at end of file
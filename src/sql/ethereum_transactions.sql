@@ -4,9 +4,14 @@
 -- (both sent and received)
 -- 
 -- Parameters:
--- {{wallet_address}} - The Ethereum address to query transactions for
+-- {{chain}} - Transpose chain schema, e.g. ethereum or polygon
+-- {{wallet_address}} - The address to query transactions for
 -- {{limit}} - Maximum number of transactions to return per query
 -- {{offset}} - Number of transactions to skip for pagination
+-- {{from_timestamp}} / {{to_timestamp}} - Inclusive time window (the whole
+--   history when no dates are given)
+-- {{from_block}} / {{to_block}} - Inclusive block window (every block when
+--   none are given)
 -- 
 -- Returns:
 -- - transaction_hash: Unique transaction identifier
@@ -59,9 +64,11 @@ SELECT
     t.transaction_fee,
     t.type,
     t.value
-FROM ethereum.transactions t
-WHERE t.from_address = '{{wallet_address}}'
-   OR t.to_address = '{{wallet_address}}'
+FROM {{chain}}.transactions t
+WHERE (t.from_address = '{{wallet_address}}'
+    OR t.to_address = '{{wallet_address}}')
+  AND t.timestamp BETWEEN '{{from_timestamp}}' AND '{{to_timestamp}}'
+  AND t.block_number BETWEEN {{from_block}} AND {{to_block}}
 ORDER BY t.timestamp DESC
 LIMIT {{limit}}
 OFFSET {{offset}}
\ No newline at end of file
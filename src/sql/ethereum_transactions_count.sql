@@ -0,0 +1,19 @@
+-- Ethereum Transaction Count Query
+-- 
+-- Counts all transactions associated with a specific Ethereum address
+-- (both sent and received). Used as a lightweight probe before a full pull.
+-- 
+-- Parameters:
+-- {{chain}} - Transpose chain schema, e.g. ethereum or polygon
+-- {{wallet_address}} - The address to count transactions for
+-- {{from_timestamp}} / {{to_timestamp}} - Inclusive time window
+-- {{from_block}} / {{to_block}} - Inclusive block window
+-- 
+-- Returns:
+-- - transaction_count: Number of matching transactions
+SELECT COUNT(*) AS transaction_count
+FROM {{chain}}.transactions t
+WHERE (t.from_address = '{{wallet_address}}'
+    OR t.to_address = '{{wallet_address}}')
+  AND t.timestamp BETWEEN '{{from_timestamp}}' AND '{{to_timestamp}}'
+  AND t.block_number BETWEEN {{from_block}} AND {{to_block}}
@@ -0,0 +1,30 @@
+-- NFT Holdings Query
+--
+-- Retrieves the ERC-721 and ERC-1155 tokens currently owned by a specific address
+--
+-- Parameters:
+-- {{chain}} - Transpose chain schema, e.g. ethereum or polygon
+-- {{wallet_address}} - The address to list NFT holdings for
+-- {{limit}} - Maximum number of holdings to return per query
+-- {{offset}} - Number of holdings to skip for pagination
+--
+-- Returns:
+-- - owner_address: The queried address
+-- - contract_address: NFT collection contract address
+-- - token_id: Token ID, as text since IDs can exceed 64 bits
+-- - balance: Number of tokens held (always 1 for ERC-721), as text
+-- - collection_name: Name of the NFT collection, if known
+-- - token_standard: ERC721 or ERC1155
+SELECT
+    o.owner_address,
+    o.contract_address,
+    CAST(o.token_id AS VARCHAR) AS token_id,
+    CAST(o.balance AS VARCHAR) AS balance,
+    c.name AS collection_name,
+    c.standard AS token_standard
+FROM {{chain}}.nft_owners o
+LEFT JOIN {{chain}}.nft_collections c ON c.contract_address = o.contract_address
+WHERE o.owner_address = '{{wallet_address}}'
+ORDER BY o.contract_address, o.token_id
+LIMIT {{limit}}
+OFFSET {{offset}}
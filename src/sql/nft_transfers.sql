@@ -0,0 +1,42 @@
+-- NFT Transfer Query
+--
+-- Retrieves all ERC-721 and ERC-1155 transfers associated with a specific
+-- address (both sent and received), including mints and burns
+--
+-- Parameters:
+-- {{chain}} - Transpose chain schema, e.g. ethereum or polygon
+-- {{wallet_address}} - The address to query NFT transfers for
+-- {{limit}} - Maximum number of transfers to return per query
+-- {{offset}} - Number of transfers to skip for pagination
+--
+-- Returns:
+-- - transaction_hash: Transaction that emitted the transfer
+-- - log_index: Position of the transfer event within the block's logs
+-- - block_number: Block number containing the transaction
+-- - timestamp: Transfer timestamp
+-- - contract_address: NFT collection contract address
+-- - token_id: Token ID, as text since IDs can exceed 64 bits
+-- - from_address: Sender address
+-- - to_address: Recipient address
+-- - quantity: Number of tokens moved (always 1 for ERC-721), as text
+-- - collection_name: Name of the NFT collection, if known
+-- - token_standard: ERC721 or ERC1155
+SELECT
+    nt.transaction_hash,
+    nt.log_index,
+    nt.block_number,
+    nt.timestamp,
+    nt.contract_address,
+    CAST(nt.token_id AS VARCHAR) AS token_id,
+    nt.from_address,
+    nt.to_address,
+    CAST(nt.quantity AS VARCHAR) AS quantity,
+    c.name AS collection_name,
+    c.standard AS token_standard
+FROM {{chain}}.nft_transfers nt
+LEFT JOIN {{chain}}.nft_collections c ON c.contract_address = nt.contract_address
+WHERE nt.from_address = '{{wallet_address}}'
+   OR nt.to_address = '{{wallet_address}}'
+ORDER BY nt.timestamp DESC, nt.log_index
+LIMIT {{limit}}
+OFFSET {{offset}}
@@ -0,0 +1,42 @@
+-- ERC-20 Token Transfer Query
+--
+-- Retrieves all fungible token transfers associated with a specific address
+-- (both sent and received)
+--
+-- Parameters:
+-- {{chain}} - Transpose chain schema, e.g. ethereum or polygon
+-- {{wallet_address}} - The address to query token transfers for
+-- {{limit}} - Maximum number of transfers to return per query
+-- {{offset}} - Number of transfers to skip for pagination
+--
+-- Returns:
+-- - transaction_hash: Transaction that emitted the transfer
+-- - log_index: Position of the transfer event within the block's logs
+-- - block_number: Block number containing the transaction
+-- - timestamp: Transfer timestamp
+-- - contract_address: Token contract address
+-- - token_symbol: Token ticker, e.g. USDC
+-- - token_decimals: Decimal places of the token
+-- - from_address: Sender address
+-- - to_address: Recipient address
+-- - raw_amount: Amount in the token's smallest unit, as text
+-- - amount: Amount divided by 10^decimals
+SELECT
+    tt.transaction_hash,
+    tt.log_index,
+    tt.block_number,
+    tt.timestamp,
+    tt.contract_address,
+    tk.symbol AS token_symbol,
+    tk.decimals AS token_decimals,
+    tt.from_address,
+    tt.to_address,
+    CAST(tt.quantity AS VARCHAR) AS raw_amount,
+    tt.quantity / POWER(10, COALESCE(tk.decimals, 0)) AS amount
+FROM {{chain}}.token_transfers tt
+LEFT JOIN {{chain}}.tokens tk ON tk.contract_address = tt.contract_address
+WHERE tt.from_address = '{{wallet_address}}'
+   OR tt.to_address = '{{wallet_address}}'
+ORDER BY tt.timestamp DESC, tt.log_index
+LIMIT {{limit}}
+OFFSET {{offset}}